@@ -1,10 +1,10 @@
-/// End-to-end demonstration of the prompt compression system
-///
-/// Shows the complete optimization pipeline with real-world examples:
-/// 1. v0.2 pattern-based optimization (boilerplate, fillers, structural)
-/// 2. v0.3 concept-based optimization (Q-ID resolution + surface form selection)
-/// 3. Protected region detection (never optimize code/instructions)
-/// 4. Token savings analysis
+//! End-to-end demonstration of the prompt compression system
+//!
+//! Shows the complete optimization pipeline with real-world examples:
+//! 1. v0.2 pattern-based optimization (boilerplate, fillers, structural)
+//! 2. v0.3 concept-based optimization (Q-ID resolution + surface form selection)
+//! 3. Protected region detection (never optimize code/instructions)
+//! 4. Token savings analysis
 
 use prompt_compress::{
     ConceptOptimizer, Database, DirectiveFormat, Language, OptimizationRequest,
@@ -22,6 +22,9 @@ fn main() -> anyhow::Result<()> {
 
     // Create v0.3 concept optimizer
     let mut optimizer = ConceptOptimizer::new(db)?;
+    if !optimizer.concepts_enabled()? {
+        println!("Note: data/atlas.db has no concepts yet; showing pattern optimizations only\n");
+    }
 
     // Test cases covering different optimization layers
     let test_cases = vec![
@@ -65,13 +68,10 @@ fn main() -> anyhow::Result<()> {
         println!("Original ({} chars):", prompt.len());
         println!("{}\n", prompt);
 
-        let request = OptimizationRequest {
-            prompt: prompt.to_string(),
-            output_language: Language::English,
-            confidence_threshold: 0.85,
-            aggressive_mode: false,
-            directive_format: DirectiveFormat::Bracketed,
-        };
+        let request = OptimizationRequest::builder(prompt)
+            .output_language(Language::English)
+            .directive_format(DirectiveFormat::Bracketed)
+            .build();
 
         match optimizer.optimize(&request) {
             Ok(result) => {
@@ -85,9 +85,7 @@ fn main() -> anyhow::Result<()> {
                     result.token_savings,
                     result.savings_percentage
                 );
-                println!("  Characters saved:  {}",
-                    prompt.len() as i64 - result.optimized_prompt.len() as i64
-                );
+                println!("  Characters saved:  {}", result.diff_stats.chars_saved());
 
                 if !result.optimizations.is_empty() {
                     println!("\n  Applied optimizations:");
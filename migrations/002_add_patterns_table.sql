@@ -23,9 +23,9 @@ CREATE TABLE IF NOT EXISTS patterns (
     UNIQUE(pattern_type, regex_pattern)       -- No duplicate patterns
 );
 
-CREATE INDEX idx_patterns_type ON patterns(pattern_type);
-CREATE INDEX idx_patterns_confidence ON patterns(base_confidence DESC);
-CREATE INDEX idx_patterns_enabled ON patterns(enabled);
+CREATE INDEX IF NOT EXISTS idx_patterns_type ON patterns(pattern_type);
+CREATE INDEX IF NOT EXISTS idx_patterns_confidence ON patterns(base_confidence DESC);
+CREATE INDEX IF NOT EXISTS idx_patterns_enabled ON patterns(enabled);
 
 -- ==============================================================================
 -- HITL_DECISIONS TABLE
@@ -46,9 +46,9 @@ CREATE TABLE IF NOT EXISTS hitl_decisions (
     FOREIGN KEY (pattern_id) REFERENCES patterns(id) ON DELETE CASCADE
 );
 
-CREATE INDEX idx_decisions_pattern ON hitl_decisions(pattern_id);
-CREATE INDEX idx_decisions_session ON hitl_decisions(session_id);
-CREATE INDEX idx_decisions_decision ON hitl_decisions(decision);
+CREATE INDEX IF NOT EXISTS idx_decisions_pattern ON hitl_decisions(pattern_id);
+CREATE INDEX IF NOT EXISTS idx_decisions_session ON hitl_decisions(session_id);
+CREATE INDEX IF NOT EXISTS idx_decisions_decision ON hitl_decisions(decision);
 
 -- ==============================================================================
 -- TRIGGERS
@@ -78,8 +78,11 @@ BEGIN
                 CAST(accepted_count + (CASE WHEN NEW.decision = 'accept' THEN 1 ELSE 0 END) AS REAL) /
                 CAST(accepted_count + rejected_count + 1 AS REAL)
             ELSE
-                -- Before 10 feedbacks, blend base confidence with early feedback
-                (base_confidence * 10.0 +
+                -- Before 10 feedbacks, blend the original prior with early feedback.
+                -- The stored confidence already includes earlier feedback, so the
+                -- prior weight is recovered as: base_conf * (10 + n) - accepted
+                (base_confidence * (10.0 + CAST(accepted_count + rejected_count AS REAL)) -
+                 CAST(accepted_count AS REAL) +
                  CAST(accepted_count + (CASE WHEN NEW.decision = 'accept' THEN 1 ELSE 0 END) AS REAL)) /
                 (10.0 + CAST(accepted_count + rejected_count + 1 AS REAL))
             END
@@ -129,4 +132,4 @@ WHERE enabled = 1
 GROUP BY pattern_type;
 
 -- Update metadata
-INSERT INTO metadata (key, value) VALUES ('patterns_schema_version', '2');
+INSERT OR IGNORE INTO metadata (key, value) VALUES ('patterns_schema_version', '2');
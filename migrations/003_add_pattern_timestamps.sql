@@ -0,0 +1,13 @@
+-- Phase 3.6: Track when patterns last fired
+-- Purpose: Surface stale patterns that are never applied or accepted anymore
+
+-- ==============================================================================
+-- PATTERNS TABLE
+-- ==============================================================================
+ALTER TABLE patterns ADD COLUMN last_applied_at INTEGER;   -- Unix timestamp, NULL = never
+ALTER TABLE patterns ADD COLUMN last_accepted_at INTEGER;  -- Unix timestamp, NULL = never
+
+CREATE INDEX IF NOT EXISTS idx_patterns_last_applied ON patterns(last_applied_at);
+
+-- Update metadata
+INSERT OR REPLACE INTO metadata (key, value) VALUES ('patterns_schema_version', '3');
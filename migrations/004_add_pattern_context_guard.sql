@@ -0,0 +1,13 @@
+-- Phase 3.7: Context-conditional patterns
+-- Purpose: Only apply a pattern when its surrounding window matches (or doesn't
+-- match) a guard regex, e.g. keep "please" inside quoted policies
+
+-- ==============================================================================
+-- PATTERNS TABLE
+-- ==============================================================================
+-- Regex evaluated against the match's surrounding context window.
+-- NULL = no guard; a leading '!' forbids the context instead of requiring it.
+ALTER TABLE patterns ADD COLUMN context_guard TEXT;
+
+-- Update metadata
+INSERT OR REPLACE INTO metadata (key, value) VALUES ('patterns_schema_version', '4');
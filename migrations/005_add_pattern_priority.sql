@@ -0,0 +1,12 @@
+-- Phase 3.8: Curated pattern priority
+-- Purpose: Let curators force a pattern to win conflicts against overlapping
+-- patterns of equal confidence
+
+-- ==============================================================================
+-- PATTERNS TABLE
+-- ==============================================================================
+-- Higher wins; NULL ranks below any explicit priority.
+ALTER TABLE patterns ADD COLUMN priority INTEGER;
+
+-- Update metadata
+INSERT OR REPLACE INTO metadata (key, value) VALUES ('patterns_schema_version', '5');
@@ -0,0 +1,12 @@
+-- Phase 3.9: Aggressive-only patterns
+-- Purpose: Keep higher-risk patterns (hedge deletion, sentence-opener
+-- compression) out of normal mode entirely rather than just scoring them lower
+
+-- ==============================================================================
+-- PATTERNS TABLE
+-- ==============================================================================
+-- 1 = only detected when aggressive_mode is on.
+ALTER TABLE patterns ADD COLUMN aggressive_only INTEGER NOT NULL DEFAULT 0;
+
+-- Update metadata
+INSERT OR REPLACE INTO metadata (key, value) VALUES ('patterns_schema_version', '6');
@@ -0,0 +1,12 @@
+-- Phase 3.10: Cumulative pattern savings
+-- Purpose: Rank patterns by the tokens they have saved overall, not just by
+-- acceptance rate, so curators know which ones to keep when pruning
+
+-- ==============================================================================
+-- PATTERNS TABLE
+-- ==============================================================================
+-- Sum of the token savings of every recorded application.
+ALTER TABLE patterns ADD COLUMN total_token_savings INTEGER NOT NULL DEFAULT 0;
+
+-- Update metadata
+INSERT OR REPLACE INTO metadata (key, value) VALUES ('patterns_schema_version', '7');
@@ -1,65 +1,377 @@
-use crate::models::{OptimizationRequest, OptimizationResult, ReviewDecision, ReviewSession};
+use crate::edits::TextEdit;
+use crate::error::{PromptCompressError, Result};
+use crate::models::{
+    DiffStats, Optimization, OptimizationRequest, OptimizationResult, ReviewDecision, ReviewSession,
+};
 use crate::optimizer::Optimizer;
-use actix_web::{web, HttpResponse, Responder};
+use crate::save_corpus;
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, Responder};
+use futures_util::StreamExt;
+use lru::LruCache;
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex};
+use sha2::{Digest, Sha256};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Shared application state
 pub struct AppState {
-    pub optimizer: Arc<Mutex<Optimizer>>,
+    /// Shared for reading by every request; written only by corpus updates
+    pub optimizer: Arc<RwLock<Optimizer>>,
     pub review_sessions: Arc<Mutex<std::collections::HashMap<String, ReviewSession>>>,
+    /// Results of recent requests (None disables caching)
+    pub result_cache: Option<Arc<Mutex<ResultCache>>>,
+    /// How long an unfinished review session is kept
+    pub session_ttl: Duration,
+    /// Where review feedback is persisted (None keeps it in memory only)
+    pub corpus_path: Option<String>,
+    /// Feedback was recorded since the corpus was last written
+    corpus_dirty: Arc<AtomicBool>,
+}
+
+/// Default lifetime of a review session
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(60 * 60);
+
+impl AppState {
+    /// Create state without a result cache
+    pub fn new(optimizer: Optimizer) -> Self {
+        Self {
+            optimizer: Arc::new(RwLock::new(optimizer)),
+            review_sessions: Arc::new(Mutex::new(std::collections::HashMap::new())),
+            result_cache: None,
+            session_ttl: DEFAULT_SESSION_TTL,
+            corpus_path: None,
+            corpus_dirty: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Keep unfinished review sessions for `ttl`
+    pub fn with_session_ttl(mut self, ttl: Duration) -> Self {
+        self.session_ttl = ttl;
+        self
+    }
+
+    /// Drop completed sessions and sessions older than the TTL; returns the
+    /// number removed
+    pub fn sweep_review_sessions(&self) -> usize {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        match self.review_sessions.lock() {
+            Ok(mut sessions) => {
+                let before = sessions.len();
+                sessions.retain(|_, session| !session.is_expired(now, self.session_ttl));
+                before - sessions.len()
+            }
+            Err(_) => 0,
+        }
+    }
+
+    /// Cache up to `capacity` optimization results by request fingerprint
+    pub fn with_result_cache(mut self, capacity: usize) -> Self {
+        self.result_cache = Some(Arc::new(Mutex::new(ResultCache::new(capacity))));
+        self
+    }
+
+    /// Persist review feedback to `path` after each submission and on every
+    /// `spawn_corpus_flusher` tick
+    pub fn with_corpus_path(mut self, path: impl Into<String>) -> Self {
+        self.corpus_path = Some(path.into());
+        self
+    }
+
+    /// Write the corpus to `corpus_path` if feedback arrived since the last
+    /// write; returns whether it wrote
+    ///
+    /// A failed write stays pending and is retried on the next flush.
+    pub fn flush_corpus(&self) -> Result<bool> {
+        let Some(path) = &self.corpus_path else {
+            return Ok(false);
+        };
+        if !self.corpus_dirty.swap(false, Ordering::SeqCst) {
+            return Ok(false);
+        }
+
+        let corpus = self
+            .optimizer
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .calculator()
+            .corpus()
+            .clone();
+        save_corpus(&corpus, path).inspect_err(|_| self.corpus_dirty.store(true, Ordering::SeqCst))?;
+        Ok(true)
+    }
+
+    /// Update corpus priors with accept/reject feedback, persist it and drop
+    /// cached results (they were scored with the old priors)
+    ///
+    /// The whole update happens under one optimizer write lock, released
+    /// before the write, so concurrent requests see all of the feedback or
+    /// none of it. Callers must not hold the optimizer lock.
+    fn record_feedback<'a>(&self, feedback: impl IntoIterator<Item = (&'a Optimization, bool)>) {
+        {
+            let mut optimizer = self.optimizer.write().unwrap_or_else(PoisonError::into_inner);
+            for (opt, accepted) in feedback {
+                optimizer
+                    .calculator_mut()
+                    .update_corpus(&opt.original_text, accepted, opt.token_savings);
+            }
+        }
+
+        self.corpus_dirty.store(true, Ordering::SeqCst);
+        if let Err(e) = self.flush_corpus() {
+            log::warn!("Failed to save corpus, will retry: {}", e);
+        }
+
+        // Updated priors change confidence scores
+        self.invalidate_result_cache();
+    }
+
+    /// Drop cached results (call after patterns or priors change)
+    pub fn invalidate_result_cache(&self) {
+        if let Some(cache) = &self.result_cache {
+            if let Ok(mut cache) = cache.lock() {
+                cache.clear();
+            }
+        }
+    }
+
+    /// Optimize through the result cache when enabled
+    ///
+    /// Cache hits never touch the optimizer; misses optimize under a read
+    /// lock, so concurrent requests run in parallel.
+    fn optimize_cached(&self, request: &OptimizationRequest) -> Result<OptimizationResult> {
+        let optimize = || {
+            self.optimizer
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .optimize(request)
+        };
+        let Some(cache) = &self.result_cache else {
+            return optimize();
+        };
+
+        let key = ResultCache::fingerprint(request);
+        if let Some(result) = cache.lock().ok().and_then(|mut c| c.get(&key)) {
+            return Ok(result);
+        }
+
+        let result = optimize()?;
+        if let Ok(mut cache) = cache.lock() {
+            cache.put(key, result.clone());
+        }
+        Ok(result)
+    }
+}
+
+/// HTTP status for a library error: caller mistakes are 4xx, an unavailable
+/// tokenizer or database is 503 so clients can retry, anything else is 500
+pub fn error_status(error: &PromptCompressError) -> StatusCode {
+    match error {
+        PromptCompressError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+        PromptCompressError::NotFound(_) => StatusCode::NOT_FOUND,
+        PromptCompressError::Tokenizer(_) => StatusCode::SERVICE_UNAVAILABLE,
+        PromptCompressError::Database(rusqlite::Error::SqliteFailure(e, _))
+            if e.code == rusqlite::ErrorCode::CannotOpen =>
+        {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        e if e.is_retryable() => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Error response for a failed library call
+fn error_response(action: &str, error: &PromptCompressError) -> HttpResponse {
+    HttpResponse::build(error_status(error)).json(ErrorResponse {
+        error: format!("{} failed: {}", action, error),
+        code: error.code(),
+    })
+}
+
+/// Reject undecodable or oversized JSON bodies with an `ErrorResponse`
+fn json_error(error: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let (status, code) = match error {
+        JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+            (StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large")
+        }
+        _ => (StatusCode::BAD_REQUEST, "invalid_request"),
+    };
+    let response = HttpResponse::build(status).json(ErrorResponse {
+        error: format!("Invalid request body: {}", error),
+        code,
+    });
+    InternalError::from_response(error, response).into()
+}
+
+/// Sweep expired review sessions every `every` on the actix runtime
+pub fn spawn_session_sweeper(data: web::Data<AppState>, every: Duration) {
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(every);
+        loop {
+            interval.tick().await;
+            let removed = data.sweep_review_sessions();
+            if removed > 0 {
+                log::info!("Evicted {} review session(s)", removed);
+            }
+        }
+    });
+}
+
+/// Write pending review feedback to the corpus file every `every` on the
+/// actix runtime
+pub fn spawn_corpus_flusher(data: web::Data<AppState>, every: Duration) {
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(every);
+        loop {
+            interval.tick().await;
+            if let Err(e) = data.flush_corpus() {
+                log::warn!("Failed to save corpus: {}", e);
+            }
+        }
+    });
+}
+
+/// LRU cache of optimization results keyed by request fingerprint
+pub struct ResultCache {
+    entries: LruCache<String, OptimizationResult>,
+    hits: u64,
+    misses: u64,
+}
+
+impl ResultCache {
+    /// Create cache holding at most `capacity` results (minimum 1)
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: LruCache::new(NonZeroUsize::new(capacity.max(1)).unwrap()),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// SHA-256 of the prompt and every request parameter
+    pub fn fingerprint(request: &OptimizationRequest) -> String {
+        let canonical = serde_json::to_string(request).unwrap_or_default();
+        Sha256::digest(canonical.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn get(&mut self, key: &str) -> Option<OptimizationResult> {
+        let result = self.entries.get(key).cloned();
+        if result.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        result
+    }
+
+    fn put(&mut self, key: String, result: OptimizationResult) {
+        self.entries.put(key, result);
+    }
+
+    /// Remove all cached results (hit/miss counters are kept)
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn stats(&self) -> ResultCacheStats {
+        ResultCacheStats {
+            entries: self.entries.len(),
+            capacity: self.entries.cap().get(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+}
+
+/// Result cache metrics
+#[derive(Debug, Clone, Serialize)]
+pub struct ResultCacheStats {
+    pub entries: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
 }
 
 /// Health check endpoint
-pub async fn health_check() -> impl Responder {
+pub async fn health_check(data: web::Data<AppState>) -> impl Responder {
+    let result_cache = data
+        .result_cache
+        .as_ref()
+        .and_then(|cache| cache.lock().ok().map(|c| c.stats()));
+
     HttpResponse::Ok().json(serde_json::json!({
         "status": "healthy",
         "service": "prompt-compress",
-        "version": env!("CARGO_PKG_VERSION")
+        "version": env!("CARGO_PKG_VERSION"),
+        "result_cache": result_cache,
     }))
 }
 
+/// Body shape of the optimize response
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseFormat {
+    /// The full `OptimizationResult`
+    #[default]
+    Full,
+    /// Only span edits against the submitted prompt (`EditsResponse`)
+    Edits,
+}
+
+/// Query parameters for the optimize endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct OptimizeParams {
+    /// `?format=edits` returns span edits instead of the full result
+    #[serde(default)]
+    pub format: ResponseFormat,
+}
+
 /// Optimize a prompt
 pub async fn optimize_prompt(
     data: web::Data<AppState>,
+    params: web::Query<OptimizeParams>,
     request: web::Json<OptimizationRequest>,
 ) -> impl Responder {
-    let mut optimizer = match data.optimizer.lock() {
-        Ok(opt) => opt,
-        Err(_) => return HttpResponse::InternalServerError().json(ErrorResponse {
-            error: "Failed to acquire optimizer lock".to_string(),
-        }),
-    };
-
-    match optimizer.optimize(&request) {
+    match data.optimize_cached(&request) {
         Ok(result) => {
             // If there are items requiring review, create a session
-            if !result.requires_review.is_empty() {
+            let review_session_id = (!result.requires_review.is_empty()).then(|| {
                 let session_id = uuid::Uuid::new_v4().to_string();
-                let session = ReviewSession {
-                    session_id: session_id.clone(),
-                    pending_optimizations: result.requires_review.clone(),
-                    decisions: std::collections::HashMap::new(),
-                };
+                let session = ReviewSession::new(session_id.clone(), result.requires_review.clone());
 
                 if let Ok(mut sessions) = data.review_sessions.lock() {
                     sessions.insert(session_id.clone(), session);
                 }
+                session_id
+            });
 
-                HttpResponse::Ok().json(OptimizationResponse {
-                    result,
-                    review_session_id: Some(session_id),
-                })
-            } else {
-                HttpResponse::Ok().json(OptimizationResponse {
+            match params.format {
+                ResponseFormat::Full => HttpResponse::Ok().json(OptimizationResponse {
                     result,
-                    review_session_id: None,
-                })
+                    review_session_id,
+                }),
+                ResponseFormat::Edits => HttpResponse::Ok().json(EditsResponse {
+                    edits: result.edits(),
+                    original_tokens: result.original_tokens,
+                    optimized_tokens: result.optimized_tokens,
+                    token_savings: result.token_savings,
+                    directive_tokens: result.directive_tokens,
+                    savings_excluding_directive: result.savings_excluding_directive,
+                    diff_stats: result.diff_stats,
+                    review_session_id,
+                }),
             }
         }
-        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse {
-            error: format!("Optimization failed: {}", e),
-        }),
+        Err(e) => error_response("Optimization", &e),
     }
 }
 
@@ -76,6 +388,18 @@ pub struct WebhookRequest {
     pub aggressive_mode: Option<bool>,
     #[serde(default)]
     pub callback_url: Option<String>,
+    /// Record the caller's verdict on the applied optimizations in the
+    /// corpus priors
+    #[serde(default)]
+    pub record_feedback: Option<WebhookFeedback>,
+}
+
+/// Caller's verdict on a webhook result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFeedback {
+    Accepted,
+    Rejected,
 }
 
 #[derive(Debug, Serialize)]
@@ -93,31 +417,29 @@ pub async fn webhook_optimize(
     data: web::Data<AppState>,
     request: web::Json<WebhookRequest>,
 ) -> impl Responder {
-    let mut optimizer = match data.optimizer.lock() {
-        Ok(opt) => opt,
-        Err(_) => return HttpResponse::InternalServerError().json(ErrorResponse {
-            error: "Failed to acquire optimizer lock".to_string(),
-        }),
-    };
-
     // Convert webhook request to optimization request
-    let output_language = match request.output_language.as_deref() {
-        Some("mandarin") | Some("zh") => crate::models::Language::Mandarin,
-        _ => crate::models::Language::English,
+    let output_language = match request.output_language.as_deref().map(str::parse) {
+        Some(Ok(language)) => language,
+        Some(Err(e)) => return error_response("Optimization", &e),
+        None => crate::models::Language::English,
     };
 
-    let opt_request = OptimizationRequest {
-        prompt: request.prompt.clone(),
-        output_language,
-        confidence_threshold: request.confidence_threshold.unwrap_or(0.85),
-        aggressive_mode: request.aggressive_mode.unwrap_or(false),
-        directive_format: crate::models::DirectiveFormat::Bracketed,
-    };
+    let opt_request = OptimizationRequest::builder(request.prompt.clone())
+        .output_language(output_language)
+        .confidence_threshold(request.confidence_threshold.unwrap_or(0.85))
+        .aggressive_mode(request.aggressive_mode.unwrap_or(false))
+        .directive_format(crate::models::DirectiveFormat::Bracketed)
+        .build();
 
-    match optimizer.optimize(&opt_request) {
+    match data.optimize_cached(&opt_request) {
         Ok(result) => {
             let request_id = uuid::Uuid::new_v4().to_string();
 
+            if let Some(feedback) = request.record_feedback {
+                let accepted = feedback == WebhookFeedback::Accepted;
+                data.record_feedback(result.optimizations.iter().map(|opt| (opt, accepted)));
+            }
+
             // If callback URL is provided, send result asynchronously
             if let Some(callback_url) = &request.callback_url {
                 let response = WebhookResponse {
@@ -148,9 +470,7 @@ pub async fn webhook_optimize(
                 status: "completed".to_string(),
             })
         }
-        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse {
-            error: format!("Optimization failed: {}", e),
-        }),
+        Err(e) => error_response("Optimization", &e),
     }
 }
 
@@ -163,6 +483,7 @@ pub async fn get_review_session(
         Ok(s) => s,
         Err(_) => return HttpResponse::InternalServerError().json(ErrorResponse {
             error: "Failed to acquire sessions lock".to_string(),
+            code: "internal",
         }),
     };
 
@@ -170,6 +491,7 @@ pub async fn get_review_session(
         Some(session) => HttpResponse::Ok().json(session),
         None => HttpResponse::NotFound().json(ErrorResponse {
             error: "Review session not found".to_string(),
+            code: "not_found",
         }),
     }
 }
@@ -189,35 +511,31 @@ pub async fn submit_review(
         Ok(s) => s,
         Err(_) => return HttpResponse::InternalServerError().json(ErrorResponse {
             error: "Failed to acquire sessions lock".to_string(),
+            code: "internal",
         }),
     };
 
     match sessions.get_mut(session_id.as_str()) {
         Some(session) => {
             session.decisions.extend(submission.decisions.clone());
+            // Fully decided sessions are dropped by the next sweep
+            session.completed = session
+                .pending_optimizations
+                .iter()
+                .all(|opt| session.decisions.contains_key(&opt.id));
 
-            // Update corpus with feedback
-            let mut optimizer = match data.optimizer.lock() {
-                Ok(opt) => opt,
-                Err(_) => return HttpResponse::InternalServerError().json(ErrorResponse {
-                    error: "Failed to acquire optimizer lock".to_string(),
-                }),
-            };
-
-            for (opt_id, decision) in &submission.decisions {
-                if let Some(opt) = session
-                    .pending_optimizations
-                    .iter()
-                    .find(|o| &o.id == opt_id)
-                {
-                    let accepted = matches!(decision, ReviewDecision::Accept);
-                    optimizer.calculator_mut().update_corpus(
-                        &opt.original_text,
-                        accepted,
-                        opt.token_savings,
-                    );
-                }
-            }
+            // Update corpus with feedback once the sessions lock is released,
+            // so it is never held while waiting on the optimizer
+            let feedback: Vec<(Optimization, bool)> = submission
+                .decisions
+                .iter()
+                .filter_map(|(opt_id, decision)| {
+                    let opt = session.pending_optimizations.iter().find(|o| &o.id == opt_id)?;
+                    Some((opt.clone(), matches!(decision, ReviewDecision::Accept)))
+                })
+                .collect();
+            drop(sessions);
+            data.record_feedback(feedback.iter().map(|(opt, accepted)| (opt, *accepted)));
 
             HttpResponse::Ok().json(serde_json::json!({
                 "status": "success",
@@ -226,41 +544,99 @@ pub async fn submit_review(
         }
         None => HttpResponse::NotFound().json(ErrorResponse {
             error: "Review session not found".to_string(),
+            code: "not_found",
         }),
     }
 }
 
+/// One line of the NDJSON batch response
+#[derive(Debug, Serialize)]
+pub struct BatchLine {
+    /// Position of the request in the submitted batch
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<OptimizationResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Optimize a batch of requests, streaming results as NDJSON
+///
+/// The body is a JSON array of optimization requests. The response has
+/// content type `application/x-ndjson`: one `BatchLine` per request, in order,
+/// written as soon as that request completes.
+pub async fn batch_optimize(
+    data: web::Data<AppState>,
+    requests: web::Json<Vec<OptimizationRequest>>,
+) -> impl Responder {
+    let lines = futures_util::stream::iter(requests.into_inner().into_iter().enumerate()).map(
+        move |(index, request)| {
+            let outcome = data
+                .optimize_cached(&request)
+                .map_err(|e| format!("Optimization failed: {}", e));
+            let line = match outcome {
+                Ok(result) => BatchLine {
+                    index,
+                    result: Some(result),
+                    error: None,
+                },
+                Err(error) => BatchLine {
+                    index,
+                    result: None,
+                    error: Some(error),
+                },
+            };
+
+            let mut json = serde_json::to_vec(&line)?;
+            json.push(b'\n');
+            Ok::<_, actix_web::Error>(web::Bytes::from(json))
+        },
+    );
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(lines)
+}
+
+/// Query parameters for the analyze endpoint
+#[derive(Debug, Default, Deserialize)]
+pub struct AnalyzeParams {
+    /// Include the protected regions report (`?show_protected=true`)
+    #[serde(default)]
+    pub show_protected: bool,
+}
+
 /// Analyze prompt without optimizing
 pub async fn analyze_prompt(
     data: web::Data<AppState>,
+    params: web::Query<AnalyzeParams>,
     request: web::Json<OptimizationRequest>,
 ) -> impl Responder {
-    let mut optimizer = match data.optimizer.lock() {
-        Ok(opt) => opt,
-        Err(_) => return HttpResponse::InternalServerError().json(ErrorResponse {
-            error: "Failed to acquire optimizer lock".to_string(),
-        }),
-    };
-
-    match optimizer.optimize(&request) {
+    match data.optimize_cached(&request) {
         Ok(result) => {
             // Return analysis without applying optimizations
             let analysis = AnalysisResponse {
                 original_tokens: result.original_tokens,
                 potential_savings: result.token_savings,
+                directive_tokens: result.directive_tokens,
+                savings_excluding_directive: result.savings_excluding_directive,
                 savings_percentage: result.savings_percentage,
                 detected_optimizations: result.optimizations.len() + result.requires_review.len(),
                 auto_apply_count: result.optimizations.len(),
                 review_required_count: result.requires_review.len(),
                 optimizations: result.optimizations,
                 requires_review: result.requires_review,
+                protected: params.show_protected.then(|| {
+                    data.optimizer
+                        .read()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .protection_report(&request.prompt)
+                }),
             };
 
             HttpResponse::Ok().json(analysis)
         }
-        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse {
-            error: format!("Analysis failed: {}", e),
-        }),
+        Err(e) => error_response("Analysis", &e),
     }
 }
 
@@ -277,28 +653,383 @@ pub struct OptimizationResponse {
 pub struct AnalysisResponse {
     pub original_tokens: usize,
     pub potential_savings: i64,
+    pub directive_tokens: usize,
+    pub savings_excluding_directive: i64,
     pub savings_percentage: f64,
     pub detected_optimizations: usize,
     pub auto_apply_count: usize,
     pub review_required_count: usize,
     pub optimizations: Vec<crate::models::Optimization>,
     pub requires_review: Vec<crate::models::Optimization>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protected: Option<crate::protected_regions::ProtectionReport>,
+}
+
+/// Optimize response with `?format=edits`
+///
+/// Edits are sorted, non-overlapping byte spans of the submitted prompt;
+/// applying all of them yields the optimized prompt.
+#[derive(Debug, Serialize)]
+pub struct EditsResponse {
+    pub edits: Vec<TextEdit>,
+    pub original_tokens: usize,
+    pub optimized_tokens: usize,
+    pub token_savings: i64,
+    pub directive_tokens: usize,
+    pub savings_excluding_directive: i64,
+    pub diff_stats: DiffStats,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub review_session_id: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
     pub error: String,
+    /// Error kind (`invalid_request`, `not_found`, `database`, ...)
+    pub code: &'static str,
 }
 
 /// Configure API routes
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api/v1")
+            .app_data(web::JsonConfig::default().error_handler(json_error))
             .route("/health", web::get().to(health_check))
             .route("/optimize", web::post().to(optimize_prompt))
             .route("/analyze", web::post().to(analyze_prompt))
+            .route("/batch/optimize", web::post().to(batch_optimize))
             .route("/webhook/optimize", web::post().to(webhook_optimize))
             .route("/review/{session_id}", web::get().to(get_review_session))
             .route("/review/{session_id}", web::post().to(submit_review)),
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+
+    #[actix_web::test]
+    async fn test_batch_streams_ndjson() {
+        let state = web::Data::new(AppState::new(Optimizer::default()));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(configure_routes),
+        )
+        .await;
+
+        let prompts = [
+            "I would really appreciate it if you could analyze this code.",
+            "Summarize the attached report.",
+            "Thank you so much in advance for your help with this! Explain recursion.",
+        ];
+        let body: Vec<serde_json::Value> = prompts
+            .iter()
+            .map(|p| serde_json::json!({ "prompt": p, "output_language": "english" }))
+            .collect();
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/batch/optimize")
+            .set_json(&body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "application/x-ndjson"
+        );
+
+        let bytes = test::read_body(resp).await;
+        let lines: Vec<serde_json::Value> = std::str::from_utf8(&bytes)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), prompts.len());
+        for (i, line) in lines.iter().enumerate() {
+            assert_eq!(line["index"], i);
+            assert_eq!(line["result"]["original_prompt"], prompts[i]);
+            assert!(line.get("error").is_none());
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_error_kinds_map_to_status_codes() {
+        let state = web::Data::new(AppState::new(Optimizer::default()));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(configure_routes),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/optimize")
+            .set_json(serde_json::json!({ "prompt": "Summarize this.", "output_language": "english", "confidence_threshold": 1.5 }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "invalid_request");
+
+        // So are blank prompts and unknown enum values, with a message
+        // naming the problem
+        for (body, expected) in [
+            (serde_json::json!({ "prompt": "  ", "output_language": "english" }), "prompt must not be empty"),
+            (
+                serde_json::json!({ "prompt": "Summarize this.", "output_language": "english", "directive_format": "yaml" }),
+                "unknown variant `yaml`",
+            ),
+            (
+                serde_json::json!({ "prompt": "Summarize this.", "output_language": "english", "confidence_threshold": -0.2 }),
+                "confidence_threshold must be between 0 and 1, got -0.2",
+            ),
+        ] {
+            let req = test::TestRequest::post().uri("/api/v1/optimize").set_json(body).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            assert_eq!(body["code"], "invalid_request");
+            assert!(body["error"].as_str().unwrap().contains(expected), "{}", body["error"]);
+        }
+        let req = test::TestRequest::post()
+            .uri("/api/v1/webhook/optimize")
+            .set_json(serde_json::json!({ "prompt": "Summarize this.", "output_language": "klingon" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // Malformed JSON is the caller's fault too
+        let req = test::TestRequest::post()
+            .uri("/api/v1/optimize")
+            .insert_header(("content-type", "application/json"))
+            .set_payload("{\"prompt\": ")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "invalid_request");
+
+        // Unknown review sessions are 404 with a code
+        let req = test::TestRequest::get().uri("/api/v1/review/missing").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["code"], "not_found");
+
+        assert_eq!(
+            error_status(&PromptCompressError::NotFound("Pattern 7".to_string())),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            error_status(&PromptCompressError::Tokenizer("missing".to_string())),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        let unclosed = String::from("(");
+        assert_eq!(
+            error_status(&regex::Regex::new(&unclosed).unwrap_err().into()),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_expired_review_sessions_swept() {
+        let state = web::Data::new(
+            AppState::new(Optimizer::default()).with_session_ttl(Duration::from_secs(600)),
+        );
+
+        let mut abandoned = ReviewSession::new("abandoned".to_string(), Vec::new());
+        abandoned.created_at -= 601;
+        let mut finished = ReviewSession::new("finished".to_string(), Vec::new());
+        finished.completed = true;
+        let fresh = ReviewSession::new("fresh".to_string(), Vec::new());
+        {
+            let mut sessions = state.review_sessions.lock().unwrap();
+            for session in [abandoned, finished, fresh] {
+                sessions.insert(session.session_id.clone(), session);
+            }
+        }
+
+        assert_eq!(state.sweep_review_sessions(), 2);
+        assert_eq!(
+            state.review_sessions.lock().unwrap().keys().collect::<Vec<_>>(),
+            vec!["fresh"]
+        );
+
+        // The background sweeper runs on the actix runtime
+        let state = web::Data::new(
+            AppState::new(Optimizer::default()).with_session_ttl(Duration::ZERO),
+        );
+        state
+            .review_sessions
+            .lock()
+            .unwrap()
+            .insert("old".to_string(), ReviewSession::new("old".to_string(), Vec::new()));
+        spawn_session_sweeper(state.clone(), Duration::from_millis(10));
+        actix_rt::time::sleep(Duration::from_millis(50)).await;
+        assert!(state.review_sessions.lock().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_review_feedback_persisted_to_corpus() {
+        let path = std::env::temp_dir()
+            .join(format!("corpus-{}.json", uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .into_owned();
+        let state = web::Data::new(AppState::new(Optimizer::default()).with_corpus_path(path.clone()));
+        let app = test::init_service(App::new().app_data(state.clone()).configure(configure_routes)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/optimize")
+            .set_json(serde_json::json!({
+                "prompt": "I would really appreciate it if you could please analyze this code.",
+                "output_language": "english"
+            }))
+            .to_request();
+        let response: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let session_id = response["review_session_id"].as_str().unwrap();
+        let pending = &response["result"]["requires_review"][0];
+
+        // Nothing to write until feedback arrives
+        assert!(!state.flush_corpus().unwrap());
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/v1/review/{}", session_id))
+            .set_json(serde_json::json!({
+                "decisions": { pending["id"].as_str().unwrap(): { "type": "accept" } }
+            }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+
+        // Written on submit, so a reload sees the decision
+        let corpus = crate::load_corpus(&path).unwrap();
+        let stats = &corpus.patterns[pending["original_text"].as_str().unwrap()];
+        assert_eq!(stats.successful_optimizations, 1);
+        assert_eq!(corpus.total_optimizations, 1);
+        assert!(!state.flush_corpus().unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_webhook_records_feedback_concurrently() {
+        let path = std::env::temp_dir()
+            .join(format!("corpus-{}.json", uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .into_owned();
+        let state = web::Data::new(
+            AppState::new(Optimizer::default())
+                .with_result_cache(8)
+                .with_corpus_path(path.clone()),
+        );
+        let app = test::init_service(App::new().app_data(state.clone()).configure(configure_routes)).await;
+
+        let prompt = "I would really appreciate it if you could analyze this code.";
+        let applied = Optimizer::default()
+            .optimize(&OptimizationRequest {
+                prompt: prompt.to_string(),
+                directive_format: crate::models::DirectiveFormat::Bracketed,
+                ..Default::default()
+            })
+            .unwrap()
+            .optimizations;
+        assert!(!applied.is_empty());
+
+        let webhook = |feedback: Option<&str>| {
+            test::TestRequest::post()
+                .uri("/api/v1/webhook/optimize")
+                .set_json(serde_json::json!({ "prompt": prompt, "record_feedback": feedback }))
+                .to_request()
+        };
+
+        // Without feedback the corpus is untouched
+        assert!(test::call_service(&app, webhook(None)).await.status().is_success());
+        assert!(!state.flush_corpus().unwrap());
+
+        let feedback = ["accepted", "accepted", "accepted", "rejected"];
+        let responses = futures_util::future::join_all(
+            feedback.iter().map(|f| test::call_service(&app, webhook(Some(f)))),
+        )
+        .await;
+        assert!(responses.iter().all(|resp| resp.status().is_success()));
+
+        let corpus = crate::load_corpus(&path).unwrap();
+        assert_eq!(corpus.total_optimizations, feedback.len() * applied.len());
+        let stats = &corpus.patterns[&applied[0].original_text];
+        assert_eq!(stats.successful_optimizations, 3);
+        assert_eq!(stats.failed_optimizations, 1);
+        let in_memory = state.optimizer.read().unwrap().calculator().corpus().total_optimizations;
+        assert_eq!(in_memory, corpus.total_optimizations);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_identical_request_served_from_cache() {
+        let state = web::Data::new(AppState::new(Optimizer::default()).with_result_cache(8));
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .configure(configure_routes),
+        )
+        .await;
+
+        let body = serde_json::json!({
+            "prompt": "I would really appreciate it if you could analyze this code.",
+            "output_language": "english"
+        });
+
+        let mut responses = Vec::new();
+        for _ in 0..2 {
+            let req = test::TestRequest::post()
+                .uri("/api/v1/optimize")
+                .set_json(&body)
+                .to_request();
+            let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+            responses.push(resp);
+        }
+
+        let stats = state.result_cache.as_ref().unwrap().lock().unwrap().stats();
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.entries, 1);
+        assert_eq!(
+            responses[0]["result"]["optimized_prompt"],
+            responses[1]["result"]["optimized_prompt"]
+        );
+
+        // Different parameters are a different fingerprint
+        let mut aggressive = body.clone();
+        aggressive["aggressive_mode"] = serde_json::json!(true);
+        let req = test::TestRequest::post()
+            .uri("/api/v1/optimize")
+            .set_json(&aggressive)
+            .to_request();
+        test::call_service(&app, req).await;
+        assert_eq!(state.result_cache.as_ref().unwrap().lock().unwrap().stats().misses, 2);
+
+        state.invalidate_result_cache();
+        assert_eq!(state.result_cache.as_ref().unwrap().lock().unwrap().stats().entries, 0);
+    }
+
+    #[actix_web::test]
+    async fn test_optimizer_shared_between_requests() {
+        let state = AppState::new(Optimizer::default()).with_result_cache(8);
+        let request = OptimizationRequest::builder("I would really appreciate it if you could analyze this code.").build();
+
+        // Another request holding the optimizer does not block this one
+        let reader = state.optimizer.read().unwrap();
+        let result = state.optimize_cached(&request).unwrap();
+        drop(reader);
+
+        // Cache hits are served without the optimizer, even mid-update
+        let writer = state.optimizer.write().unwrap();
+        let cached = state.optimize_cached(&request).unwrap();
+        drop(writer);
+        assert_eq!(cached.optimized_prompt, result.optimized_prompt);
+        assert_eq!(state.result_cache.as_ref().unwrap().lock().unwrap().stats().hits, 1);
+    }
+}
@@ -1,21 +1,70 @@
-/// Pattern Migration Tool
-/// Migrates hardcoded patterns from patterns.rs into the SQLite database
-///
-/// Usage: cargo run --bin migrate_patterns -- atlas.db
-
-use anyhow::{Context, Result};
+//! Pattern Migration Tool
+//! Migrates hardcoded patterns from patterns.rs into the SQLite database
+//!
+//! Usage: cargo run --bin migrate_patterns -- [--replace | --append] atlas.db
+//!
+//! Built-in patterns are keyed on their type and regex. `--replace` (the
+//! default) refreshes each built-in's replacement, confidence, reasoning and
+//! guard in place, so its applied/accepted/rejected counts survive a
+//! re-migration; `--append` only inserts the ones the database lacks.
+//! Patterns added through HITL mining or by hand are never touched.
+
+use anyhow::{bail, Context, Result};
+use prompt_compress::Locale;
 use rusqlite::Connection;
 use std::env;
 use std::path::PathBuf;
 
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
+/// How built-in patterns meet rows already in the database
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    /// Update existing built-ins in place, keeping their counts
+    Replace,
+    /// Insert only built-ins not yet in the database
+    Append,
+}
 
-    let db_path = if args.len() > 1 {
-        PathBuf::from(&args[1])
-    } else {
-        PathBuf::from("atlas.db")
-    };
+impl Mode {
+    /// Insert statement for `columns`, keyed on (pattern_type, regex_pattern)
+    fn insert_sql(self, columns: &[&str]) -> String {
+        let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+        let insert = format!(
+            "INTO patterns ({}) VALUES ({})",
+            columns.join(", "),
+            placeholders.join(", ")
+        );
+
+        match self {
+            Mode::Append => format!("INSERT OR IGNORE {}", insert),
+            Mode::Replace => {
+                let updates: Vec<String> = columns
+                    .iter()
+                    .filter(|column| !matches!(**column, "pattern_type" | "regex_pattern"))
+                    .map(|column| format!("{0} = excluded.{0}", column))
+                    .collect();
+                format!(
+                    "INSERT {} ON CONFLICT(pattern_type, regex_pattern) DO UPDATE SET {}",
+                    insert,
+                    updates.join(", ")
+                )
+            }
+        }
+    }
+}
+
+const COLUMNS: &[&str] = &["pattern_type", "regex_pattern", "replacement", "base_confidence", "reasoning"];
+
+fn main() -> Result<()> {
+    let mut mode = Mode::Replace;
+    let mut db_path = PathBuf::from("atlas.db");
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--replace" => mode = Mode::Replace,
+            "--append" => mode = Mode::Append,
+            flag if flag.starts_with("--") => bail!("Unknown option: {} (expected --replace or --append)", flag),
+            path => db_path = PathBuf::from(path),
+        }
+    }
 
     println!("Migrating patterns to database: {:?}", db_path);
 
@@ -25,21 +74,24 @@ fn main() -> Result<()> {
     // Apply schema migration
     apply_schema_migration(&conn)?;
 
-    // Clear existing patterns (for fresh migration)
-    conn.execute("DELETE FROM patterns", [])?;
-    println!("Cleared existing patterns");
+    let before: i64 = conn.query_row("SELECT COUNT(*) FROM patterns", [], |row| row.get(0))?;
+    match mode {
+        Mode::Replace => println!("Refreshing built-in patterns ({} patterns already stored)", before),
+        Mode::Append => println!("Adding missing built-in patterns ({} patterns already stored)", before),
+    }
 
     // Migrate all pattern types
-    migrate_boilerplate_patterns(&conn)?;
-    migrate_filler_patterns(&conn)?;
-    migrate_instruction_patterns(&conn)?;
-    migrate_redundant_phrases(&conn)?;
-    migrate_structural_patterns(&conn)?;
+    migrate_boilerplate_patterns(&conn, mode)?;
+    migrate_filler_patterns(&conn, mode)?;
+    migrate_instruction_patterns(&conn, mode)?;
+    migrate_redundant_phrases(&conn, mode)?;
+    migrate_structural_patterns(&conn, mode)?;
+    migrate_aggressive_patterns(&conn, mode)?;
 
     // Print summary
     let total: i64 = conn.query_row("SELECT COUNT(*) FROM patterns", [], |row| row.get(0))?;
     println!("\n✅ Migration complete!");
-    println!("   Total patterns migrated: {}", total);
+    println!("   Total patterns stored: {} ({} new)", total, total - before);
 
     // Print breakdown by type
     println!("\nBreakdown by type:");
@@ -85,135 +137,221 @@ fn apply_schema_migration(conn: &Connection) -> Result<()> {
         println!("✓ Patterns table already exists");
     }
 
+    let has_timestamps: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info('patterns') WHERE name = 'last_applied_at'",
+        [],
+        |row| row.get(0),
+    )?;
+
+    if has_timestamps == 0 {
+        println!("Applying schema migration: 003_add_pattern_timestamps.sql");
+        let schema_sql = include_str!("../../migrations/003_add_pattern_timestamps.sql");
+        conn.execute_batch(schema_sql)
+            .context("Failed to execute pattern timestamps migration")?;
+        println!("✓ Schema migration applied");
+    }
+
+    let has_context_guard: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info('patterns') WHERE name = 'context_guard'",
+        [],
+        |row| row.get(0),
+    )?;
+
+    if has_context_guard == 0 {
+        println!("Applying schema migration: 004_add_pattern_context_guard.sql");
+        let schema_sql = include_str!("../../migrations/004_add_pattern_context_guard.sql");
+        conn.execute_batch(schema_sql)
+            .context("Failed to execute pattern context guard migration")?;
+        println!("✓ Schema migration applied");
+    }
+
+    let has_priority: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info('patterns') WHERE name = 'priority'",
+        [],
+        |row| row.get(0),
+    )?;
+
+    if has_priority == 0 {
+        println!("Applying schema migration: 005_add_pattern_priority.sql");
+        let schema_sql = include_str!("../../migrations/005_add_pattern_priority.sql");
+        conn.execute_batch(schema_sql)
+            .context("Failed to execute pattern priority migration")?;
+        println!("✓ Schema migration applied");
+    }
+
+    let has_aggressive_only: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info('patterns') WHERE name = 'aggressive_only'",
+        [],
+        |row| row.get(0),
+    )?;
+
+    if has_aggressive_only == 0 {
+        println!("Applying schema migration: 006_add_pattern_aggressive_only.sql");
+        let schema_sql = include_str!("../../migrations/006_add_pattern_aggressive_only.sql");
+        conn.execute_batch(schema_sql)
+            .context("Failed to execute pattern aggressive-only migration")?;
+        println!("✓ Schema migration applied");
+    }
+
     Ok(())
 }
 
-fn migrate_boilerplate_patterns(conn: &Connection) -> Result<()> {
+fn migrate_boilerplate_patterns(conn: &Connection, mode: Mode) -> Result<()> {
     println!("\nMigrating boilerplate patterns...");
 
     let patterns = prompt_compress::patterns::BOILERPLATE_PATTERNS;
 
-    let mut stmt = conn.prepare(
-        "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
-         VALUES (?1, ?2, ?3, ?4, ?5)"
-    )?;
+    let mut stmt = conn.prepare(&mode.insert_sql(COLUMNS))?;
 
     let mut count = 0;
     for (pattern, replacement, confidence, reasoning) in patterns {
-        stmt.execute(rusqlite::params![
+        count += stmt.execute(rusqlite::params![
             "boilerplate",
             pattern,
             replacement,
             confidence,
             reasoning,
         ])?;
-        count += 1;
     }
 
     println!("   ✓ Migrated {} boilerplate patterns", count);
     Ok(())
 }
 
-fn migrate_filler_patterns(conn: &Connection) -> Result<()> {
+fn migrate_filler_patterns(conn: &Connection, mode: Mode) -> Result<()> {
     println!("Migrating filler word patterns...");
 
     let patterns = prompt_compress::patterns::FILLER_WORDS;
 
-    let mut stmt = conn.prepare(
-        "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
-         VALUES (?1, ?2, ?3, ?4, ?5)"
-    )?;
+    let mut stmt = conn.prepare(&mode.insert_sql(COLUMNS))?;
 
     let mut count = 0;
     for (pattern, confidence, reasoning) in patterns {
-        stmt.execute(rusqlite::params![
+        count += stmt.execute(rusqlite::params![
             "filler",
             pattern,
             "", // Fillers are always removed (empty replacement)
             confidence,
             reasoning,
         ])?;
-        count += 1;
     }
 
     println!("   ✓ Migrated {} filler patterns", count);
     Ok(())
 }
 
-fn migrate_instruction_patterns(conn: &Connection) -> Result<()> {
+fn migrate_instruction_patterns(conn: &Connection, mode: Mode) -> Result<()> {
     println!("Migrating instruction compression patterns...");
 
     let patterns = prompt_compress::patterns::INSTRUCTION_PATTERNS;
 
-    let mut stmt = conn.prepare(
-        "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
-         VALUES (?1, ?2, ?3, ?4, ?5)"
-    )?;
+    let mut stmt = conn.prepare(&mode.insert_sql(COLUMNS))?;
 
     let mut count = 0;
     for (pattern, replacement, confidence, reasoning) in patterns {
-        stmt.execute(rusqlite::params![
+        count += stmt.execute(rusqlite::params![
             "instruction",
             pattern,
             replacement,
             confidence,
             reasoning,
         ])?;
-        count += 1;
     }
 
     println!("   ✓ Migrated {} instruction patterns", count);
     Ok(())
 }
 
-fn migrate_redundant_phrases(conn: &Connection) -> Result<()> {
+fn migrate_redundant_phrases(conn: &Connection, mode: Mode) -> Result<()> {
     println!("Migrating redundant phrase patterns...");
 
     let patterns = prompt_compress::patterns::REDUNDANT_PHRASES;
 
-    let mut stmt = conn.prepare(
-        "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
-         VALUES (?1, ?2, ?3, ?4, ?5)"
-    )?;
+    let mut stmt = conn.prepare(&mode.insert_sql(COLUMNS))?;
 
     let mut count = 0;
     for (pattern, replacement, confidence, reasoning) in patterns {
-        stmt.execute(rusqlite::params![
+        count += stmt.execute(rusqlite::params![
             "redundant",
             pattern,
             replacement,
             confidence,
             reasoning,
         ])?;
-        count += 1;
     }
 
     println!("   ✓ Migrated {} redundant phrase patterns", count);
     Ok(())
 }
 
-fn migrate_structural_patterns(conn: &Connection) -> Result<()> {
+fn migrate_structural_patterns(conn: &Connection, mode: Mode) -> Result<()> {
     println!("Migrating structural optimization patterns...");
 
     let patterns = prompt_compress::patterns::STRUCTURAL_PATTERNS;
 
-    let mut stmt = conn.prepare(
-        "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
-         VALUES (?1, ?2, ?3, ?4, ?5)"
+    // Whitespace rules used to be stored as "structural"; retag them so
+    // their counts carry over to the "whitespace" rows
+    let mut retag = conn.prepare(
+        "UPDATE OR IGNORE patterns SET pattern_type = 'whitespace'
+         WHERE pattern_type = 'structural' AND regex_pattern = ?1",
     )?;
+    for (pattern, ..) in patterns {
+        retag.execute([pattern])?;
+    }
+
+    let mut stmt = conn.prepare(&mode.insert_sql(COLUMNS))?;
 
     let mut count = 0;
     for (pattern, replacement, confidence, reasoning) in patterns {
-        stmt.execute(rusqlite::params![
+        count += stmt.execute(rusqlite::params![
+            "whitespace",
+            pattern,
+            replacement,
+            confidence,
+            reasoning,
+        ])?;
+    }
+
+    // The database carries the default (en-US) unit rules
+    for (pattern, replacement, confidence, reasoning) in Locale::default().expanded_unit_patterns() {
+        count += stmt.execute(rusqlite::params![
+            "structural",
+            pattern,
+            replacement,
+            confidence,
+            reasoning,
+        ])?;
+    }
+
+    let mut stmt = conn.prepare(&mode.insert_sql(&[COLUMNS, &["context_guard"]].concat()))?;
+    for (pattern, replacement, confidence, reasoning, guard) in prompt_compress::patterns::RANGE_PATTERNS {
+        count += stmt.execute(rusqlite::params![
             "structural",
             pattern,
             replacement,
             confidence,
             reasoning,
+            guard,
         ])?;
-        count += 1;
     }
 
     println!("   ✓ Migrated {} structural patterns", count);
     Ok(())
 }
+
+fn migrate_aggressive_patterns(conn: &Connection, mode: Mode) -> Result<()> {
+    println!("Migrating aggressive-only patterns...");
+
+    let mut stmt = conn.prepare(&mode.insert_sql(&[COLUMNS, &["aggressive_only"]].concat()))?;
+
+    let mut count = 0;
+    for (pattern, confidence, reasoning) in prompt_compress::patterns::HEDGE_PHRASES {
+        count += stmt.execute(rusqlite::params!["filler", pattern, "", confidence, reasoning, true])?;
+    }
+    for (pattern, replacement, confidence, reasoning) in prompt_compress::patterns::SENTENCE_OPENERS {
+        count += stmt.execute(rusqlite::params!["instruction", pattern, replacement, confidence, reasoning, true])?;
+    }
+
+    println!("   ✓ Migrated {} aggressive-only patterns", count);
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use actix_web::{middleware, web, App, HttpServer};
-use prompt_compress::{api, init_optimizer};
-use std::sync::{Arc, Mutex};
+use prompt_compress::{api, init_optimizer_with_config, Config};
+use std::path::Path;
+use std::time::Duration;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -8,14 +9,36 @@ async fn main() -> std::io::Result<()> {
 
     log::info!("Starting prompt-compress API server...");
 
-    // Initialize optimizer
-    let optimizer = init_optimizer().expect("Failed to initialize optimizer");
+    // Config file from PROMPT_COMPRESS_CONFIG, defaults otherwise
+    let config = match std::env::var("PROMPT_COMPRESS_CONFIG") {
+        Ok(path) => Config::load(Path::new(&path)).expect("Failed to load config"),
+        Err(_) => Config::default(),
+    };
 
-    // Create shared state
-    let state = web::Data::new(api::AppState {
-        optimizer: Arc::new(Mutex::new(optimizer)),
-        review_sessions: Arc::new(Mutex::new(std::collections::HashMap::new())),
-    });
+    // Initialize optimizer, resuming from feedback saved by a previous run
+    let optimizer = init_optimizer_with_config(&config).expect("Failed to initialize optimizer");
+    let corpus_path = config.bayesian.prior_corpus_path.clone();
+    log::info!(
+        "Loaded {} pattern priors from {}",
+        optimizer.calculator().corpus().patterns.len(),
+        corpus_path
+    );
+
+    // Create shared state (identical requests are served from the result cache)
+    let state = web::Data::new(
+        api::AppState::new(optimizer)
+            .with_result_cache(1000)
+            .with_corpus_path(corpus_path),
+    );
+
+    // Abandoned and finished review sessions are evicted every minute
+    api::spawn_session_sweeper(state.clone(), Duration::from_secs(60));
+
+    // Feedback is saved on submit; the flusher retries failed writes
+    api::spawn_corpus_flusher(
+        state.clone(),
+        Duration::from_secs(config.bayesian.flush_interval_secs.max(1)),
+    );
 
     log::info!("Server starting on http://0.0.0.0:8080");
 
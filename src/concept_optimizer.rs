@@ -1,22 +1,54 @@
-/// Phase 3: Concept Optimizer - Main optimization pipeline
-///
-/// Purpose: Integrate all Phase 3 components into a unified optimization system:
-/// 1. Protected regions detection (never optimize code/instructions)
-/// 2. Structural optimizations (units, formatting, JSON keys)
-/// 3. Concept-based optimizations (Q-ID → cheapest surface form)
-/// 4. v0.2 pattern-based optimizations (boilerplate, fillers, etc.)
-///
-/// Philosophy: Layered optimization with safety guarantees
+//! Phase 3: Concept Optimizer - Main optimization pipeline
+//!
+//! Purpose: Integrate all Phase 3 components into a unified optimization system:
+//! 1. Protected regions detection (never optimize code/instructions)
+//! 2. Structural optimizations (units, formatting, JSON keys)
+//! 3. Concept-based optimizations (Q-ID → cheapest surface form)
+//! 4. v0.2 pattern-based optimizations (boilerplate, fillers, etc.)
+//!
+//! Philosophy: Layered optimization with safety guarantees
 
 use crate::concept_resolver::{ConceptResolver, ResolutionPolicy};
-use crate::database::Database;
-use crate::models::{DirectiveFormat, Language, OptimizationRequest, OptimizationResult};
-use crate::optimizer::Optimizer as V2Optimizer;
+use crate::confidence::{extract_context, ConfidenceCalculator};
+use crate::database::{Concept, Database, SurfaceForm};
+use crate::models::{
+    compression_ratio, savings_percentage, ConfidenceSummary, DiffStats, Optimization, OptimizationRequest,
+    OptimizationResult, OptimizationType,
+};
+use crate::optimizer::{apply_pass, Optimizer as V2Optimizer};
+use crate::patterns::DetectedPattern;
 use crate::protected_regions::{ProtectedRegionDetector, ProtectionPolicy};
-use crate::surface_selector::{SelectionPolicy, SurfaceSelector};
+use crate::sentence::SentenceBoundaries;
+use crate::surface_selector::{OptimizationCandidate, SelectionPolicy, SurfaceSelector};
+use crate::tokenizer::Tokenizer;
 use crate::tokenizer_registry::{TokenizerId, TokenizerRegistry};
-use anyhow::Result;
+use crate::error::{PromptCompressError, Result};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use uuid::Uuid;
+
+/// Base confidence for swapping a word for a cheaper surface form of its concept
+const CONCEPT_BASE_CONFIDENCE: f64 = 0.90;
+
+/// Longest run of words tried as one concept under an atomic selection policy
+const MAX_PHRASE_WORDS: usize = 3;
+
+/// Function words skipped before concept resolution
+///
+/// They never have a cheaper surface form, yet make up roughly 40-50% of
+/// the words in ordinary English prose, so skipping them cuts resolver
+/// lookups (and the cache churn and DB queries behind them) about in half.
+pub const DEFAULT_STOPWORDS: &[&str] = &[
+    "a", "an", "the", "and", "or", "but", "nor", "so", "if", "then", "than", "as", "of", "to",
+    "in", "on", "at", "by", "for", "with", "from", "into", "onto", "about", "over", "under",
+    "up", "down", "out", "off", "is", "am", "are", "was", "were", "be", "been", "being", "do",
+    "does", "did", "has", "have", "had", "will", "would", "shall", "should", "can", "could",
+    "may", "might", "must", "i", "me", "my", "we", "us", "our", "you", "your", "he", "him",
+    "his", "she", "her", "it", "its", "they", "them", "their", "this", "that", "these",
+    "those", "what", "which", "who", "whom", "not", "no", "all", "any", "some", "each",
+    "also", "just", "very",
+];
 
 /// Concept-based optimizer (v0.3)
 pub struct ConceptOptimizer {
@@ -26,15 +58,24 @@ pub struct ConceptOptimizer {
     selector: SurfaceSelector,
     tokenizer_registry: TokenizerRegistry,
     region_detector: ProtectedRegionDetector,
+    calculator: ConfidenceCalculator,
+    sentences: SentenceBoundaries,
 
-    // v0.2 fallback optimizer
+    // v0.2 fallback optimizer (cl100k_base), plus one per other encoding
+    // used through `optimize_for`
     v2_optimizer: V2Optimizer,
+    v2_by_tokenizer: HashMap<TokenizerId, V2Optimizer>,
 
     // Configuration
     tokenizer_id: TokenizerId,
     protection_policy: ProtectionPolicy,
     resolution_policy: ResolutionPolicy,
     selection_policy: SelectionPolicy,
+    /// Lowercase words never sent to the resolver
+    stopwords: HashSet<String>,
+    /// False while the atlas has no concepts; only the v0.2 pattern layer
+    /// runs then. Rechecked until the atlas is populated.
+    concepts_enabled: AtomicBool,
 }
 
 impl ConceptOptimizer {
@@ -46,30 +87,59 @@ impl ConceptOptimizer {
         let region_detector = ProtectedRegionDetector::new(ProtectionPolicy::Conservative);
         let v2_optimizer = V2Optimizer::default();
 
+        // An unpopulated atlas is usable, just without the concept layer
+        let concepts_enabled = db.has_concepts()?;
+        if !concepts_enabled {
+            log::info!("Concept atlas is empty; concept optimization disabled until it is populated");
+        }
+
         Ok(Self {
             db,
             resolver,
             selector,
             tokenizer_registry,
             region_detector,
+            calculator: ConfidenceCalculator::default(),
+            sentences: SentenceBoundaries::default(),
             v2_optimizer,
+            v2_by_tokenizer: HashMap::new(),
             tokenizer_id: TokenizerId::Cl100kBase,
             protection_policy: ProtectionPolicy::Conservative,
             resolution_policy: ResolutionPolicy::Normalized,
             selection_policy: SelectionPolicy::MinTokens,
+            stopwords: DEFAULT_STOPWORDS.iter().map(|w| w.to_string()).collect(),
+            concepts_enabled: AtomicBool::new(concepts_enabled),
         })
     }
 
+    /// Whether concept substitution runs (the atlas has concepts)
+    ///
+    /// An empty atlas is checked again on every call, so one filled later
+    /// through the same database enables the concept layer.
+    pub fn concepts_enabled(&self) -> Result<bool> {
+        if !self.concepts_enabled.load(Ordering::Relaxed) && self.db.has_concepts()? {
+            self.concepts_enabled.store(true, Ordering::Relaxed);
+        }
+        Ok(self.concepts_enabled.load(Ordering::Relaxed))
+    }
+
     /// Configure tokenizer to use
     pub fn with_tokenizer(mut self, tokenizer_id: TokenizerId) -> Self {
         self.tokenizer_id = tokenizer_id;
         self
     }
 
-    /// Configure protection policy
+    /// Configure the protection policy of `coverage_report` and `simulate`
+    ///
+    /// `optimize` follows each request's `protection_policy` instead.
     pub fn with_protection_policy(mut self, policy: ProtectionPolicy) -> Self {
         self.protection_policy = policy;
-        self.region_detector = ProtectedRegionDetector::new(policy);
+        self.region_detector = ProtectedRegionDetector::with_instruction_keywords(
+            policy,
+            self.region_detector.instruction_keywords(),
+        )
+        .with_comment_markers(self.region_detector.comment_markers())
+        .with_template_delimiters(self.region_detector.template_delimiters().iter().cloned());
         self
     }
 
@@ -80,6 +150,20 @@ impl ConceptOptimizer {
         self
     }
 
+    /// Replace the stopword list (case-insensitive); an empty list resolves every word
+    pub fn with_stopwords<I, S>(mut self, stopwords: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.stopwords = stopwords
+            .into_iter()
+            .map(|w| w.as_ref().trim().to_lowercase())
+            .filter(|w| !w.is_empty())
+            .collect();
+        self
+    }
+
     /// Configure selection policy
     pub fn with_selection_policy(mut self, policy: SelectionPolicy) -> Self {
         self.selection_policy = policy;
@@ -88,100 +172,404 @@ impl ConceptOptimizer {
 
     /// Main optimization pipeline
     pub fn optimize(&mut self, request: &OptimizationRequest) -> Result<OptimizationResult> {
+        self.optimize_for(request, self.tokenizer_id)
+    }
+
+    /// Optimize for `tokenizer_id` instead of the configured tokenizer
+    ///
+    /// Surface forms are picked by their stored costs under that tokenizer,
+    /// the v0.2 fallback counts with its encoding, and the totals are
+    /// measured with its backend.
+    pub fn optimize_for(
+        &mut self,
+        request: &OptimizationRequest,
+        tokenizer_id: TokenizerId,
+    ) -> Result<OptimizationResult> {
+        request.validate()?;
+
         // Step 1: Detect protected regions
-        let protected_regions = self.region_detector.detect(&request.prompt);
+        let mut protected_regions = self
+            .region_detector
+            .detect_with_policy(&request.prompt, request.protection_policy);
+        if request.preserve_first_person {
+            protected_regions.extend(self.region_detector.detect_examples(&request.prompt));
+        }
 
         // Step 2: Try concept-based optimization first
-        let concept_optimized = self.try_concept_optimization(&request.prompt, &protected_regions)?;
+        let concept_optimizations =
+            self.score_concept_optimizations(request, &protected_regions, tokenizer_id)?;
+        let (requires_review, auto_apply): (Vec<_>, Vec<_>) = concept_optimizations
+            .into_iter()
+            .partition(|opt| opt.requires_review);
+        let concept_optimized = self.apply_concept_optimizations(
+            &request.prompt,
+            &auto_apply,
+            &protected_regions,
+            !request.preserve_case,
+        );
 
         // Step 3: Fall back to v0.2 pattern-based optimization
         // (This handles boilerplate, fillers, structural patterns, etc.)
-        let fully_optimized = self.v2_optimizer.optimize(&OptimizationRequest {
+        let mut fully_optimized = self.fallback_for(tokenizer_id)?.optimize(&OptimizationRequest {
             prompt: concept_optimized.clone(),
-            output_language: request.output_language.clone(),
-            confidence_threshold: request.confidence_threshold,
-            aggressive_mode: request.aggressive_mode,
-            directive_format: request.directive_format.clone(),
+            ..request.clone()
         })?;
 
+        // Report against the caller's prompt, including the concept swaps
+        // (their positions refer to the original prompt)
+        let tokenizer = self
+            .tokenizer_registry
+            .get(tokenizer_id)
+            .ok_or_else(|| PromptCompressError::Tokenizer("Tokenizer not available".to_string()))?;
+        fully_optimized.original_prompt = request.prompt.clone();
+        fully_optimized.original_tokens = tokenizer.count_tokens(&request.prompt);
+        fully_optimized.optimized_tokens = tokenizer.count_tokens(&fully_optimized.optimized_prompt);
+        fully_optimized.token_savings =
+            fully_optimized.original_tokens as i64 - fully_optimized.optimized_tokens as i64;
+        fully_optimized.savings_including_directive = fully_optimized.token_savings;
+        fully_optimized.savings_excluding_directive =
+            fully_optimized.token_savings + fully_optimized.directive_tokens as i64;
+        fully_optimized.savings_percentage =
+            savings_percentage(fully_optimized.original_tokens, fully_optimized.optimized_tokens);
+        fully_optimized.compression_ratio =
+            compression_ratio(fully_optimized.original_tokens, fully_optimized.optimized_tokens);
+        fully_optimized.net_savings_negative = fully_optimized.token_savings < 0;
+
+        // Concept swaps are the first pass (positions in the original prompt);
+        // the fallback's passes follow, each in the previous pass's output,
+        // and so do its review items
+        if !auto_apply.is_empty() {
+            let fallback = fully_optimized.optimizations.iter_mut().chain(&mut fully_optimized.requires_review);
+            for opt in fallback {
+                opt.pass += 1;
+            }
+        }
+        fully_optimized.optimizations.splice(0..0, auto_apply);
+        // Low-confidence concept swaps go through review instead of being applied
+        fully_optimized.requires_review.extend(requires_review);
+        fully_optimized.replayable &= self.region_detector.has_default_rules();
+        fully_optimized.diff_stats = DiffStats::between(&request.prompt, &fully_optimized.optimized_prompt);
+        fully_optimized.confidence_summary = ConfidenceSummary::new(
+            &fully_optimized.optimizations,
+            &fully_optimized.requires_review,
+            &request.review_policy,
+            !request.aggressive_mode,
+        );
+
         Ok(fully_optimized)
     }
 
-    /// Try concept-based optimization
-    fn try_concept_optimization(
+    /// The v0.2 optimizer counting with `tokenizer_id`'s encoding
+    ///
+    /// HuggingFace tokenizers have no tiktoken encoding, so their pattern
+    /// pass counts with cl100k_base.
+    fn fallback_for(&mut self, tokenizer_id: TokenizerId) -> Result<&V2Optimizer> {
+        match tokenizer_id {
+            TokenizerId::Cl100kBase | TokenizerId::Claude | TokenizerId::Llama3 => Ok(&self.v2_optimizer),
+            TokenizerId::R50kBase | TokenizerId::P50kBase => {
+                if let Entry::Vacant(entry) = self.v2_by_tokenizer.entry(tokenizer_id) {
+                    let tokenizer = Tokenizer::for_id(tokenizer_id)?;
+                    entry.insert(V2Optimizer::new(ConfidenceCalculator::default(), tokenizer));
+                }
+                Ok(&self.v2_by_tokenizer[&tokenizer_id])
+            }
+        }
+    }
+
+    /// Measure how much of a prompt the concept atlas covers
+    ///
+    /// Only unprotected words are counted, since protected ones are never rewritten.
+    pub fn coverage_report(&self, prompt: &str) -> Result<CoverageReport> {
+        let protected_regions = self.region_detector.detect(prompt);
+        let total_words = extract_words(prompt)
+            .iter()
+            .filter(|word| !self.is_word_protected(word, &protected_regions, prompt))
+            .count();
+
+        let resolved = self.resolve_candidates(prompt, &protected_regions, self.tokenizer_id, |_, _| true)?;
+        let candidates: Vec<&OptimizationCandidate> =
+            resolved.iter().filter_map(|(_, candidate)| candidate.as_ref()).collect();
+
+        Ok(CoverageReport {
+            total_words,
+            resolved_words: resolved
+                .iter()
+                .map(|(word, _)| word.text.split_whitespace().count())
+                .sum(),
+            words_with_cheaper_form: candidates.len(),
+            potential_token_savings: candidates.iter().map(|c| c.token_savings).sum(),
+        })
+    }
+
+    /// Simulate concept substitution over a corpus of prompts
+    ///
+    /// Only concept swaps are considered (no pattern optimization) and nothing
+    /// is applied: the report totals what every cheaper surface form would
+    /// save and which concepts it comes from.
+    pub fn simulate(&self, prompts: &[String]) -> Result<SimulationReport> {
+        let tokenizer = self
+            .tokenizer_registry
+            .get(self.tokenizer_id)
+            .ok_or_else(|| PromptCompressError::Tokenizer("Tokenizer not available".to_string()))?;
+
+        let mut report = SimulationReport {
+            prompts: prompts.len(),
+            ..Default::default()
+        };
+        let mut by_concept: HashMap<String, ConceptSavings> = HashMap::new();
+
+        for prompt in prompts {
+            report.original_tokens += tokenizer.count_tokens(prompt);
+
+            let protected_regions = self.region_detector.detect(prompt);
+            for (_, candidate) in
+                self.resolve_candidates(prompt, &protected_regions, self.tokenizer_id, |_, _| true)?
+            {
+                let Some(candidate) = candidate else {
+                    continue;
+                };
+                report.substitutions += 1;
+                report.potential_token_savings += candidate.token_savings;
+
+                let entry = match by_concept.entry(candidate.qid.clone()) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let label = self
+                            .db
+                            .get_concept(&candidate.qid)?
+                            .map_or_else(|| candidate.qid.clone(), |concept| concept.label_en);
+                        entry.insert(ConceptSavings {
+                            qid: candidate.qid.clone(),
+                            label,
+                            optimized_form: candidate.optimized_form.clone(),
+                            substitutions: 0,
+                            token_savings: 0,
+                        })
+                    }
+                };
+                entry.substitutions += 1;
+                entry.token_savings += candidate.token_savings;
+            }
+        }
+
+        // Biggest contributors first
+        report.concepts = by_concept.into_values().collect();
+        report.concepts.sort_by(|a, b| {
+            b.token_savings
+                .cmp(&a.token_savings)
+                .then(b.substitutions.cmp(&a.substitutions))
+                .then_with(|| a.qid.cmp(&b.qid))
+        });
+
+        Ok(report)
+    }
+
+    /// Score concept substitutions with the confidence calculator
+    ///
+    /// Swaps that change script (e.g. into Mandarin) carry extra comprehension
+    /// risk, so they usually land in review. Returns the swaps that clear the minimum confidence, flagged for review
+    /// when below the auto-apply threshold.
+    fn score_concept_optimizations(
+        &self,
+        request: &OptimizationRequest,
+        protected_regions: &[crate::protected_regions::ProtectedRegion],
+        tokenizer_id: TokenizerId,
+    ) -> Result<Vec<Optimization>> {
+        let prompt = &request.prompt;
+        if !request.allows_type(&OptimizationType::ConceptSubstitution) || !self.concepts_enabled()? {
+            return Ok(Vec::new());
+        }
+
+        // Same thresholds as the pattern optimizers
+        let min_confidence = if request.aggressive_mode { 0.4 } else { 0.5 };
+        let auto_apply_threshold = if request.aggressive_mode {
+            0.70
+        } else {
+            request.confidence_threshold
+        };
+
+        let allowed = |word: &Word, concept: &Concept| {
+            request.allows_concept(&word.text, &concept.label_en, &concept.qid)
+        };
+
+        let mut optimizations = Vec::new();
+        for (word, candidate) in self.resolve_candidates(prompt, protected_regions, tokenizer_id, allowed)? {
+            let Some(candidate) = candidate else {
+                continue;
+            };
+
+            let pattern = DetectedPattern {
+                pattern_type: OptimizationType::ConceptSubstitution,
+                original_text: word.text.clone(),
+                optimized_text: candidate.optimized_form.clone(),
+                start_pos: word.start_pos,
+                end_pos: word.end_pos,
+                base_confidence: CONCEPT_BASE_CONFIDENCE,
+                reasoning: format!(
+                    "{} → cheapest surface form ({})",
+                    candidate.qid, candidate.language
+                ),
+                priority: None,
+            };
+
+            let context = extract_context(prompt, word.start_pos, word.end_pos, 50);
+            let confidence = self.calculator.calculate_confidence_with_mode(
+                &pattern,
+                &context,
+                request.aggressive_mode,
+            );
+
+            if confidence.final_confidence < min_confidence {
+                continue;
+            }
+
+            let requires_review = request.review_policy.requires_review(
+                &pattern.pattern_type,
+                confidence.final_confidence,
+                auto_apply_threshold,
+            );
+            let reasoning = request
+                .reasoning_style
+                .render(&pattern.pattern_type, pattern.reasoning);
+            optimizations.push(Optimization {
+                id: Uuid::new_v4().to_string(),
+                optimization_type: pattern.pattern_type,
+                original_text: pattern.original_text,
+                optimized_text: pattern.optimized_text,
+                token_savings: candidate.token_savings,
+                confidence,
+                requires_review,
+                reasoning,
+                start_pos: pattern.start_pos,
+                end_pos: pattern.end_pos,
+                pass: 0,
+            });
+        }
+
+        Ok(optimizations)
+    }
+
+    /// Apply concept substitutions at their word positions, with the same
+    /// cleanup as a pattern pass so the swaps replay like one
+    fn apply_concept_optimizations(
+        &self,
+        prompt: &str,
+        optimizations: &[Optimization],
+        protected_regions: &[crate::protected_regions::ProtectedRegion],
+        capitalize: bool,
+    ) -> String {
+        apply_pass(prompt, optimizations, protected_regions, capitalize.then_some(&self.sentences))
+    }
+
+    /// Resolve unprotected words to concepts, pairing each with a surface
+    /// form that is cheaper under `tokenizer_id` when one exists; concepts
+    /// `allowed` rejects are dropped
+    fn resolve_candidates(
         &self,
         prompt: &str,
         protected_regions: &[crate::protected_regions::ProtectedRegion],
-    ) -> Result<String> {
-        let mut result = prompt.to_string();
-        let words = self.extract_words(prompt);
+        tokenizer_id: TokenizerId,
+        allowed: impl Fn(&Word, &Concept) -> bool,
+    ) -> Result<Vec<(Word, Option<OptimizationCandidate>)>> {
+        let words = extract_words(prompt);
 
         let tokenizer = self.tokenizer_registry
-            .get(self.tokenizer_id)
-            .ok_or_else(|| anyhow::anyhow!("Tokenizer not available"))?;
+            .get(tokenizer_id)
+            .ok_or_else(|| PromptCompressError::Tokenizer("Tokenizer not available".to_string()))?;
 
-        for word in words {
-            // Skip if word is in protected region
-            if self.is_word_protected(&word, protected_regions, prompt) {
+        // Resolve unprotected words to concepts
+        let mut resolved = Vec::new();
+        let mut i = 0;
+        while i < words.len() {
+            // Under an atomic policy a phrase concept claims all of its words,
+            // so they are swapped together or not at all
+            if self.selection_policy.is_atomic() {
+                if let Some((phrase, concept, len)) =
+                    self.resolve_phrase(prompt, &words[i..], protected_regions)?
+                {
+                    resolved.push((phrase, concept));
+                    i += len;
+                    continue;
+                }
+            }
+
+            let word = &words[i];
+            i += 1;
+
+            // Skip if word is in protected region or a stopword
+            if self.is_word_protected(word, protected_regions, prompt) || self.is_stopword(word) {
                 continue;
             }
 
             // Try to resolve to concept
             if let Some(concept) = self.resolver.resolve(&word.text)? {
-                // Get original token count
-                let original_tokens = tokenizer.count_tokens(&word.text);
+                resolved.push((word.clone(), concept));
+            }
+        }
+
+        resolved.retain(|(word, concept)| allowed(word, concept));
 
-                // Try to find better surface form
-                if let Some(candidate) = self.selector.calculate_savings(
+        // Get original token counts in one batch
+        let texts: Vec<&str> = resolved.iter().map(|(word, _)| word.text.as_str()).collect();
+        let token_counts = tokenizer.count_tokens_batch(&texts);
+
+        let mut candidates = Vec::with_capacity(resolved.len());
+        for ((word, concept), original_tokens) in resolved.into_iter().zip(token_counts) {
+            // Try to find better surface form
+            let candidate = self
+                .selector
+                .calculate_savings(
                     &concept.qid,
-                    self.tokenizer_id,
+                    tokenizer_id,
                     &word.text,
                     original_tokens,
                     &self.selection_policy,
-                )? {
-                    // Only apply if we save tokens and have high confidence
-                    if candidate.token_savings > 0 {
-                        result = result.replace(&word.text, &candidate.optimized_form);
-                    }
-                }
-            }
+                )?
+                .filter(|candidate| candidate.token_savings > 0);
+            candidates.push((word, candidate));
         }
 
-        Ok(result)
+        Ok(candidates)
     }
 
-    /// Extract words from text
-    fn extract_words(&self, text: &str) -> Vec<Word> {
-        let mut words = Vec::new();
-        let mut current_word = String::new();
-        let mut start_pos = 0;
+    /// Resolve the longest whitespace-separated run of leading `words`
+    /// (at least two) that names a concept, returning it as a single span
+    fn resolve_phrase(
+        &self,
+        prompt: &str,
+        words: &[Word],
+        protected_regions: &[crate::protected_regions::ProtectedRegion],
+    ) -> Result<Option<(Word, Concept, usize)>> {
+        let max_len = words.len().min(MAX_PHRASE_WORDS);
+        for len in (2..=max_len).rev() {
+            let run = &words[..len];
+            let contiguous = run
+                .windows(2)
+                .all(|pair| prompt[pair[0].end_pos..pair[1].start_pos].chars().all(char::is_whitespace));
+            if !contiguous {
+                continue;
+            }
 
-        for (pos, ch) in text.char_indices() {
-            if ch.is_alphabetic() {
-                if current_word.is_empty() {
-                    start_pos = pos;
-                }
-                current_word.push(ch);
-            } else if !current_word.is_empty() {
-                words.push(Word {
-                    text: current_word.clone(),
-                    start_pos,
-                    end_pos: pos,
-                });
-                current_word.clear();
+            let phrase = Word {
+                text: prompt[run[0].start_pos..run[len - 1].end_pos].to_string(),
+                start_pos: run[0].start_pos,
+                end_pos: run[len - 1].end_pos,
+            };
+            if self.is_word_protected(&phrase, protected_regions, prompt) {
+                continue;
             }
-        }
 
-        // Handle last word
-        if !current_word.is_empty() {
-            words.push(Word {
-                text: current_word,
-                start_pos,
-                end_pos: text.len(),
-            });
+            if let Some(concept) = self.resolver.resolve(&phrase.text)? {
+                return Ok(Some((phrase, concept, len)));
+            }
         }
 
-        words
+        Ok(None)
+    }
+
+    /// Check if word is a stopword (never resolved on its own)
+    fn is_stopword(&self, word: &Word) -> bool {
+        self.stopwords.contains(&word.text.to_lowercase())
     }
 
     /// Check if word is in protected region
@@ -198,7 +586,7 @@ impl ConceptOptimizer {
     pub fn get_stats(&self) -> OptimizerStats {
         OptimizerStats {
             cache_stats: self.resolver.cache_stats(),
-            db_stats: self.db.get_stats().unwrap_or_else(|_| crate::database::DatabaseStats {
+            db_stats: self.db.get_stats().unwrap_or(crate::database::DatabaseStats {
                 total_concepts: 0,
                 total_surface_forms: 0,
                 cache_size: 0,
@@ -215,6 +603,158 @@ struct Word {
     end_pos: usize,
 }
 
+/// Extract words from text
+fn extract_words(text: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut current_word = String::new();
+    let mut start_pos = 0;
+
+    for (pos, ch) in text.char_indices() {
+        if ch.is_alphabetic() {
+            if current_word.is_empty() {
+                start_pos = pos;
+            }
+            current_word.push(ch);
+        } else if !current_word.is_empty() {
+            words.push(Word {
+                text: current_word.clone(),
+                start_pos,
+                end_pos: pos,
+            });
+            current_word.clear();
+        }
+    }
+
+    // Handle last word
+    if !current_word.is_empty() {
+        words.push(Word {
+            text: current_word,
+            start_pos,
+            end_pos: text.len(),
+        });
+    }
+
+    words
+}
+
+/// Concept swaps for the single words of `text`, as detected patterns
+///
+/// Used by pipelines that score and resolve conflicts themselves (e.g.
+/// `DatabaseOptimizer`). Stopwords and words whose concept `allowed`
+/// rejects are skipped, and only swaps that save tokens under `tokenizer_id`
+/// are returned; protected regions are left to the caller.
+pub(crate) fn detect_concept_substitutions(
+    text: &str,
+    resolver: &ConceptResolver,
+    selector: &SurfaceSelector,
+    tokenizer_id: TokenizerId,
+    count_tokens: impl Fn(&str) -> usize,
+    allowed: impl Fn(&str, &Concept) -> bool,
+) -> Result<Vec<DetectedPattern>> {
+    let mut detected = Vec::new();
+    for word in extract_words(text) {
+        if DEFAULT_STOPWORDS.iter().any(|w| w.eq_ignore_ascii_case(&word.text)) {
+            continue;
+        }
+        let Some(concept) = resolver.resolve(&word.text)? else {
+            continue;
+        };
+        if !allowed(&word.text, &concept) {
+            continue;
+        }
+        let Some(candidate) = selector.calculate_savings(
+            &concept.qid,
+            tokenizer_id,
+            &word.text,
+            count_tokens(&word.text),
+            &SelectionPolicy::MinTokens,
+        )?
+        else {
+            continue;
+        };
+
+        detected.push(DetectedPattern {
+            pattern_type: OptimizationType::ConceptSubstitution,
+            original_text: word.text,
+            optimized_text: candidate.optimized_form,
+            start_pos: word.start_pos,
+            end_pos: word.end_pos,
+            base_confidence: CONCEPT_BASE_CONFIDENCE,
+            reasoning: format!("{} → cheapest surface form ({})", candidate.qid, candidate.language),
+            priority: None,
+        });
+    }
+
+    Ok(detected)
+}
+
+/// How much of a prompt the concept atlas covers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoverageReport {
+    /// Unprotected words in the prompt
+    pub total_words: usize,
+    /// Words that resolved to a concept
+    pub resolved_words: usize,
+    /// Resolved words with a cheaper surface form
+    pub words_with_cheaper_form: usize,
+    /// Tokens saved if every cheaper form were applied
+    pub potential_token_savings: i64,
+}
+
+impl CoverageReport {
+    /// Add another report's counts into this one
+    pub fn merge(&mut self, other: &CoverageReport) {
+        self.total_words += other.total_words;
+        self.resolved_words += other.resolved_words;
+        self.words_with_cheaper_form += other.words_with_cheaper_form;
+        self.potential_token_savings += other.potential_token_savings;
+    }
+
+    /// Percentage of words resolved to a concept
+    pub fn coverage_percentage(&self) -> f64 {
+        if self.total_words == 0 {
+            0.0
+        } else {
+            self.resolved_words as f64 / self.total_words as f64 * 100.0
+        }
+    }
+}
+
+/// Projected effect of concept substitution over a prompt corpus
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimulationReport {
+    /// Prompts simulated
+    pub prompts: usize,
+    /// Tokens across all prompts before substitution
+    pub original_tokens: usize,
+    /// Words that would be swapped for a cheaper surface form
+    pub substitutions: usize,
+    /// Tokens saved if every swap were applied
+    pub potential_token_savings: i64,
+    /// Per-concept totals, largest savings first
+    pub concepts: Vec<ConceptSavings>,
+}
+
+impl SimulationReport {
+    /// Potential savings as a percentage of the corpus's tokens
+    pub fn savings_percentage(&self) -> f64 {
+        let optimized = (self.original_tokens as i64 - self.potential_token_savings).max(0) as usize;
+        savings_percentage(self.original_tokens, optimized)
+    }
+}
+
+/// One concept's share of a `SimulationReport`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConceptSavings {
+    pub qid: String,
+    /// English label, or the Q-ID when the concept has none stored
+    pub label: String,
+    /// Cheapest surface form the words were swapped for (first seen)
+    pub optimized_form: String,
+    pub substitutions: usize,
+    pub token_savings: i64,
+}
+
 /// Optimizer statistics
 #[derive(Debug, Clone)]
 pub struct OptimizerStats {
@@ -222,10 +762,153 @@ pub struct OptimizerStats {
     pub db_stats: crate::database::DatabaseStats,
 }
 
+/// Built-in starter concept: (Q-ID, English label, description, category, translations)
+type SeedConcept = (&'static str, &'static str, &'static str, &'static str, [(&'static str, &'static str); 4]);
+
+/// Common concepts across domains, used to bootstrap an empty atlas
+const SEED_CONCEPTS: &[SeedConcept] = &[
+    // Technical concepts
+    ("Q40056", "code", "computer code", "technical",
+        [("es", "código"), ("fr", "code"), ("zh", "代码"), ("ja", "コード")]),
+    ("Q1931388", "bug", "software bug", "technical",
+        [("es", "error"), ("fr", "bogue"), ("zh", "错误"), ("ja", "バグ")]),
+    ("Q187931", "function", "programming function", "technical",
+        [("es", "función"), ("fr", "fonction"), ("zh", "函数"), ("ja", "関数")]),
+    ("Q165194", "API", "application programming interface", "technical",
+        [("es", "API"), ("fr", "API"), ("zh", "API"), ("ja", "API")]),
+    ("Q8513", "database", "structured data storage", "technical",
+        [("es", "base de datos"), ("fr", "base de données"), ("zh", "数据库"), ("ja", "データベース")]),
+    ("Q44127", "server", "computer server", "technical",
+        [("es", "servidor"), ("fr", "serveur"), ("zh", "服务器"), ("ja", "サーバー")]),
+    // Action verbs
+    ("Q217602", "analyze", "examine in detail", "action",
+        [("es", "analizar"), ("fr", "analyser"), ("zh", "分析"), ("ja", "分析する")]),
+    ("Q79030", "verify", "confirm truth or accuracy", "action",
+        [("es", "verificar"), ("fr", "vérifier"), ("zh", "验证"), ("ja", "検証する")]),
+    ("Q188507", "optimize", "make as effective as possible", "action",
+        [("es", "optimizar"), ("fr", "optimiser"), ("zh", "优化"), ("ja", "最適化する")]),
+    ("Q13143958", "explain", "make clear", "action",
+        [("es", "explicar"), ("fr", "expliquer"), ("zh", "解释"), ("ja", "説明する")]),
+    ("Q1302249", "implement", "put into effect", "action",
+        [("es", "implementar"), ("fr", "implémenter"), ("zh", "实现"), ("ja", "実装する")]),
+    // Medical concepts
+    ("Q16917", "hospital", "healthcare facility", "medical",
+        [("es", "hospital"), ("fr", "hôpital"), ("zh", "医院"), ("ja", "病院")]),
+    ("Q131512", "patient", "person receiving medical care", "medical",
+        [("es", "paciente"), ("fr", "patient"), ("zh", "患者"), ("ja", "患者")]),
+    ("Q788750", "diagnosis", "identification of disease", "medical",
+        [("es", "diagnóstico"), ("fr", "diagnostic"), ("zh", "诊断"), ("ja", "診断")]),
+    // Qualifiers/adjectives
+    ("Q685363", "comprehensive", "complete and thorough", "qualifier",
+        [("es", "integral"), ("fr", "complet"), ("zh", "全面"), ("ja", "包括的")]),
+    ("Q339356", "thorough", "complete with attention to detail", "qualifier",
+        [("es", "minucioso"), ("fr", "minutieux"), ("zh", "彻底"), ("ja", "徹底的")]),
+    ("Q1860557", "detailed", "having many details", "qualifier",
+        [("es", "detallado"), ("fr", "détaillé"), ("zh", "详细"), ("ja", "詳細")]),
+    // Common nouns
+    ("Q395", "issue", "problem or matter", "general",
+        [("es", "problema"), ("fr", "problème"), ("zh", "问题"), ("ja", "問題")]),
+];
+
+/// Built-in starter concepts with surface forms (English label plus
+/// translations) token-counted for every tokenizer in the registry
+pub fn seed_concepts(registry: &TokenizerRegistry) -> Vec<(Concept, Vec<SurfaceForm>)> {
+    SEED_CONCEPTS
+        .iter()
+        .map(|(qid, label_en, description, category, translations)| {
+            let concept = Concept {
+                qid: qid.to_string(),
+                label_en: label_en.to_string(),
+                description: Some(description.to_string()),
+                category: Some(category.to_string()),
+            };
+
+            let mut forms: Vec<(&str, &str)> = vec![("en", label_en)];
+            forms.extend(translations.iter().copied());
+
+            (concept, count_surface_forms(registry, qid, &forms))
+        })
+        .collect()
+}
+
+/// Build surface forms for `(lang, form)` pairs, token-counted for every
+/// tokenizer in the registry
+pub(crate) fn count_surface_forms(
+    registry: &TokenizerRegistry,
+    qid: &str,
+    forms: &[(&str, &str)],
+) -> Vec<SurfaceForm> {
+    let texts: Vec<&str> = forms.iter().map(|(_, form)| *form).collect();
+    let mut surface_forms = Vec::new();
+
+    for id in TokenizerId::all() {
+        let Some(backend) = registry.get(id) else {
+            continue;
+        };
+
+        let token_counts = backend.count_tokens_batch(&texts);
+        for ((lang, form), token_count) in forms.iter().zip(token_counts) {
+            surface_forms.push(SurfaceForm {
+                qid: qid.to_string(),
+                tokenizer_id: id.as_str().to_string(),
+                lang: lang.to_string(),
+                form: form.to_string(),
+                token_count,
+                char_count: form.chars().count(),
+            });
+        }
+    }
+
+    surface_forms
+}
+
+/// Write the built-in starter concepts into an atlas
+///
+/// Safe to re-run: concepts and surface forms are upserted. Returns the number
+/// of concepts and surface forms written.
+pub fn seed_atlas(db: &Database, registry: &TokenizerRegistry) -> Result<(usize, usize)> {
+    let seeds = seed_concepts(registry);
+    let mut surface_forms = 0;
+
+    for (concept, forms) in &seeds {
+        db.upsert_concept(concept)?;
+        for form in forms {
+            db.insert_surface_form(form)?;
+        }
+        surface_forms += forms.len();
+    }
+
+    Ok((seeds.len(), surface_forms))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::database::{Concept, Database, SurfaceForm};
+    use crate::models::{DirectiveFormat, Language, ReviewPolicy, ReviewRule};
+
+    #[test]
+    fn test_seed_atlas() {
+        let db = Database::in_memory().unwrap();
+        let registry = TokenizerRegistry::new().unwrap();
+
+        let (concepts, forms) = seed_atlas(&db, &registry).unwrap();
+        assert_eq!(concepts, SEED_CONCEPTS.len());
+
+        // English plus four translations per available tokenizer
+        let tokenizers = registry.available().len();
+        assert_eq!(forms, concepts * 5 * tokenizers);
+
+        let stats = db.get_stats().unwrap();
+        assert_eq!(stats.total_concepts, concepts);
+        assert_eq!(stats.total_surface_forms, forms);
+
+        let cheapest = db.get_cheapest_form("Q16917", "cl100k_base").unwrap().unwrap();
+        assert_eq!(cheapest.token_count, 1);
+
+        // Re-seeding updates in place
+        seed_atlas(&db, &registry).unwrap();
+        assert_eq!(db.get_stats().unwrap().total_concepts, concepts);
+    }
 
     fn setup_test_optimizer() -> ConceptOptimizer {
         let db = Database::in_memory().unwrap();
@@ -270,14 +953,35 @@ mod tests {
 
     #[test]
     fn test_extract_words() {
-        let optimizer = setup_test_optimizer();
-        let words = optimizer.extract_words("The hospital is open today.");
+        let words = extract_words("The hospital is open today.");
 
         assert!(words.len() >= 4); // The, hospital, is, open, today
         assert!(words.iter().any(|w| w.text == "hospital"));
         assert!(words.iter().any(|w| w.text == "today"));
     }
 
+    #[test]
+    fn test_stopwords_not_resolved() {
+        let prompt = "The hospital is open to all of the patients.";
+
+        // Only hospital, open and patients reach the resolver
+        let optimizer = setup_test_optimizer();
+        let report = optimizer.coverage_report(prompt).unwrap();
+        assert_eq!(report.resolved_words, 1);
+        assert_eq!(optimizer.get_stats().cache_stats.size, 3);
+
+        // Custom list, matched case-insensitively
+        let optimizer = setup_test_optimizer().with_stopwords(["THE", "hospital"]);
+        let report = optimizer.coverage_report(prompt).unwrap();
+        assert_eq!(report.resolved_words, 0);
+        assert_eq!(optimizer.get_stats().cache_stats.size, 6);
+
+        // An empty list resolves every word
+        let optimizer = setup_test_optimizer().with_stopwords(Vec::<String>::new());
+        optimizer.coverage_report(prompt).unwrap();
+        assert_eq!(optimizer.get_stats().cache_stats.size, 9);
+    }
+
     #[test]
     fn test_protected_region_skip() {
         let optimizer = setup_test_optimizer();
@@ -305,6 +1009,7 @@ mod tests {
             confidence_threshold: 0.85,
             aggressive_mode: false,
             directive_format: DirectiveFormat::Bracketed,
+            ..Default::default()
         };
 
         let result = optimizer.optimize(&request).unwrap();
@@ -319,13 +1024,236 @@ mod tests {
         let optimizer = setup_test_optimizer();
         let prompt = "Visit the hospital today.";
 
+        let request = OptimizationRequest {
+            prompt: prompt.to_string(),
+            ..Default::default()
+        };
         let protected = optimizer.region_detector.detect(prompt);
-        let optimized = optimizer.try_concept_optimization(prompt, &protected).unwrap();
+        let optimizations = optimizer.score_concept_optimizations(&request, &protected, TokenizerId::Cl100kBase).unwrap();
+        let optimized = optimizer.apply_concept_optimizations(prompt, &optimizations, &protected, true);
 
         // Should keep the text (hospital is already optimal in English)
+        assert!(optimizations.is_empty());
         assert!(optimized.contains("hospital"));
     }
 
+    #[test]
+    fn test_concept_swaps_replay_before_fallback() {
+        let mut optimizer = setup_test_optimizer();
+        optimizer.db.upsert_concept(&Concept {
+            qid: "Q180370".to_string(),
+            label_en: "hospitalization".to_string(),
+            description: None,
+            category: Some("medical".to_string()),
+        }).unwrap();
+        optimizer.db.insert_surface_form(&SurfaceForm {
+            qid: "Q180370".to_string(),
+            tokenizer_id: "cl100k_base".to_string(),
+            lang: "en".to_string(),
+            form: "hosp".to_string(),
+            token_count: 1,
+            char_count: 4,
+        }).unwrap();
+
+        let request = OptimizationRequest {
+            prompt: "I would really appreciate it if you could basically summarize the hospitalization notes.".to_string(),
+            confidence_threshold: 0.5,
+            language_directive: false,
+            ..Default::default()
+        };
+        let result = optimizer.optimize(&request).unwrap();
+
+        // A concept swap in pass 0, the fallback's edits after it
+        let swap = result.optimizations.iter().find(|opt| opt.original_text == "hospitalization").unwrap();
+        assert_eq!(swap.pass, 0);
+        assert!(result.optimizations.iter().any(|opt| opt.pass > 0));
+
+        assert_eq!(result.apply_to(&result.original_prompt).unwrap(), result.optimized_prompt);
+    }
+
+    #[test]
+    fn test_diff_stats_include_concept_swaps() {
+        let mut optimizer = setup_test_optimizer();
+        optimizer.db.upsert_concept(&Concept {
+            qid: "Q180370".to_string(),
+            label_en: "hospitalization".to_string(),
+            description: None,
+            category: Some("medical".to_string()),
+        }).unwrap();
+        optimizer.db.insert_surface_form(&SurfaceForm {
+            qid: "Q180370".to_string(),
+            tokenizer_id: "cl100k_base".to_string(),
+            lang: "en".to_string(),
+            form: "hosp".to_string(),
+            token_count: 1,
+            char_count: 4,
+        }).unwrap();
+
+        // Only the concept layer changes this prompt
+        let request = OptimizationRequest {
+            prompt: "Summarize the hospitalization notes.".to_string(),
+            confidence_threshold: 0.5,
+            language_directive: false,
+            ..Default::default()
+        };
+        let result = optimizer.optimize(&request).unwrap();
+
+        assert_eq!(result.optimized_prompt, "Summarize the hosp notes.");
+        assert_eq!(result.diff_stats, DiffStats::between(&request.prompt, &result.optimized_prompt));
+        assert_eq!(result.diff_stats.chars_saved(), ("hospitalization".len() - "hosp".len()) as i64);
+        let counted: usize = result.confidence_summary.distribution.iter().map(|b| b.count).sum();
+        assert_eq!(counted, result.optimizations.len() + result.requires_review.len());
+    }
+
+    #[test]
+    fn test_review_items_locate_their_pass() {
+        let mut optimizer = setup_test_optimizer();
+        for (qid, label, lang, form) in [
+            ("Q180370", "hospitalization", "en", "hosp"),
+            ("Q12140", "medication", "zh", "药物"),
+        ] {
+            optimizer.db.upsert_concept(&Concept {
+                qid: qid.to_string(),
+                label_en: label.to_string(),
+                description: None,
+                category: Some("medical".to_string()),
+            }).unwrap();
+            optimizer.db.insert_surface_form(&SurfaceForm {
+                qid: qid.to_string(),
+                tokenizer_id: "cl100k_base".to_string(),
+                lang: lang.to_string(),
+                form: form.to_string(),
+                token_count: 1,
+                char_count: form.chars().count(),
+            }).unwrap();
+        }
+
+        // The English swap clears the threshold, the Mandarin one waits for
+        // review, and so does every filler removal
+        let request = OptimizationRequest {
+            prompt: "Summarize the hospitalization notes and basically the medication list.".to_string(),
+            confidence_threshold: 0.8,
+            language_directive: false,
+            review_policy: ReviewPolicy::default().with_rule(OptimizationType::FillerRemoval, ReviewRule::AlwaysReview),
+            ..Default::default()
+        };
+        let result = optimizer.optimize(&request).unwrap();
+        assert_eq!(result.optimized_prompt, "Summarize the hosp notes and basically the medication list.");
+
+        // The concept swap is reviewed against the caller's prompt, the
+        // fallback's filler against the prompt after the concept pass
+        let passes = [request.prompt.clone(), request.prompt.replace("hospitalization", "hosp")];
+        let review = |text: &str| result.requires_review.iter().find(|opt| opt.original_text == text).unwrap();
+        assert_eq!(review("medication").pass, 0);
+        assert_eq!(review("basically").pass, 1);
+        for opt in &result.requires_review {
+            assert_eq!(&passes[opt.pass][opt.start_pos..opt.end_pos], opt.original_text);
+        }
+    }
+
+    #[test]
+    fn test_cross_language_swap_requires_review() {
+        let mut optimizer = setup_test_optimizer();
+
+        // Mandarin form is cheaper than the English word
+        optimizer.db.upsert_concept(&Concept {
+            qid: "Q180370".to_string(),
+            label_en: "hospitalization".to_string(),
+            description: None,
+            category: Some("medical".to_string()),
+        }).unwrap();
+        optimizer.db.insert_surface_form(&SurfaceForm {
+            qid: "Q180370".to_string(),
+            tokenizer_id: "cl100k_base".to_string(),
+            lang: "zh".to_string(),
+            form: "住院".to_string(),
+            token_count: 1,
+            char_count: 6,
+        }).unwrap();
+
+        let request = OptimizationRequest {
+            prompt: "Summarize the patient's hospitalization history.".to_string(),
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+
+        // Not applied automatically...
+        assert!(result.optimized_prompt.contains("hospitalization"));
+        assert!(!result.optimized_prompt.contains("住院"));
+
+        // ...but offered for review with its confidence breakdown
+        let review = result
+            .requires_review
+            .iter()
+            .find(|opt| opt.original_text == "hospitalization")
+            .expect("cross-language swap should require review");
+        assert_eq!(review.optimized_text, "住院");
+        assert_eq!(review.optimization_type, OptimizationType::ConceptSubstitution);
+        assert!(review.confidence.final_confidence < request.confidence_threshold);
+        assert!(review.token_savings > 0);
+    }
+
+    #[test]
+    fn test_atomic_phrase_selection() {
+        let optimizer = setup_test_optimizer();
+        let form = |qid: &str, lang: &str, form: &str, token_count: usize| SurfaceForm {
+            qid: qid.to_string(),
+            tokenizer_id: "cl100k_base".to_string(),
+            lang: lang.to_string(),
+            form: form.to_string(),
+            token_count,
+            char_count: form.chars().count(),
+        };
+
+        // A single-word concept with a cheap Mandarin form...
+        optimizer.db.upsert_concept(&Concept {
+            qid: "Q180370".to_string(),
+            label_en: "hospitalization".to_string(),
+            description: None,
+            category: Some("medical".to_string()),
+        }).unwrap();
+        optimizer.db.insert_surface_form(&form("Q180370", "zh", "住院", 1)).unwrap();
+
+        // ...inside a two-word concept whose cheapest form mixes scripts
+        optimizer.db.upsert_concept(&Concept {
+            qid: "Q2000001".to_string(),
+            label_en: "psychiatric hospitalization".to_string(),
+            description: None,
+            category: Some("medical".to_string()),
+        }).unwrap();
+        optimizer.db.insert_surface_form(&form("Q2000001", "zh", "精神科住院", 3)).unwrap();
+        optimizer.db.insert_surface_form(&form("Q2000001", "zh", "psychiatric 住院", 2)).unwrap();
+
+        let prompt = "Review the psychiatric hospitalization notes.";
+        let protected = optimizer.region_detector.detect(prompt);
+
+        // Word by word, only half the phrase is swapped
+        let candidates = optimizer.resolve_candidates(prompt, &protected, TokenizerId::Cl100kBase, |_, _| true).unwrap();
+        let swapped: Vec<_> = candidates
+            .iter()
+            .filter_map(|(word, c)| c.as_ref().map(|c| (word.text.as_str(), c.optimized_form.as_str())))
+            .collect();
+        assert_eq!(swapped, vec![("hospitalization", "住院")]);
+
+        // Atomically, the whole phrase takes its cheapest single-script form
+        let optimizer = optimizer.with_selection_policy(SelectionPolicy::MinTokens.atomic());
+        let candidates = optimizer.resolve_candidates(prompt, &protected, TokenizerId::Cl100kBase, |_, _| true).unwrap();
+        let swapped: Vec<_> = candidates
+            .iter()
+            .filter_map(|(word, c)| c.as_ref().map(|c| (word.text.as_str(), c.optimized_form.as_str())))
+            .collect();
+        assert_eq!(swapped, vec![("psychiatric hospitalization", "精神科住院")]);
+
+        // When the phrase has no cheaper form it is left whole
+        let optimizer = optimizer.with_selection_policy(
+            SelectionPolicy::AllowedLanguages { langs: vec!["en".to_string()] }.atomic(),
+        );
+        let candidates = optimizer.resolve_candidates(prompt, &protected, TokenizerId::Cl100kBase, |_, _| true).unwrap();
+        assert!(candidates.iter().any(|(word, c)| word.text == "psychiatric hospitalization" && c.is_none()));
+        assert!(candidates.iter().all(|(word, _)| word.text != "hospitalization"));
+    }
+
     #[test]
     fn test_with_configuration() {
         let optimizer = setup_test_optimizer()
@@ -336,4 +1264,388 @@ mod tests {
         let stats = optimizer.get_stats();
         assert_eq!(stats.db_stats.total_concepts, 1);
     }
+
+    #[test]
+    fn test_request_protection_policy_applies() {
+        let mut optimizer = setup_test_optimizer();
+        optimizer.db.upsert_concept(&Concept {
+            qid: "Q180370".to_string(),
+            label_en: "hospitalization".to_string(),
+            description: None,
+            category: Some("medical".to_string()),
+        }).unwrap();
+        optimizer.db.insert_surface_form(&SurfaceForm {
+            qid: "Q180370".to_string(),
+            tokenizer_id: "cl100k_base".to_string(),
+            lang: "en".to_string(),
+            form: "hosp".to_string(),
+            token_count: 1,
+            char_count: 4,
+        }).unwrap();
+
+        let request = OptimizationRequest {
+            prompt: "Find the \"hospitalization\" field.".to_string(),
+            confidence_threshold: 0.5,
+            language_directive: false,
+            ..Default::default()
+        };
+        let swapped = |result: &OptimizationResult| {
+            result.optimizations.iter().any(|opt| opt.optimization_type == OptimizationType::ConceptSubstitution)
+        };
+
+        // Quoted strings are protected under the default conservative policy...
+        let conservative = optimizer.optimize(&request).unwrap();
+        assert!(!swapped(&conservative));
+        assert!(conservative.optimized_prompt.contains("\"hospitalization\""));
+
+        // ...but not when the request asks for aggressive protection
+        let request = OptimizationRequest { protection_policy: ProtectionPolicy::Aggressive, ..request };
+        let aggressive = optimizer.optimize(&request).unwrap();
+        assert!(swapped(&aggressive));
+        assert!(aggressive.optimized_prompt.contains("\"hosp\""));
+    }
+
+    #[test]
+    fn test_coverage_report() {
+        let optimizer = setup_test_optimizer();
+
+        // Concept whose English label costs more than its cheapest form
+        optimizer.db.upsert_concept(&Concept {
+            qid: "Q180370".to_string(),
+            label_en: "hospitalization".to_string(),
+            description: None,
+            category: Some("medical".to_string()),
+        }).unwrap();
+        optimizer.db.insert_surface_form(&SurfaceForm {
+            qid: "Q180370".to_string(),
+            tokenizer_id: "cl100k_base".to_string(),
+            lang: "en".to_string(),
+            form: "hosp".to_string(),
+            token_count: 1,
+            char_count: 4,
+        }).unwrap();
+
+        let report = optimizer
+            .coverage_report("Visit the hospital before hospitalization `hospital`.")
+            .unwrap();
+
+        // Inline code is protected and not counted
+        assert_eq!(report.total_words, 5);
+        assert_eq!(report.resolved_words, 2);
+        assert_eq!(report.words_with_cheaper_form, 1);
+        assert!(report.potential_token_savings > 0);
+        assert!((report.coverage_percentage() - 40.0).abs() < 1e-9);
+
+        let mut total = CoverageReport::default();
+        total.merge(&report);
+        total.merge(&report);
+        assert_eq!(total.total_words, 10);
+        assert_eq!(total.potential_token_savings, report.potential_token_savings * 2);
+    }
+
+    #[test]
+    fn test_simulate_over_corpus() {
+        let optimizer = setup_test_optimizer();
+
+        for (qid, label, form) in [
+            ("Q180370", "hospitalization", "hosp"),
+            ("Q2000002", "pharmaceutical", "drug"),
+        ] {
+            optimizer.db.upsert_concept(&Concept {
+                qid: qid.to_string(),
+                label_en: label.to_string(),
+                description: None,
+                category: Some("medical".to_string()),
+            }).unwrap();
+            optimizer.db.insert_surface_form(&SurfaceForm {
+                qid: qid.to_string(),
+                tokenizer_id: "cl100k_base".to_string(),
+                lang: "en".to_string(),
+                form: form.to_string(),
+                token_count: 1,
+                char_count: form.len(),
+            }).unwrap();
+        }
+
+        let prompts = vec![
+            "Track hospitalization and pharmaceutical costs.".to_string(),
+            "Every hospitalization needs review, except `hospitalization`.".to_string(),
+        ];
+        let report = optimizer.simulate(&prompts).unwrap();
+
+        assert_eq!(report.prompts, 2);
+        assert!(report.original_tokens > 0);
+        // The inline-code mention is protected
+        assert_eq!(report.substitutions, 3);
+        assert_eq!(report.concepts.len(), 2);
+        assert_eq!(
+            report.potential_token_savings,
+            report.concepts.iter().map(|c| c.token_savings).sum::<i64>()
+        );
+        assert!(report.savings_percentage() > 0.0);
+        assert!(report.concepts.windows(2).all(|w| w[0].token_savings >= w[1].token_savings));
+
+        let hospitalization = report.concepts.iter().find(|c| c.qid == "Q180370").unwrap();
+        assert_eq!(hospitalization.label, "hospitalization");
+        assert_eq!(hospitalization.optimized_form, "hosp");
+        assert_eq!(hospitalization.substitutions, 2);
+
+        // Nothing to simulate
+        assert_eq!(optimizer.simulate(&[]).unwrap(), SimulationReport::default());
+    }
+
+    #[test]
+    fn test_concept_denylist_and_allowlist() {
+        let mut optimizer = setup_test_optimizer();
+        optimizer.db.upsert_concept(&Concept {
+            qid: "Q180370".to_string(),
+            label_en: "hospitalization".to_string(),
+            description: None,
+            category: Some("medical".to_string()),
+        }).unwrap();
+        optimizer.db.insert_surface_form(&SurfaceForm {
+            qid: "Q180370".to_string(),
+            tokenizer_id: "cl100k_base".to_string(),
+            lang: "en".to_string(),
+            form: "admission".to_string(),
+            token_count: 1,
+            char_count: 9,
+        }).unwrap();
+
+        let base = OptimizationRequest {
+            prompt: "Hospitalization records are attached.".to_string(),
+            ..Default::default()
+        };
+        let mut swapped = |request: OptimizationRequest| {
+            let result = optimizer.optimize(&request).unwrap();
+            result
+                .optimizations
+                .iter()
+                .chain(&result.requires_review)
+                .any(|opt| opt.optimization_type == OptimizationType::ConceptSubstitution)
+        };
+        assert!(swapped(base.clone()));
+
+        // Denylisted by word, label or QID: skipped although cheaper
+        for name in ["HOSPITALIZATION", " hospitalization ", "q180370"] {
+            let request = OptimizationRequest { concept_denylist: vec![name.to_string()], ..base.clone() };
+            assert!(!swapped(request), "{}", name);
+        }
+
+        // Only allowlisted concepts are substituted, and the denylist wins
+        let allow = |names: &[&str]| Some(names.iter().map(|n| n.to_string()).collect());
+        assert!(swapped(OptimizationRequest { concept_allowlist: allow(&["Q180370"]), ..base.clone() }));
+        assert!(!swapped(OptimizationRequest { concept_allowlist: allow(&["hospital"]), ..base.clone() }));
+        assert!(!swapped(OptimizationRequest {
+            concept_allowlist: allow(&["Q180370"]),
+            concept_denylist: vec!["hospitalization".to_string()],
+            ..base.clone()
+        }));
+    }
+
+    #[test]
+    fn test_optimize_for_tokenizer() {
+        let mut optimizer = setup_test_optimizer();
+        optimizer.db.upsert_concept(&Concept {
+            qid: "Q180370".to_string(),
+            label_en: "hospitalization".to_string(),
+            description: None,
+            category: Some("medical".to_string()),
+        }).unwrap();
+
+        // "admission" is cheap under cl100k_base but not under r50k_base
+        for (tokenizer_id, token_count) in [("cl100k_base", 1), ("r50k_base", 9)] {
+            optimizer.db.insert_surface_form(&SurfaceForm {
+                qid: "Q180370".to_string(),
+                tokenizer_id: tokenizer_id.to_string(),
+                lang: "en".to_string(),
+                form: "admission".to_string(),
+                token_count,
+                char_count: 9,
+            }).unwrap();
+        }
+
+        let request = OptimizationRequest {
+            prompt: "Hospitalization records are attached.".to_string(),
+            ..Default::default()
+        };
+        let swapped = |result: &OptimizationResult| {
+            result
+                .optimizations
+                .iter()
+                .chain(&result.requires_review)
+                .any(|opt| opt.optimization_type == OptimizationType::ConceptSubstitution)
+        };
+
+        let gpt4 = optimizer.optimize_for(&request, TokenizerId::Cl100kBase).unwrap();
+        assert!(swapped(&gpt4));
+        assert!(gpt4.optimized_prompt.starts_with("Admission records"));
+
+        let gpt3 = optimizer.optimize_for(&request, TokenizerId::R50kBase).unwrap();
+        assert!(!swapped(&gpt3));
+        assert!(gpt3.optimized_prompt.starts_with("Hospitalization records"));
+
+        // Totals are counted with the selected backend
+        let r50k = optimizer.tokenizer_registry.get(TokenizerId::R50kBase).unwrap();
+        assert_eq!(gpt3.original_tokens, r50k.count_tokens(&request.prompt));
+        assert_eq!(gpt3.optimized_tokens, r50k.count_tokens(&gpt3.optimized_prompt));
+
+        // The configured tokenizer is unchanged
+        assert!(swapped(&optimizer.optimize(&request).unwrap()));
+    }
+
+    #[test]
+    fn test_concept_swap_listed_in_result() {
+        let mut optimizer = setup_test_optimizer();
+
+        // Cheaper English form of the same concept
+        optimizer.db.upsert_concept(&Concept {
+            qid: "Q180370".to_string(),
+            label_en: "hospitalization".to_string(),
+            description: None,
+            category: Some("medical".to_string()),
+        }).unwrap();
+        optimizer.db.insert_surface_form(&SurfaceForm {
+            qid: "Q180370".to_string(),
+            tokenizer_id: "cl100k_base".to_string(),
+            lang: "en".to_string(),
+            form: "admission".to_string(),
+            token_count: 1,
+            char_count: 9,
+        }).unwrap();
+
+        let prompt = "Hospitalization records are attached.";
+        let request = OptimizationRequest {
+            prompt: prompt.to_string(),
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+
+        let swap = result
+            .optimizations
+            .iter()
+            .find(|opt| opt.optimization_type == OptimizationType::ConceptSubstitution)
+            .expect("concept swap should be listed");
+        assert_eq!(swap.original_text, "Hospitalization");
+        assert_eq!(swap.optimized_text, "admission");
+        assert!(swap.token_savings > 0);
+        assert!(swap.reasoning.starts_with("Q180370 → cheapest surface form"));
+        assert_eq!(&prompt[swap.start_pos..swap.end_pos], "Hospitalization");
+
+        // Totals are measured against the caller's prompt
+        assert_eq!(result.original_prompt, prompt);
+        assert!(result.optimized_prompt.contains("dmission records are attached."));
+        assert_eq!(
+            result.token_savings,
+            result.original_tokens as i64 - result.optimized_tokens as i64
+        );
+    }
+
+    #[test]
+    fn test_empty_atlas_falls_back_to_patterns() {
+        let db = Database::in_memory().unwrap();
+        let mut optimizer = ConceptOptimizer::new(Arc::new(db)).unwrap();
+        assert!(!optimizer.concepts_enabled().unwrap());
+        assert!(setup_test_optimizer().concepts_enabled().unwrap());
+
+        let request = OptimizationRequest {
+            prompt: "I would really appreciate it if you could please help me analyze this code."
+                .to_string(),
+            ..Default::default()
+        };
+        let result = optimizer.optimize(&request).unwrap();
+
+        assert!(result.token_savings > 0);
+        assert!(result
+            .optimizations
+            .iter()
+            .all(|opt| opt.optimization_type != OptimizationType::ConceptSubstitution));
+    }
+
+    #[test]
+    fn test_atlas_filled_after_construction_enables_concepts() {
+        let db = Arc::new(Database::in_memory().unwrap());
+        let mut optimizer = ConceptOptimizer::new(Arc::clone(&db)).unwrap();
+        let request = OptimizationRequest {
+            prompt: "Summarize the hospitalization notes.".to_string(),
+            confidence_threshold: 0.5,
+            language_directive: false,
+            ..Default::default()
+        };
+        assert_eq!(optimizer.optimize(&request).unwrap().optimized_prompt, request.prompt);
+
+        // Populated through the shared database, as import-wikidata would
+        db.upsert_concept(&Concept {
+            qid: "Q180370".to_string(),
+            label_en: "hospitalization".to_string(),
+            description: None,
+            category: Some("medical".to_string()),
+        }).unwrap();
+        db.insert_surface_form(&SurfaceForm {
+            qid: "Q180370".to_string(),
+            tokenizer_id: "cl100k_base".to_string(),
+            lang: "en".to_string(),
+            form: "hosp".to_string(),
+            token_count: 1,
+            char_count: 4,
+        }).unwrap();
+
+        assert!(optimizer.concepts_enabled().unwrap());
+        let result = optimizer.optimize(&request).unwrap();
+        assert_eq!(result.optimized_prompt, "Summarize the hosp notes.");
+        assert_eq!(result.optimizations[0].optimization_type, OptimizationType::ConceptSubstitution);
+    }
+
+    #[test]
+    fn test_instruction_keyword_not_substituted() {
+        let mut optimizer = setup_test_optimizer();
+        optimizer.db.upsert_concept(&Concept {
+            qid: "Q1000001".to_string(),
+            label_en: "mandatory".to_string(),
+            description: None,
+            category: None,
+        }).unwrap();
+        for (form, token_count) in [("mandatory", 1), ("must", 1)] {
+            optimizer.db.insert_surface_form(&SurfaceForm {
+                qid: "Q1000001".to_string(),
+                tokenizer_id: "cl100k_base".to_string(),
+                lang: "en".to_string(),
+                form: form.to_string(),
+                token_count,
+                char_count: form.len(),
+            }).unwrap();
+        }
+
+        // "MANDATORY" costs two tokens, so the concept layer alone would swap it
+        let prompt = "All fields are MANDATORY.";
+        let tokenizer = Tokenizer::new().unwrap();
+        let detected = detect_concept_substitutions(
+            prompt,
+            &optimizer.resolver,
+            &optimizer.selector,
+            TokenizerId::Cl100kBase,
+            |text| tokenizer.count_tokens(text),
+            |_, _| true,
+        )
+        .unwrap();
+        assert!(detected.iter().any(|p| p.original_text == "MANDATORY"));
+
+        // ...but it is an instruction keyword, so the protected-region check wins
+        let regions = optimizer.region_detector.detect(prompt);
+        let keyword = extract_words(prompt).into_iter().find(|w| w.text == "MANDATORY").unwrap();
+        assert!(optimizer.is_word_protected(&keyword, &regions, prompt));
+
+        let request = OptimizationRequest {
+            prompt: prompt.to_string(),
+            ..Default::default()
+        };
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result.optimized_prompt.contains("MANDATORY"));
+        assert!(result
+            .optimizations
+            .iter()
+            .chain(&result.requires_review)
+            .all(|opt| opt.optimization_type != OptimizationType::ConceptSubstitution));
+    }
 }
@@ -1,34 +1,29 @@
-/// Phase 3: Concept Resolver - Map text to Wikidata Q-IDs
-///
-/// Purpose: Resolve words/phrases to their underlying concepts (Q-IDs)
-/// This separates "Are A and B the same concept?" from tokenization.
-///
-/// Example: "hospital" → Q16917 (concept of hospital facility)
+//! Phase 3: Concept Resolver - Map text to Wikidata Q-IDs
+//!
+//! Purpose: Resolve words/phrases to their underlying concepts (Q-IDs)
+//! This separates "Are A and B the same concept?" from tokenization.
+//!
+//! Example: "hospital" → Q16917 (concept of hospital facility)
 
 use crate::database::{Concept, Database};
-use anyhow::Result;
+use crate::error::Result;
 use lru::LruCache;
 use std::num::NonZeroUsize;
 use std::sync::{Arc, Mutex};
 use unicode_normalization::UnicodeNormalization;
 
 /// Policy for concept resolution
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum ResolutionPolicy {
     /// Only exact label matches (fast, precise)
     ExactOnly,
     /// Exact matches + case/normalization variants (recommended)
+    #[default]
     Normalized,
-    /// Include fuzzy matching via embeddings (slow, requires embeddings)
+    /// Include fuzzy matching by edit distance (catches typos)
     Fuzzy { threshold: u8 }, // threshold 0-100
 }
 
-impl Default for ResolutionPolicy {
-    fn default() -> Self {
-        Self::Normalized
-    }
-}
-
 /// Concept resolver with caching
 pub struct ConceptResolver {
     db: Arc<Database>,
@@ -119,14 +114,41 @@ impl ConceptResolver {
         Ok(None)
     }
 
-    /// Fuzzy matching via embeddings (not yet implemented)
-    fn resolve_fuzzy(&self, _text: &str, _threshold: u8) -> Result<Option<Concept>> {
-        // TODO: Implement embedding-based fuzzy matching
-        // 1. Generate embedding for input text
-        // 2. Query concept_embeddings table
-        // 3. Find nearest neighbors above threshold
-        // 4. Return best match
-        Ok(None)
+    /// Fuzzy matching for typos and near-misses
+    ///
+    /// `threshold` is the minimum similarity (0-100): a label matches when its
+    /// edit distance is at most `(100 - threshold)%` of the longer word. Only
+    /// labels with the same first letter and a compatible length are compared.
+    fn resolve_fuzzy(&self, text: &str, threshold: u8) -> Result<Option<Concept>> {
+        let word: String = text.nfc().collect::<String>().to_lowercase();
+        let Some(first_char) = word.chars().next() else {
+            return Ok(None);
+        };
+
+        let len = word.chars().count();
+        let max_ratio = f64::from(100 - threshold.min(100)) / 100.0;
+        let max_edits = (len as f64 * max_ratio).floor() as usize;
+        if max_edits == 0 {
+            return Ok(None);
+        }
+
+        let mut best: Option<(f64, Concept)> = None;
+        for concept in self.db.find_concept_candidates(
+            first_char,
+            len.saturating_sub(max_edits),
+            len + max_edits,
+        )? {
+            let label = concept.label_en.to_lowercase();
+            let longest = len.max(label.chars().count());
+            let ratio = levenshtein(&word, &label) as f64 / longest as f64;
+
+            if ratio <= max_ratio && best.as_ref().is_none_or(|(r, _)| ratio < *r) {
+                best = Some((ratio, concept));
+            }
+        }
+
+        // TODO: fall back to embedding nearest neighbours (concept_embeddings)
+        Ok(best.map(|(_, concept)| concept))
     }
 
     /// Create cache key from text and policy
@@ -150,6 +172,24 @@ impl ConceptResolver {
     }
 }
 
+/// Levenshtein edit distance between two strings (by character)
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b.len()]
+}
+
 /// Cache statistics
 #[derive(Debug, Clone)]
 pub struct CacheStats {
@@ -283,6 +323,25 @@ mod tests {
         assert_ne!(key1, key2);
     }
 
+    #[test]
+    fn test_resolve_fuzzy_typo() {
+        let db = setup_test_db();
+        let resolver = ConceptResolver::new(db, ResolutionPolicy::Fuzzy { threshold: 85 });
+
+        // One missing character
+        let concept = resolver.resolve("hospitl").unwrap();
+        assert_eq!(concept.unwrap().qid, "Q16917");
+
+        // Too far from any label
+        assert!(resolver.resolve("hostel").unwrap().is_none());
+
+        // Short words have no edit budget
+        assert!(resolver.resolve("bag").unwrap().is_none());
+
+        assert_eq!(levenshtein("hsopital", "hospital"), 2);
+        assert_eq!(levenshtein("", "bug"), 3);
+    }
+
     #[test]
     fn test_not_found() {
         let db = setup_test_db();
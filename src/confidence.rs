@@ -1,3 +1,4 @@
+use crate::error::{PromptCompressError, Result};
 use crate::models::{OptimizationConfidence, OptimizationCorpus};
 use crate::patterns::DetectedPattern;
 
@@ -18,13 +19,36 @@ pub enum SentencePosition {
 }
 
 /// Confidence calculator using Bayesian inference
+///
+/// Each factor can be switched off to isolate its effect on
+/// `final_confidence`; a disabled factor contributes 0.
 pub struct ConfidenceCalculator {
     corpus: OptimizationCorpus,
+    pub use_context_penalty: bool,
+    pub use_frequency_bonus: bool,
+    pub use_semantic_risk: bool,
 }
 
 impl ConfidenceCalculator {
     pub fn new(corpus: OptimizationCorpus) -> Self {
-        Self { corpus }
+        Self {
+            corpus,
+            use_context_penalty: true,
+            use_frequency_bonus: true,
+            use_semantic_risk: true,
+        }
+    }
+
+    /// Calculator seeded with the corpus saved at `path`, or with an empty
+    /// corpus when no file has been saved there yet
+    pub fn from_corpus_file(path: &str) -> Result<Self> {
+        match crate::load_corpus(path) {
+            Ok(corpus) => Ok(Self::new(corpus)),
+            Err(PromptCompressError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                Ok(Self::default())
+            }
+            Err(e) => Err(e),
+        }
     }
 
     /// Calculate confidence for a detected pattern
@@ -46,16 +70,28 @@ impl ConfidenceCalculator {
         let base_confidence = pattern.base_confidence;
 
         // Calculate context penalty (reduced in aggressive mode)
-        let mut context_penalty = self.assess_context_risk(pattern, context);
+        let mut context_penalty = if self.use_context_penalty {
+            self.assess_context_risk(pattern, context)
+        } else {
+            0.0
+        };
         if aggressive {
             context_penalty *= 0.5;  // Halve penalties in aggressive mode
         }
 
         // Get frequency bonus from corpus
-        let frequency_bonus = self.corpus.get_frequency_bonus(&pattern.original_text);
+        let frequency_bonus = if self.use_frequency_bonus {
+            self.corpus.get_frequency_bonus(&pattern.original_text)
+        } else {
+            0.0
+        };
 
         // Calculate semantic risk (reduced in aggressive mode)
-        let mut semantic_risk = self.calculate_semantic_risk(pattern, context);
+        let mut semantic_risk = if self.use_semantic_risk {
+            self.calculate_semantic_risk(pattern, context)
+        } else {
+            0.0
+        };
         if aggressive {
             semantic_risk *= 0.6;  // Reduce risk assessment
         }
@@ -137,6 +173,18 @@ impl ConfidenceCalculator {
             risk += 0.08;
         }
 
+        // Concept swaps change wording; switching script (e.g. into Mandarin)
+        // carries the same comprehension risk as a Mandarin substitution
+        if matches!(
+            pattern.pattern_type,
+            crate::models::OptimizationType::ConceptSubstitution
+        ) {
+            risk += 0.03;
+            if pattern.original_text.is_ascii() != pattern.optimized_text.is_ascii() {
+                risk += 0.08;
+            }
+        }
+
         // Synonym consolidation needs careful analysis
         if matches!(
             pattern.pattern_type,
@@ -283,6 +331,7 @@ mod tests {
             end_pos: 41,
             base_confidence: 0.97,
             reasoning: "Common boilerplate".to_string(),
+            priority: None,
         };
 
         let context = Context {
@@ -301,6 +350,61 @@ mod tests {
         assert_eq!(confidence.base_confidence, 0.97);
     }
 
+    #[test]
+    fn test_factor_toggles() {
+        let pattern = DetectedPattern {
+            pattern_type: OptimizationType::FillerRemoval,
+            original_text: "very".to_string(),
+            optimized_text: String::new(),
+            start_pos: 20,
+            end_pos: 24,
+            base_confidence: 0.80,
+            reasoning: "Filler".to_string(),
+            priority: None,
+        };
+        let context = Context {
+            surrounding_text: "The function might be very slow on the server.".to_string(),
+            is_technical: true,
+            has_code_blocks: false,
+            sentence_position: SentencePosition::Middle,
+        };
+
+        let mut calculator = ConfidenceCalculator::default();
+        calculator.corpus_mut().patterns.entry("very".to_string()).or_default().occurrences = 100;
+
+        let all = calculator.calculate_confidence(&pattern, &context);
+        assert!(all.context_penalty > 0.0);
+        assert!(all.frequency_bonus > 0.0);
+        assert!(all.semantic_risk > 0.0);
+
+        // Dropping a penalty raises confidence, dropping the bonus lowers it,
+        // and the other factors are untouched
+        calculator.use_context_penalty = false;
+        let no_penalty = calculator.calculate_confidence(&pattern, &context);
+        assert_eq!(no_penalty.context_penalty, 0.0);
+        assert_eq!(no_penalty.semantic_risk, all.semantic_risk);
+        assert!(no_penalty.final_confidence > all.final_confidence);
+
+        calculator.use_context_penalty = true;
+        calculator.use_semantic_risk = false;
+        let no_risk = calculator.calculate_confidence(&pattern, &context);
+        assert_eq!(no_risk.semantic_risk, 0.0);
+        assert_eq!(no_risk.context_penalty, all.context_penalty);
+        assert!(no_risk.final_confidence > all.final_confidence);
+
+        calculator.use_semantic_risk = true;
+        calculator.use_frequency_bonus = false;
+        let no_bonus = calculator.calculate_confidence(&pattern, &context);
+        assert_eq!(no_bonus.frequency_bonus, 0.0);
+        assert!(no_bonus.final_confidence < all.final_confidence);
+
+        // With every factor off, final confidence is the base confidence
+        calculator.use_context_penalty = false;
+        calculator.use_semantic_risk = false;
+        let base_only = calculator.calculate_confidence(&pattern, &context);
+        assert_eq!(base_only.final_confidence, pattern.base_confidence);
+    }
+
     #[test]
     fn test_context_extraction() {
         let text = "This is a test. I would like help. Thank you.";
@@ -325,4 +429,28 @@ mod tests {
         assert_eq!(determine_position(text, 0), SentencePosition::Beginning);
         assert_eq!(determine_position(text, 10), SentencePosition::Middle);
     }
+
+    #[test]
+    fn test_from_corpus_file() {
+        let path = std::env::temp_dir().join(format!("priors-{}.json", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+
+        // Nothing saved yet: empty corpus
+        let calculator = ConfidenceCalculator::from_corpus_file(path).unwrap();
+        assert_eq!(calculator.corpus().total_optimizations, 0);
+
+        let mut corpus = OptimizationCorpus::default();
+        corpus.update_priors("very", true, 1);
+        crate::save_corpus(&corpus, path).unwrap();
+        let calculator = ConfidenceCalculator::from_corpus_file(path).unwrap();
+        assert_eq!(calculator.corpus().patterns["very"].successful_optimizations, 1);
+
+        // A corrupt file is an error, not silently empty priors
+        std::fs::write(path, "not json").unwrap();
+        assert!(matches!(
+            ConfidenceCalculator::from_corpus_file(path),
+            Err(PromptCompressError::Serde(_))
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
 }
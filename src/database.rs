@@ -1,86 +1,198 @@
-/// Phase 3: Database connection and schema management for Concept Atlas
-///
-/// Purpose: Manage SQLite database connection, migrations, and provide
-/// data access layer for concepts, surface forms, and optimization cache.
-
-use anyhow::{Context, Result};
+//! Phase 3: Database connection and schema management for Concept Atlas
+//!
+//! Purpose: Manage SQLite database connection, migrations, and provide
+//! data access layer for concepts, surface forms, and optimization cache.
+//!
+//! File databases are served from an r2d2 connection pool in WAL mode, so
+//! server workers read concurrently; in-memory databases (tests) keep a
+//! single connection behind a lock.
+
+use crate::error::{PromptCompressError, Result};
+use crate::tokenizer_registry::TokenizerBackend;
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::{Connection, OptionalExtension};
+use std::ops::Deref;
 use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+/// Connections kept by a file database's pool
+const POOL_SIZE: u32 = 8;
+
+/// How long a pooled connection waits on another connection's write lock
+const BUSY_TIMEOUT_MS: u32 = 5000;
 
 /// Database connection manager
 pub struct Database {
-    conn: Connection,
+    backend: Backend,
+}
+
+enum Backend {
+    /// One connection shared behind a lock (in-memory databases)
+    Single(Mutex<Connection>),
+    /// A connection per concurrent caller (file databases)
+    Pool(Pool<SqliteConnectionManager>),
+}
+
+/// A connection borrowed from a `Database`; derefs to `rusqlite::Connection`
+///
+/// Pooled connections are returned on drop. Consecutive calls to
+/// `Database::connection` may hand out different connections, so keep one
+/// borrow for anything connection-scoped (transactions, `last_insert_rowid`).
+pub enum DbConnection<'a> {
+    Single(MutexGuard<'a, Connection>),
+    Pooled(PooledConnection<SqliteConnectionManager>),
+}
+
+impl Deref for DbConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            DbConnection::Single(conn) => conn,
+            DbConnection::Pooled(conn) => conn,
+        }
+    }
 }
 
 impl Database {
     /// Open or create database at path
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let conn = Connection::open(path.as_ref())
-            .with_context(|| format!("Failed to open database at {:?}", path.as_ref()))?;
-
-        // Enable foreign keys
-        conn.execute("PRAGMA foreign_keys = ON", [])
-            .context("Failed to enable foreign keys")?;
-
-        let db = Self { conn };
-        db.initialize_schema()?;
-        Ok(db)
+        // Migrate on a direct connection first, so a bad path still fails
+        // with SQLite's own error
+        let conn = Connection::open(path.as_ref())?;
+        conn.execute_batch("PRAGMA journal_mode = WAL")?;
+        conn.execute("PRAGMA foreign_keys = ON", [])?;
+        Self::single(conn).initialize_schema()?;
+
+        let manager = SqliteConnectionManager::file(path.as_ref()).with_init(|conn| {
+            conn.execute("PRAGMA foreign_keys = ON", [])?;
+            conn.busy_timeout(std::time::Duration::from_millis(BUSY_TIMEOUT_MS.into()))
+        });
+        let pool = Pool::builder().max_size(POOL_SIZE).build(manager)?;
+
+        Ok(Self {
+            backend: Backend::Pool(pool),
+        })
     }
 
     /// Create in-memory database (for testing)
     pub fn in_memory() -> Result<Self> {
-        let conn = Connection::open_in_memory().context("Failed to create in-memory database")?;
-        conn.execute("PRAGMA foreign_keys = ON", [])
-            .context("Failed to enable foreign keys")?;
+        let conn = Connection::open_in_memory()?;
+        conn.execute("PRAGMA foreign_keys = ON", [])?;
 
-        let db = Self { conn };
+        let db = Self::single(conn);
         db.initialize_schema()?;
         Ok(db)
     }
 
-    /// Initialize schema from migration file
+    fn single(conn: Connection) -> Self {
+        Self {
+            backend: Backend::Single(Mutex::new(conn)),
+        }
+    }
+
+    /// Initialize schema from migration files
     fn initialize_schema(&self) -> Result<()> {
-        // Check if metadata table exists
-        let table_exists: i64 = self
-            .conn
-            .query_row(
-                "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name='metadata'",
-                [],
-                |row| row.get(0),
-            )
-            .context("Failed to check if metadata table exists")?;
+        // Concept atlas tables (metadata is created by this migration)
+        if !self.table_exists("metadata")? {
+            let schema_sql = include_str!("../migrations/001_initial_schema.sql");
+            self.connection()?.execute_batch(schema_sql)?;
+        }
+
+        // Pattern tables (may be missing from atlases created before Phase 3.5)
+        if !self.table_exists("patterns")? {
+            let patterns_sql = include_str!("../migrations/002_add_patterns_table.sql");
+            self.connection()?.execute_batch(patterns_sql)?;
+        }
+
+        // Pattern staleness timestamps
+        if !self.column_exists("patterns", "last_applied_at")? {
+            let timestamps_sql = include_str!("../migrations/003_add_pattern_timestamps.sql");
+            self.connection()?.execute_batch(timestamps_sql)?;
+        }
+
+        // Context-conditional patterns
+        if !self.column_exists("patterns", "context_guard")? {
+            let guard_sql = include_str!("../migrations/004_add_pattern_context_guard.sql");
+            self.connection()?.execute_batch(guard_sql)?;
+        }
+
+        // Curated pattern priority
+        if !self.column_exists("patterns", "priority")? {
+            let priority_sql = include_str!("../migrations/005_add_pattern_priority.sql");
+            self.connection()?.execute_batch(priority_sql)?;
+        }
 
-        if table_exists > 0 {
-            // Schema already initialized
-            return Ok(());
+        // Higher-risk patterns reserved for aggressive mode
+        if !self.column_exists("patterns", "aggressive_only")? {
+            let aggressive_sql = include_str!("../migrations/006_add_pattern_aggressive_only.sql");
+            self.connection()?.execute_batch(aggressive_sql)?;
         }
 
-        // Load and execute schema migration
-        let schema_sql = include_str!("../migrations/001_initial_schema.sql");
-        self.conn
-            .execute_batch(schema_sql)
-            .context("Failed to execute schema migration")?;
+        // Cumulative savings for ranking patterns
+        if !self.column_exists("patterns", "total_token_savings")? {
+            let savings_sql = include_str!("../migrations/007_add_pattern_total_savings.sql");
+            self.connection()?.execute_batch(savings_sql)?;
+        }
 
         Ok(())
     }
 
+    /// Check whether a table exists
+    fn table_exists(&self, name: &str) -> Result<bool> {
+        let count: i64 = self
+            .connection()?
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name=?1",
+                [name],
+                |row| row.get(0),
+            )?;
+        Ok(count > 0)
+    }
+
+    /// Whether the atlas holds at least one concept
+    ///
+    /// False for a freshly created atlas, and for databases missing the
+    /// concept tables altogether.
+    pub fn has_concepts(&self) -> Result<bool> {
+        if !self.table_exists("concepts")? {
+            return Ok(false);
+        }
+        let exists: bool = self
+            .connection()?
+            .query_row("SELECT EXISTS(SELECT 1 FROM concepts)", [], |row| row.get(0))?;
+        Ok(exists)
+    }
+
+    /// Check whether a column exists on a table
+    fn column_exists(&self, table: &str, column: &str) -> Result<bool> {
+        let count: i64 = self
+            .connection()?
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+                [table, column],
+                |row| row.get(0),
+            )?;
+        Ok(count > 0)
+    }
+
     /// Get current schema version
     pub fn schema_version(&self) -> Result<String> {
         let version: String = self
-            .conn
+            .connection()?
             .query_row(
                 "SELECT value FROM metadata WHERE key = 'schema_version'",
                 [],
                 |row| row.get(0),
-            )
-            .context("Failed to get schema version")?;
+            )?;
         Ok(version)
     }
 
     /// Get database statistics
     pub fn get_stats(&self) -> Result<DatabaseStats> {
         let total_concepts_str: String = self
-            .conn
+            .connection()?
             .query_row(
                 "SELECT value FROM metadata WHERE key = 'total_concepts'",
                 [],
@@ -89,7 +201,7 @@ impl Database {
         let total_concepts = total_concepts_str.parse::<usize>().unwrap_or(0);
 
         let total_surface_forms_str: String = self
-            .conn
+            .connection()?
             .query_row(
                 "SELECT value FROM metadata WHERE key = 'total_surface_forms'",
                 [],
@@ -98,7 +210,7 @@ impl Database {
         let total_surface_forms = total_surface_forms_str.parse::<usize>().unwrap_or(0);
 
         let cache_size: i64 = self
-            .conn
+            .connection()?
             .query_row("SELECT COUNT(*) FROM optimization_cache", [], |row| {
                 row.get(0)
             })?;
@@ -112,7 +224,7 @@ impl Database {
 
     /// Insert or update concept
     pub fn upsert_concept(&self, concept: &Concept) -> Result<()> {
-        self.conn.execute(
+        self.connection()?.execute(
             "INSERT INTO concepts (qid, label_en, description, category)
              VALUES (?1, ?2, ?3, ?4)
              ON CONFLICT(qid) DO UPDATE SET
@@ -133,7 +245,7 @@ impl Database {
     /// Get concept by Q-ID
     pub fn get_concept(&self, qid: &str) -> Result<Option<Concept>> {
         let concept = self
-            .conn
+            .connection()?
             .query_row(
                 "SELECT qid, label_en, description, category FROM concepts WHERE qid = ?1",
                 [qid],
@@ -153,7 +265,7 @@ impl Database {
     /// Find concept by English label (exact match)
     pub fn find_concept_by_label(&self, label: &str) -> Result<Option<Concept>> {
         let concept = self
-            .conn
+            .connection()?
             .query_row(
                 "SELECT qid, label_en, description, category FROM concepts
                  WHERE LOWER(label_en) = LOWER(?1)",
@@ -171,9 +283,90 @@ impl Database {
         Ok(concept)
     }
 
+    /// Find fuzzy-match candidates: concepts whose English label starts with
+    /// `first_char` (case-insensitive) and is `min_len..=max_len` characters long
+    pub fn find_concept_candidates(
+        &self,
+        first_char: char,
+        min_len: usize,
+        max_len: usize,
+    ) -> Result<Vec<Concept>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT qid, label_en, description, category FROM concepts
+             WHERE LOWER(SUBSTR(label_en, 1, 1)) = ?1
+               AND LENGTH(label_en) BETWEEN ?2 AND ?3
+             ORDER BY label_en",
+        )?;
+
+        let concepts = stmt
+            .query_map(
+                rusqlite::params![first_char.to_lowercase().to_string(), min_len, max_len],
+                |row| {
+                    Ok(Concept {
+                        qid: row.get(0)?,
+                        label_en: row.get(1)?,
+                        description: row.get(2)?,
+                        category: row.get(3)?,
+                    })
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(concepts)
+    }
+
+    /// Case-insensitive search on English labels, best matches first
+    ///
+    /// Exact labels rank first, then labels starting with `query`, then
+    /// labels containing it, then concepts whose description contains it;
+    /// shorter labels win within a rank.
+    pub fn search_concepts(&self, query: &str, limit: usize) -> Result<Vec<Concept>> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() || limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        let escaped = query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        let prefix = format!("{}%", escaped);
+        let substring = format!("%{}%", escaped);
+
+        let conn = self.connection()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT qid, label_en, description, category FROM concepts
+             WHERE LOWER(label_en) LIKE ?3 ESCAPE '\\'
+                OR LOWER(COALESCE(description, '')) LIKE ?3 ESCAPE '\\'
+             ORDER BY CASE
+                          WHEN LOWER(label_en) = ?1 THEN 0
+                          WHEN LOWER(label_en) LIKE ?2 ESCAPE '\\' THEN 1
+                          WHEN LOWER(label_en) LIKE ?3 ESCAPE '\\' THEN 2
+                          ELSE 3
+                      END,
+                      LENGTH(label_en), label_en
+             LIMIT ?4",
+        )?;
+
+        let concepts = stmt
+            .query_map(
+                rusqlite::params![query, prefix, substring, limit as i64],
+                |row| {
+                    Ok(Concept {
+                        qid: row.get(0)?,
+                        label_en: row.get(1)?,
+                        description: row.get(2)?,
+                        category: row.get(3)?,
+                    })
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(concepts)
+    }
+
     /// Insert surface form
     pub fn insert_surface_form(&self, form: &SurfaceForm) -> Result<()> {
-        self.conn.execute(
+        self.connection()?.execute(
             "INSERT INTO surface_forms (qid, tokenizer_id, lang, form, token_count, char_count)
              VALUES (?1, ?2, ?3, ?4, ?5, ?6)
              ON CONFLICT(qid, tokenizer_id, lang, form) DO UPDATE SET
@@ -193,24 +386,57 @@ impl Database {
 
     /// Get all surface forms for a concept
     pub fn get_surface_forms(&self, qid: &str, tokenizer_id: &str) -> Result<Vec<SurfaceForm>> {
-        let mut stmt = self.conn.prepare(
+        self.get_surface_forms_filtered(qid, tokenizer_id, None)
+    }
+
+    /// Get a concept's surface forms, only in `langs` when given
+    ///
+    /// The language filter runs in SQL, so concepts with many languages
+    /// don't pull rows the caller would discard.
+    pub fn get_surface_forms_filtered(
+        &self,
+        qid: &str,
+        tokenizer_id: &str,
+        langs: Option<&[String]>,
+    ) -> Result<Vec<SurfaceForm>> {
+        let lang_filter = match langs {
+            Some(langs) => format!(
+                " AND lang IN ({})",
+                (3..3 + langs.len()).map(|i| format!("?{}", i)).collect::<Vec<_>>().join(", ")
+            ),
+            None => String::new(),
+        };
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(&format!(
             "SELECT qid, tokenizer_id, lang, form, token_count, char_count
              FROM surface_forms
-             WHERE qid = ?1 AND tokenizer_id = ?2
+             WHERE qid = ?1 AND tokenizer_id = ?2{}
              ORDER BY token_count ASC",
+            lang_filter
+        ))?;
+
+        let params = [qid, tokenizer_id]
+            .into_iter()
+            .chain(langs.unwrap_or_default().iter().map(String::as_str));
+        let forms = stmt
+            .query_map(rusqlite::params_from_iter(params), SurfaceForm::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(forms)
+    }
+
+    /// Get every surface form for a concept, across all tokenizers
+    pub fn get_surface_forms_all(&self, qid: &str) -> Result<Vec<SurfaceForm>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT qid, tokenizer_id, lang, form, token_count, char_count
+             FROM surface_forms
+             WHERE qid = ?1
+             ORDER BY tokenizer_id ASC, token_count ASC",
         )?;
 
         let forms = stmt
-            .query_map([qid, tokenizer_id], |row| {
-                Ok(SurfaceForm {
-                    qid: row.get(0)?,
-                    tokenizer_id: row.get(1)?,
-                    lang: row.get(2)?,
-                    form: row.get(3)?,
-                    token_count: row.get::<_, i64>(4)? as usize,
-                    char_count: row.get::<_, i64>(5)? as usize,
-                })
-            })?
+            .query_map([qid], SurfaceForm::from_row)?
             .collect::<Result<Vec<_>, _>>()?;
 
         Ok(forms)
@@ -219,7 +445,7 @@ impl Database {
     /// Get cheapest surface form for concept
     pub fn get_cheapest_form(&self, qid: &str, tokenizer_id: &str) -> Result<Option<SurfaceForm>> {
         let form = self
-            .conn
+            .connection()?
             .query_row(
                 "SELECT qid, tokenizer_id, lang, form, token_count, char_count
                  FROM surface_forms
@@ -227,79 +453,148 @@ impl Database {
                  ORDER BY token_count ASC
                  LIMIT 1",
                 [qid, tokenizer_id],
-                |row| {
-                    Ok(SurfaceForm {
-                        qid: row.get(0)?,
-                        tokenizer_id: row.get(1)?,
-                        lang: row.get(2)?,
-                        form: row.get(3)?,
-                        token_count: row.get::<_, i64>(4)? as usize,
-                        char_count: row.get::<_, i64>(5)? as usize,
-                    })
-                },
+                SurfaceForm::from_row,
             )
             .optional()?;
         Ok(form)
     }
 
-    /// Get reference to underlying connection
-    pub fn connection(&self) -> &Connection {
-        &self.conn
+    /// Find concepts that share a normalized (trimmed, lowercase) English label
+    ///
+    /// Each group holds two or more concepts, ordered by Q-ID.
+    pub fn find_duplicate_labels(&self) -> Result<Vec<Vec<Concept>>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT qid, label_en, description, category FROM concepts
+             WHERE LOWER(TRIM(label_en)) IN (
+                 SELECT LOWER(TRIM(label_en)) FROM concepts
+                 GROUP BY LOWER(TRIM(label_en))
+                 HAVING COUNT(*) > 1
+             )
+             ORDER BY LOWER(TRIM(label_en)), qid",
+        )?;
+
+        let concepts = stmt
+            .query_map([], |row| {
+                Ok(Concept {
+                    qid: row.get(0)?,
+                    label_en: row.get(1)?,
+                    description: row.get(2)?,
+                    category: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut groups: Vec<Vec<Concept>> = Vec::new();
+        for concept in concepts {
+            match groups.last_mut() {
+                Some(group)
+                    if group[0].label_en.trim().to_lowercase()
+                        == concept.label_en.trim().to_lowercase() =>
+                {
+                    group.push(concept)
+                }
+                _ => groups.push(vec![concept]),
+            }
+        }
+
+        Ok(groups)
+    }
+
+    /// Merge duplicate concepts into `keep_qid`
+    ///
+    /// Surface forms, cache entries and embeddings of the merged concepts are
+    /// repointed to the kept Q-ID (forms it already has are dropped), then the
+    /// merged concepts are deleted. Runs in a single transaction and returns
+    /// the number of concepts removed. HITL decisions reference patterns, not
+    /// concepts, so they need no repointing.
+    pub fn merge_concepts(&self, keep_qid: &str, merge_qids: &[&str]) -> Result<usize> {
+        if self.get_concept(keep_qid)?.is_none() {
+            return Err(PromptCompressError::NotFound(format!("Concept {}", keep_qid)));
+        }
+
+        let conn = self.connection()?;
+        let tx = conn.unchecked_transaction()?;
+        let mut merged = 0;
+
+        for &qid in merge_qids.iter().filter(|&&qid| qid != keep_qid) {
+            tx.execute(
+                "UPDATE OR IGNORE surface_forms SET qid = ?1 WHERE qid = ?2",
+                [keep_qid, qid],
+            )?;
+            tx.execute(
+                "UPDATE optimization_cache SET qid = ?1 WHERE qid = ?2",
+                [keep_qid, qid],
+            )?;
+            tx.execute(
+                "UPDATE OR IGNORE concept_embeddings SET qid = ?1 WHERE qid = ?2",
+                [keep_qid, qid],
+            )?;
+
+            // Leftover duplicates cascade with the concept
+            merged += tx.execute("DELETE FROM concepts WHERE qid = ?1", [qid])?;
+        }
+
+        tx.commit()?;
+        Ok(merged)
+    }
+
+    /// Borrow a connection (from the pool, for a file database)
+    pub fn connection(&self) -> Result<DbConnection<'_>> {
+        match &self.backend {
+            // A panic mid-statement leaves nothing half-applied in SQLite
+            Backend::Single(conn) => Ok(DbConnection::Single(
+                conn.lock().unwrap_or_else(|poisoned| poisoned.into_inner()),
+            )),
+            Backend::Pool(pool) => Ok(DbConnection::Pooled(pool.get()?)),
+        }
     }
 
     /// Load all active patterns from database
     pub fn load_patterns(&self) -> Result<Vec<PatternRecord>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, pattern_type, regex_pattern, replacement, base_confidence, reasoning,
-                    applied_count, accepted_count, rejected_count
-             FROM patterns
+        let sql = format!(
+            "SELECT {} FROM patterns
              WHERE enabled = 1
-             ORDER BY base_confidence DESC"
-        )?;
+             ORDER BY base_confidence DESC",
+            PATTERN_COLUMNS
+        );
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(&sql)?;
 
         let patterns = stmt
-            .query_map([], |row| {
-                Ok(PatternRecord {
-                    id: row.get(0)?,
-                    pattern_type: row.get(1)?,
-                    regex_pattern: row.get(2)?,
-                    replacement: row.get(3)?,
-                    base_confidence: row.get(4)?,
-                    reasoning: row.get(5)?,
-                    applied_count: row.get::<_, i64>(6)? as usize,
-                    accepted_count: row.get::<_, i64>(7)? as usize,
-                    rejected_count: row.get::<_, i64>(8)? as usize,
-                })
-            })?
+            .query_map([], PatternRecord::from_row)?
             .collect::<Result<Vec<_>, _>>()?;
 
         Ok(patterns)
     }
 
+    /// Set (or clear) a pattern's curated priority
+    pub fn set_pattern_priority(&self, pattern_id: i64, priority: Option<i64>) -> Result<()> {
+        let updated = self
+            .connection()?
+            .execute(
+                "UPDATE patterns SET priority = ?1 WHERE id = ?2",
+                rusqlite::params![priority, pattern_id],
+            )?;
+        if updated == 0 {
+            return Err(PromptCompressError::NotFound(format!("Pattern {}", pattern_id)));
+        }
+        Ok(())
+    }
+
     /// Load patterns filtered by type
     pub fn load_patterns_by_type(&self, pattern_type: &str) -> Result<Vec<PatternRecord>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, pattern_type, regex_pattern, replacement, base_confidence, reasoning,
-                    applied_count, accepted_count, rejected_count
-             FROM patterns
+        let sql = format!(
+            "SELECT {} FROM patterns
              WHERE enabled = 1 AND pattern_type = ?1
-             ORDER BY base_confidence DESC"
-        )?;
+             ORDER BY base_confidence DESC",
+            PATTERN_COLUMNS
+        );
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(&sql)?;
 
         let patterns = stmt
-            .query_map([pattern_type], |row| {
-                Ok(PatternRecord {
-                    id: row.get(0)?,
-                    pattern_type: row.get(1)?,
-                    regex_pattern: row.get(2)?,
-                    replacement: row.get(3)?,
-                    base_confidence: row.get(4)?,
-                    reasoning: row.get(5)?,
-                    applied_count: row.get::<_, i64>(6)? as usize,
-                    accepted_count: row.get::<_, i64>(7)? as usize,
-                    rejected_count: row.get::<_, i64>(8)? as usize,
-                })
-            })?
+            .query_map([pattern_type], PatternRecord::from_row)?
             .collect::<Result<Vec<_>, _>>()?;
 
         Ok(patterns)
@@ -307,45 +602,126 @@ impl Database {
 
     /// Load patterns with minimum confidence threshold
     pub fn load_patterns_with_confidence(&self, min_confidence: f64) -> Result<Vec<PatternRecord>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, pattern_type, regex_pattern, replacement, base_confidence, reasoning,
-                    applied_count, accepted_count, rejected_count
-             FROM patterns
+        let sql = format!(
+            "SELECT {} FROM patterns
              WHERE enabled = 1 AND base_confidence >= ?1
-             ORDER BY base_confidence DESC"
-        )?;
+             ORDER BY base_confidence DESC",
+            PATTERN_COLUMNS
+        );
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(&sql)?;
 
         let patterns = stmt
-            .query_map([min_confidence], |row| {
-                Ok(PatternRecord {
-                    id: row.get(0)?,
-                    pattern_type: row.get(1)?,
-                    regex_pattern: row.get(2)?,
-                    replacement: row.get(3)?,
-                    base_confidence: row.get(4)?,
-                    reasoning: row.get(5)?,
-                    applied_count: row.get::<_, i64>(6)? as usize,
-                    accepted_count: row.get::<_, i64>(7)? as usize,
-                    rejected_count: row.get::<_, i64>(8)? as usize,
-                })
-            })?
+            .query_map([min_confidence], PatternRecord::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(patterns)
+    }
+
+    /// Load active patterns not applied or accepted within `stale_days`
+    ///
+    /// Patterns that have never fired are always considered stale.
+    pub fn load_stale_patterns(&self, stale_days: u64) -> Result<Vec<PatternRecord>> {
+        let sql = format!(
+            "SELECT {} FROM patterns
+             WHERE enabled = 1
+               AND MAX(COALESCE(last_applied_at, 0), COALESCE(last_accepted_at, 0))
+                   < CAST(strftime('%s', 'now') AS INTEGER) - ?1
+             ORDER BY COALESCE(last_applied_at, 0) ASC, id ASC",
+            PATTERN_COLUMNS
+        );
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(&sql)?;
+
+        let stale_secs = stale_days.saturating_mul(86_400) as i64;
+        let patterns = stmt
+            .query_map([stale_secs], PatternRecord::from_row)?
             .collect::<Result<Vec<_>, _>>()?;
 
         Ok(patterns)
     }
 
+    /// Flag active patterns whose replacement costs at least as many tokens
+    /// as the text they match
+    ///
+    /// Only literal patterns are checked: the regex must be a plain phrase
+    /// (so a sample match can be built from it) and the replacement must not
+    /// reference capture groups. Everything else is counted as skipped.
+    pub fn audit_patterns(&self, tokenizer: &dyn TokenizerBackend) -> Result<PatternAudit> {
+        let mut audit = PatternAudit::default();
+
+        for pattern in self.load_patterns()? {
+            let sample = if pattern.replacement.contains('$') {
+                None
+            } else {
+                literal_sample(&pattern.regex_pattern)
+            };
+            let Some(sample) = sample else {
+                audit.skipped += 1;
+                continue;
+            };
+
+            audit.checked += 1;
+            let sample_tokens = tokenizer.count_tokens(&sample);
+            let replacement_tokens = tokenizer.count_tokens(&pattern.replacement);
+            if replacement_tokens >= sample_tokens {
+                audit.inefficient.push(InefficientPattern {
+                    id: pattern.id,
+                    regex_pattern: pattern.regex_pattern,
+                    replacement: pattern.replacement,
+                    sample,
+                    sample_tokens,
+                    replacement_tokens,
+                });
+            }
+        }
+
+        Ok(audit)
+    }
+
     /// Record pattern application
     pub fn record_pattern_application(&self, pattern_id: i64) -> Result<()> {
-        self.conn.execute(
-            "UPDATE patterns SET applied_count = applied_count + 1 WHERE id = ?1",
+        self.connection()?.execute(
+            "UPDATE patterns
+             SET applied_count = applied_count + 1,
+                 last_applied_at = strftime('%s', 'now')
+             WHERE id = ?1",
             [pattern_id],
         )?;
         Ok(())
     }
 
+    /// Add the tokens an applied optimization saved to its pattern's running total
+    pub fn record_pattern_savings(&self, pattern_id: i64, token_savings: i64) -> Result<()> {
+        self.connection()?.execute(
+            "UPDATE patterns SET total_token_savings = total_token_savings + ?2 WHERE id = ?1",
+            [pattern_id, token_savings],
+        )?;
+        Ok(())
+    }
+
+    /// Enabled patterns that have saved tokens, most cumulative savings first
+    pub fn top_patterns_by_savings(&self, limit: usize) -> Result<Vec<PatternRecord>> {
+        let sql = format!(
+            "SELECT {} FROM patterns
+             WHERE enabled = 1 AND total_token_savings > 0
+             ORDER BY total_token_savings DESC, applied_count DESC, id
+             LIMIT ?1",
+            PATTERN_COLUMNS
+        );
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(&sql)?;
+
+        let patterns = stmt
+            .query_map([limit as i64], PatternRecord::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(patterns)
+    }
+
     /// Record HITL decision
     pub fn record_hitl_decision(&self, decision: &HitlDecision) -> Result<()> {
-        self.conn.execute(
+        self.connection()?.execute(
             "INSERT INTO hitl_decisions
              (pattern_id, session_id, original_text, optimized_text, decision,
               user_alternative, context_before, context_after)
@@ -361,14 +737,24 @@ impl Database {
                 &decision.context_after,
             ],
         )?;
+
+        if decision.decision == "accept" {
+            self.connection()?.execute(
+                "UPDATE patterns SET last_accepted_at = strftime('%s', 'now') WHERE id = ?1",
+                [decision.pattern_id],
+            )?;
+        }
+
         Ok(())
     }
 
     /// Get pattern statistics
     pub fn get_pattern_stats(&self) -> Result<Vec<PatternTypeStats>> {
-        let mut stmt = self.conn.prepare(
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(
             "SELECT pattern_type, COUNT(*), AVG(base_confidence),
-                    SUM(applied_count), SUM(accepted_count), SUM(rejected_count)
+                    SUM(applied_count), SUM(accepted_count), SUM(rejected_count),
+                    SUM(total_token_savings)
              FROM patterns
              WHERE enabled = 1
              GROUP BY pattern_type
@@ -393,6 +779,7 @@ impl Database {
                     total_accepted: accepted,
                     total_rejected: rejected,
                     acceptance_rate,
+                    total_token_savings: row.get(6)?,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
@@ -401,6 +788,69 @@ impl Database {
     }
 }
 
+/// Outcome of `Database::audit_patterns`
+#[derive(Debug, Clone, Default)]
+pub struct PatternAudit {
+    /// Literal patterns whose savings were checked
+    pub checked: usize,
+    /// Patterns with regex syntax or capture references, not checked
+    pub skipped: usize,
+    pub inefficient: Vec<InefficientPattern>,
+}
+
+/// A pattern that does not save tokens on a sample match
+#[derive(Debug, Clone)]
+pub struct InefficientPattern {
+    pub id: i64,
+    pub regex_pattern: String,
+    pub replacement: String,
+    /// Text built from the regex that it matches
+    pub sample: String,
+    pub sample_tokens: usize,
+    pub replacement_tokens: usize,
+}
+
+/// Text matched by a plain-phrase regex, or `None` when the regex uses
+/// groups, classes, repetition or alternation
+///
+/// Leading inline flags, `\b`, anchors and escaped punctuation are allowed;
+/// `\s`, `\s+` and `\s*` are read as one space.
+fn literal_sample(pattern: &str) -> Option<String> {
+    let body = match pattern.strip_prefix("(?") {
+        Some(rest) => {
+            let (flags, rest) = rest.split_once(')')?;
+            if !flags.chars().all(|c| c.is_ascii_alphabetic()) {
+                return None;
+            }
+            rest
+        }
+        None => pattern,
+    };
+
+    let mut sample = String::new();
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                'b' => {}
+                's' => {
+                    chars.next_if(|c| matches!(c, '+' | '*'));
+                    sample.push(' ');
+                }
+                c if c.is_ascii_punctuation() => sample.push(c),
+                _ => return None,
+            },
+            '^' | '$' => {}
+            '(' | ')' | '[' | ']' | '{' | '}' | '|' | '*' | '+' | '?' | '.' => return None,
+            c => sample.push(c),
+        }
+    }
+
+    // Anything the walk misread is caught by actually matching
+    let regex = regex::Regex::new(pattern).ok()?;
+    (!sample.trim().is_empty() && regex.is_match(&sample)).then_some(sample)
+}
+
 /// Concept data structure
 #[derive(Debug, Clone, PartialEq)]
 pub struct Concept {
@@ -421,6 +871,20 @@ pub struct SurfaceForm {
     pub char_count: usize,
 }
 
+impl SurfaceForm {
+    /// Map a `qid, tokenizer_id, lang, form, token_count, char_count` row
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            qid: row.get(0)?,
+            tokenizer_id: row.get(1)?,
+            lang: row.get(2)?,
+            form: row.get(3)?,
+            token_count: row.get::<_, i64>(4)? as usize,
+            char_count: row.get::<_, i64>(5)? as usize,
+        })
+    }
+}
+
 /// Database statistics
 #[derive(Debug, Clone)]
 pub struct DatabaseStats {
@@ -429,6 +893,11 @@ pub struct DatabaseStats {
     pub cache_size: usize,
 }
 
+/// Columns selected for `PatternRecord::from_row`
+const PATTERN_COLUMNS: &str = "id, pattern_type, regex_pattern, replacement, base_confidence, reasoning,
+     applied_count, accepted_count, rejected_count, last_applied_at, last_accepted_at,
+     context_guard, priority, aggressive_only, total_token_savings";
+
 /// Pattern record from database
 #[derive(Debug, Clone)]
 pub struct PatternRecord {
@@ -441,6 +910,35 @@ pub struct PatternRecord {
     pub applied_count: usize,
     pub accepted_count: usize,
     pub rejected_count: usize,
+    pub last_applied_at: Option<i64>,  // Unix timestamp, None = never applied
+    pub last_accepted_at: Option<i64>, // Unix timestamp, None = never accepted
+    pub context_guard: Option<String>,  // Guard regex, '!' prefix = forbidden context
+    pub priority: Option<i64>,          // Higher wins overlap ties, None = unranked
+    pub aggressive_only: bool,          // Only detected in aggressive mode
+    pub total_token_savings: i64,       // Tokens saved across all applications
+}
+
+impl PatternRecord {
+    /// Map a row selected with `PATTERN_COLUMNS`
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            pattern_type: row.get(1)?,
+            regex_pattern: row.get(2)?,
+            replacement: row.get(3)?,
+            base_confidence: row.get(4)?,
+            reasoning: row.get(5)?,
+            applied_count: row.get::<_, i64>(6)? as usize,
+            accepted_count: row.get::<_, i64>(7)? as usize,
+            rejected_count: row.get::<_, i64>(8)? as usize,
+            last_applied_at: row.get(9)?,
+            last_accepted_at: row.get(10)?,
+            context_guard: row.get(11)?,
+            priority: row.get(12)?,
+            aggressive_only: row.get(13)?,
+            total_token_savings: row.get(14)?,
+        })
+    }
 }
 
 /// HITL decision record
@@ -466,6 +964,7 @@ pub struct PatternTypeStats {
     pub total_accepted: usize,
     pub total_rejected: usize,
     pub acceptance_rate: f64,
+    pub total_token_savings: i64,
 }
 
 #[cfg(test)]
@@ -488,6 +987,40 @@ mod tests {
         assert_eq!(stats.cache_size, 0);
     }
 
+    #[test]
+    fn test_hitl_feedback_blends_with_the_original_prior() {
+        let db = Database::in_memory().unwrap();
+        let id = insert_test_pattern(&db, "(?i)please");
+        let decide = |decision: &str| {
+            db.record_hitl_decision(&HitlDecision {
+                pattern_id: id,
+                session_id: "session".to_string(),
+                original_text: "please".to_string(),
+                optimized_text: "".to_string(),
+                decision: decision.to_string(),
+                user_alternative: None,
+                context_before: "".to_string(),
+                context_after: "".to_string(),
+            })
+            .unwrap();
+            db.load_patterns().unwrap()[0].base_confidence
+        };
+
+        // Each update weighs the 0.90 prior as 10 decisions, not the
+        // already-updated confidence
+        for (n, decision) in ["reject", "accept", "reject"].into_iter().enumerate() {
+            let accepted = usize::from(n >= 1) as f64;
+            let expected = (0.90 * 10.0 + accepted) / (10.0 + n as f64 + 1.0);
+            assert!((decide(decision) - expected).abs() < 1e-9, "after {} decisions", n + 1);
+        }
+
+        // From the tenth decision on, the empirical rate takes over
+        for _ in 0..6 {
+            decide("accept");
+        }
+        assert!((decide("accept") - 8.0 / 10.0).abs() < 1e-9);
+    }
+
     #[test]
     fn test_upsert_concept() {
         let db = Database::in_memory().unwrap();
@@ -532,6 +1065,138 @@ mod tests {
         assert!(not_found.is_none());
     }
 
+    #[test]
+    fn test_search_concepts_ranks_prefix_before_substring() {
+        let db = Database::in_memory().unwrap();
+        for (qid, label, description) in [
+            ("Q1", "hospital ship", None),
+            ("Q2", "field hospital", None),
+            ("Q3", "hospital", None),
+            ("Q4", "clinic", Some("small hospital")),
+            ("Q5", "hostel", None),
+            ("Q6", "100% juice", None),
+        ] {
+            db.upsert_concept(&Concept {
+                qid: qid.to_string(),
+                label_en: label.to_string(),
+                description: description.map(str::to_string),
+                category: None,
+            })
+            .unwrap();
+        }
+
+        let qids = |query: &str, limit| {
+            db.search_concepts(query, limit)
+                .unwrap()
+                .into_iter()
+                .map(|c| c.qid)
+                .collect::<Vec<_>>()
+        };
+
+        // Exact, then prefix, then substring, then description
+        assert_eq!(qids("Hospital", 10), vec!["Q3", "Q1", "Q2", "Q4"]);
+        assert_eq!(qids("hos", 10), vec!["Q5", "Q3", "Q1", "Q2", "Q4"]);
+        assert_eq!(qids("hospital", 2), vec!["Q3", "Q1"]);
+
+        // LIKE wildcards are literal
+        assert_eq!(qids("%", 10), vec!["Q6"]);
+        assert!(qids("  ", 10).is_empty());
+    }
+
+    #[test]
+    fn test_merge_duplicate_concepts() {
+        let db = Database::in_memory().unwrap();
+
+        for (qid, label) in [("Q16917", "hospital"), ("Q99999", "Hospital "), ("Q11862829", "code")] {
+            db.upsert_concept(&Concept {
+                qid: qid.to_string(),
+                label_en: label.to_string(),
+                description: None,
+                category: None,
+            })
+            .unwrap();
+        }
+
+        let form = |qid: &str, lang: &str, text: &str| SurfaceForm {
+            qid: qid.to_string(),
+            tokenizer_id: "cl100k_base".to_string(),
+            lang: lang.to_string(),
+            form: text.to_string(),
+            token_count: 1,
+            char_count: text.chars().count(),
+        };
+        db.insert_surface_form(&form("Q16917", "en", "hospital")).unwrap();
+        db.insert_surface_form(&form("Q99999", "en", "hospital")).unwrap();
+        db.insert_surface_form(&form("Q99999", "zh", "医院")).unwrap();
+
+        let groups = db.find_duplicate_labels().unwrap();
+        assert_eq!(groups.len(), 1);
+        let qids: Vec<&str> = groups[0].iter().map(|c| c.qid.as_str()).collect();
+        assert_eq!(qids, vec!["Q16917", "Q99999"]);
+
+        assert_eq!(db.merge_concepts("Q16917", &["Q99999"]).unwrap(), 1);
+
+        // Unique forms repointed, the shared one not duplicated
+        let forms = db.get_surface_forms("Q16917", "cl100k_base").unwrap();
+        assert_eq!(forms.len(), 2);
+        assert!(forms.iter().any(|f| f.form == "医院"));
+        assert!(db.get_surface_forms("Q99999", "cl100k_base").unwrap().is_empty());
+
+        assert!(db.get_concept("Q99999").unwrap().is_none());
+        assert!(db.find_duplicate_labels().unwrap().is_empty());
+
+        let stats = db.get_stats().unwrap();
+        assert_eq!(stats.total_concepts, 2);
+        assert_eq!(stats.total_surface_forms, 2);
+
+        // Unknown target is rejected
+        assert!(db.merge_concepts("Q0", &["Q16917"]).is_err());
+    }
+
+    #[test]
+    fn test_surface_forms_all_tokenizers() {
+        let db = Database::in_memory().unwrap();
+        db.upsert_concept(&Concept {
+            qid: "Q16917".to_string(),
+            label_en: "hospital".to_string(),
+            description: None,
+            category: None,
+        })
+        .unwrap();
+
+        for (tokenizer_id, lang, form, token_count) in [
+            ("o200k_base", "zh", "医院", 1),
+            ("cl100k_base", "zh", "医院", 4),
+            ("cl100k_base", "en", "hospital", 1),
+        ] {
+            db.insert_surface_form(&SurfaceForm {
+                qid: "Q16917".to_string(),
+                tokenizer_id: tokenizer_id.to_string(),
+                lang: lang.to_string(),
+                form: form.to_string(),
+                token_count,
+                char_count: form.chars().count(),
+            })
+            .unwrap();
+        }
+
+        let forms = db.get_surface_forms_all("Q16917").unwrap();
+        let rows: Vec<(&str, &str, usize)> = forms
+            .iter()
+            .map(|f| (f.tokenizer_id.as_str(), f.lang.as_str(), f.token_count))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("cl100k_base", "en", 1),
+                ("cl100k_base", "zh", 4),
+                ("o200k_base", "zh", 1),
+            ]
+        );
+
+        assert!(db.get_surface_forms_all("Q0").unwrap().is_empty());
+    }
+
     #[test]
     fn test_surface_forms() {
         let db = Database::in_memory().unwrap();
@@ -581,6 +1246,18 @@ mod tests {
         let retrieved = db.get_surface_forms("Q16917", "cl100k_base").unwrap();
         assert_eq!(retrieved.len(), 3);
 
+        // Only the requested languages
+        let langs = ["zh".to_string(), "es".to_string()];
+        let filtered = db.get_surface_forms_filtered("Q16917", "cl100k_base", Some(&langs)).unwrap();
+        let mut filtered_langs: Vec<&str> = filtered.iter().map(|f| f.lang.as_str()).collect();
+        filtered_langs.sort();
+        assert_eq!(filtered_langs, ["es", "zh"]);
+        assert!(db
+            .get_surface_forms_filtered("Q16917", "cl100k_base", Some(&["fr".to_string()]))
+            .unwrap()
+            .is_empty());
+        assert!(db.get_surface_forms_filtered("Q16917", "cl100k_base", Some(&[])).unwrap().is_empty());
+
         // Get cheapest (should be one of the 1-token forms)
         let cheapest = db.get_cheapest_form("Q16917", "cl100k_base").unwrap();
         assert!(cheapest.is_some());
@@ -589,4 +1266,178 @@ mod tests {
         let stats = db.get_stats().unwrap();
         assert_eq!(stats.total_surface_forms, 3);
     }
+
+    fn insert_test_pattern(db: &Database, regex: &str) -> i64 {
+        let conn = db.connection().unwrap();
+        conn.execute(
+            "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
+             VALUES ('boilerplate', ?1, '', 0.90, 'Test pattern')",
+            [regex],
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    #[test]
+    fn test_pooled_file_database_serves_parallel_reads() {
+        let path = std::env::temp_dir().join(format!("prompt-compress-pool-{}.db", uuid::Uuid::new_v4()));
+        let db = Database::open(&path).unwrap();
+
+        let journal_mode: String = db
+            .connection()
+            .unwrap()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(journal_mode, "wal");
+        assert!(matches!(db.connection().unwrap(), DbConnection::Pooled(_)));
+
+        for i in 0..20 {
+            insert_test_pattern(&db, &format!("(?i)pattern{}", i));
+        }
+
+        // Workers read in parallel, each on a pooled connection
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| (0..10).map(|_| db.load_patterns().unwrap().len()).collect::<Vec<_>>()))
+                .collect();
+            for worker in workers {
+                assert!(worker.join().unwrap().iter().all(|&count| count == 20));
+            }
+        });
+
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    #[test]
+    fn test_pattern_timestamps() {
+        let db = Database::in_memory().unwrap();
+        let id = insert_test_pattern(&db, "(?i)please");
+
+        let pattern = &db.load_patterns().unwrap()[0];
+        assert!(pattern.last_applied_at.is_none());
+        assert!(pattern.last_accepted_at.is_none());
+
+        db.record_pattern_application(id).unwrap();
+        db.record_hitl_decision(&HitlDecision {
+            pattern_id: id,
+            session_id: "session".to_string(),
+            original_text: "please".to_string(),
+            optimized_text: "".to_string(),
+            decision: "accept".to_string(),
+            user_alternative: None,
+            context_before: "".to_string(),
+            context_after: "".to_string(),
+        })
+        .unwrap();
+
+        let pattern = &db.load_patterns().unwrap()[0];
+        assert!(pattern.last_applied_at.is_some());
+        assert!(pattern.last_accepted_at.is_some());
+    }
+
+    #[test]
+    fn test_top_patterns_by_savings() {
+        let db = Database::in_memory().unwrap();
+
+        // Atlases from before the column gain it on open
+        db.connection()
+            .unwrap()
+            .execute_batch("ALTER TABLE patterns DROP COLUMN total_token_savings")
+            .unwrap();
+        db.initialize_schema().unwrap();
+
+        let frequent = insert_test_pattern(&db, "(?i)please");
+        let valuable = insert_test_pattern(&db, "(?i)as per my last email");
+        insert_test_pattern(&db, "(?i)never");
+        for _ in 0..3 {
+            db.record_pattern_application(frequent).unwrap();
+            db.record_pattern_savings(frequent, 1).unwrap();
+        }
+        db.record_pattern_application(valuable).unwrap();
+        db.record_pattern_savings(valuable, 5).unwrap();
+
+        let top = db.top_patterns_by_savings(10).unwrap();
+        let ranked: Vec<_> = top.iter().map(|p| (p.id, p.total_token_savings)).collect();
+        assert_eq!(ranked, vec![(valuable, 5), (frequent, 3)]);
+        assert_eq!(top[1].applied_count, 3);
+        assert_eq!(db.top_patterns_by_savings(1).unwrap().len(), 1);
+
+        let stats = db.get_pattern_stats().unwrap();
+        assert_eq!(stats[0].total_token_savings, 8);
+    }
+
+    #[test]
+    fn test_rejection_does_not_set_last_accepted() {
+        let db = Database::in_memory().unwrap();
+        let id = insert_test_pattern(&db, "(?i)please");
+
+        db.record_hitl_decision(&HitlDecision {
+            pattern_id: id,
+            session_id: "session".to_string(),
+            original_text: "please".to_string(),
+            optimized_text: "".to_string(),
+            decision: "reject".to_string(),
+            user_alternative: None,
+            context_before: "".to_string(),
+            context_after: "".to_string(),
+        })
+        .unwrap();
+
+        let pattern = &db.load_patterns().unwrap()[0];
+        assert!(pattern.last_accepted_at.is_none());
+    }
+
+    #[test]
+    fn test_load_stale_patterns() {
+        let db = Database::in_memory().unwrap();
+        let fresh = insert_test_pattern(&db, "(?i)fresh");
+        let old = insert_test_pattern(&db, "(?i)old");
+        let never = insert_test_pattern(&db, "(?i)never");
+
+        db.record_pattern_application(fresh).unwrap();
+        db.connection()
+            .unwrap()
+            .execute(
+                "UPDATE patterns SET last_applied_at = strftime('%s', 'now') - 200 * 86400 WHERE id = ?1",
+                [old],
+            )
+            .unwrap();
+
+        let stale: Vec<i64> = db.load_stale_patterns(90).unwrap().iter().map(|p| p.id).collect();
+        assert_eq!(stale, vec![never, old]);
+    }
+
+    #[test]
+    fn test_audit_patterns_flags_replacements_that_save_nothing() {
+        let db = Database::in_memory().unwrap();
+        let insert = |regex: &str, replacement: &str| {
+            let conn = db.connection().unwrap();
+            conn.execute(
+                "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
+                 VALUES ('synonym', ?1, ?2, 0.90, 'Test pattern')",
+                [regex, replacement],
+            )
+            .unwrap();
+            conn.last_insert_rowid()
+        };
+        insert(r"(?i)\bin order to\b", "to");
+        let wasteful = insert(r"(?i)\bhi\b", "hello");
+        insert(r"\b(\d+)\s*kilometers?\b", "${1}km");
+        insert(r"(?i)\b(?:very|really)\s+", "");
+
+        let tokenizer = crate::tokenizer_registry::TiktokenBackend::cl100k_base().unwrap();
+        let audit = db.audit_patterns(&tokenizer).unwrap();
+
+        assert_eq!((audit.checked, audit.skipped), (2, 2));
+        assert_eq!(audit.inefficient.len(), 1);
+        let flagged = &audit.inefficient[0];
+        assert_eq!((flagged.id, flagged.sample.as_str()), (wasteful, "hi"));
+        assert!(flagged.replacement_tokens >= flagged.sample_tokens);
+
+        assert_eq!(literal_sample(r"(?i)make\s+sure\s+to"), Some("make sure to".to_string()));
+        assert_eq!(literal_sample(r"\.{2,}"), None);
+    }
 }
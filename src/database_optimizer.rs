@@ -1,23 +1,42 @@
-/// Database-backed Optimizer
-/// Similar to Optimizer but loads patterns from SQLite database
+//! Database-backed Optimizer
+//! Similar to Optimizer but loads patterns from SQLite database
 
+use crate::concept_optimizer::detect_concept_substitutions;
+use crate::concept_resolver::{ConceptResolver, ResolutionPolicy};
 use crate::confidence::{extract_context, ConfidenceCalculator};
 use crate::database::Database;
 use crate::database_pattern_detector::DatabasePatternDetector;
 use crate::models::{
-    DirectiveFormat, Language, Optimization, OptimizationRequest, OptimizationResult,
+    compression_ratio, savings_percentage, ConfidenceSummary, DiffStats, Optimization, OptimizationRequest,
+    OptimizationResult, RejectedOptimization, RejectionReason,
 };
+use crate::optimizer::{
+    add_language_directive, apply_pass, apply_savings_floor, recount_savings_in_context,
+    validate_optimized_prompt, ProtectedEnds,
+};
+use crate::patterns::{detect_contractions, detect_json_keys, DetectedPattern};
+use crate::protected_regions::{ProtectedRegion, ProtectedRegionDetector, ProtectionPolicy};
+use crate::sentence::SentenceBoundaries;
+use crate::surface_selector::SurfaceSelector;
 use crate::tokenizer::Tokenizer;
-use anyhow::Result;
+use crate::tokenizer_registry::TokenizerId;
+use crate::error::Result;
+use std::collections::HashMap;
 use std::sync::Arc;
 use uuid::Uuid;
 
 /// Database-backed optimization engine
 pub struct DatabaseOptimizer {
     detector: DatabasePatternDetector,
+    region_detector: ProtectedRegionDetector,
+    sentences: SentenceBoundaries,
     calculator: ConfidenceCalculator,
     tokenizer: Tokenizer,
     db: Arc<Database>,
+    /// Also swap words for cheaper surface forms from the concept atlas
+    concept_substitution: bool,
+    resolver: ConceptResolver,
+    selector: SurfaceSelector,
 }
 
 impl DatabaseOptimizer {
@@ -31,8 +50,13 @@ impl DatabaseOptimizer {
 
         Ok(Self {
             detector,
+            region_detector: ProtectedRegionDetector::default(),
+            sentences: SentenceBoundaries::default(),
             calculator,
             tokenizer,
+            resolver: ConceptResolver::new(Arc::clone(&db), ResolutionPolicy::Normalized),
+            selector: SurfaceSelector::new(Arc::clone(&db)),
+            concept_substitution: false,
             db,
         })
     }
@@ -48,24 +72,114 @@ impl DatabaseOptimizer {
 
         Ok(Self {
             detector,
+            region_detector: ProtectedRegionDetector::default(),
+            sentences: SentenceBoundaries::default(),
+            calculator,
+            tokenizer,
+            resolver: ConceptResolver::new(Arc::clone(&db), ResolutionPolicy::Normalized),
+            selector: SurfaceSelector::new(Arc::clone(&db)),
+            concept_substitution: false,
+            db,
+        })
+    }
+
+    /// Create optimizer loading at most `max_patterns` patterns
+    ///
+    /// See [`DatabasePatternDetector::with_max_patterns`].
+    pub fn with_max_patterns(
+        db: Arc<Database>,
+        calculator: ConfidenceCalculator,
+        tokenizer: Tokenizer,
+        max_patterns: usize,
+    ) -> Result<Self> {
+        let detector = DatabasePatternDetector::with_max_patterns(db.clone(), max_patterns)?;
+
+        Ok(Self {
+            detector,
+            region_detector: ProtectedRegionDetector::default(),
+            sentences: SentenceBoundaries::default(),
             calculator,
             tokenizer,
+            resolver: ConceptResolver::new(Arc::clone(&db), ResolutionPolicy::Normalized),
+            selector: SurfaceSelector::new(Arc::clone(&db)),
+            concept_substitution: false,
             db,
         })
     }
 
+    /// Also substitute words with their cheapest surface form from the
+    /// concept atlas (off by default)
+    ///
+    /// Swaps are scored, protected and conflict-resolved alongside the regex
+    /// patterns, so one pass applies both.
+    pub fn with_concept_substitution(mut self, enabled: bool) -> Self {
+        self.concept_substitution = enabled;
+        self
+    }
+
     /// Optimize a prompt according to the request
     pub fn optimize(&mut self, request: &OptimizationRequest) -> Result<OptimizationResult> {
+        request.validate()?;
+        if let Some(ends) = ProtectedEnds::split(request, &self.region_detector) {
+            let result = self.optimize(ends.inner())?;
+            return Ok(ends.rejoin(result, &self.tokenizer));
+        }
+
         let original_prompt = &request.prompt;
         let original_tokens = self.tokenizer.count_tokens(original_prompt);
 
-        // Detect all patterns
-        let detected = self.detector.detect_all(original_prompt);
+        // Detect all patterns, keeping the database id of those that have one
+        let mut detected: Vec<(Option<i64>, DetectedPattern)> = self
+            .detector
+            .detect_with_ids(original_prompt, request.aggressive_mode)
+            .into_iter()
+            .map(|(id, pattern)| (Some(id), pattern))
+            .collect();
+        let mut extra = detect_contractions(original_prompt, request.contraction_policy, &self.tokenizer);
+        if request.rewrite_json_keys {
+            extra.extend(detect_json_keys(original_prompt));
+        }
+        if self.concept_substitution {
+            extra.extend(detect_concept_substitutions(
+                original_prompt,
+                &self.resolver,
+                &self.selector,
+                TokenizerId::Cl100kBase,
+                |text| self.tokenizer.count_tokens(text),
+                |word, concept| request.allows_concept(word, &concept.label_en, &concept.qid),
+            )?);
+        }
+        detected.extend(extra.into_iter().map(|pattern| (None, pattern)));
+        detected.retain(|(_, p)| request.allows_type(&p.pattern_type));
+
+        // Code, templates, URLs, keywords (and, when conservative, identifiers
+        // and quoted strings) are never rewritten
+        let mut protected_regions = self
+            .region_detector
+            .detect_with_policy(original_prompt, request.protection_policy);
+
+        // Example/quoted user text is left verbatim when requested
+        if request.preserve_first_person {
+            protected_regions.extend(self.region_detector.detect_examples(original_prompt));
+        }
 
         // Calculate confidence for each pattern
         let mut optimizations: Vec<Optimization> = Vec::new();
+        let mut rejected: Vec<RejectedOptimization> = Vec::new();
+        // Curated priorities by optimization id, for conflict resolution
+        let mut priorities: HashMap<String, i64> = HashMap::new();
+        // Database pattern ids by optimization id, for crediting savings
+        let mut pattern_ids: HashMap<String, i64> = HashMap::new();
+
+        // Adjust threshold based on mode
+        let min_confidence = if request.aggressive_mode { 0.4 } else { 0.5 };
+        let auto_apply_threshold = if request.aggressive_mode {
+            0.70
+        } else {
+            request.confidence_threshold
+        };
 
-        for pattern in detected {
+        for (pattern_id, pattern) in detected {
             let context = extract_context(
                 original_prompt,
                 pattern.start_pos,
@@ -84,58 +198,156 @@ impl DatabaseOptimizer {
                 .tokenizer
                 .estimate_savings(&pattern.original_text, &pattern.optimized_text);
 
-            // Adjust threshold based on mode
-            let min_confidence = if request.aggressive_mode { 0.4 } else { 0.5 };
-            let auto_apply_threshold = if request.aggressive_mode {
-                0.70
+            let rejection = if self
+                .region_detector
+                .is_protected(&protected_regions, pattern.start_pos, pattern.end_pos)
+            {
+                Some(RejectionReason::Protected)
+            } else if request.safe_mode && pattern.optimized_text.is_empty() {
+                // Safe mode keeps rewrites but drops pure removals
+                Some(RejectionReason::SafeMode)
+            } else if confidence.final_confidence < min_confidence {
+                Some(RejectionReason::BelowMinConfidence)
+            } else if token_savings <= 0 {
+                Some(RejectionReason::NoSavings)
             } else {
-                request.confidence_threshold
+                None
+            };
+
+            let requires_review = request.review_policy.requires_review(
+                &pattern.pattern_type,
+                confidence.final_confidence,
+                auto_apply_threshold,
+            );
+            let reasoning = request
+                .reasoning_style
+                .render(&pattern.pattern_type, pattern.reasoning);
+            let optimization = Optimization {
+                id: Uuid::new_v4().to_string(),
+                optimization_type: pattern.pattern_type,
+                original_text: pattern.original_text,
+                optimized_text: pattern.optimized_text,
+                token_savings,
+                confidence,
+                requires_review,
+                reasoning,
+                start_pos: pattern.start_pos,
+                end_pos: pattern.end_pos,
+                pass: 0,
             };
 
             // Only include if meets minimum confidence and saves tokens
-            if confidence.final_confidence >= min_confidence && token_savings > 0 {
-                let requires_review = confidence.final_confidence < auto_apply_threshold;
-
-                optimizations.push(Optimization {
-                    id: Uuid::new_v4().to_string(),
-                    optimization_type: pattern.pattern_type,
-                    original_text: pattern.original_text,
-                    optimized_text: pattern.optimized_text,
-                    token_savings,
-                    confidence,
-                    requires_review,
-                    reasoning: pattern.reasoning,
-                    start_pos: pattern.start_pos,
-                    end_pos: pattern.end_pos,
-                });
+            match rejection {
+                None => {
+                    if let Some(priority) = pattern.priority {
+                        priorities.insert(optimization.id.clone(), priority);
+                    }
+                    if let Some(pattern_id) = pattern_id {
+                        pattern_ids.insert(optimization.id.clone(), pattern_id);
+                    }
+                    optimizations.push(optimization)
+                }
+                Some(reason) if request.include_rejected => {
+                    rejected.push(RejectedOptimization::new(optimization, reason));
+                }
+                Some(_) => {}
             }
         }
 
         // Resolve conflicts (overlapping optimizations)
-        let optimizations = self.resolve_conflicts(optimizations);
+        let candidates = request.include_rejected.then(|| optimizations.clone());
+        let mut optimizations = self.resolve_conflicts(optimizations, &priorities);
+        recount_savings_in_context(original_prompt, &mut optimizations, &self.tokenizer);
+        if let Some(candidates) = candidates {
+            rejected.extend(
+                candidates
+                    .into_iter()
+                    .filter(|c| !optimizations.iter().any(|opt| opt.id == c.id))
+                    .map(|c| RejectedOptimization::new(c, RejectionReason::ConflictLoser)),
+            );
+        }
 
         // Split into auto-apply and requires-review
-        let (auto_apply, requires_review): (Vec<_>, Vec<_>) = optimizations
+        let (mut auto_apply, requires_review): (Vec<_>, Vec<_>) = optimizations
             .into_iter()
             .partition(|opt| !opt.requires_review);
 
         // Apply auto-approved optimizations
-        let mut optimized_prompt = self.apply_optimizations(original_prompt, &auto_apply);
-
-        // Add output language directive
-        optimized_prompt = self.add_language_directive(
-            &optimized_prompt,
-            &request.output_language,
-            &request.directive_format,
+        let optimized_prompt = self.apply_optimizations(
+            original_prompt,
+            &auto_apply,
+            &protected_regions,
+            !request.preserve_case,
         );
+        let confidence_summary = ConfidenceSummary::new(
+            &auto_apply,
+            &requires_review,
+            &request.review_policy,
+            !request.aggressive_mode,
+        );
+
+        // Never hand back an empty or corrupted prompt
+        let mut warnings = Vec::new();
+        let mut directive_tokens = 0;
+        let mut directive_added = false;
+        let optimized_prompt = match validate_optimized_prompt(original_prompt, &optimized_prompt) {
+            // Add output language directive
+            Ok(()) => {
+                let with_directive = if request.language_directive {
+                    add_language_directive(
+                        &optimized_prompt,
+                        &request.output_language,
+                        &request.directive_format,
+                        request.directive_placement,
+                        &request.directive_separator,
+                        request.directive_trim,
+                    )
+                } else {
+                    optimized_prompt.clone()
+                };
+                let directive_cost = self
+                    .tokenizer
+                    .count_tokens(&with_directive)
+                    .saturating_sub(self.tokenizer.count_tokens(&optimized_prompt));
+                let with_directive_len = with_directive.len();
+                let (prompt, reverted) = apply_savings_floor(
+                    request.savings_floor,
+                    &self.tokenizer,
+                    original_prompt,
+                    optimized_prompt,
+                    with_directive,
+                    &mut warnings,
+                );
+                if reverted {
+                    auto_apply.clear();
+                }
+                // The floor may have dropped the directive (or everything)
+                if !reverted && prompt.len() == with_directive_len {
+                    directive_tokens = directive_cost;
+                    directive_added = request.language_directive;
+                }
+                prompt
+            }
+            Err(warning) => {
+                warnings.push(warning);
+                auto_apply.clear();
+                original_prompt.to_string()
+            }
+        };
+
+        // Credit each pattern with what it actually saved in the final prompt
+        for opt in &auto_apply {
+            let Some(&pattern_id) = pattern_ids.get(&opt.id) else {
+                continue;
+            };
+            if let Err(e) = self.db.record_pattern_savings(pattern_id, opt.token_savings.max(0)) {
+                eprintln!("Warning: Failed to record pattern savings: {}", e);
+            }
+        }
 
         let optimized_tokens = self.tokenizer.count_tokens(&optimized_prompt);
         let token_savings = original_tokens as i64 - optimized_tokens as i64;
-        let savings_percentage = if original_tokens > 0 {
-            (token_savings as f64 / original_tokens as f64) * 100.0
-        } else {
-            0.0
-        };
+        let diff_stats = DiffStats::between(original_prompt, &optimized_prompt);
 
         Ok(OptimizationResult {
             original_prompt: original_prompt.to_string(),
@@ -143,25 +355,47 @@ impl DatabaseOptimizer {
             original_tokens,
             optimized_tokens,
             token_savings,
-            savings_percentage,
+            directive_tokens,
+            savings_including_directive: token_savings,
+            savings_excluding_directive: token_savings + directive_tokens as i64,
+            savings_percentage: savings_percentage(original_tokens, optimized_tokens),
+            compression_ratio: compression_ratio(original_tokens, optimized_tokens),
             optimizations: auto_apply,
             requires_review,
             output_language: request.output_language.clone(),
+            rejected,
+            warnings,
+            net_savings_negative: token_savings < 0,
+            diff_stats,
+            replayable: !directive_added
+                && !request.preserve_case
+                && request.protection_policy == ProtectionPolicy::Conservative
+                && !request.preserve_first_person,
+            confidence_summary,
+            profile: None,
         })
     }
 
     /// Resolve overlapping optimizations
-    fn resolve_conflicts(&self, optimizations: Vec<Optimization>) -> Vec<Optimization> {
+    ///
+    /// Highest confidence wins; ties go to the higher curated priority
+    /// (unranked patterns last), then to the larger token savings.
+    fn resolve_conflicts(
+        &self,
+        optimizations: Vec<Optimization>,
+        priorities: &HashMap<String, i64>,
+    ) -> Vec<Optimization> {
         let mut resolved = Vec::new();
         let mut covered_ranges: Vec<(usize, usize)> = Vec::new();
 
-        // Sort by confidence (highest first)
         let mut sorted = optimizations;
         sorted.sort_by(|a, b| {
             b.confidence
                 .final_confidence
                 .partial_cmp(&a.confidence.final_confidence)
                 .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| priorities.get(&b.id).cmp(&priorities.get(&a.id)))
+                .then_with(|| b.token_savings.cmp(&a.token_savings))
         });
 
         for opt in sorted {
@@ -183,96 +417,15 @@ impl DatabaseOptimizer {
         resolved
     }
 
-    /// Apply optimizations to text
-    fn apply_optimizations(&self, text: &str, optimizations: &[Optimization]) -> String {
-        let mut result = text.to_string();
-        let mut offset: i64 = 0;
-
-        // Optimizations should be sorted by position
-        for opt in optimizations {
-            let start = (opt.start_pos as i64 + offset) as usize;
-            let end = (opt.end_pos as i64 + offset) as usize;
-
-            if start <= result.len() && end <= result.len() && start <= end {
-                result.replace_range(start..end, &opt.optimized_text);
-
-                // Update offset for next optimization
-                let original_len = opt.end_pos - opt.start_pos;
-                let new_len = opt.optimized_text.len();
-                offset += new_len as i64 - original_len as i64;
-            }
-        }
-
-        // Clean up whitespace
-        self.clean_whitespace(&result)
-    }
-
-    /// Clean whitespace and formatting
-    fn clean_whitespace(&self, text: &str) -> String {
-        use regex::Regex;
-
-        let mut result = text.to_string();
-
-        // Remove excessive newlines
-        let newline_re = Regex::new(r"\n\n\n+").unwrap();
-        result = newline_re.replace_all(&result, "\n\n").to_string();
-
-        // Collapse multiple spaces
-        let space_re = Regex::new(r"  +").unwrap();
-        result = space_re.replace_all(&result, " ").to_string();
-
-        // Remove spaces before punctuation
-        let punct_re = Regex::new(r" ([.,!?;:])").unwrap();
-        result = punct_re.replace_all(&result, "$1").to_string();
-
-        // Capitalize sentences
-        self.capitalize_sentences(&result)
-    }
-
-    /// Capitalize first letter after sentence-ending punctuation
-    fn capitalize_sentences(&self, text: &str) -> String {
-        let mut result = String::new();
-        let mut capitalize_next = true;
-
-        for ch in text.chars() {
-            if capitalize_next && ch.is_alphabetic() {
-                result.push(ch.to_ascii_uppercase());
-                capitalize_next = false;
-            } else {
-                result.push(ch);
-                if ch == '.' || ch == '!' || ch == '?' {
-                    capitalize_next = true;
-                }
-            }
-        }
-
-        result
-    }
-
-    /// Add language directive to prompt
-    fn add_language_directive(
+    /// Apply optimizations to text, leaving `protected_regions` byte-for-byte
+    fn apply_optimizations(
         &self,
-        prompt: &str,
-        language: &Language,
-        format: &DirectiveFormat,
+        text: &str,
+        optimizations: &[Optimization],
+        protected_regions: &[ProtectedRegion],
+        capitalize: bool,
     ) -> String {
-        let lang_str = match language {
-            Language::English => "english",
-            Language::Mandarin => "mandarin",
-        };
-
-        let directive = match format {
-            DirectiveFormat::Bracketed => format!("[output_language: {}]", lang_str),
-            DirectiveFormat::Instructive => format!("Respond in {}.", lang_str),
-            DirectiveFormat::Xml => {
-                format!("<output_language>{}</output_language>", lang_str)
-            }
-            DirectiveFormat::Natural => {
-                format!("Please respond to me in {}.", lang_str)
-            }
-        };
-
-        format!("{}\n\n{}", prompt.trim(), directive)
+        apply_pass(text, optimizations, protected_regions, capitalize.then_some(&self.sentences))
     }
 
     /// Reload patterns from database
@@ -289,12 +442,17 @@ impl DatabaseOptimizer {
     pub fn pattern_count(&self) -> usize {
         self.detector.pattern_count()
     }
+
+    /// Get number of patterns available before the `max_patterns` cap
+    pub fn available_pattern_count(&self) -> usize {
+        self.detector.available_pattern_count()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::Language;
+    use crate::models::{DirectiveFormat, Language, OptimizationType, ReviewPolicy, ReviewRule};
 
     #[test]
     fn test_database_optimizer() {
@@ -302,6 +460,7 @@ mod tests {
 
         // Insert test patterns
         db.connection()
+            .unwrap()
             .execute(
                 "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
                  VALUES ('boilerplate', '(?i)I would really appreciate', '', 0.95, 'Test')",
@@ -316,11 +475,12 @@ mod tests {
             DatabaseOptimizer::new(Arc::new(db), calculator, tokenizer).unwrap();
 
         let request = OptimizationRequest {
-            prompt: "I would really appreciate your help with this.".to_string(),
+            prompt: "I would really appreciate your help with this. I would really appreciate a short answer. I would really appreciate some examples.".to_string(),
             output_language: Language::English,
             confidence_threshold: 0.85,
             aggressive_mode: false,
             directive_format: DirectiveFormat::Bracketed,
+            ..Default::default()
         };
 
         let result = optimizer.optimize(&request).unwrap();
@@ -328,4 +488,316 @@ mod tests {
         assert!(result.token_savings > 0);
         assert!(result.savings_percentage > 0.0);
     }
+
+    #[test]
+    fn test_pattern_savings_credit_only_applied_matches() {
+        let db = Arc::new(Database::in_memory().unwrap());
+        db.connection()
+            .unwrap()
+            .execute(
+                "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
+                 VALUES ('filler', '(?i)\\bbasically\\s+', '', 0.95, 'Test')",
+                [],
+            )
+            .unwrap();
+        let mut optimizer =
+            DatabaseOptimizer::new(db.clone(), ConfidenceCalculator::default(), Tokenizer::new().unwrap()).unwrap();
+
+        // The match inside inline code is protected and saves nothing
+        let request = OptimizationRequest::builder("Run `basically stop` first.")
+            .language_directive(false)
+            .build();
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result.optimizations.is_empty());
+        assert_eq!(db.load_patterns().unwrap()[0].total_token_savings, 0);
+
+        let request = OptimizationRequest::builder("Basically run `basically stop` first.")
+            .language_directive(false)
+            .build();
+        let result = optimizer.optimize(&request).unwrap();
+        assert_eq!(result.optimizations.len(), 1);
+        assert!(result.optimizations[0].token_savings > 0);
+        assert_eq!(
+            db.load_patterns().unwrap()[0].total_token_savings,
+            result.optimizations[0].token_savings
+        );
+    }
+
+    #[test]
+    fn test_capitalizes_accented_sentence_start() {
+        let db = Database::in_memory().unwrap();
+        db.connection()
+            .unwrap()
+            .execute(
+                "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
+                 VALUES ('boilerplate', '(?i)I would really appreciate it if you could\\s*', '', 0.97, 'Test')",
+                [],
+            )
+            .unwrap();
+
+        let mut optimizer = DatabaseOptimizer::new(
+            Arc::new(db),
+            ConfidenceCalculator::default(),
+            Tokenizer::new().unwrap(),
+        )
+        .unwrap();
+
+        let request = OptimizationRequest {
+            prompt: "I would really appreciate it if you could évaluer ce code. \
+                     I would really appreciate it if you could übersetzen es."
+                .to_string(),
+            ..Default::default()
+        };
+        let result = optimizer.optimize(&request).unwrap();
+
+        // Non-ASCII first letters are uppercased like ASCII ones
+        assert!(result.optimized_prompt.starts_with("Évaluer ce code. Übersetzen es."));
+    }
+
+    #[test]
+    fn test_priority_breaks_conflict_ties() {
+        let db = Arc::new(Database::in_memory().unwrap());
+
+        // Same match, type and confidence; only the replacement differs
+        let mut ids = Vec::new();
+        for (regex, replacement) in [(r"(?i)in order to", "to"), (r"(?i)\bin order to\b", "so")] {
+            let conn = db.connection().unwrap();
+            conn.execute(
+                "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
+                 VALUES ('instruction', ?1, ?2, 0.95, 'Test')",
+                [regex, replacement],
+            )
+            .unwrap();
+            ids.push(conn.last_insert_rowid());
+        }
+
+        let request = OptimizationRequest {
+            prompt: "Read the logs in order to find the failing request.".to_string(),
+            ..Default::default()
+        };
+        let chosen = |db: &Arc<Database>| {
+            let mut optimizer = DatabaseOptimizer::new(
+                db.clone(),
+                ConfidenceCalculator::default(),
+                Tokenizer::new().unwrap(),
+            )
+            .unwrap();
+            let result = optimizer.optimize(&request).unwrap();
+            assert_eq!(result.optimizations.len(), 1);
+            result.optimizations[0].optimized_text.clone()
+        };
+
+        db.set_pattern_priority(ids[1], Some(10)).unwrap();
+        assert_eq!(chosen(&db), "so");
+
+        db.set_pattern_priority(ids[0], Some(20)).unwrap();
+        assert_eq!(chosen(&db), "to");
+
+        assert!(db.set_pattern_priority(9999, Some(1)).is_err());
+    }
+
+    #[test]
+    fn test_review_policy_routes_by_type() {
+        let db = Arc::new(Database::in_memory().unwrap());
+        db.connection()
+            .unwrap()
+            .execute_batch(
+                "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
+                 VALUES ('structural', '(?i)in the form of a list', 'as a list', 0.70, 'Structural');
+                 INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
+                 VALUES ('mandarin', '(?i)in a step-by-step manner', '逐步', 0.99, 'Mandarin')",
+            )
+            .unwrap();
+
+        let mut optimizer = DatabaseOptimizer::new(
+            db,
+            ConfidenceCalculator::default(),
+            Tokenizer::new().unwrap(),
+        )
+        .unwrap();
+
+        let request = OptimizationRequest {
+            prompt: "Explain the migration in a step-by-step manner and give the risks in the form of a list.".to_string(),
+            ..Default::default()
+        };
+
+        // Default policy: the low-confidence structural edit auto-applies and
+        // the confident Mandarin swap waits for review
+        let result = optimizer.optimize(&request).unwrap();
+        assert_eq!(result.optimizations.len(), 1);
+        assert_eq!(result.optimizations[0].optimization_type, OptimizationType::FormatConsolidation);
+        assert!(result.optimizations[0].confidence.final_confidence < request.confidence_threshold);
+        assert!(result.optimized_prompt.contains("as a list"));
+        assert_eq!(result.requires_review.len(), 1);
+        assert_eq!(result.requires_review[0].optimization_type, OptimizationType::MandarinSubstitution);
+
+        // Threshold-only routing reviews the structural edit instead
+        let result = optimizer
+            .optimize(&OptimizationRequest {
+                review_policy: ReviewPolicy::threshold_only()
+                    .with_rule(OptimizationType::MandarinSubstitution, ReviewRule::AlwaysReview),
+                ..request
+            })
+            .unwrap();
+        assert!(result.optimizations.is_empty());
+        assert_eq!(result.requires_review.len(), 2);
+    }
+
+    #[test]
+    fn test_destructive_pattern_falls_back_to_original() {
+        let db = Database::in_memory().unwrap();
+
+        // A broken pattern that deletes the whole prompt
+        db.connection()
+            .unwrap()
+            .execute(
+                "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
+                 VALUES ('boilerplate', '(?s).+', '', 0.99, 'Destructive')",
+                [],
+            )
+            .unwrap();
+
+        let mut optimizer = DatabaseOptimizer::new(
+            Arc::new(db),
+            ConfidenceCalculator::default(),
+            Tokenizer::new().unwrap(),
+        )
+        .unwrap();
+
+        let request = OptimizationRequest {
+            prompt: "Summarize the quarterly report in three bullet points.".to_string(),
+            confidence_threshold: 0.5,
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+
+        assert_eq!(result.optimized_prompt, request.prompt);
+        assert!(result.optimizations.is_empty());
+        assert_eq!(result.token_savings, 0);
+        assert_eq!(result.warnings.len(), 1);
+        assert!(result.warnings[0].contains("empty"));
+    }
+
+    #[test]
+    fn test_max_patterns_keeps_highest_confidence() {
+        let db = Database::in_memory().unwrap();
+        for i in 0..500 {
+            db.connection()
+                .unwrap()
+                .execute(
+                    "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
+                     VALUES ('filler', ?1, '', ?2, 'Generated')",
+                    rusqlite::params![format!(r"\bfiller{}\b", i), 0.5 + i as f64 / 1000.0],
+                )
+                .unwrap();
+        }
+        let db = Arc::new(db);
+
+        let mut optimizer = DatabaseOptimizer::with_max_patterns(
+            Arc::clone(&db),
+            ConfidenceCalculator::default(),
+            Tokenizer::new().unwrap(),
+            100,
+        )
+        .unwrap();
+        assert_eq!(optimizer.pattern_count(), 100);
+        assert_eq!(optimizer.available_pattern_count(), 500);
+
+        // Only the 100 most confident patterns (filler400..filler499) load
+        let request = OptimizationRequest {
+            prompt: "Check filler10 and filler450 now.".to_string(),
+            include_rejected: true,
+            ..Default::default()
+        };
+        let result = optimizer.optimize(&request).unwrap();
+        let matched: Vec<_> = result
+            .optimizations
+            .iter()
+            .chain(&result.requires_review)
+            .map(|opt| opt.original_text.as_str())
+            .chain(result.rejected.iter().map(|r| r.original_text.as_str()))
+            .collect();
+        assert!(matched.contains(&"filler450"));
+        assert!(!matched.contains(&"filler10"));
+
+        // Reloading keeps the cap
+        optimizer.reload_patterns().unwrap();
+        assert_eq!(optimizer.pattern_count(), 100);
+
+        // Without a cap everything loads
+        let uncapped = DatabaseOptimizer::new(
+            db,
+            ConfidenceCalculator::default(),
+            Tokenizer::new().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(uncapped.pattern_count(), 500);
+        assert_eq!(uncapped.available_pattern_count(), 500);
+    }
+
+    #[test]
+    fn test_concept_substitution_with_regex_patterns() {
+        use crate::database::{Concept, SurfaceForm};
+
+        let db = Database::in_memory().unwrap();
+        db.connection()
+            .unwrap()
+            .execute(
+                "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
+                 VALUES ('boilerplate', '(?i)I would really appreciate it if you could\\s*', '', 0.97, 'Test')",
+                [],
+            )
+            .unwrap();
+        db.upsert_concept(&Concept {
+            qid: "Q37828".to_string(),
+            label_en: "refrigerator".to_string(),
+            description: Some("cooling appliance".to_string()),
+            category: Some("household".to_string()),
+        })
+        .unwrap();
+        for (form, token_count) in [("refrigerator", 3), ("fridge", 1)] {
+            db.insert_surface_form(&SurfaceForm {
+                qid: "Q37828".to_string(),
+                tokenizer_id: "cl100k_base".to_string(),
+                lang: "en".to_string(),
+                form: form.to_string(),
+                token_count,
+                char_count: form.len(),
+            })
+            .unwrap();
+        }
+        let db = Arc::new(db);
+
+        let request = OptimizationRequest {
+            prompt: "I would really appreciate it if you could clean the refrigerator today.".to_string(),
+            confidence_threshold: 0.7,
+            ..Default::default()
+        };
+
+        // Off by default: only the regex removal applies
+        let mut optimizer = DatabaseOptimizer::new(
+            Arc::clone(&db),
+            ConfidenceCalculator::default(),
+            Tokenizer::new().unwrap(),
+        )
+        .unwrap();
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result.optimized_prompt.starts_with("Clean the refrigerator today."));
+
+        let mut optimizer = optimizer.with_concept_substitution(true);
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result.optimized_prompt.starts_with("Clean the fridge today."));
+        let types: Vec<_> = result.optimizations.iter().map(|o| &o.optimization_type).collect();
+        assert!(types.contains(&&OptimizationType::BoilerplateRemoval));
+        assert!(types.contains(&&OptimizationType::ConceptSubstitution));
+
+        // The request's denylist keeps the word
+        let request = OptimizationRequest {
+            concept_denylist: vec!["refrigerator".to_string()],
+            ..request
+        };
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result.optimized_prompt.starts_with("Clean the refrigerator today."));
+    }
 }
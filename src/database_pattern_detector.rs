@@ -1,63 +1,142 @@
-/// Database-backed Pattern Detector
-/// Loads regex patterns from SQLite instead of hardcoded constants
+//! Database-backed Pattern Detector
+//! Loads regex patterns from SQLite instead of hardcoded constants
 
 use crate::database::{Database, PatternRecord};
-use crate::models::OptimizationType;
-use crate::patterns::DetectedPattern;
-use anyhow::Result;
+use crate::models::Locale;
+use crate::pattern_source::{detect_with, detect_with_mode, detect_with_mode_indexed, parse_pattern_type, PatternSource};
+use crate::patterns::{ContextGuard, DetectedPattern, Pattern};
+use crate::error::Result;
 use regex::Regex;
 use std::sync::Arc;
 
 /// Pattern detector that loads patterns from database
 pub struct DatabasePatternDetector {
     db: Arc<Database>,
-    patterns: Vec<CompiledPattern>,
+    patterns: Vec<Pattern>,
+    /// Database id of each entry in `patterns`
+    ids: Vec<i64>,
+    compile_errors: Vec<PatternCompileError>,
+    /// Cap on loaded patterns (None = load every enabled pattern)
+    max_patterns: Option<usize>,
+    /// Patterns eligible before the cap was applied
+    available_pattern_count: usize,
 }
 
-struct CompiledPattern {
-    id: i64,
-    pattern_type: OptimizationType,
-    regex: Regex,
-    replacement: String,
-    base_confidence: f64,
-    reasoning: String,
+/// A pattern whose regex failed to compile
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternCompileError {
+    pub id: i64,
+    pub regex_pattern: String,
+    pub error: String,
 }
 
 impl DatabasePatternDetector {
     /// Create new detector and load patterns from database
     pub fn new(db: Arc<Database>) -> Result<Self> {
         let pattern_records = db.load_patterns()?;
-        let patterns = Self::compile_patterns(pattern_records)?;
-
-        Ok(Self { db, patterns })
+        Ok(Self::from_records(db, pattern_records, None))
     }
 
     /// Create new detector with minimum confidence threshold
     pub fn with_confidence(db: Arc<Database>, min_confidence: f64) -> Result<Self> {
         let pattern_records = db.load_patterns_with_confidence(min_confidence)?;
-        let patterns = Self::compile_patterns(pattern_records)?;
+        Ok(Self::from_records(db, pattern_records, None))
+    }
+
+    /// Create new detector loading at most `max_patterns` patterns
+    ///
+    /// Detection cost grows with every regex, so an oversized database is
+    /// cut down to the highest-confidence patterns (ties go to the higher
+    /// curated priority), with a warning.
+    pub fn with_max_patterns(db: Arc<Database>, max_patterns: usize) -> Result<Self> {
+        let pattern_records = db.load_patterns()?;
+        Ok(Self::from_records(db, pattern_records, Some(max_patterns)))
+    }
+
+    fn from_records(
+        db: Arc<Database>,
+        records: Vec<PatternRecord>,
+        max_patterns: Option<usize>,
+    ) -> Self {
+        let available_pattern_count = records.len();
+        let records = Self::cap_records(records, max_patterns);
+        let (patterns, ids, compile_errors) = Self::compile_patterns(records);
+
+        Self {
+            db,
+            patterns,
+            ids,
+            compile_errors,
+            max_patterns,
+            available_pattern_count,
+        }
+    }
 
-        Ok(Self { db, patterns })
+    /// Keep the top `max_patterns` records by confidence, then priority
+    fn cap_records(mut records: Vec<PatternRecord>, max_patterns: Option<usize>) -> Vec<PatternRecord> {
+        let Some(max) = max_patterns.filter(|&max| records.len() > max) else {
+            return records;
+        };
+
+        eprintln!(
+            "Warning: pattern database has {} patterns; loading only the top {}",
+            records.len(),
+            max
+        );
+        records.sort_by(|a, b| {
+            b.base_confidence
+                .total_cmp(&a.base_confidence)
+                .then_with(|| b.priority.cmp(&a.priority))
+        });
+        records.truncate(max);
+        records
     }
 
     /// Compile pattern records into regex patterns
-    fn compile_patterns(records: Vec<PatternRecord>) -> Result<Vec<CompiledPattern>> {
+    ///
+    /// Invalid regexes are skipped and returned alongside the compiled set.
+    fn compile_patterns(
+        records: Vec<PatternRecord>,
+    ) -> (Vec<Pattern>, Vec<i64>, Vec<PatternCompileError>) {
         let mut compiled = Vec::new();
+        let mut ids = Vec::new();
+        let mut errors = Vec::new();
 
         for record in records {
+            // Compile the optional context guard first
+            let context_guard = match record.context_guard.as_deref().map(ContextGuard::parse) {
+                None => None,
+                Some(Ok(guard)) => Some(guard),
+                Some(Err(e)) => {
+                    eprintln!(
+                        "Warning: Failed to compile context guard for pattern {}: {} - {}",
+                        record.id,
+                        record.context_guard.as_deref().unwrap_or_default(),
+                        e
+                    );
+                    errors.push(PatternCompileError {
+                        id: record.id,
+                        regex_pattern: record.context_guard.unwrap_or_default(),
+                        error: format!("context guard: {}", e),
+                    });
+                    continue;
+                }
+            };
+
             // Try to compile regex
             match Regex::new(&record.regex_pattern) {
                 Ok(regex) => {
-                    let pattern_type = Self::parse_pattern_type(&record.pattern_type);
-
-                    compiled.push(CompiledPattern {
-                        id: record.id,
-                        pattern_type,
+                    compiled.push(Pattern {
+                        pattern_type: parse_pattern_type(&record.pattern_type),
                         regex,
                         replacement: record.replacement,
                         base_confidence: record.base_confidence,
                         reasoning: record.reasoning,
+                        context_guard,
+                        priority: record.priority,
+                        aggressive_only: record.aggressive_only,
                     });
+                    ids.push(record.id);
                 }
                 Err(e) => {
                     eprintln!(
@@ -65,61 +144,47 @@ impl DatabasePatternDetector {
                         record.id, record.regex_pattern, e
                     );
                     // Continue with other patterns
+                    errors.push(PatternCompileError {
+                        id: record.id,
+                        regex_pattern: record.regex_pattern,
+                        error: e.to_string(),
+                    });
                 }
             }
         }
 
-        Ok(compiled)
-    }
-
-    /// Parse pattern type string into enum
-    fn parse_pattern_type(type_str: &str) -> OptimizationType {
-        match type_str {
-            "boilerplate" => OptimizationType::BoilerplateRemoval,
-            "filler" => OptimizationType::FillerRemoval,
-            "instruction" => OptimizationType::InstructionCompression,
-            "redundant" => OptimizationType::FormatConsolidation,
-            "structural" => OptimizationType::FormatConsolidation,
-            "synonym" => OptimizationType::SynonymConsolidation,
-            "mandarin" => OptimizationType::MandarinSubstitution,
-            _ => OptimizationType::FormatConsolidation, // Default
-        }
+        (compiled, ids, errors)
     }
 
     /// Detect all patterns in text (similar to PatternDetector::detect_all)
     pub fn detect_all(&self, text: &str) -> Vec<DetectedPattern> {
-        let mut detected = Vec::new();
-
-        for pattern in &self.patterns {
-            for mat in pattern.regex.find_iter(text) {
-                let optimized = pattern.regex.replace(mat.as_str(), &pattern.replacement);
-
-                detected.push(DetectedPattern {
-                    pattern_type: pattern.pattern_type.clone(),
-                    original_text: mat.as_str().to_string(),
-                    optimized_text: optimized.to_string(),
-                    start_pos: mat.start(),
-                    end_pos: mat.end(),
-                    base_confidence: pattern.base_confidence,
-                    reasoning: pattern.reasoning.clone(),
-                });
-
-                // Record pattern application in database
-                if let Err(e) = self.db.record_pattern_application(pattern.id) {
-                    eprintln!("Warning: Failed to record pattern application: {}", e);
-                }
-            }
-        }
+        detect_with(self, text, Locale::default())
+    }
+
+    /// Detect all patterns in text, including `aggressive_only` ones when
+    /// `aggressive` is set
+    pub fn detect_all_with_mode(&self, text: &str, aggressive: bool) -> Vec<DetectedPattern> {
+        detect_with_mode(self, text, Locale::default(), aggressive)
+    }
 
-        // Sort by position to handle overlaps later
-        detected.sort_by_key(|d| d.start_pos);
-        detected
+    /// Detect like `detect_all_with_mode`, pairing each match with the
+    /// database id of the pattern that found it
+    pub fn detect_with_ids(&self, text: &str, aggressive: bool) -> Vec<(i64, DetectedPattern)> {
+        detect_with_mode_indexed(self, text, Locale::default(), aggressive)
+            .into_iter()
+            .map(|(index, detected)| (self.ids[index], detected))
+            .collect()
     }
 
     /// Reload patterns from database
     pub fn reload_patterns(&mut self) -> Result<()> {
         let pattern_records = self.db.load_patterns()?;
-        self.patterns = Self::compile_patterns(pattern_records)?;
+        self.available_pattern_count = pattern_records.len();
+        let pattern_records = Self::cap_records(pattern_records, self.max_patterns);
+        let (patterns, ids, compile_errors) = Self::compile_patterns(pattern_records);
+        self.patterns = patterns;
+        self.ids = ids;
+        self.compile_errors = compile_errors;
         Ok(())
     }
 
@@ -128,15 +193,39 @@ impl DatabasePatternDetector {
         self.patterns.len()
     }
 
+    /// Get number of enabled patterns in the database before `max_patterns`
+    pub fn available_pattern_count(&self) -> usize {
+        self.available_pattern_count
+    }
+
+    /// Get patterns skipped because their regex failed to compile
+    pub fn compile_errors(&self) -> &[PatternCompileError] {
+        &self.compile_errors
+    }
+
     /// Get database reference
     pub fn database(&self) -> &Arc<Database> {
         &self.db
     }
 }
 
+impl PatternSource for DatabasePatternDetector {
+    fn patterns(&self, _locale: Locale) -> Vec<&Pattern> {
+        self.patterns.iter().collect()
+    }
+
+    /// Record pattern application in database
+    fn on_match(&self, _locale: Locale, index: usize) {
+        if let Err(e) = self.db.record_pattern_application(self.ids[index]) {
+            eprintln!("Warning: Failed to record pattern application: {}", e);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::OptimizationType;
     use std::sync::Arc;
 
     #[test]
@@ -146,6 +235,7 @@ mod tests {
 
         // Insert a test pattern
         db.connection()
+            .unwrap()
             .execute(
                 "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
                  VALUES (?1, ?2, ?3, ?4, ?5)",
@@ -170,6 +260,15 @@ mod tests {
 
         assert!(!detected.is_empty());
         assert_eq!(detected[0].base_confidence, 0.95);
+
+        // Detection counts the application; savings wait until it is applied
+        let record = &detector.database().load_patterns().unwrap()[0];
+        assert_eq!(record.applied_count, 1);
+        assert_eq!(record.total_token_savings, 0);
+
+        let with_ids = detector.detect_with_ids(text, false);
+        assert_eq!(with_ids[0].0, record.id);
+        assert_eq!(with_ids[0].1.original_text, detected[0].original_text);
     }
 
     #[test]
@@ -178,6 +277,7 @@ mod tests {
 
         // Insert patterns with different confidences
         db.connection()
+            .unwrap()
             .execute(
                 "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
                  VALUES ('boilerplate', 'test1', '', 0.95, 'High confidence')",
@@ -186,6 +286,7 @@ mod tests {
             .unwrap();
 
         db.connection()
+            .unwrap()
             .execute(
                 "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
                  VALUES ('boilerplate', 'test2', '', 0.50, 'Low confidence')",
@@ -199,4 +300,144 @@ mod tests {
         // Should only load the high-confidence pattern
         assert_eq!(detector.pattern_count(), 1);
     }
+
+    #[test]
+    fn test_compile_errors_collected() {
+        let db = Database::in_memory().unwrap();
+
+        db.connection()
+            .unwrap()
+            .execute(
+                "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
+                 VALUES ('boilerplate', '(?i)valid', '', 0.95, 'Valid pattern')",
+                [],
+            )
+            .unwrap();
+
+        let broken_id = {
+            let conn = db.connection().unwrap();
+            conn.execute(
+                "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
+                 VALUES ('boilerplate', '(unclosed', '', 0.90, 'Broken pattern')",
+                [],
+            )
+            .unwrap();
+            conn.last_insert_rowid()
+        };
+
+        let detector = DatabasePatternDetector::new(Arc::new(db)).unwrap();
+
+        // Valid pattern still loads; broken one is reported
+        assert_eq!(detector.pattern_count(), 1);
+        assert_eq!(detector.compile_errors().len(), 1);
+
+        let error = &detector.compile_errors()[0];
+        assert_eq!(error.id, broken_id);
+        assert_eq!(error.regex_pattern, "(unclosed");
+        assert!(!error.error.is_empty());
+    }
+
+    #[test]
+    fn test_context_guard_prevents_match() {
+        let db = Database::in_memory().unwrap();
+
+        // "please" is boilerplate, except when quoting a policy
+        db.connection()
+            .unwrap()
+            .execute(
+                "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning, context_guard)
+                 VALUES ('boilerplate', '(?i)\\bplease\\b\\s*', '', 0.90, 'Politeness marker', '!(?i)policy')",
+                [],
+            )
+            .unwrap();
+        // Required guard: only strip "kindly" in requests to the assistant
+        db.connection()
+            .unwrap()
+            .execute(
+                "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning, context_guard)
+                 VALUES ('boilerplate', '(?i)\\bkindly\\b\\s*', '', 0.90, 'Politeness marker', '(?i)\\byou\\b')",
+                [],
+            )
+            .unwrap();
+
+        let detector = DatabasePatternDetector::new(Arc::new(db)).unwrap();
+        assert_eq!(detector.pattern_count(), 2);
+
+        let detected = detector.detect_all("Please summarize this document.");
+        assert_eq!(detected.len(), 1);
+
+        // Forbidden context blocks the match
+        let detected = detector.detect_all("Our policy says: \"Please wait to be seated.\"");
+        assert!(detected.is_empty());
+
+        // Required context must be present
+        assert!(detector.detect_all("Guests kindly wait here.").is_empty());
+        assert_eq!(detector.detect_all("Could you kindly check this?").len(), 1);
+    }
+
+    #[test]
+    fn test_aggressive_only_pattern() {
+        let db = Database::in_memory().unwrap();
+        db.connection()
+            .unwrap()
+            .execute(
+                "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning, aggressive_only)
+                 VALUES ('filler', '(?i)\\bI think\\s+', '', 0.75, 'Hedge phrase', 1)",
+                [],
+            )
+            .unwrap();
+
+        let detector = DatabasePatternDetector::new(Arc::new(db)).unwrap();
+        let text = "I think the cache is stale.";
+
+        assert!(detector.detect_all(text).is_empty());
+        assert!(detector.detect_all_with_mode(text, false).is_empty());
+        assert_eq!(detector.detect_all_with_mode(text, true).len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_context_guard_reported() {
+        let db = Database::in_memory().unwrap();
+
+        db.connection()
+            .unwrap()
+            .execute(
+                "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning, context_guard)
+                 VALUES ('boilerplate', '(?i)please', '', 0.90, 'Guarded', '!(unclosed')",
+                [],
+            )
+            .unwrap();
+
+        let detector = DatabasePatternDetector::new(Arc::new(db)).unwrap();
+
+        assert_eq!(detector.pattern_count(), 0);
+        assert_eq!(detector.compile_errors().len(), 1);
+        assert!(detector.compile_errors()[0].error.starts_with("context guard"));
+    }
+
+    #[test]
+    fn test_concept_pattern_type_roundtrip() {
+        let db = Database::in_memory().unwrap();
+
+        db.connection()
+            .unwrap()
+            .execute(
+                "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
+                 VALUES ('concept', '(?i)\\bhospitalization\\b', 'admission', 0.90, 'Cheaper surface form')",
+                [],
+            )
+            .unwrap();
+
+        let db = Arc::new(db);
+        let records = db.load_patterns_by_type("concept").unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].pattern_type, "concept");
+
+        let detector = DatabasePatternDetector::new(db).unwrap();
+        let detected = detector.detect_all("Review the hospitalization notes.");
+
+        assert_eq!(detected.len(), 1);
+        assert_eq!(detected[0].pattern_type, OptimizationType::ConceptSubstitution);
+        assert_eq!(detected[0].optimized_text, "admission");
+    }
 }
@@ -0,0 +1,187 @@
+//! Span edits that turn a submitted prompt into its optimized form
+//!
+//! Thin clients that keep their own copy of the prompt apply these edits
+//! instead of replacing the whole text. Edits are derived by diffing the
+//! original and optimized prompts, so applying all of them reproduces the
+//! optimized prompt exactly, including whitespace cleanup, capitalization
+//! and the language directive.
+
+use crate::models::Optimization;
+use serde::{Deserialize, Serialize};
+
+/// Largest diff table (segments × segments) before falling back to one
+/// edit over the whole changed middle
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Replace bytes `start..end` of the submitted prompt with `replacement`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextEdit {
+    pub start: usize,
+    pub end: usize,
+    pub replacement: String,
+    /// Optimization that produced this edit; `None` for cleanup (whitespace,
+    /// capitalization), the language directive and later detection passes
+    pub optimization_id: Option<String>,
+}
+
+/// Edits turning `original` into `optimized`, sorted by position and
+/// non-overlapping
+pub fn compute_edits(original: &str, optimized: &str, optimizations: &[Optimization]) -> Vec<TextEdit> {
+    let a = segments(original);
+    let b = segments(optimized);
+
+    // Trim the common prefix and suffix before diffing the middle
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x.1 == y.1).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x.1 == y.1)
+        .count();
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+
+    let mut edits = Vec::new();
+    let a_pos = |i: usize| a.get(i).map_or(original.len(), |s| s.0);
+    let b_pos = |j: usize| b.get(j).map_or(optimized.len(), |s| s.0);
+    let mut push = |(ai, aj): (usize, usize), (bi, bj): (usize, usize)| {
+        if ai == aj && bi == bj {
+            return;
+        }
+        edits.push(TextEdit {
+            start: a_pos(ai),
+            end: a_pos(aj),
+            replacement: optimized[b_pos(bi)..b_pos(bj)].to_string(),
+            optimization_id: None,
+        });
+    };
+
+    if a_mid.len() * b_mid.len() > MAX_DIFF_CELLS {
+        push((prefix, a.len() - suffix), (prefix, b.len() - suffix));
+    } else {
+        // Longest common subsequence of segments, walked front to back
+        let (n, m) = (a_mid.len(), b_mid.len());
+        let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * (m + 1) + j] = if a_mid[i].1 == b_mid[j].1 {
+                    lcs[(i + 1) * (m + 1) + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        let (mut hunk_i, mut hunk_j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && a_mid[i].1 == b_mid[j].1 {
+                push((prefix + hunk_i, prefix + i), (prefix + hunk_j, prefix + j));
+                i += 1;
+                j += 1;
+                hunk_i = i;
+                hunk_j = j;
+            } else if j < m && (i == n || lcs[i * (m + 1) + j + 1] >= lcs[(i + 1) * (m + 1) + j]) {
+                j += 1;
+            } else {
+                i += 1;
+            }
+        }
+        push((prefix + hunk_i, prefix + n), (prefix + hunk_j, prefix + m));
+    }
+
+    // Credit each edit to the optimization whose span it touches; spans
+    // from later passes don't line up with the original and are skipped
+    let in_original: Vec<&Optimization> = optimizations
+        .iter()
+        .filter(|opt| original.get(opt.start_pos..opt.end_pos) == Some(opt.original_text.as_str()))
+        .collect();
+    for edit in &mut edits {
+        edit.optimization_id = in_original
+            .iter()
+            .find(|opt| opt.start_pos <= edit.end && edit.start <= opt.end_pos)
+            .map(|opt| opt.id.clone());
+    }
+
+    edits
+}
+
+/// Apply sorted, non-overlapping edits to `text`
+pub fn apply_edits(text: &str, edits: &[TextEdit]) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut last = 0;
+    for edit in edits {
+        result.push_str(&text[last..edit.start]);
+        result.push_str(&edit.replacement);
+        last = edit.end;
+    }
+    result.push_str(&text[last..]);
+    result
+}
+
+/// Split text into word runs, whitespace runs and single other chars,
+/// each with its byte offset
+fn segments(text: &str) -> Vec<(usize, &str)> {
+    #[derive(PartialEq)]
+    enum Kind {
+        Word,
+        Space,
+        Other,
+    }
+    let kind = |c: char| {
+        if c.is_alphanumeric() || c == '_' || c == '\'' {
+            Kind::Word
+        } else if c.is_whitespace() {
+            Kind::Space
+        } else {
+            Kind::Other
+        }
+    };
+
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut current: Option<Kind> = None;
+    for (pos, c) in text.char_indices() {
+        let k = kind(c);
+        let continues = matches!((&current, &k), (Some(Kind::Word), Kind::Word) | (Some(Kind::Space), Kind::Space));
+        if !continues {
+            if current.is_some() {
+                segments.push((start, &text[start..pos]));
+            }
+            start = pos;
+        }
+        current = Some(k);
+    }
+    if current.is_some() {
+        segments.push((start, &text[start..]));
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edits_reproduce_optimized_prompt() {
+        let cases = [
+            ("Please analyze this code.", "Analyze this code.\n\n[output_language: english]"),
+            ("I would like you to, in order to help, explain it.", "Explain it to help."),
+            ("unchanged", "unchanged"),
+            ("", "added"),
+            ("医院 hospital", "hospital"),
+        ];
+        for (original, optimized) in cases {
+            let edits = compute_edits(original, optimized, &[]);
+            assert_eq!(apply_edits(original, &edits), optimized, "{:?}", original);
+            assert!(edits.windows(2).all(|w| w[0].end <= w[1].start));
+        }
+
+        // Whole-word changes stay local
+        let edits = compute_edits("Please analyze this code.", "Analyze this code.", &[]);
+        assert_eq!(
+            edits.iter().map(|e| (e.start, e.end, e.replacement.as_str())).collect::<Vec<_>>(),
+            vec![(0, 14, "Analyze")]
+        );
+    }
+}
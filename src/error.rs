@@ -0,0 +1,113 @@
+//! Error types for the library
+//!
+//! Library functions return [`PromptCompressError`] so callers can match on
+//! the kind of failure (e.g. retry a locked database, reject a bad request);
+//! the binaries wrap it in `anyhow` and add file-level context.
+
+use thiserror::Error;
+
+/// Errors returned by the library
+#[derive(Debug, Error)]
+pub enum PromptCompressError {
+    /// SQLite failure (locked, missing table, constraint violation, ...)
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+
+    /// No pooled database connection came free in time
+    #[error("database pool error: {0}")]
+    Pool(#[from] r2d2::Error),
+
+    /// A pattern regex failed to compile
+    #[error("regex error: {0}")]
+    Regex(#[from] regex::Error),
+
+    /// A tokenizer failed to load, or failed to decode tokens
+    #[error("tokenizer error: {0}")]
+    Tokenizer(String),
+
+    /// Reading or writing a file failed
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// JSON, TOML or TSV input could not be parsed or written
+    #[error("serialization error: {0}")]
+    Serde(String),
+
+    /// The caller passed a value outside its valid range
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+
+    /// A concept, pattern or session does not exist
+    #[error("not found: {0}")]
+    NotFound(String),
+}
+
+impl PromptCompressError {
+    /// Stable machine-readable name of the error kind
+    pub fn code(&self) -> &'static str {
+        match self {
+            PromptCompressError::Database(_) | PromptCompressError::Pool(_) => "database",
+            PromptCompressError::Regex(_) => "regex",
+            PromptCompressError::Tokenizer(_) => "tokenizer",
+            PromptCompressError::Io(_) => "io",
+            PromptCompressError::Serde(_) => "serialization",
+            PromptCompressError::InvalidRequest(_) => "invalid_request",
+            PromptCompressError::NotFound(_) => "not_found",
+        }
+    }
+
+    /// Whether retrying the same call may succeed (the database was busy or
+    /// locked by another connection, or every pooled connection was in use)
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            PromptCompressError::Database(rusqlite::Error::SqliteFailure(e, _))
+                if matches!(
+                    e.code,
+                    rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked
+                )
+        ) || matches!(self, PromptCompressError::Pool(_))
+    }
+}
+
+impl From<serde_json::Error> for PromptCompressError {
+    fn from(e: serde_json::Error) -> Self {
+        PromptCompressError::Serde(e.to_string())
+    }
+}
+
+impl From<toml::de::Error> for PromptCompressError {
+    fn from(e: toml::de::Error) -> Self {
+        PromptCompressError::Serde(e.to_string())
+    }
+}
+
+/// Library result type
+pub type Result<T, E = PromptCompressError> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_kinds() {
+        let locked = PromptCompressError::from(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            None,
+        ));
+        assert!(matches!(locked, PromptCompressError::Database(_)));
+        assert!(locked.is_retryable());
+
+        let missing = PromptCompressError::from(rusqlite::Error::QueryReturnedNoRows);
+        assert!(!missing.is_retryable());
+
+        let unclosed = String::from("(");
+        let regex = PromptCompressError::from(regex::Regex::new(&unclosed).unwrap_err());
+        assert!(matches!(regex, PromptCompressError::Regex(_)));
+
+        let json = PromptCompressError::from(serde_json::from_str::<u32>("x").unwrap_err());
+        assert!(matches!(json, PromptCompressError::Serde(_)));
+        assert!(json.to_string().starts_with("serialization error:"));
+        assert_eq!(json.code(), "serialization");
+    }
+}
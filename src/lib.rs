@@ -1,8 +1,12 @@
 pub mod api;
 pub mod confidence;
+pub mod edits;
+pub mod error;
 pub mod models;
 pub mod optimizer;
 pub mod patterns;
+pub mod pattern_source;
+pub mod sentence;
 pub mod tokenizer;
 
 // Phase 3: Concept Atlas modules
@@ -11,27 +15,47 @@ pub mod database;
 pub mod concept_resolver;
 pub mod surface_selector;
 pub mod protected_regions;
+pub mod report;
 pub mod concept_optimizer;
 pub mod database_pattern_detector;
 pub mod database_optimizer;
+pub mod wikidata_import;
 
 pub use confidence::{extract_context, ConfidenceCalculator, Context};
+pub use edits::{apply_edits, TextEdit};
+pub use error::{PromptCompressError, Result};
 pub use models::{
-    Config, DirectiveFormat, Language, Optimization, OptimizationConfidence, OptimizationCorpus,
-    OptimizationRequest, OptimizationResult, OptimizationType, PatternStats, ReviewDecision,
-    ReviewSession,
+    ConfidenceBucket, ConfidenceSummary, Config, ContractionPolicy, DiffStats, DirectiveFormat, DirectivePlacement, FeedbackRecord, IngestStats, Language, Locale, Message, MessagesResult, Optimization, OptimizationConfidence, OptimizationCorpus,
+    OptimizationRequest, OptimizationRequestBuilder, OptimizationResult, OptimizationStep, OptimizationType, PatternStats, PatternsConfig, ReasoningStyle, RejectedOptimization,
+    RejectionReason, ReviewDecision, ReviewPolicy, ReviewRule, ReviewSession, SavingsFloor, StageTimings,
 };
 pub use optimizer::Optimizer;
-pub use patterns::{DetectedPattern, Pattern, PatternDetector};
+pub use patterns::{ContextGuard, DetectedPattern, Pattern, PatternDetector, PatternImpact, PatternMatchExample};
+pub use pattern_source::{
+    detect_with, detect_with_mode, detect_with_mode_indexed, FilePatternSource, LayeredSource, PatternSource,
+};
+pub use report::render_markdown;
+pub use sentence::SentenceBoundaries;
 pub use tokenizer::Tokenizer;
-pub use tokenizer_registry::{TokenizerBackend, TokenizerId, TokenizerRegistry};
-pub use database::{Concept, Database, DatabaseStats, SurfaceForm, PatternRecord, HitlDecision, PatternTypeStats};
+pub use tokenizer_registry::{
+    HuggingFaceBackend, TiktokenBackend, TokenizerBackend, TokenizerId, TokenizerRegistry,
+};
+pub use database::{
+    Concept, Database, DatabaseStats, DbConnection, HitlDecision, InefficientPattern, PatternAudit, PatternRecord,
+    PatternTypeStats, SurfaceForm,
+};
 pub use concept_resolver::{CacheStats, ConceptResolver, ResolutionPolicy};
 pub use surface_selector::{OptimizationCandidate, SelectionPolicy, SurfaceSelector};
-pub use protected_regions::{ProtectedRegion, ProtectedRegionDetector, ProtectionPolicy, RegionType};
-pub use concept_optimizer::{ConceptOptimizer, OptimizerStats};
-pub use database_pattern_detector::DatabasePatternDetector;
+pub use protected_regions::{
+    ProtectedRegion, ProtectedRegionDetector, ProtectedRegionSummary, ProtectionPolicy,
+    ProtectionReport, RegionType,
+};
+pub use concept_optimizer::{
+    seed_atlas, seed_concepts, ConceptOptimizer, ConceptSavings, CoverageReport, OptimizerStats, SimulationReport,
+};
+pub use database_pattern_detector::{DatabasePatternDetector, PatternCompileError};
 pub use database_optimizer::DatabaseOptimizer;
+pub use wikidata_import::{ImportStats, WikidataLabel};
 
 /// Library version
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -40,10 +64,18 @@ pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 ///
 /// **Deprecated**: Use `init_database_optimizer()` for production use.
 /// This function uses hardcoded patterns and is kept for backward compatibility.
-pub fn init_optimizer() -> anyhow::Result<Optimizer> {
+pub fn init_optimizer() -> Result<Optimizer> {
+    init_optimizer_with_config(&Config::default())
+}
+
+/// Initialize the optimizer from `config`
+///
+/// Priors come from `[bayesian] prior_corpus_path` once feedback has been
+/// saved there, and only the `[patterns]` families enabled are run.
+pub fn init_optimizer_with_config(config: &Config) -> Result<Optimizer> {
     let tokenizer = Tokenizer::new()?;
-    let calculator = ConfidenceCalculator::default();
-    Ok(Optimizer::new(calculator, tokenizer))
+    let calculator = ConfidenceCalculator::from_corpus_file(&config.bayesian.prior_corpus_path)?;
+    Ok(Optimizer::new(calculator, tokenizer).with_patterns_config(config.patterns.clone()))
 }
 
 /// Initialize the database-backed optimizer
@@ -60,7 +92,7 @@ pub fn init_optimizer() -> anyhow::Result<Optimizer> {
 ///
 /// let optimizer = init_database_optimizer("atlas.db").unwrap();
 /// ```
-pub fn init_database_optimizer(db_path: &str) -> anyhow::Result<DatabaseOptimizer> {
+pub fn init_database_optimizer(db_path: &str) -> Result<DatabaseOptimizer> {
     use std::sync::Arc;
 
     let db = Database::open(db_path)?;
@@ -76,7 +108,7 @@ pub fn init_database_optimizer(db_path: &str) -> anyhow::Result<DatabaseOptimize
 pub fn init_database_optimizer_with_confidence(
     db_path: &str,
     min_confidence: f64,
-) -> anyhow::Result<DatabaseOptimizer> {
+) -> Result<DatabaseOptimizer> {
     use std::sync::Arc;
 
     let db = Database::open(db_path)?;
@@ -87,16 +119,21 @@ pub fn init_database_optimizer_with_confidence(
 }
 
 /// Load corpus from file
-pub fn load_corpus(path: &str) -> anyhow::Result<OptimizationCorpus> {
+pub fn load_corpus(path: &str) -> Result<OptimizationCorpus> {
     let data = std::fs::read_to_string(path)?;
     let corpus: OptimizationCorpus = serde_json::from_str(&data)?;
     Ok(corpus)
 }
 
 /// Save corpus to file
-pub fn save_corpus(corpus: &OptimizationCorpus, path: &str) -> anyhow::Result<()> {
+///
+/// Written to a temporary file and renamed into place, so a crash mid-write
+/// leaves the previous corpus intact.
+pub fn save_corpus(corpus: &OptimizationCorpus, path: &str) -> Result<()> {
     let data = serde_json::to_string_pretty(corpus)?;
-    std::fs::write(path, data)?;
+    let tmp = format!("{}.tmp", path);
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)?;
     Ok(())
 }
 
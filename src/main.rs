@@ -1,9 +1,15 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use prompt_compress::{
-    init_optimizer, DirectiveFormat, Language, OptimizationRequest,
+    init_optimizer, init_optimizer_with_config, load_corpus, save_corpus, seed_atlas, ConceptOptimizer, Config, ContractionPolicy, CoverageReport, Database,
+    DatabasePatternDetector, DirectiveFormat, DirectivePlacement, HuggingFaceBackend, Language, Locale, OptimizationCorpus, OptimizationRequest, OptimizationType, PatternDetector,
+    ProtectionPolicy, ReasoningStyle, render_markdown, SavingsFloor, TokenizerId, TokenizerRegistry,
+    wikidata_import::import_labels_file,
 };
+use notify::{EventKind, RecursiveMode, Watcher};
 use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
 
 #[derive(Parser)]
 #[command(name = "prompt-compress")]
@@ -17,35 +23,7 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Optimize a prompt
-    Optimize {
-        /// Input file containing the prompt
-        #[arg(short, long)]
-        input: PathBuf,
-
-        /// Output file for optimized prompt
-        #[arg(short, long)]
-        output: Option<PathBuf>,
-
-        /// Output language (english or mandarin)
-        #[arg(long, default_value = "english")]
-        output_lang: String,
-
-        /// Confidence threshold (0.0-1.0)
-        #[arg(long, default_value = "0.85")]
-        threshold: f64,
-
-        /// Aggressive mode (lower threshold)
-        #[arg(long)]
-        aggressive: bool,
-
-        /// Directive format (bracketed, instructive, xml, natural)
-        #[arg(long, default_value = "bracketed")]
-        directive_format: String,
-
-        /// Interactive mode for HITL review
-        #[arg(long)]
-        interactive: bool,
-    },
+    Optimize(Box<OptimizeArgs>),
 
     /// Analyze prompt without optimizing
     Analyze {
@@ -56,13 +34,31 @@ enum Commands {
         /// Report output file
         #[arg(short, long)]
         report: Option<PathBuf>,
+
+        /// List protected regions (type, char range, snippet) and their tokens
+        #[arg(long)]
+        show_protected: bool,
+
+        /// Replay the edits in order with the token count after each
+        #[arg(long)]
+        steps: bool,
+
+        /// Report format: json, or markdown (which ignores --show-protected
+        /// and --steps)
+        #[arg(long, default_value = "json")]
+        format: String,
     },
 
     /// Update priors from feedback
+    #[command(args_conflicts_with_subcommands = true)]
     Train {
-        /// Feedback file (JSON)
-        #[arg(short, long)]
-        feedback: PathBuf,
+        #[command(subcommand)]
+        command: Option<TrainCommand>,
+
+        /// Feedback file (JSONL: one {original_text, accepted, token_savings}
+        /// decision per line)
+        #[arg(short, long, required = true)]
+        feedback: Option<PathBuf>,
 
         /// Corpus file to update
         #[arg(short, long, default_value = "data/priors.json")]
@@ -83,6 +79,342 @@ enum Commands {
         #[arg(long, default_value = "english")]
         output_lang: String,
     },
+
+    /// Compare savings of two configurations over the same prompts
+    Compare {
+        /// Prompt file or directory of .txt prompts (repeatable)
+        #[arg(short, long, required = true)]
+        input: Vec<PathBuf>,
+
+        /// First configuration (TOML)
+        #[arg(long)]
+        config_a: PathBuf,
+
+        /// Second configuration (TOML)
+        #[arg(long)]
+        config_b: PathBuf,
+
+        /// Also write per-file results as CSV
+        #[arg(long)]
+        csv: Option<PathBuf>,
+    },
+
+    /// Inspect database-backed patterns
+    Patterns {
+        #[command(subcommand)]
+        command: PatternsCommand,
+    },
+
+    /// Inspect the concept atlas
+    Atlas {
+        #[command(subcommand)]
+        command: AtlasCommand,
+    },
+
+    /// Check tokenizer backends
+    Tokenizer {
+        #[command(subcommand)]
+        command: TokenizerCommand,
+    },
+}
+
+#[derive(Args, Clone)]
+struct OptimizeArgs {
+    /// Input file containing the prompt
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Output file for optimized prompt
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Output language (english or mandarin)
+    #[arg(long, default_value = "english")]
+    output_lang: String,
+
+    /// Confidence threshold (0.0-1.0)
+    #[arg(long, default_value = "0.85")]
+    threshold: f64,
+
+    /// Aggressive mode (lower threshold)
+    #[arg(long)]
+    aggressive: bool,
+
+    /// Directive format (bracketed, instructive, xml, natural)
+    #[arg(long, default_value = "bracketed")]
+    directive_format: String,
+
+    /// Directive placement (append or prepend)
+    #[arg(long, default_value = "append")]
+    directive_placement: String,
+
+    /// Interactive mode for HITL review
+    #[arg(long)]
+    interactive: bool,
+
+    /// Preserve original casing (skip sentence capitalization)
+    #[arg(long)]
+    no_capitalize: bool,
+
+    /// Protection policy (conservative, or aggressive to also compress
+    /// identifiers and quoted strings)
+    #[arg(long, default_value = "conservative")]
+    protection: String,
+
+    /// When the directive outweighs the savings: flag, omit-directive, or
+    /// original
+    #[arg(long, default_value = "flag")]
+    savings_floor: String,
+
+    /// Rewrite contractions when cheaper: off, cheapest, expand, contract
+    #[arg(long, default_value = "off")]
+    contractions: String,
+
+    /// Print only the savings summary; neither show nor write the prompt
+    #[arg(long)]
+    stats_only: bool,
+
+    /// Report milliseconds spent in each optimizer stage
+    #[arg(long)]
+    profile: bool,
+
+    /// Unit, currency and number conventions (en-US, en-GB, de-DE)
+    #[arg(long, default_value = "en-US")]
+    locale: String,
+
+    /// Shorten verbose JSON keys ("configuration" → "config")
+    #[arg(long)]
+    rewrite_json_keys: bool,
+
+    /// Reasoning wording: verbose, or code for stable machine-readable codes
+    #[arg(long, default_value = "verbose")]
+    reasoning_style: String,
+
+    /// Literal text at the start of the prompt to keep verbatim
+    #[arg(long)]
+    protected_prefix: Option<String>,
+
+    /// Literal text at the end of the prompt (e.g. a footer) to keep verbatim
+    #[arg(long)]
+    protected_suffix: Option<String>,
+
+    /// Re-optimize whenever the input file changes (until Ctrl-C)
+    #[arg(long)]
+    watch: bool,
+
+    /// Summary format: text, or markdown for a shareable report
+    #[arg(long, default_value = "text")]
+    format: String,
+
+    /// Detect only these optimization types, comma-separated (boilerplate,
+    /// filler, instruction, synonym, whitespace, structural/format, mandarin,
+    /// concept)
+    #[arg(long, value_delimiter = ',')]
+    only_types: Vec<String>,
+}
+
+#[derive(Subcommand)]
+enum PatternsCommand {
+    /// List active patterns
+    List {
+        /// Pattern database
+        #[arg(long, default_value = "atlas.db")]
+        db: PathBuf,
+
+        /// Only show patterns not applied or accepted in this many days
+        #[arg(long)]
+        stale_days: Option<u64>,
+    },
+
+    /// Rank patterns by cumulative token savings
+    Top {
+        /// Pattern database
+        #[arg(long, default_value = "atlas.db")]
+        db: PathBuf,
+
+        /// Number of patterns to list
+        #[arg(long, default_value = "10")]
+        limit: usize,
+    },
+
+    /// Report patterns whose regex fails to compile
+    Validate {
+        /// Pattern database
+        #[arg(long, default_value = "atlas.db")]
+        db: PathBuf,
+
+        /// Load only the N highest-confidence patterns
+        #[arg(long)]
+        max_patterns: Option<usize>,
+    },
+
+    /// Report literal patterns whose replacement saves no tokens
+    Audit {
+        /// Pattern database
+        #[arg(long, default_value = "atlas.db")]
+        db: PathBuf,
+
+        /// Tokenizer to count with (cl100k_base, claude, llama3, r50k_base, p50k_base)
+        #[arg(long, default_value = "cl100k_base")]
+        tokenizer: String,
+
+        /// Also load a HuggingFace tokenizer.json (registered as llama3)
+        #[arg(long)]
+        tokenizer_json: Option<PathBuf>,
+    },
+
+    /// Measure a candidate pattern across prompts before adding it
+    TestRegex {
+        /// Regex to try
+        #[arg(long)]
+        regex: String,
+
+        /// Replacement (may reference capture groups as $1)
+        #[arg(long, default_value = "")]
+        replacement: String,
+
+        /// Prompt files or directories of .txt prompts
+        #[arg(short, long, required = true, num_args = 1..)]
+        input: Vec<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TrainCommand {
+    /// Combine corpora from several sources into one
+    Merge {
+        /// Corpus files to merge (JSON)
+        #[arg(required = true, num_args = 2..)]
+        corpora: Vec<PathBuf>,
+
+        /// Merged corpus output file
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum AtlasCommand {
+    /// Report how many prompt words the atlas covers
+    Coverage {
+        /// Prompt file or directory of .txt prompts (repeatable)
+        #[arg(short, long, required = true)]
+        input: Vec<PathBuf>,
+
+        /// Concept atlas database
+        #[arg(long, default_value = "atlas.db")]
+        db: PathBuf,
+    },
+
+    /// Project concept-substitution savings over a prompt corpus, without
+    /// changing any prompt
+    Simulate {
+        /// Prompt file or directory of .txt prompts (repeatable)
+        #[arg(short, long, required = true)]
+        input: Vec<PathBuf>,
+
+        /// Number of top contributing concepts to list
+        #[arg(long, default_value = "10")]
+        top: usize,
+
+        /// Concept atlas database
+        #[arg(long, default_value = "atlas.db")]
+        db: PathBuf,
+    },
+
+    /// Populate the atlas with the built-in starter concepts
+    Seed {
+        /// Concept atlas database (created if missing)
+        #[arg(long, default_value = "atlas.db")]
+        db: PathBuf,
+    },
+
+    /// Import multilingual labels from a Wikidata labels TSV
+    /// (qid, lang, label, optional description per line)
+    ImportWikidata {
+        /// Labels file
+        #[arg(long)]
+        labels: PathBuf,
+
+        /// Only import these Q-IDs (repeatable; default all)
+        #[arg(long)]
+        qid: Vec<String>,
+
+        /// Concept atlas database (created if missing)
+        #[arg(long, default_value = "atlas.db")]
+        db: PathBuf,
+    },
+
+    /// Print a concept and all of its surface forms, across tokenizers
+    Show {
+        /// Wikidata Q-ID
+        qid: String,
+
+        /// Concept atlas database
+        #[arg(long, default_value = "atlas.db")]
+        db: PathBuf,
+    },
+
+    /// Find concepts by label prefix or substring (case-insensitive)
+    Search {
+        /// Text to look for in labels and descriptions
+        query: String,
+
+        /// Maximum number of results
+        #[arg(long, default_value = "20")]
+        limit: usize,
+
+        /// Concept atlas database
+        #[arg(long, default_value = "atlas.db")]
+        db: PathBuf,
+    },
+
+    /// Find concepts sharing a label and merge them into one Q-ID
+    Dedup {
+        /// Concept atlas database
+        #[arg(long, default_value = "atlas.db")]
+        db: PathBuf,
+
+        /// Merge the duplicates (default only lists them)
+        #[arg(long)]
+        apply: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TokenizerCommand {
+    /// Report encode/decode round-trip mismatches per backend on a corpus
+    Verify {
+        /// Prompt file or directory of .txt prompts (repeatable)
+        #[arg(short, long, required = true)]
+        input: Vec<PathBuf>,
+
+        /// Also check a HuggingFace tokenizer.json (registered as llama3)
+        #[arg(long)]
+        tokenizer_json: Option<PathBuf>,
+
+        /// Maximum number of non-empty lines sampled from the corpus
+        #[arg(long, default_value = "1000")]
+        samples: usize,
+    },
+
+    /// Print the token count and decoded pieces of a string
+    Encode {
+        /// Text to encode
+        text: String,
+
+        /// Tokenizer (cl100k_base, claude, llama3, r50k_base, p50k_base)
+        #[arg(long, default_value = "cl100k_base")]
+        tokenizer: String,
+
+        /// Show every registered tokenizer instead of one
+        #[arg(long)]
+        compare: bool,
+
+        /// Also load a HuggingFace tokenizer.json (registered as llama3)
+        #[arg(long)]
+        tokenizer_json: Option<PathBuf>,
+    },
 }
 
 fn main() -> Result<()> {
@@ -91,31 +423,34 @@ fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Optimize {
+        Commands::Optimize(args) => {
+            if args.watch {
+                watch_command(*args)?;
+            } else {
+                optimize_command(*args)?;
+            }
+        }
+        Commands::Analyze {
             input,
-            output,
-            output_lang,
-            threshold,
-            aggressive,
-            directive_format,
-            interactive,
+            report,
+            show_protected,
+            steps,
+            format,
         } => {
-            optimize_command(
-                input,
-                output,
-                output_lang,
-                threshold,
-                aggressive,
-                directive_format,
-                interactive,
-            )?;
-        }
-        Commands::Analyze { input, report } => {
-            analyze_command(input, report)?;
-        }
-        Commands::Train { feedback, corpus } => {
-            train_command(feedback, corpus)?;
+            analyze_command(input, report, show_protected, steps, &format)?;
         }
+        Commands::Train {
+            command,
+            feedback,
+            corpus,
+        } => match command {
+            Some(TrainCommand::Merge { corpora, output }) => {
+                train_merge_command(corpora, output)?;
+            }
+            None => {
+                train_command(feedback.expect("clap requires --feedback"), corpus)?;
+            }
+        },
         Commands::Batch {
             input,
             output,
@@ -123,46 +458,198 @@ fn main() -> Result<()> {
         } => {
             batch_command(input, output, output_lang)?;
         }
+        Commands::Compare {
+            input,
+            config_a,
+            config_b,
+            csv,
+        } => {
+            compare_command(input, config_a, config_b, csv)?;
+        }
+        Commands::Patterns { command } => match command {
+            PatternsCommand::List { db, stale_days } => {
+                patterns_list_command(db, stale_days)?;
+            }
+            PatternsCommand::Top { db, limit } => {
+                patterns_top_command(db, limit)?;
+            }
+            PatternsCommand::Validate { db, max_patterns } => {
+                patterns_validate_command(db, max_patterns)?;
+            }
+            PatternsCommand::Audit {
+                db,
+                tokenizer,
+                tokenizer_json,
+            } => {
+                patterns_audit_command(db, tokenizer, tokenizer_json)?;
+            }
+            PatternsCommand::TestRegex {
+                regex,
+                replacement,
+                input,
+            } => {
+                patterns_test_regex_command(regex, replacement, input)?;
+            }
+        },
+        Commands::Atlas { command } => match command {
+            AtlasCommand::Coverage { input, db } => {
+                atlas_coverage_command(input, db)?;
+            }
+            AtlasCommand::Simulate { input, top, db } => {
+                atlas_simulate_command(input, top, db)?;
+            }
+            AtlasCommand::Seed { db } => {
+                atlas_seed_command(db)?;
+            }
+            AtlasCommand::ImportWikidata { labels, qid, db } => {
+                atlas_import_wikidata_command(labels, qid, db)?;
+            }
+            AtlasCommand::Show { qid, db } => {
+                atlas_show_command(qid, db)?;
+            }
+            AtlasCommand::Search { query, limit, db } => {
+                atlas_search_command(query, limit, db)?;
+            }
+            AtlasCommand::Dedup { db, apply } => {
+                atlas_dedup_command(db, apply)?;
+            }
+        },
+        Commands::Tokenizer { command } => match command {
+            TokenizerCommand::Verify {
+                input,
+                tokenizer_json,
+                samples,
+            } => {
+                tokenizer_verify_command(input, tokenizer_json, samples)?;
+            }
+            TokenizerCommand::Encode {
+                text,
+                tokenizer,
+                compare,
+                tokenizer_json,
+            } => {
+                tokenizer_encode_command(text, tokenizer, compare, tokenizer_json)?;
+            }
+        },
     }
 
     Ok(())
 }
 
-fn optimize_command(
-    input: PathBuf,
-    output: Option<PathBuf>,
-    output_lang: String,
-    threshold: f64,
-    aggressive: bool,
-    directive_format: String,
-    interactive: bool,
-) -> Result<()> {
+fn optimize_command(args: OptimizeArgs) -> Result<()> {
+    let OptimizeArgs {
+        input,
+        output,
+        output_lang,
+        threshold,
+        aggressive,
+        directive_format,
+        directive_placement,
+        interactive,
+        no_capitalize,
+        protection,
+        savings_floor,
+        contractions,
+        stats_only,
+        profile,
+        locale,
+        rewrite_json_keys,
+        reasoning_style,
+        protected_prefix,
+        protected_suffix,
+        watch: _,
+        format: report_format,
+        only_types,
+    } = args;
+
     let prompt = std::fs::read_to_string(&input)
         .with_context(|| format!("Failed to read input file: {:?}", input))?;
 
-    let language = match output_lang.to_lowercase().as_str() {
-        "mandarin" | "zh" => Language::Mandarin,
-        _ => Language::English,
+    let language: Language = output_lang.parse()?;
+    let format: DirectiveFormat = directive_format.parse()?;
+
+    let directive_placement = match directive_placement.to_lowercase().as_str() {
+        "prepend" => DirectivePlacement::Prepend,
+        _ => DirectivePlacement::Append,
+    };
+
+    let protection_policy = match protection.to_lowercase().as_str() {
+        "aggressive" => ProtectionPolicy::Aggressive,
+        _ => ProtectionPolicy::Conservative,
+    };
+
+    let savings_floor = match savings_floor.to_lowercase().as_str() {
+        "omit-directive" => SavingsFloor::OmitDirective,
+        "original" => SavingsFloor::Original,
+        _ => SavingsFloor::Flag,
+    };
+
+    let contraction_policy = match contractions.to_lowercase().as_str() {
+        "cheapest" => ContractionPolicy::Cheapest,
+        "expand" => ContractionPolicy::Expand,
+        "contract" => ContractionPolicy::Contract,
+        _ => ContractionPolicy::Off,
+    };
+
+    let locale = match locale.to_lowercase().as_str() {
+        "en-gb" | "en_gb" => Locale::EnGb,
+        "de-de" | "de_de" => Locale::DeDe,
+        _ => Locale::EnUs,
     };
 
-    let format = match directive_format.to_lowercase().as_str() {
-        "instructive" => DirectiveFormat::Instructive,
-        "xml" => DirectiveFormat::Xml,
-        "natural" => DirectiveFormat::Natural,
-        _ => DirectiveFormat::Bracketed,
+    let reasoning_style = match reasoning_style.to_lowercase().as_str() {
+        "code" => ReasoningStyle::Code,
+        _ => ReasoningStyle::Verbose,
     };
 
-    let request = OptimizationRequest {
-        prompt,
-        output_language: language,
-        confidence_threshold: threshold,
-        aggressive_mode: aggressive,
-        directive_format: format,
+    let only_types = if only_types.is_empty() {
+        None
+    } else {
+        Some(
+            only_types
+                .iter()
+                .map(|name| name.parse::<OptimizationType>())
+                .collect::<prompt_compress::Result<Vec<_>>>()?,
+        )
     };
 
-    let mut optimizer = init_optimizer()?;
+    let mut builder = OptimizationRequest::builder(prompt)
+        .output_language(language)
+        .confidence_threshold(threshold)
+        .aggressive_mode(aggressive)
+        .directive_format(format)
+        .directive_placement(directive_placement)
+        .preserve_case(no_capitalize)
+        .protection_policy(protection_policy)
+        .savings_floor(savings_floor)
+        .contraction_policy(contraction_policy)
+        .profile(profile)
+        .rewrite_json_keys(rewrite_json_keys)
+        .locale(locale)
+        .reasoning_style(reasoning_style);
+    if let Some(prefix) = protected_prefix {
+        builder = builder.protected_prefix(prefix);
+    }
+    if let Some(suffix) = protected_suffix {
+        builder = builder.protected_suffix(suffix);
+    }
+    if let Some(only_types) = only_types {
+        builder = builder.only_types(only_types);
+    }
+    let request = builder.build();
+
+    let optimizer = init_optimizer()?;
     let result = optimizer.optimize(&request)?;
 
+    if report_format.eq_ignore_ascii_case("markdown") {
+        print!("{}", render_markdown(&result));
+        if let Some(output_path) = output.filter(|_| !stats_only) {
+            std::fs::write(&output_path, &result.optimized_prompt)
+                .with_context(|| format!("Failed to write output file: {:?}", output_path))?;
+        }
+        return Ok(());
+    }
+
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
     println!("Optimization complete!");
     println!();
@@ -172,6 +659,27 @@ fn optimize_command(
         "Savings: {} tokens ({:.1}%)",
         result.token_savings, result.savings_percentage
     );
+    if result.directive_tokens > 0 {
+        println!(
+            "Directive: {} tokens (savings excluding it: {} tokens)",
+            result.directive_tokens, result.savings_excluding_directive
+        );
+    }
+    let diff = &result.diff_stats;
+    println!(
+        "Characters: -{} +{} ({} saved), lines: {} → {}",
+        diff.chars_removed,
+        diff.chars_added,
+        diff.chars_saved(),
+        diff.lines_before,
+        diff.lines_after
+    );
+    if result.net_savings_negative {
+        println!("⚠ Optimized prompt is larger than the original (see --savings-floor)");
+    }
+    for warning in &result.warnings {
+        println!("⚠ {}", warning);
+    }
     println!();
     println!(
         "Auto-applied optimizations: {}",
@@ -181,8 +689,42 @@ fn optimize_command(
         "Requires review: {}",
         result.requires_review.len()
     );
+    let confidence = &result.confidence_summary;
+    if let (true, Some(max)) = (result.optimizations.is_empty(), confidence.max_confidence_seen) {
+        println!("Max confidence seen: {:.2} (threshold {:.2})", max, threshold);
+        let buckets: Vec<String> = confidence
+            .distribution
+            .iter()
+            .map(|b| format!("{:.1}–{:.1}: {}", b.lower, b.upper, b.count))
+            .collect();
+        println!("Confidence distribution: {}", buckets.join(", "));
+        if let Some(suggested) = confidence.suggested_threshold {
+            println!(
+                "Hint: lower --threshold to {:.2} to auto-apply {} more",
+                suggested, confidence.unlocked_by_suggestion
+            );
+        }
+    }
+    if let Some(timings) = &result.profile {
+        println!();
+        println!("Stage timings:");
+        for (stage, ms) in [
+            ("detect", timings.detect_ms),
+            ("score", timings.score_ms),
+            ("resolve", timings.resolve_ms),
+            ("apply", timings.apply_ms),
+            ("directive", timings.directive_ms),
+        ] {
+            println!("  {:<10} {:>9.3} ms", stage, ms);
+        }
+        println!("  {:<10} {:>9.3} ms", "total", timings.total_ms());
+    }
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
 
+    if stats_only {
+        return Ok(());
+    }
+
     if interactive && !result.requires_review.is_empty() {
         println!("\nReview mode not yet implemented in CLI");
         println!("Use the API server for interactive review");
@@ -203,28 +745,117 @@ fn optimize_command(
     Ok(())
 }
 
-fn analyze_command(input: PathBuf, report: Option<PathBuf>) -> Result<()> {
+/// Quiet period that ends a burst of saves
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// How long a file replaced by an atomic save may stay missing
+const WATCH_MISSING_GRACE: Duration = Duration::from_secs(2);
+
+fn watch_command(args: OptimizeArgs) -> Result<()> {
+    let input = args.input.clone();
+    let file_name = input
+        .file_name()
+        .with_context(|| format!("Not a file: {:?}", input))?
+        .to_owned();
+    // Editors often save by writing a temp file and renaming it over the
+    // input, so watch the directory rather than the file itself
+    let dir = match input.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {:?}", dir))?;
+
+    // Reading the input ourselves raises access events; only edits count
+    let touches_input = |event: &notify::Result<notify::Event>| {
+        event.as_ref().is_ok_and(|event| {
+            !matches!(event.kind, EventKind::Access(_) | EventKind::Other)
+                && event.paths.iter().any(|path| path.file_name() == Some(file_name.as_os_str()))
+        })
+    };
+
+    println!("Watching {:?} (Ctrl-C to stop)", input);
+    if let Err(e) = optimize_command(args.clone()) {
+        eprintln!("✗ {:#}", e);
+    }
+
+    loop {
+        let event = rx.recv().context("File watcher stopped")?;
+        if !touches_input(&event) {
+            continue;
+        }
+
+        // An atomic save can leave the input briefly missing
+        let deadline = Instant::now() + WATCH_MISSING_GRACE;
+        while !input.exists() && Instant::now() < deadline {
+            std::thread::sleep(WATCH_DEBOUNCE);
+        }
+
+        // Debounce: wait until saves stop arriving
+        while rx.recv_timeout(WATCH_DEBOUNCE).is_ok() {}
+
+        if !input.exists() {
+            println!("⚠ {:?} is missing; waiting for it to come back", input);
+            continue;
+        }
+
+        println!("\n↻ {:?} changed", input);
+        if let Err(e) = optimize_command(args.clone()) {
+            eprintln!("✗ {:#}", e);
+        }
+    }
+}
+
+fn analyze_command(
+    input: PathBuf,
+    report: Option<PathBuf>,
+    show_protected: bool,
+    steps: bool,
+    format: &str,
+) -> Result<()> {
     let prompt = std::fs::read_to_string(&input)
         .with_context(|| format!("Failed to read input file: {:?}", input))?;
 
-    let request = OptimizationRequest {
-        prompt,
-        output_language: Language::English,
-        confidence_threshold: 0.85,
-        aggressive_mode: false,
-        directive_format: DirectiveFormat::Bracketed,
-    };
+    let request = OptimizationRequest::builder(prompt)
+        .output_language(Language::English)
+        .directive_format(DirectiveFormat::Bracketed)
+        .build();
 
-    let mut optimizer = init_optimizer()?;
+    let optimizer = init_optimizer()?;
     let result = optimizer.optimize(&request)?;
 
-    let analysis = serde_json::json!({
+    if format.eq_ignore_ascii_case("markdown") {
+        let markdown = render_markdown(&result);
+        if let Some(report_path) = report {
+            std::fs::write(&report_path, markdown)
+                .with_context(|| format!("Failed to write report: {:?}", report_path))?;
+            println!("Analysis report saved to: {:?}", report_path);
+        } else {
+            print!("{}", markdown);
+        }
+        return Ok(());
+    }
+
+    let mut analysis = serde_json::json!({
         "original_tokens": result.original_tokens,
         "potential_savings": result.token_savings,
+        "directive_tokens": result.directive_tokens,
+        "savings_excluding_directive": result.savings_excluding_directive,
         "savings_percentage": result.savings_percentage,
+        "compression_ratio": result.compression_ratio,
         "optimizations": result.optimizations,
         "requires_review": result.requires_review,
     });
+    if show_protected {
+        analysis["protected"] = serde_json::to_value(optimizer.protection_report(&request.prompt))?;
+    }
+    if steps {
+        analysis["steps"] = serde_json::to_value(optimizer.optimize_steps(&request)?)?;
+    }
 
     if let Some(report_path) = report {
         let json = serde_json::to_string_pretty(&analysis)?;
@@ -238,9 +869,62 @@ fn analyze_command(input: PathBuf, report: Option<PathBuf>) -> Result<()> {
     Ok(())
 }
 
-fn train_command(_feedback: PathBuf, _corpus: PathBuf) -> Result<()> {
-    println!("Training from feedback not yet implemented");
-    println!("Use the API server for interactive training");
+fn train_command(feedback: PathBuf, corpus_path: PathBuf) -> Result<()> {
+    if feedback.extension().and_then(|ext| ext.to_str()) != Some("jsonl") {
+        println!("Training from JSON feedback not yet implemented");
+        println!("Use a .jsonl feedback file, or the API server for interactive training");
+        return Ok(());
+    }
+
+    let mut corpus = if corpus_path.exists() {
+        load_corpus(&corpus_path.to_string_lossy())
+            .with_context(|| format!("Failed to load corpus: {:?}", corpus_path))?
+    } else {
+        OptimizationCorpus::default()
+    };
+
+    let file = std::fs::File::open(&feedback)
+        .with_context(|| format!("Failed to open feedback: {:?}", feedback))?;
+    let stats = corpus
+        .ingest_jsonl(std::io::BufReader::new(file))
+        .with_context(|| format!("Failed to read feedback: {:?}", feedback))?;
+
+    save_corpus(&corpus, &corpus_path.to_string_lossy())
+        .with_context(|| format!("Failed to write corpus: {:?}", corpus_path))?;
+
+    for (line, error) in &stats.skipped {
+        eprintln!("⚠ Feedback line {}: {}; skipped", line, error);
+    }
+    println!(
+        "✓ Trained on {} decisions ({} malformed lines skipped): {} patterns, {:.1}% success rate",
+        stats.applied,
+        stats.skipped.len(),
+        corpus.patterns.len(),
+        corpus.success_rate * 100.0
+    );
+    println!("  Written to {:?}", corpus_path);
+    Ok(())
+}
+
+fn train_merge_command(corpora: Vec<PathBuf>, output: PathBuf) -> Result<()> {
+    let mut merged = OptimizationCorpus::default();
+    for path in &corpora {
+        let corpus = load_corpus(&path.to_string_lossy())
+            .with_context(|| format!("Failed to load corpus: {:?}", path))?;
+        merged.merge(&corpus);
+    }
+
+    save_corpus(&merged, &output.to_string_lossy())
+        .with_context(|| format!("Failed to write corpus: {:?}", output))?;
+
+    println!(
+        "✓ Merged {} corpora: {} patterns, {} optimizations, {:.1}% success rate",
+        corpora.len(),
+        merged.patterns.len(),
+        merged.total_optimizations,
+        merged.success_rate * 100.0
+    );
+    println!("  Written to {:?}", output);
     Ok(())
 }
 
@@ -252,12 +936,9 @@ fn batch_command(input: PathBuf, output: PathBuf, output_lang: String) -> Result
     std::fs::create_dir_all(&output)
         .with_context(|| format!("Failed to create output directory: {:?}", output))?;
 
-    let language = match output_lang.to_lowercase().as_str() {
-        "mandarin" | "zh" => Language::Mandarin,
-        _ => Language::English,
-    };
+    let language: Language = output_lang.parse()?;
 
-    let mut optimizer = init_optimizer()?;
+    let optimizer = init_optimizer()?;
     let mut total_processed = 0;
     let mut total_savings = 0i64;
 
@@ -268,13 +949,10 @@ fn batch_command(input: PathBuf, output: PathBuf, output_lang: String) -> Result
         if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("txt") {
             let prompt = std::fs::read_to_string(&path)?;
 
-            let request = OptimizationRequest {
-                prompt,
-                output_language: language.clone(),
-                confidence_threshold: 0.85,
-                aggressive_mode: false,
-                directive_format: DirectiveFormat::Bracketed,
-            };
+            let request = OptimizationRequest::builder(prompt)
+                .output_language(language.clone())
+                .directive_format(DirectiveFormat::Bracketed)
+                .build();
 
             match optimizer.optimize(&request) {
                 Ok(result) => {
@@ -306,3 +984,606 @@ fn batch_command(input: PathBuf, output: PathBuf, output_lang: String) -> Result
 
     Ok(())
 }
+
+fn patterns_list_command(db_path: PathBuf, stale_days: Option<u64>) -> Result<()> {
+    let db = Database::open(&db_path)
+        .with_context(|| format!("Failed to open database at {:?}", db_path))?;
+
+    let patterns = match stale_days {
+        Some(days) => db.load_stale_patterns(days)?,
+        None => db.load_patterns()?,
+    };
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let age = |ts: Option<i64>| match ts {
+        Some(ts) => format!("{}d ago", (now - ts).max(0) / 86_400),
+        None => "never".to_string(),
+    };
+
+    println!(
+        "{:>5} {:<12} {:>6} {:>8} {:>8} {:>12} {:>12}  Pattern",
+        "ID", "Type", "Conf", "Applied", "Accepted", "Last applied", "Last accept"
+    );
+    for p in &patterns {
+        println!(
+            "{:>5} {:<12} {:>6.2} {:>8} {:>8} {:>12} {:>12}  {}",
+            p.id,
+            p.pattern_type,
+            p.base_confidence,
+            p.applied_count,
+            p.accepted_count,
+            age(p.last_applied_at),
+            age(p.last_accepted_at),
+            p.regex_pattern
+        );
+    }
+
+    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    match stale_days {
+        Some(days) => println!("Stale patterns (>{} days): {}", days, patterns.len()),
+        None => println!("Active patterns: {}", patterns.len()),
+    }
+
+    Ok(())
+}
+
+fn patterns_top_command(db_path: PathBuf, limit: usize) -> Result<()> {
+    let db = Database::open(&db_path)
+        .with_context(|| format!("Failed to open database at {:?}", db_path))?;
+    let patterns = db.top_patterns_by_savings(limit)?;
+
+    println!(
+        "{:>4} {:>5} {:<12} {:>8} {:>8} {:>8}  Pattern",
+        "Rank", "ID", "Type", "Saved", "Applied", "Avg"
+    );
+    for (rank, p) in patterns.iter().enumerate() {
+        println!(
+            "{:>4} {:>5} {:<12} {:>8} {:>8} {:>8.1}  {}",
+            rank + 1,
+            p.id,
+            p.pattern_type,
+            p.total_token_savings,
+            p.applied_count,
+            p.total_token_savings as f64 / p.applied_count.max(1) as f64,
+            p.regex_pattern
+        );
+    }
+
+    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    println!(
+        "Tokens saved by these patterns: {}",
+        patterns.iter().map(|p| p.total_token_savings).sum::<i64>()
+    );
+    Ok(())
+}
+
+fn patterns_validate_command(db_path: PathBuf, max_patterns: Option<usize>) -> Result<()> {
+    let db = Database::open(&db_path)
+        .with_context(|| format!("Failed to open database at {:?}", db_path))?;
+    let detector = match max_patterns {
+        Some(max) => DatabasePatternDetector::with_max_patterns(Arc::new(db), max)?,
+        None => DatabasePatternDetector::new(Arc::new(db))?,
+    };
+    let errors = detector.compile_errors();
+
+    for error in errors {
+        println!("✗ Pattern {}: {}", error.id, error.regex_pattern);
+        println!("    {}", error.error.replace('\n', "\n    "));
+    }
+
+    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    println!("Valid patterns: {}", detector.pattern_count());
+    println!("Invalid patterns: {}", errors.len());
+    if max_patterns.is_some() {
+        println!("Available patterns: {}", detector.available_pattern_count());
+    }
+
+    if !errors.is_empty() {
+        anyhow::bail!("{} pattern(s) failed to compile", errors.len());
+    }
+
+    Ok(())
+}
+
+fn patterns_audit_command(
+    db_path: PathBuf,
+    tokenizer: String,
+    tokenizer_json: Option<PathBuf>,
+) -> Result<()> {
+    let db = Database::open(&db_path)
+        .with_context(|| format!("Failed to open database at {:?}", db_path))?;
+    let registry = load_registry(tokenizer_json)?;
+    let id: TokenizerId = tokenizer.parse()?;
+    let Some(backend) = registry.get(id) else {
+        anyhow::bail!("Tokenizer not available: {} (pass --tokenizer-json for llama3)", id);
+    };
+
+    let audit = db.audit_patterns(backend.as_ref())?;
+    for pattern in &audit.inefficient {
+        println!(
+            "⚠ Pattern {}: {:?} → {:?} ({} → {} tokens on {:?})",
+            pattern.id,
+            pattern.regex_pattern,
+            pattern.replacement,
+            pattern.sample_tokens,
+            pattern.replacement_tokens,
+            pattern.sample
+        );
+    }
+
+    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    println!("Checked: {} literal patterns ({})", audit.checked, id);
+    println!("Skipped: {} (regex syntax or capture references)", audit.skipped);
+    println!("No savings: {}", audit.inefficient.len());
+
+    Ok(())
+}
+
+fn patterns_test_regex_command(regex: String, replacement: String, inputs: Vec<PathBuf>) -> Result<()> {
+    let files = collect_prompt_files(inputs)?;
+    let prompts = files
+        .iter()
+        .map(|path| {
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read input file: {:?}", path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let impact = PatternDetector::default().dry_run_pattern(&regex, &replacement, &prompts)?;
+
+    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    println!("Prompts matched: {} of {}", impact.prompts_matched, impact.prompts);
+    println!("Matches: {}", impact.match_count);
+    println!("Estimated token savings: {}", impact.token_savings);
+    if impact.overlapping_existing > 0 {
+        println!(
+            "⚠ {} match(es) overlap existing patterns",
+            impact.overlapping_existing
+        );
+    }
+
+    if !impact.examples.is_empty() {
+        println!();
+        println!("Examples:");
+        for example in &impact.examples {
+            println!(
+                "  {:?}: {:?} → {:?} ({} tokens)",
+                files[example.prompt_index], example.original_text, example.optimized_text, example.token_savings
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Expand directories into their .txt prompts (sorted)
+/// Savings of one configuration on one prompt
+struct CompareRun {
+    optimized_tokens: usize,
+    savings_percentage: f64,
+    review_items: usize,
+}
+
+fn compare_command(
+    inputs: Vec<PathBuf>,
+    config_a: PathBuf,
+    config_b: PathBuf,
+    csv: Option<PathBuf>,
+) -> Result<()> {
+    let load = |path: &PathBuf| {
+        Config::load(path).with_context(|| format!("Failed to load config file: {:?}", path))
+    };
+    let configs = [load(&config_a)?, load(&config_b)?];
+    let mut optimizers = configs
+        .iter()
+        .map(|config| Ok(init_optimizer_with_config(config)?))
+        .collect::<Result<Vec<_>>>()?;
+    let files = collect_prompt_files(inputs)?;
+
+    let mut rows = Vec::new();
+    for path in &files {
+        let prompt = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read input file: {:?}", path))?;
+
+        let mut original_tokens = 0;
+        let mut runs = Vec::new();
+        for (config, optimizer) in configs.iter().zip(optimizers.iter_mut()) {
+            let result = optimizer.optimize(&config.request(prompt.clone()))?;
+            original_tokens = result.original_tokens;
+            runs.push(CompareRun {
+                optimized_tokens: result.optimized_tokens,
+                savings_percentage: result.savings_percentage,
+                review_items: result.requires_review.len(),
+            });
+        }
+        rows.push((path, original_tokens, runs));
+    }
+
+    println!(
+        "{:<40} {:>8} {:>9} {:>9} {:>8}",
+        "file", "tokens", "A %", "B %", "Δ (B-A)"
+    );
+    for (path, original_tokens, runs) in &rows {
+        println!(
+            "{:<40} {:>8} {:>9.1} {:>9.1} {:>+8.1}",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            original_tokens,
+            runs[0].savings_percentage,
+            runs[1].savings_percentage,
+            runs[1].savings_percentage - runs[0].savings_percentage
+        );
+    }
+
+    println!();
+    println!("{:<16} {:>10} {:>10}", "", "A", "B");
+    let summaries: Vec<_> = (0..2)
+        .map(|i| {
+            let mut savings: Vec<f64> = rows.iter().map(|(_, _, runs)| runs[i].savings_percentage).collect();
+            let review: usize = rows.iter().map(|(_, _, runs)| runs[i].review_items).sum();
+            let tokens: usize = rows.iter().map(|(_, _, runs)| runs[i].optimized_tokens).sum();
+            (mean(&savings), median(&mut savings), review, tokens)
+        })
+        .collect();
+    println!("{:<16} {:>9.1}% {:>9.1}%", "mean savings", summaries[0].0, summaries[1].0);
+    println!("{:<16} {:>9.1}% {:>9.1}%", "median savings", summaries[0].1, summaries[1].1);
+    println!("{:<16} {:>10} {:>10}", "review items", summaries[0].2, summaries[1].2);
+    println!("{:<16} {:>10} {:>10}", "output tokens", summaries[0].3, summaries[1].3);
+    println!("\n{} prompts: A = {:?}, B = {:?}", rows.len(), config_a, config_b);
+
+    if let Some(csv_path) = csv {
+        let mut out = String::from(
+            "file,original_tokens,a_tokens,a_savings_pct,a_review,b_tokens,b_savings_pct,b_review,delta_pct\n",
+        );
+        for (path, original_tokens, runs) in &rows {
+            out.push_str(&format!(
+                "{},{},{},{:.2},{},{},{:.2},{},{:.2}\n",
+                path.display(),
+                original_tokens,
+                runs[0].optimized_tokens,
+                runs[0].savings_percentage,
+                runs[0].review_items,
+                runs[1].optimized_tokens,
+                runs[1].savings_percentage,
+                runs[1].review_items,
+                runs[1].savings_percentage - runs[0].savings_percentage
+            ));
+        }
+        std::fs::write(&csv_path, out)
+            .with_context(|| format!("Failed to write CSV file: {:?}", csv_path))?;
+        println!("Per-file results saved to: {:?}", csv_path);
+    }
+
+    Ok(())
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+fn collect_prompt_files(inputs: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            for entry in std::fs::read_dir(&input)? {
+                let path = entry?.path();
+                if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("txt") {
+                    files.push(path);
+                }
+            }
+        } else {
+            files.push(input);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn atlas_coverage_command(inputs: Vec<PathBuf>, db_path: PathBuf) -> Result<()> {
+    let db = Database::open(&db_path)
+        .with_context(|| format!("Failed to open database at {:?}", db_path))?;
+    let optimizer = ConceptOptimizer::new(Arc::new(db))?;
+    let files = collect_prompt_files(inputs)?;
+
+    let mut total = CoverageReport::default();
+    for path in &files {
+        let prompt = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read input file: {:?}", path))?;
+        let report = optimizer.coverage_report(&prompt)?;
+
+        println!(
+            "{:?}: {}/{} words resolved ({:.1}%), {} cheaper, {} tokens",
+            path,
+            report.resolved_words,
+            report.total_words,
+            report.coverage_percentage(),
+            report.words_with_cheaper_form,
+            report.potential_token_savings
+        );
+        total.merge(&report);
+    }
+
+    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    println!("Files: {}", files.len());
+    println!("Words: {}", total.total_words);
+    println!(
+        "Resolved to concepts: {} ({:.1}%)",
+        total.resolved_words,
+        total.coverage_percentage()
+    );
+    println!("With cheaper surface form: {}", total.words_with_cheaper_form);
+    println!("Potential token savings: {}", total.potential_token_savings);
+
+    Ok(())
+}
+
+fn atlas_simulate_command(inputs: Vec<PathBuf>, top: usize, db_path: PathBuf) -> Result<()> {
+    let db = Database::open(&db_path)
+        .with_context(|| format!("Failed to open database at {:?}", db_path))?;
+    let optimizer = ConceptOptimizer::new(Arc::new(db))?;
+    let files = collect_prompt_files(inputs)?;
+
+    let prompts = files
+        .iter()
+        .map(|path| {
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read input file: {:?}", path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let report = optimizer.simulate(&prompts)?;
+
+    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    println!("Prompts: {}", report.prompts);
+    println!("Tokens: {}", report.original_tokens);
+    println!("Substitutions: {}", report.substitutions);
+    println!(
+        "Potential token savings: {} ({:.1}%)",
+        report.potential_token_savings,
+        report.savings_percentage()
+    );
+
+    if !report.concepts.is_empty() {
+        println!();
+        println!("Top concepts:");
+        for concept in report.concepts.iter().take(top) {
+            println!(
+                "  {} {} → {}: {} swaps, {} tokens",
+                concept.qid, concept.label, concept.optimized_form, concept.substitutions, concept.token_savings
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn atlas_seed_command(db_path: PathBuf) -> Result<()> {
+    let db = Database::open(&db_path)
+        .with_context(|| format!("Failed to open database at {:?}", db_path))?;
+    let registry = TokenizerRegistry::new()?;
+
+    let (concepts, surface_forms) = seed_atlas(&db, &registry)?;
+
+    let mut tokenizers: Vec<&str> = registry.available().iter().map(|id| id.as_str()).collect();
+    tokenizers.sort();
+
+    println!("Seeded {:?}", db_path);
+    println!("Concepts: {}", concepts);
+    println!("Surface forms: {}", surface_forms);
+    println!("Tokenizers: {}", tokenizers.join(", "));
+
+    Ok(())
+}
+
+fn atlas_import_wikidata_command(labels: PathBuf, qids: Vec<String>, db_path: PathBuf) -> Result<()> {
+    let db = Database::open(&db_path)
+        .with_context(|| format!("Failed to open database at {:?}", db_path))?;
+    let registry = TokenizerRegistry::new()?;
+
+    let stats = import_labels_file(&db, &registry, &labels, &qids)
+        .with_context(|| format!("Failed to import labels file: {:?}", labels))?;
+
+    println!("Imported {:?} into {:?}", labels, db_path);
+    println!("Concepts: {}", stats.concepts);
+    println!("Surface forms: {}", stats.surface_forms);
+    if !stats.skipped.is_empty() {
+        println!("Skipped (no English label): {}", stats.skipped.join(", "));
+    }
+
+    Ok(())
+}
+
+fn atlas_show_command(qid: String, db_path: PathBuf) -> Result<()> {
+    let db = Database::open(&db_path)
+        .with_context(|| format!("Failed to open database at {:?}", db_path))?;
+    let concept = db
+        .get_concept(&qid)?
+        .with_context(|| format!("Concept {} not found in {:?}", qid, db_path))?;
+
+    println!("{}: {}", concept.qid, concept.label_en);
+    if let Some(description) = &concept.description {
+        println!("  {}", description);
+    }
+    if let Some(category) = &concept.category {
+        println!("  category: {}", category);
+    }
+    println!();
+
+    let forms = db.get_surface_forms_all(&qid)?;
+    if forms.is_empty() {
+        println!("No surface forms");
+        return Ok(());
+    }
+
+    println!("{:<14} {:<5} {:<24} {:>6} {:>6}", "tokenizer", "lang", "form", "tokens", "chars");
+    for form in &forms {
+        println!(
+            "{:<14} {:<5} {:<24} {:>6} {:>6}",
+            form.tokenizer_id, form.lang, form.form, form.token_count, form.char_count
+        );
+    }
+
+    Ok(())
+}
+
+fn atlas_search_command(query: String, limit: usize, db_path: PathBuf) -> Result<()> {
+    let db = Database::open(&db_path)
+        .with_context(|| format!("Failed to open database at {:?}", db_path))?;
+    let concepts = db.search_concepts(&query, limit)?;
+
+    if concepts.is_empty() {
+        println!("No concepts match {:?}", query);
+        return Ok(());
+    }
+
+    for concept in &concepts {
+        match &concept.description {
+            Some(description) => println!("{:<10} {} — {}", concept.qid, concept.label_en, description),
+            None => println!("{:<10} {}", concept.qid, concept.label_en),
+        }
+    }
+
+    Ok(())
+}
+
+fn atlas_dedup_command(db_path: PathBuf, apply: bool) -> Result<()> {
+    let db = Database::open(&db_path)
+        .with_context(|| format!("Failed to open database at {:?}", db_path))?;
+    let groups = db.find_duplicate_labels()?;
+
+    if groups.is_empty() {
+        println!("No duplicate labels found");
+        return Ok(());
+    }
+
+    let mut merged = 0;
+    for group in &groups {
+        // Keep the oldest Wikidata item (lowest numeric Q-ID)
+        let keep = group
+            .iter()
+            .min_by_key(|c| c.qid.trim_start_matches('Q').parse::<u64>().unwrap_or(u64::MAX))
+            .expect("duplicate groups are never empty");
+        let others: Vec<&str> = group
+            .iter()
+            .filter(|c| c.qid != keep.qid)
+            .map(|c| c.qid.as_str())
+            .collect();
+
+        println!("{:?}: keep {}, merge {}", keep.label_en, keep.qid, others.join(", "));
+
+        if apply {
+            merged += db.merge_concepts(&keep.qid, &others)?;
+        }
+    }
+
+    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    if apply {
+        println!("Merged {} concept(s) in {} group(s)", merged, groups.len());
+    } else {
+        println!("{} duplicate group(s); rerun with --apply to merge", groups.len());
+    }
+
+    Ok(())
+}
+
+fn tokenizer_verify_command(
+    inputs: Vec<PathBuf>,
+    tokenizer_json: Option<PathBuf>,
+    max_samples: usize,
+) -> Result<()> {
+    let registry = load_registry(tokenizer_json)?;
+
+    // Each non-empty line is one sample
+    let mut samples = Vec::new();
+    for path in collect_prompt_files(inputs)? {
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read input file: {:?}", path))?;
+        samples.extend(text.lines().filter(|l| !l.trim().is_empty()).map(str::to_string));
+        if samples.len() >= max_samples {
+            samples.truncate(max_samples);
+            break;
+        }
+    }
+
+    if samples.is_empty() {
+        anyhow::bail!("No samples found in input");
+    }
+
+    println!("Samples: {}", samples.len());
+    for id in TokenizerId::all() {
+        let Some(backend) = registry.get(id) else {
+            continue;
+        };
+
+        let mismatches = samples.iter().filter(|s| !backend.verify_roundtrip(s)).count();
+        println!(
+            "{:<12} {:>6} mismatches ({:.2}%)",
+            id.as_str(),
+            mismatches,
+            mismatches as f64 / samples.len() as f64 * 100.0
+        );
+    }
+
+    Ok(())
+}
+
+fn tokenizer_encode_command(
+    text: String,
+    tokenizer: String,
+    compare: bool,
+    tokenizer_json: Option<PathBuf>,
+) -> Result<()> {
+    let registry = load_registry(tokenizer_json)?;
+
+    let ids = if compare {
+        TokenizerId::all().into_iter().filter(|id| registry.has(*id)).collect()
+    } else {
+        let id: TokenizerId = tokenizer.parse()?;
+        if !registry.has(id) {
+            anyhow::bail!("Tokenizer not available: {} (pass --tokenizer-json for llama3)", id);
+        }
+        vec![id]
+    };
+
+    for id in ids {
+        let Some(backend) = registry.get(id) else {
+            continue;
+        };
+
+        let tokens = backend.encode(&text);
+        println!("{} ({} tokens)", id, tokens.len());
+        for token in tokens {
+            // A single token can be part of a multi-byte character
+            let piece = backend.decode(&[token]).unwrap_or_else(|_| "\u{fffd}".to_string());
+            println!("  {:>7}  {:?}", token, piece);
+        }
+    }
+
+    Ok(())
+}
+
+/// Built-in tokenizers plus an optional HuggingFace tokenizer.json as llama3
+fn load_registry(tokenizer_json: Option<PathBuf>) -> Result<TokenizerRegistry> {
+    let mut registry = TokenizerRegistry::new()?;
+    if let Some(path) = tokenizer_json {
+        let path = path.to_string_lossy();
+        registry.register_backend(Arc::new(HuggingFaceBackend::from_file(&path, TokenizerId::Llama3)?));
+    }
+    Ok(registry)
+}
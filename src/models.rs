@@ -1,38 +1,157 @@
+use crate::edits::{compute_edits, TextEdit};
+use crate::optimizer::apply_pass;
+use crate::protected_regions::{ProtectedRegionDetector, ProtectionPolicy};
+use crate::error::{PromptCompressError, Result};
+use crate::sentence::SentenceBoundaries;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::io::BufRead;
+use std::path::Path;
+use std::str::FromStr;
 
 /// Supported output languages for the optimized prompt
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum Language {
+    #[default]
     English,
     Mandarin,
 }
 
-impl Default for Language {
-    fn default() -> Self {
-        Language::English
-    }
-}
-
 /// Format for the output language directive
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum DirectiveFormat {
+    #[default]
     Bracketed,   // [output_language: english]
     Instructive, // "Respond in English."
     Xml,         // <output_language>english</output_language>
     Natural,     // "Please respond to me in English."
 }
 
-impl Default for DirectiveFormat {
-    fn default() -> Self {
-        DirectiveFormat::Bracketed
+impl FromStr for Language {
+    type Err = PromptCompressError;
+
+    /// Parse a language name or code, case-insensitively
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "english" | "en" => Ok(Self::English),
+            "mandarin" | "zh" | "chinese" => Ok(Self::Mandarin),
+            _ => Err(PromptCompressError::InvalidRequest(format!("Unknown output language: {}", s))),
+        }
+    }
+}
+
+impl FromStr for DirectiveFormat {
+    type Err = PromptCompressError;
+
+    /// Parse a directive format name, case-insensitively
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "bracketed" | "bracket" | "brackets" => Ok(Self::Bracketed),
+            "instructive" | "instruction" => Ok(Self::Instructive),
+            "xml" => Ok(Self::Xml),
+            "natural" => Ok(Self::Natural),
+            _ => Err(PromptCompressError::InvalidRequest(format!("Unknown directive format: {}", s))),
+        }
+    }
+}
+
+/// Where the output language directive goes relative to the prompt
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DirectivePlacement {
+    /// Directive leads the prompt
+    Prepend,
+    #[default]
+    Append,
+}
+
+/// Regional conventions for unit spellings, currency and number formats
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Locale {
+    #[default]
+    #[serde(rename = "en-US")]
+    EnUs,
+    /// British spellings ("kilometres", "per cent"); pounds sterling
+    #[serde(rename = "en-GB")]
+    EnGb,
+    /// German unit names, `1.000,5` numbers and trailing `€`
+    #[serde(rename = "de-DE")]
+    DeDe,
+}
+
+/// Whether to rewrite contractions ("don't" ⇄ "do not")
+///
+/// Any rewrite must save tokens with the live tokenizer.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContractionPolicy {
+    #[default]
+    Off,
+    /// Whichever direction is cheaper, per phrase
+    Cheapest,
+    /// Only expand contractions
+    Expand,
+    /// Only contract expanded forms
+    Contract,
+}
+
+/// What to do when the language directive costs more tokens than
+/// optimization saved
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SavingsFloor {
+    /// Keep the result and set `net_savings_negative`
+    #[default]
+    Flag,
+    /// Drop the directive (falls back to the original if still larger)
+    OmitDirective,
+    /// Return the original prompt unchanged
+    Original,
+}
+
+/// How `Optimization::reasoning` is worded
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningStyle {
+    /// The pattern's English explanation
+    #[default]
+    Verbose,
+    /// A stable machine-readable code, e.g. `BOILERPLATE_POLITENESS_FILLER`
+    Code,
+}
+
+impl ReasoningStyle {
+    /// Word `reasoning` (a pattern's explanation) in this style
+    pub fn render(self, optimization_type: &OptimizationType, reasoning: String) -> String {
+        match self {
+            ReasoningStyle::Verbose => reasoning,
+            ReasoningStyle::Code => reasoning_code(optimization_type, &reasoning),
+        }
     }
 }
 
+/// Stable code for a pattern's reasoning: the type's prefix followed by the
+/// explanation's words up to any parenthetical or colon detail, upper snake
+/// case. Words with digits (concept ids, token counts) are dropped so every
+/// match of a pattern gets the same code.
+pub fn reasoning_code(optimization_type: &OptimizationType, reasoning: &str) -> String {
+    let prefix = optimization_type.code_prefix();
+    let summary = reasoning.split(['(', ':']).next().unwrap_or_default();
+    let summary = summary.split(" - ").next().unwrap_or_default();
+
+    let words = summary
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty() && !word.contains(|c: char| c.is_ascii_digit()))
+        .map(str::to_ascii_uppercase)
+        .skip_while(|word| word == prefix);
+
+    std::iter::once(prefix.to_string()).chain(words).collect::<Vec<_>>().join("_")
+}
+
 /// Types of optimizations that can be applied
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[serde(rename_all = "snake_case")]
 pub enum OptimizationType {
     BoilerplateRemoval,
@@ -41,6 +160,111 @@ pub enum OptimizationType {
     InstructionCompression,
     MandarinSubstitution,
     FormatConsolidation,
+    /// Word swapped for a cheaper surface form of the same concept
+    ConceptSubstitution,
+    /// Collapsed runs of newlines, spaces and repeated punctuation
+    Whitespace,
+}
+
+impl OptimizationType {
+    /// Leading segment of this type's reasoning codes
+    pub fn code_prefix(&self) -> &'static str {
+        match self {
+            OptimizationType::BoilerplateRemoval => "BOILERPLATE",
+            OptimizationType::SynonymConsolidation => "SYNONYM",
+            OptimizationType::FillerRemoval => "FILLER",
+            OptimizationType::InstructionCompression => "INSTRUCTION",
+            OptimizationType::MandarinSubstitution => "MANDARIN",
+            OptimizationType::FormatConsolidation => "FORMAT",
+            OptimizationType::ConceptSubstitution => "CONCEPT",
+            OptimizationType::Whitespace => "WHITESPACE",
+        }
+    }
+}
+
+impl FromStr for OptimizationType {
+    type Err = PromptCompressError;
+
+    /// Parse a type's snake_case name or its short form ("filler",
+    /// "structural"), case-insensitively
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().replace('-', "_").as_str() {
+            "boilerplate" | "boilerplate_removal" => Ok(Self::BoilerplateRemoval),
+            "synonym" | "synonym_consolidation" => Ok(Self::SynonymConsolidation),
+            "filler" | "filler_removal" => Ok(Self::FillerRemoval),
+            "instruction" | "instruction_compression" => Ok(Self::InstructionCompression),
+            "mandarin" | "mandarin_substitution" => Ok(Self::MandarinSubstitution),
+            "format" | "structural" | "format_consolidation" => Ok(Self::FormatConsolidation),
+            "concept" | "concept_substitution" => Ok(Self::ConceptSubstitution),
+            "whitespace" => Ok(Self::Whitespace),
+            _ => Err(PromptCompressError::InvalidRequest(format!("Unknown optimization type: {}", s))),
+        }
+    }
+}
+
+/// How optimizations of one type are routed between auto-apply and review
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewRule {
+    /// Review when confidence is below the auto-apply threshold
+    Threshold,
+    /// Auto-apply once past the minimum confidence (structural edits)
+    AutoApply,
+    /// Always review, however confident
+    AlwaysReview,
+}
+
+/// Per-type review rules; types without a rule use `ReviewRule::Threshold`
+///
+/// The default auto-applies whitespace and format consolidation and always
+/// reviews Mandarin substitution. Ordered so requests serialize (and
+/// fingerprint) stably.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct ReviewPolicy(pub BTreeMap<OptimizationType, ReviewRule>);
+
+impl Default for ReviewPolicy {
+    fn default() -> Self {
+        Self(BTreeMap::from([
+            (OptimizationType::FormatConsolidation, ReviewRule::AutoApply),
+            (OptimizationType::Whitespace, ReviewRule::AutoApply),
+            (OptimizationType::MandarinSubstitution, ReviewRule::AlwaysReview),
+        ]))
+    }
+}
+
+impl ReviewPolicy {
+    /// Route every type by the numeric threshold alone
+    pub fn threshold_only() -> Self {
+        Self(BTreeMap::new())
+    }
+
+    /// Set the rule for one type
+    pub fn with_rule(mut self, optimization_type: OptimizationType, rule: ReviewRule) -> Self {
+        self.0.insert(optimization_type, rule);
+        self
+    }
+
+    pub fn rule(&self, optimization_type: &OptimizationType) -> ReviewRule {
+        self.0
+            .get(optimization_type)
+            .copied()
+            .unwrap_or(ReviewRule::Threshold)
+    }
+
+    /// Whether an optimization needs review under this policy
+    pub fn requires_review(
+        &self,
+        optimization_type: &OptimizationType,
+        confidence: f64,
+        auto_apply_threshold: f64,
+    ) -> bool {
+        match self.rule(optimization_type) {
+            ReviewRule::Threshold => confidence < auto_apply_threshold,
+            ReviewRule::AutoApply => false,
+            ReviewRule::AlwaysReview => true,
+        }
+    }
 }
 
 /// Bayesian confidence breakdown for an optimization
@@ -89,10 +313,64 @@ pub struct Optimization {
     pub reasoning: String,
     pub start_pos: usize,
     pub end_pos: usize,
+    /// Detection pass that found this optimization; positions from later
+    /// passes refer to the text left by the earlier ones
+    #[serde(default)]
+    pub pass: usize,
+}
+
+/// Why a detected optimization was not offered
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectionReason {
+    /// Final confidence below the mode's minimum
+    BelowMinConfidence,
+    /// Replacement does not save tokens
+    NoSavings,
+    /// Overlapped a better optimization
+    ConflictLoser,
+    /// Overlapped a protected region
+    Protected,
+    /// Pure removal dropped by safe mode
+    SafeMode,
+}
+
+/// A detected optimization that was dropped, with the reason
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectedOptimization {
+    pub optimization_type: OptimizationType,
+    pub original_text: String,
+    pub optimized_text: String,
+    pub token_savings: i64,
+    pub confidence: OptimizationConfidence,
+    pub reasoning: String,
+    pub start_pos: usize,
+    pub end_pos: usize,
+    pub reason: RejectionReason,
+}
+
+impl RejectedOptimization {
+    pub fn new(optimization: Optimization, reason: RejectionReason) -> Self {
+        Self {
+            optimization_type: optimization.optimization_type,
+            original_text: optimization.original_text,
+            optimized_text: optimization.optimized_text,
+            token_savings: optimization.token_savings,
+            confidence: optimization.confidence,
+            reasoning: optimization.reasoning,
+            start_pos: optimization.start_pos,
+            end_pos: optimization.end_pos,
+            reason,
+        }
+    }
 }
 
 /// Request to optimize a prompt
+///
+/// Build one with [`OptimizationRequest::builder`]; the struct is
+/// `#[non_exhaustive]` so new options don't break callers.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct OptimizationRequest {
     pub prompt: String,
     pub output_language: Language,
@@ -102,24 +380,649 @@ pub struct OptimizationRequest {
     pub aggressive_mode: bool,
     #[serde(default)]
     pub directive_format: DirectiveFormat,
+    #[serde(default)]
+    pub directive_placement: DirectivePlacement,
+    /// Text between the prompt and the directive
+    #[serde(default = "default_directive_separator")]
+    pub directive_separator: String,
+    /// Trim the prompt before adding the directive; when false its leading
+    /// and trailing whitespace is kept outside the joined text (so a
+    /// required trailing newline stays last)
+    #[serde(default = "default_directive_trim")]
+    pub directive_trim: bool,
+    /// Add the output language directive at all
+    #[serde(default = "default_language_directive")]
+    pub language_directive: bool,
+    /// Leave `role: "system"` messages untouched in
+    /// `Optimizer::optimize_messages`
+    #[serde(default)]
+    pub skip_system_messages: bool,
+    /// Protect example/quoted user text (blockquotes, "example:", "e.g.")
+    #[serde(default)]
+    pub preserve_first_person: bool,
+    /// Never delete text: only apply optimizations that rewrite/shorten
+    #[serde(default)]
+    pub safe_mode: bool,
+    /// Report dropped optimizations in `OptimizationResult::rejected`
+    #[serde(default)]
+    pub include_rejected: bool,
+    /// Keep original casing (skip sentence capitalization)
+    #[serde(default)]
+    pub preserve_case: bool,
+    /// Which regions are off-limits; aggressive leaves identifiers and
+    /// quoted strings compressible
+    #[serde(default)]
+    pub protection_policy: ProtectionPolicy,
+    /// Guard against a result larger than the original prompt
+    #[serde(default)]
+    pub savings_floor: SavingsFloor,
+    /// Per-type overrides of the auto-apply threshold
+    #[serde(default)]
+    pub review_policy: ReviewPolicy,
+    /// Rewrite contractions when that saves tokens
+    #[serde(default)]
+    pub contraction_policy: ContractionPolicy,
+    /// Time each pipeline stage into `OptimizationResult::profile`
+    #[serde(default)]
+    pub profile: bool,
+    /// Literal text at the start of the prompt to keep byte-for-byte
+    #[serde(default)]
+    pub protected_prefix: Option<String>,
+    /// Literal text at the end of the prompt (e.g. a signature or footer) to
+    /// keep byte-for-byte; the directive is placed before it
+    #[serde(default)]
+    pub protected_suffix: Option<String>,
+    /// Shorten verbose JSON keys ("description" → "desc"); off by default
+    /// since a model parsing real JSON needs the exact keys
+    #[serde(default)]
+    pub rewrite_json_keys: bool,
+    /// Which unit, currency and number rules apply
+    #[serde(default)]
+    pub locale: Locale,
+    /// Report `reasoning` as prose or as stable codes
+    #[serde(default)]
+    pub reasoning_style: ReasoningStyle,
+    /// Concepts `ConceptOptimizer` must leave alone, named by the word, the
+    /// concept's English label or its QID (case-insensitive)
+    #[serde(default)]
+    pub concept_denylist: Vec<String>,
+    /// When set, the only concepts `ConceptOptimizer` may substitute (named
+    /// the same way); the denylist still wins
+    #[serde(default)]
+    pub concept_allowlist: Option<Vec<String>>,
+    /// When set, detect only these optimization types (e.g. just format
+    /// consolidation to clean up whitespace and units)
+    #[serde(default)]
+    pub only_types: Option<Vec<OptimizationType>>,
 }
 
 fn default_confidence_threshold() -> f64 {
     0.85
 }
 
+fn default_directive_separator() -> String {
+    "\n\n".to_string()
+}
+
+fn default_directive_trim() -> bool {
+    true
+}
+
+fn default_language_directive() -> bool {
+    true
+}
+
+impl Default for OptimizationRequest {
+    fn default() -> Self {
+        Self {
+            prompt: String::new(),
+            output_language: Language::default(),
+            confidence_threshold: default_confidence_threshold(),
+            aggressive_mode: false,
+            directive_format: DirectiveFormat::default(),
+            directive_placement: DirectivePlacement::default(),
+            directive_separator: default_directive_separator(),
+            directive_trim: default_directive_trim(),
+            language_directive: default_language_directive(),
+            skip_system_messages: false,
+            preserve_first_person: false,
+            safe_mode: false,
+            include_rejected: false,
+            preserve_case: false,
+            protection_policy: ProtectionPolicy::default(),
+            savings_floor: SavingsFloor::default(),
+            review_policy: ReviewPolicy::default(),
+            contraction_policy: ContractionPolicy::default(),
+            profile: false,
+            protected_prefix: None,
+            protected_suffix: None,
+            rewrite_json_keys: false,
+            locale: Locale::default(),
+            reasoning_style: ReasoningStyle::default(),
+            concept_denylist: Vec::new(),
+            concept_allowlist: None,
+            only_types: None,
+        }
+    }
+}
+
+impl OptimizationRequest {
+    /// Start a request for `prompt`, with every other option at its default
+    pub fn builder(prompt: impl Into<String>) -> OptimizationRequestBuilder {
+        OptimizationRequestBuilder {
+            request: Self {
+                prompt: prompt.into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Reject values the optimizers cannot act on
+    ///
+    /// Thresholds outside [0, 1] (or NaN) would silently review everything
+    /// or nothing, and a blank prompt has nothing to optimize.
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0..=1.0).contains(&self.confidence_threshold) {
+            return Err(PromptCompressError::InvalidRequest(format!(
+                "confidence_threshold must be between 0 and 1, got {}",
+                self.confidence_threshold
+            )));
+        }
+        if self.prompt.trim().is_empty() {
+            return Err(PromptCompressError::InvalidRequest(
+                "prompt must not be empty".to_string(),
+            ));
+        }
+        if self.only_types.as_ref().is_some_and(Vec::is_empty) {
+            return Err(PromptCompressError::InvalidRequest(
+                "only_types must name at least one optimization type".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether optimizations of `optimization_type` may be detected at all
+    pub fn allows_type(&self, optimization_type: &OptimizationType) -> bool {
+        self.only_types
+            .as_ref()
+            .is_none_or(|types| types.contains(optimization_type))
+    }
+
+    /// Whether `ConceptOptimizer` may substitute `word`, resolved to the
+    /// concept `qid` labelled `label`
+    pub fn allows_concept(&self, word: &str, label: &str, qid: &str) -> bool {
+        let names = |entry: &String| {
+            let entry = entry.trim();
+            [word, label, qid].iter().any(|name| name.trim().eq_ignore_ascii_case(entry))
+        };
+        !self.concept_denylist.iter().any(names)
+            && self
+                .concept_allowlist
+                .as_ref()
+                .is_none_or(|allowlist| allowlist.iter().any(names))
+    }
+}
+
+/// Builder for [`OptimizationRequest`], started by
+/// [`OptimizationRequest::builder`]
+#[derive(Debug, Clone)]
+pub struct OptimizationRequestBuilder {
+    request: OptimizationRequest,
+}
+
+impl OptimizationRequestBuilder {
+    /// Language the directive asks for
+    pub fn output_language(mut self, output_language: Language) -> Self {
+        self.request.output_language = output_language;
+        self
+    }
+
+    /// Auto-apply threshold (0.85 by default)
+    pub fn confidence_threshold(mut self, confidence_threshold: f64) -> Self {
+        self.request.confidence_threshold = confidence_threshold;
+        self
+    }
+
+    /// Lower the confidence bars
+    pub fn aggressive_mode(mut self, aggressive_mode: bool) -> Self {
+        self.request.aggressive_mode = aggressive_mode;
+        self
+    }
+
+    /// How the language directive is written
+    pub fn directive_format(mut self, directive_format: DirectiveFormat) -> Self {
+        self.request.directive_format = directive_format;
+        self
+    }
+
+    /// Where the language directive goes
+    pub fn directive_placement(mut self, directive_placement: DirectivePlacement) -> Self {
+        self.request.directive_placement = directive_placement;
+        self
+    }
+
+    /// Text between the prompt and the directive ("\n\n" by default)
+    pub fn directive_separator(mut self, separator: impl Into<String>) -> Self {
+        self.request.directive_separator = separator.into();
+        self
+    }
+
+    /// Whether to trim the prompt before adding the directive
+    pub fn directive_trim(mut self, directive_trim: bool) -> Self {
+        self.request.directive_trim = directive_trim;
+        self
+    }
+
+    /// Whether to add the output language directive
+    pub fn language_directive(mut self, language_directive: bool) -> Self {
+        self.request.language_directive = language_directive;
+        self
+    }
+
+    /// Leave system messages untouched in `Optimizer::optimize_messages`
+    pub fn skip_system_messages(mut self, skip_system_messages: bool) -> Self {
+        self.request.skip_system_messages = skip_system_messages;
+        self
+    }
+
+    /// Protect example/quoted user text
+    pub fn preserve_first_person(mut self, preserve_first_person: bool) -> Self {
+        self.request.preserve_first_person = preserve_first_person;
+        self
+    }
+
+    /// Only rewrite or shorten, never delete
+    pub fn safe_mode(mut self, safe_mode: bool) -> Self {
+        self.request.safe_mode = safe_mode;
+        self
+    }
+
+    /// Report dropped optimizations
+    pub fn include_rejected(mut self, include_rejected: bool) -> Self {
+        self.request.include_rejected = include_rejected;
+        self
+    }
+
+    /// Skip sentence capitalization
+    pub fn preserve_case(mut self, preserve_case: bool) -> Self {
+        self.request.preserve_case = preserve_case;
+        self
+    }
+
+    /// Which regions are off-limits
+    pub fn protection_policy(mut self, protection_policy: ProtectionPolicy) -> Self {
+        self.request.protection_policy = protection_policy;
+        self
+    }
+
+    /// Guard against a result larger than the original
+    pub fn savings_floor(mut self, savings_floor: SavingsFloor) -> Self {
+        self.request.savings_floor = savings_floor;
+        self
+    }
+
+    /// Per-type auto-apply thresholds
+    pub fn review_policy(mut self, review_policy: ReviewPolicy) -> Self {
+        self.request.review_policy = review_policy;
+        self
+    }
+
+    /// Rewrite contractions when that saves tokens
+    pub fn contraction_policy(mut self, contraction_policy: ContractionPolicy) -> Self {
+        self.request.contraction_policy = contraction_policy;
+        self
+    }
+
+    /// Time each pipeline stage
+    pub fn profile(mut self, profile: bool) -> Self {
+        self.request.profile = profile;
+        self
+    }
+
+    /// Shorten verbose JSON keys
+    pub fn rewrite_json_keys(mut self, rewrite_json_keys: bool) -> Self {
+        self.request.rewrite_json_keys = rewrite_json_keys;
+        self
+    }
+
+    /// Which unit, currency and number rules apply
+    pub fn locale(mut self, locale: Locale) -> Self {
+        self.request.locale = locale;
+        self
+    }
+
+    /// Report `reasoning` as prose or codes
+    pub fn reasoning_style(mut self, reasoning_style: ReasoningStyle) -> Self {
+        self.request.reasoning_style = reasoning_style;
+        self
+    }
+
+    /// Literal text at the start of the prompt to keep byte-for-byte
+    pub fn protected_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.request.protected_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Literal text at the end of the prompt to keep byte-for-byte
+    pub fn protected_suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.request.protected_suffix = Some(suffix.into());
+        self
+    }
+
+    /// Concepts `ConceptOptimizer` must leave alone
+    pub fn concept_denylist<I, S>(mut self, concepts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.request.concept_denylist = concepts.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The only concepts `ConceptOptimizer` may substitute
+    pub fn concept_allowlist<I, S>(mut self, concepts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.request.concept_allowlist = Some(concepts.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Detect only these optimization types
+    pub fn only_types(mut self, types: impl IntoIterator<Item = OptimizationType>) -> Self {
+        self.request.only_types = Some(types.into_iter().collect());
+        self
+    }
+
+    /// Finish the request (checked by the optimizer, see
+    /// [`OptimizationRequest::validate`])
+    pub fn build(self) -> OptimizationRequest {
+        self.request
+    }
+}
+
+/// One chat message, as sent to chat completion APIs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Message {
+    /// "system", "user", "assistant", ...
+    pub role: String,
+    pub content: String,
+}
+
+impl Message {
+    pub fn new(role: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            role: role.into(),
+            content: content.into(),
+        }
+    }
+}
+
+/// Result of `Optimizer::optimize_messages`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessagesResult {
+    /// Optimized messages in the original order and roles, plus a leading
+    /// system message carrying the directive when there was no user message
+    pub messages: Vec<Message>,
+    /// Per-message results, aligned with `messages`; None where a message
+    /// was left untouched (blank, a skipped system message, or the added one)
+    pub results: Vec<Option<OptimizationResult>>,
+    pub original_tokens: usize,
+    pub optimized_tokens: usize,
+    pub token_savings: i64,
+    /// Tokens the language directive adds to `optimized_tokens`
+    pub directive_tokens: usize,
+    pub savings_percentage: f64,
+    pub compression_ratio: f64,
+}
+
 /// Result of optimizing a prompt
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OptimizationResult {
     pub original_prompt: String,
     pub optimized_prompt: String,
     pub original_tokens: usize,
     pub optimized_tokens: usize,
     pub token_savings: i64,
+    /// Tokens the language directive adds to `optimized_tokens` (0 when no
+    /// directive was added)
+    #[serde(default)]
+    pub directive_tokens: usize,
+    /// Same as `token_savings`: the directive counts against the savings
+    #[serde(default)]
+    pub savings_including_directive: i64,
+    /// Savings when the directive is treated as free, since it replaces a
+    /// manual instruction (`token_savings + directive_tokens`)
+    #[serde(default)]
+    pub savings_excluding_directive: i64,
+    /// Share of the original tokens saved, clamped to [-100, 100]; negative
+    /// when the prompt grew (e.g. a directive on a short prompt), 0.0 for an
+    /// empty original
     pub savings_percentage: f64,
+    /// `optimized_tokens / original_tokens` (1.0 for an empty original);
+    /// above 1.0 the prompt grew
+    #[serde(default = "default_compression_ratio")]
+    pub compression_ratio: f64,
     pub optimizations: Vec<Optimization>,
     pub requires_review: Vec<Optimization>,
     pub output_language: Language,
+    /// Dropped optimizations (only populated with `include_rejected`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rejected: Vec<RejectedOptimization>,
+    /// Problems that made the optimizer drop the directive or fall back to
+    /// the original prompt
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// The returned prompt has more tokens than the original
+    #[serde(default)]
+    pub net_savings_negative: bool,
+    /// Per-stage timings (only populated with `profile`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<StageTimings>,
+    /// Character and line deltas between original and optimized prompt
+    #[serde(default)]
+    pub diff_stats: DiffStats,
+    /// `apply_to` can re-derive `optimized_prompt` from `original_prompt`:
+    /// false when a directive was added, sentence starts were left as is,
+    /// ends of the prompt were protected, or protection went beyond the
+    /// default regions
+    #[serde(default)]
+    pub replayable: bool,
+    /// Confidence of the offered optimizations, for tuning the threshold
+    #[serde(default)]
+    pub confidence_summary: ConfidenceSummary,
+}
+
+/// Character and line changes between two versions of a prompt
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffStats {
+    /// Characters deleted from the original
+    pub chars_removed: usize,
+    /// Characters inserted into the optimized prompt
+    pub chars_added: usize,
+    pub lines_before: usize,
+    pub lines_after: usize,
+}
+
+impl DiffStats {
+    /// Compare `original` with `optimized`, counting chars (not bytes)
+    pub fn between(original: &str, optimized: &str) -> Self {
+        let edits = compute_edits(original, optimized, &[]);
+        Self {
+            chars_removed: edits.iter().map(|e| original[e.start..e.end].chars().count()).sum(),
+            chars_added: edits.iter().map(|e| e.replacement.chars().count()).sum(),
+            lines_before: original.lines().count(),
+            lines_after: optimized.lines().count(),
+        }
+    }
+
+    /// Net characters saved (negative when the prompt grew)
+    pub fn chars_saved(&self) -> i64 {
+        self.chars_removed as i64 - self.chars_added as i64
+    }
+}
+
+/// Width of one `ConfidenceSummary::distribution` bucket
+const CONFIDENCE_BUCKET_WIDTH: f64 = 0.1;
+
+/// Confidence of the optimizations a run offered (auto-applied and review)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConfidenceSummary {
+    /// Highest final confidence offered (None when nothing was offered)
+    pub max_confidence_seen: Option<f64>,
+    /// Non-empty 0.1-wide buckets, lowest first
+    pub distribution: Vec<ConfidenceBucket>,
+    /// Highest threshold that would auto-apply more of the review items
+    /// (None when lowering the threshold would not change anything)
+    pub suggested_threshold: Option<f64>,
+    /// Review items `suggested_threshold` would auto-apply
+    pub unlocked_by_suggestion: usize,
+}
+
+/// Optimizations whose confidence falls in `[lower, upper)` (1.0 counts in
+/// the top bucket)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ConfidenceBucket {
+    pub lower: f64,
+    pub upper: f64,
+    pub count: usize,
+}
+
+impl ConfidenceSummary {
+    /// Summarize `applied` and `review`; the suggestion only considers review
+    /// items `review_policy` routes by threshold, and is skipped entirely
+    /// when the threshold is not adjustable (aggressive mode)
+    pub fn new(
+        applied: &[Optimization],
+        review: &[Optimization],
+        review_policy: &ReviewPolicy,
+        threshold_adjustable: bool,
+    ) -> Self {
+        let confidence = |opt: &Optimization| opt.confidence.final_confidence;
+        let all = applied.iter().chain(review).map(confidence);
+
+        let bucket_count = (1.0 / CONFIDENCE_BUCKET_WIDTH).round() as usize;
+        let mut counts = vec![0; bucket_count];
+        for value in all.clone() {
+            let index = (value / CONFIDENCE_BUCKET_WIDTH).floor() as usize;
+            counts[index.min(bucket_count - 1)] += 1;
+        }
+        let distribution = counts
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, count)| count > 0)
+            .map(|(index, count)| ConfidenceBucket {
+                lower: index as f64 * CONFIDENCE_BUCKET_WIDTH,
+                upper: (index + 1) as f64 * CONFIDENCE_BUCKET_WIDTH,
+                count,
+            })
+            .collect();
+
+        let tunable: Vec<f64> = review
+            .iter()
+            .filter(|opt| {
+                threshold_adjustable && review_policy.rule(&opt.optimization_type) == ReviewRule::Threshold
+            })
+            .map(confidence)
+            .collect();
+        // Round down to two decimals so the suggestion can be typed as is
+        let suggested_threshold = tunable.iter().copied().reduce(f64::max).map(|max| {
+            let rounded = (max * 100.0).floor() / 100.0;
+            if rounded > max { rounded - 0.01 } else { rounded }
+        });
+        let unlocked_by_suggestion = suggested_threshold
+            .map_or(0, |threshold| tunable.iter().filter(|&&c| c >= threshold).count());
+
+        Self {
+            max_confidence_seen: all.reduce(f64::max),
+            distribution,
+            suggested_threshold,
+            unlocked_by_suggestion,
+        }
+    }
+}
+
+/// Milliseconds spent in each stage of `optimize`, summed over passes
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StageTimings {
+    /// Pattern and protected-region detection
+    pub detect_ms: f64,
+    /// Confidence scoring and filtering
+    pub score_ms: f64,
+    /// Overlap resolution
+    pub resolve_ms: f64,
+    /// Applying edits and cleanup
+    pub apply_ms: f64,
+    /// Validation, language directive and savings floor
+    pub directive_ms: f64,
+}
+
+/// One auto-applied edit replayed in application order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptimizationStep {
+    pub optimization: Optimization,
+    /// Prompt tokens once this and every earlier edit are applied
+    pub tokens_after: usize,
+    /// Tokens saved so far, relative to the original prompt
+    pub cumulative_saved: i64,
+}
+
+impl StageTimings {
+    pub fn total_ms(&self) -> f64 {
+        self.detect_ms + self.score_ms + self.resolve_ms + self.apply_ms + self.directive_ms
+    }
+}
+
+fn default_compression_ratio() -> f64 {
+    1.0
+}
+
+/// `OptimizationResult::savings_percentage` for the given token counts
+pub(crate) fn savings_percentage(original_tokens: usize, optimized_tokens: usize) -> f64 {
+    if original_tokens == 0 {
+        return 0.0;
+    }
+    let saved = original_tokens as f64 - optimized_tokens as f64;
+    (saved / original_tokens as f64 * 100.0).clamp(-100.0, 100.0)
+}
+
+/// `OptimizationResult::compression_ratio` for the given token counts
+pub(crate) fn compression_ratio(original_tokens: usize, optimized_tokens: usize) -> f64 {
+    if original_tokens == 0 {
+        return 1.0;
+    }
+    optimized_tokens as f64 / original_tokens as f64
+}
+
+impl OptimizationResult {
+    /// Span edits turning `original_prompt` into `optimized_prompt`
+    pub fn edits(&self) -> Vec<TextEdit> {
+        compute_edits(&self.original_prompt, &self.optimized_prompt, &self.optimizations)
+    }
+
+    /// Re-derive the optimized prompt by applying `optimizations` to
+    /// `original`, pass by pass, with the optimizers' own cleanup
+    ///
+    /// Each pass leaves the default protected regions alone and capitalizes
+    /// sentence starts. `None` unless the result is `replayable`: a replay
+    /// could not reproduce a directive, kept case, protected ends or custom
+    /// protection.
+    pub fn apply_to(&self, original: &str) -> Option<String> {
+        if !self.replayable {
+            return None;
+        }
+
+        let detector = ProtectedRegionDetector::default();
+        let sentences = SentenceBoundaries::default();
+        let replayed = self
+            .optimizations
+            .chunk_by(|a, b| a.pass == b.pass)
+            .fold(original.to_string(), |text, pass| {
+                apply_pass(&text, pass, &detector.detect(&text), Some(&sentences))
+            });
+        Some(replayed)
+    }
 }
 
 /// Decision for a reviewed optimization
@@ -137,6 +1040,33 @@ pub struct ReviewSession {
     pub session_id: String,
     pub pending_optimizations: Vec<Optimization>,
     pub decisions: HashMap<String, ReviewDecision>,
+    /// Unix timestamp (seconds) the session was opened
+    #[serde(default)]
+    pub created_at: u64,
+    /// Every pending optimization has a decision; evicted on the next sweep
+    #[serde(default)]
+    pub completed: bool,
+}
+
+impl ReviewSession {
+    /// Open a session for `pending_optimizations`, stamped with the current time
+    pub fn new(session_id: String, pending_optimizations: Vec<Optimization>) -> Self {
+        Self {
+            session_id,
+            pending_optimizations,
+            decisions: HashMap::new(),
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            completed: false,
+        }
+    }
+
+    /// Whether the session should be dropped at `now` (Unix seconds)
+    pub fn is_expired(&self, now: u64, ttl: std::time::Duration) -> bool {
+        self.completed || now.saturating_sub(self.created_at) >= ttl.as_secs()
+    }
 }
 
 /// Statistics for a pattern in the corpus
@@ -185,7 +1115,7 @@ impl PatternStats {
 }
 
 /// Corpus of optimization patterns with Bayesian priors
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OptimizationCorpus {
     pub patterns: HashMap<String, PatternStats>,
     pub total_optimizations: usize,
@@ -202,17 +1132,109 @@ impl Default for OptimizationCorpus {
     }
 }
 
+/// One accept/reject decision, as logged one per line in JSONL feedback
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedbackRecord {
+    pub original_text: String,
+    pub accepted: bool,
+    pub token_savings: i64,
+}
+
+/// Outcome of `OptimizationCorpus::ingest_jsonl`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IngestStats {
+    /// Decisions folded into the priors
+    pub applied: usize,
+    /// Malformed lines skipped, as (1-based line number, parse error)
+    pub skipped: Vec<(usize, String)>,
+}
+
 impl OptimizationCorpus {
     pub fn update_priors(&mut self, pattern: &str, accepted: bool, token_savings: i64) {
+        self.record_decision(pattern, accepted, token_savings);
+        self.recompute_success_rate();
+    }
+
+    fn record_decision(&mut self, pattern: &str, accepted: bool, token_savings: i64) {
         let stats = self
             .patterns
             .entry(pattern.to_string())
-            .or_insert_with(PatternStats::default);
+            .or_default();
 
         stats.update(accepted, token_savings);
         self.total_optimizations += 1;
+    }
+
+    /// Update priors from JSONL feedback, one `FeedbackRecord` per line
+    ///
+    /// Streams the input, so append-only production logs of any size can be
+    /// replayed. Blank lines are ignored; malformed ones (bad JSON or bad
+    /// UTF-8) are skipped and returned with their errors.
+    pub fn ingest_jsonl<R: BufRead>(&mut self, mut reader: R) -> Result<IngestStats> {
+        let mut stats = IngestStats::default();
+        let mut buf = Vec::new();
+        let mut line_number = 0;
+
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) => break,
+                Ok(_) => line_number += 1,
+                Err(e) => {
+                    // Keep what was ingested so far consistent
+                    self.recompute_success_rate();
+                    return Err(e.into());
+                }
+            }
+
+            let line = match String::from_utf8(std::mem::take(&mut buf)) {
+                Ok(line) => line,
+                Err(e) => {
+                    stats.skipped.push((line_number, e.to_string()));
+                    continue;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
 
-        // Recalculate global success rate
+            match serde_json::from_str::<FeedbackRecord>(&line) {
+                Ok(record) => {
+                    self.record_decision(&record.original_text, record.accepted, record.token_savings);
+                    stats.applied += 1;
+                }
+                Err(e) => stats.skipped.push((line_number, e.to_string())),
+            }
+        }
+
+        self.recompute_success_rate();
+        Ok(stats)
+    }
+
+    /// Fold another corpus's feedback into this one
+    ///
+    /// Per-pattern counts are summed and `avg_token_savings` is weighted by
+    /// each side's number of decisions.
+    pub fn merge(&mut self, other: &OptimizationCorpus) {
+        for (pattern, theirs) in &other.patterns {
+            let ours = self.patterns.entry(pattern.clone()).or_default();
+            let our_decisions = ours.successful_optimizations + ours.failed_optimizations;
+            let their_decisions = theirs.successful_optimizations + theirs.failed_optimizations;
+            let decisions = our_decisions + their_decisions;
+            if decisions > 0 {
+                ours.avg_token_savings = (ours.avg_token_savings * our_decisions as f64
+                    + theirs.avg_token_savings * their_decisions as f64)
+                    / decisions as f64;
+            }
+            ours.occurrences += theirs.occurrences;
+            ours.successful_optimizations += theirs.successful_optimizations;
+            ours.failed_optimizations += theirs.failed_optimizations;
+        }
+        self.total_optimizations += other.total_optimizations;
+        self.recompute_success_rate();
+    }
+
+    fn recompute_success_rate(&mut self) {
         let total_successes: usize = self
             .patterns
             .values()
@@ -232,15 +1254,20 @@ impl OptimizationCorpus {
     }
 
     pub fn get_frequency_bonus(&self, pattern: &str) -> f64 {
+        // Feedback alone creates an entry without occurrences; log10(0) is -inf
         self.patterns
             .get(pattern)
+            .filter(|stats| stats.occurrences > 0)
             .map(|stats| (stats.occurrences as f64).log10() * 0.05)
             .unwrap_or(0.0)
     }
 }
 
 /// Configuration for the optimization system
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// Loaded from TOML; missing sections and fields take their defaults.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub optimization: OptimizationConfig,
     pub hitl: HitlConfig,
@@ -250,74 +1277,425 @@ pub struct Config {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct OptimizationConfig {
     pub confidence_threshold: f64,
     pub aggressive_mode: bool,
     pub output_language: Language,
     pub directive_format: DirectiveFormat,
+    /// Text between the prompt and the directive
+    pub directive_separator: String,
+    /// Trim the prompt before adding the directive
+    pub directive_trim: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct HitlConfig {
     pub enabled: bool,
     pub auto_accept_threshold: f64,
     pub batch_review: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Which pattern families the detector runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PatternsConfig {
     pub boilerplate_enabled: bool,
     pub synonym_consolidation: bool,
     pub filler_removal: bool,
     pub mandarin_substitution: bool,
     pub format_consolidation: bool,
+    /// Newline, space and repeated punctuation collapsing
+    pub whitespace_normalization: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BayesianConfig {
     pub prior_corpus_path: String,
     pub update_priors_on_feedback: bool,
     pub min_confidence: f64,
+    /// How often the server writes pending feedback to `prior_corpus_path`
+    pub flush_interval_secs: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct OutputConfig {
     pub save_report: bool,
     pub report_format: String,
     pub show_diff: bool,
 }
 
-impl Default for Config {
+impl Default for OptimizationConfig {
     fn default() -> Self {
         Self {
-            optimization: OptimizationConfig {
-                confidence_threshold: 0.85,
-                aggressive_mode: false,
-                output_language: Language::English,
-                directive_format: DirectiveFormat::Bracketed,
-            },
-            hitl: HitlConfig {
-                enabled: true,
-                auto_accept_threshold: 0.95,
-                batch_review: false,
-            },
-            patterns: PatternsConfig {
-                boilerplate_enabled: true,
-                synonym_consolidation: true,
-                filler_removal: true,
-                mandarin_substitution: true,
-                format_consolidation: true,
-            },
-            bayesian: BayesianConfig {
-                prior_corpus_path: "data/priors.json".to_string(),
-                update_priors_on_feedback: true,
-                min_confidence: 0.50,
-            },
-            output: OutputConfig {
-                save_report: true,
-                report_format: "json".to_string(),
-                show_diff: true,
-            },
+            confidence_threshold: 0.85,
+            aggressive_mode: false,
+            output_language: Language::English,
+            directive_format: DirectiveFormat::Bracketed,
+            directive_separator: default_directive_separator(),
+            directive_trim: default_directive_trim(),
+        }
+    }
+}
+
+impl Default for HitlConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            auto_accept_threshold: 0.95,
+            batch_review: false,
+        }
+    }
+}
+
+impl Default for PatternsConfig {
+    fn default() -> Self {
+        Self {
+            boilerplate_enabled: true,
+            synonym_consolidation: true,
+            filler_removal: true,
+            mandarin_substitution: true,
+            format_consolidation: true,
+            whitespace_normalization: true,
+        }
+    }
+}
+
+impl Default for BayesianConfig {
+    fn default() -> Self {
+        Self {
+            prior_corpus_path: "data/priors.json".to_string(),
+            update_priors_on_feedback: true,
+            min_confidence: 0.50,
+            flush_interval_secs: 30,
+        }
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            save_report: true,
+            report_format: "json".to_string(),
+            show_diff: true,
         }
     }
 }
+
+impl Config {
+    /// Load a TOML config file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&text)?)
+    }
+
+    /// Build an optimization request for `prompt` from the `[optimization]`
+    /// section
+    pub fn request(&self, prompt: String) -> OptimizationRequest {
+        OptimizationRequest::builder(prompt)
+            .output_language(self.optimization.output_language.clone())
+            .confidence_threshold(self.optimization.confidence_threshold)
+            .aggressive_mode(self.optimization.aggressive_mode)
+            .directive_format(self.optimization.directive_format.clone())
+            .directive_separator(self.optimization.directive_separator.clone())
+            .directive_trim(self.optimization.directive_trim)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config_takes_defaults() {
+        let config: Config = toml::from_str(
+            "[optimization]\naggressive_mode = true\n\n[patterns]\nmandarin_substitution = false\n",
+        )
+        .unwrap();
+
+        assert!(config.optimization.aggressive_mode);
+        assert_eq!(config.optimization.confidence_threshold, 0.85);
+        assert!(!config.patterns.mandarin_substitution);
+        assert!(config.patterns.filler_removal);
+        assert_eq!(config.bayesian.prior_corpus_path, "data/priors.json");
+
+        let request = config.request("Summarize this.".to_string());
+        assert!(request.aggressive_mode);
+        assert_eq!(request.prompt, "Summarize this.");
+        assert_eq!(request.directive_separator, "\n\n");
+        assert!(request.directive_trim);
+
+        let config: Config =
+            toml::from_str("[optimization]\ndirective_separator = \" \"\ndirective_trim = false\n").unwrap();
+        let request = config.request("Summarize this.".to_string());
+        assert_eq!(request.directive_separator, " ");
+        assert!(!request.directive_trim);
+    }
+
+    #[test]
+    fn test_request_validation() {
+        let valid = OptimizationRequest {
+            prompt: "Summarize this.".to_string(),
+            ..Default::default()
+        };
+        assert!(valid.validate().is_ok());
+        for threshold in [0.0, 1.0] {
+            assert!(OptimizationRequest { confidence_threshold: threshold, ..valid.clone() }.validate().is_ok());
+        }
+
+        for threshold in [1.5, -0.2, f64::NAN, f64::INFINITY] {
+            let request = OptimizationRequest { confidence_threshold: threshold, ..valid.clone() };
+            match request.validate() {
+                Err(PromptCompressError::InvalidRequest(message)) => {
+                    assert!(message.contains("confidence_threshold"), "{}", message);
+                    assert!(message.contains(&threshold.to_string()), "{}", message);
+                }
+                other => panic!("threshold {} accepted: {:?}", threshold, other),
+            }
+        }
+
+        for prompt in ["", " \n\t "] {
+            let request = OptimizationRequest { prompt: prompt.to_string(), ..valid.clone() };
+            assert!(matches!(
+                request.validate(),
+                Err(PromptCompressError::InvalidRequest(message)) if message.contains("prompt")
+            ));
+        }
+
+        let no_types = OptimizationRequest { only_types: Some(Vec::new()), ..valid.clone() };
+        assert!(matches!(
+            no_types.validate(),
+            Err(PromptCompressError::InvalidRequest(message)) if message.contains("only_types")
+        ));
+    }
+
+    #[test]
+    fn test_builder_with_only_prompt() {
+        let request = OptimizationRequest::builder("Summarize this.").build();
+        assert_eq!(request.prompt, "Summarize this.");
+        assert_eq!(request.confidence_threshold, 0.85);
+        assert!(!request.aggressive_mode);
+        assert!(request.only_types.is_none());
+        assert!(request.validate().is_ok());
+
+        let request = OptimizationRequest::builder("Summarize this.")
+            .aggressive_mode(true)
+            .protected_suffix("-- Sam")
+            .only_types([OptimizationType::Whitespace])
+            .build();
+        assert!(request.aggressive_mode);
+        assert_eq!(request.protected_suffix.as_deref(), Some("-- Sam"));
+        assert!(request.allows_type(&OptimizationType::Whitespace));
+        assert!(!request.allows_type(&OptimizationType::FormatConsolidation));
+    }
+
+    #[test]
+    fn test_only_types() {
+        assert_eq!("structural".parse::<OptimizationType>().unwrap(), OptimizationType::FormatConsolidation);
+        assert_eq!(" Format ".parse::<OptimizationType>().unwrap(), OptimizationType::FormatConsolidation);
+        assert_eq!("filler_removal".parse::<OptimizationType>().unwrap(), OptimizationType::FillerRemoval);
+        assert_eq!("Whitespace".parse::<OptimizationType>().unwrap(), OptimizationType::Whitespace);
+        assert!("tabs".parse::<OptimizationType>().is_err());
+
+        let request: OptimizationRequest = serde_json::from_str(
+            r#"{"prompt":"Hi","output_language":"english","only_types":["format_consolidation"]}"#,
+        )
+        .unwrap();
+        assert!(request.allows_type(&OptimizationType::FormatConsolidation));
+        assert!(!request.allows_type(&OptimizationType::Whitespace));
+        assert!(!request.allows_type(&OptimizationType::FillerRemoval));
+        assert!(OptimizationRequest::default().allows_type(&OptimizationType::FillerRemoval));
+    }
+
+    #[test]
+    fn test_parse_language_and_directive_format() {
+        for alias in ["english", "en", "English", " EN "] {
+            assert_eq!(alias.parse::<Language>().unwrap(), Language::English);
+        }
+        for alias in ["mandarin", "zh", "Chinese", "ZH"] {
+            assert_eq!(alias.parse::<Language>().unwrap(), Language::Mandarin);
+        }
+        assert!(matches!(
+            "klingon".parse::<Language>(),
+            Err(PromptCompressError::InvalidRequest(_))
+        ));
+
+        let cases = [
+            ("bracketed", DirectiveFormat::Bracketed),
+            ("Brackets", DirectiveFormat::Bracketed),
+            ("instructive", DirectiveFormat::Instructive),
+            ("instruction", DirectiveFormat::Instructive),
+            ("XML", DirectiveFormat::Xml),
+            ("natural", DirectiveFormat::Natural),
+        ];
+        for (alias, expected) in cases {
+            assert_eq!(alias.parse::<DirectiveFormat>().unwrap(), expected, "{}", alias);
+        }
+        assert!("yaml".parse::<DirectiveFormat>().is_err());
+    }
+
+    #[test]
+    fn test_savings_percentage_and_compression_ratio() {
+        // Empty original: nothing saved, nothing compressed
+        assert_eq!(savings_percentage(0, 0), 0.0);
+        assert_eq!(savings_percentage(0, 5), 0.0);
+        assert_eq!(compression_ratio(0, 0), 1.0);
+        assert_eq!(compression_ratio(0, 5), 1.0);
+
+        assert_eq!(savings_percentage(20, 15), 25.0);
+        assert_eq!(compression_ratio(20, 15), 0.75);
+
+        // Growth past double the original is clamped; the ratio is exact
+        assert_eq!(savings_percentage(2, 8), -100.0);
+        assert_eq!(compression_ratio(2, 8), 4.0);
+    }
+
+    #[test]
+    fn test_reasoning_codes_are_stable() {
+        // Codes are part of the API: changing one breaks consumers
+        for (optimization_type, reasoning, code) in [
+            (OptimizationType::BoilerplateRemoval, "Politeness filler", "BOILERPLATE_POLITENESS_FILLER"),
+            (OptimizationType::BoilerplateRemoval, "Boilerplate gratitude (complete sentence)", "BOILERPLATE_GRATITUDE"),
+            (OptimizationType::FillerRemoval, "Hedge word", "FILLER_HEDGE_WORD"),
+            (OptimizationType::InstructionCompression, "Look into→Identify", "INSTRUCTION_LOOK_INTO_IDENTIFY"),
+            (OptimizationType::SynonymConsolidation, "Consolidate to 'create'", "SYNONYM_CONSOLIDATE_TO_CREATE"),
+            (OptimizationType::SynonymConsolidation, "Verify - EQUAL tokens (1=1), unambiguous meaning", "SYNONYM_VERIFY"),
+            (OptimizationType::FormatConsolidation, "Shorten JSON key: description → desc", "FORMAT_SHORTEN_JSON_KEY"),
+            (
+                OptimizationType::FormatConsolidation,
+                "Normalize kilometers to km (3 tokens → 2 tokens)",
+                "FORMAT_NORMALIZE_KILOMETERS_TO_KM",
+            ),
+            (OptimizationType::ConceptSubstitution, "Q180370 → cheapest surface form (en)", "CONCEPT_CHEAPEST_SURFACE_FORM"),
+            (OptimizationType::MandarinSubstitution, "", "MANDARIN"),
+        ] {
+            assert_eq!(reasoning_code(&optimization_type, reasoning), code);
+            assert_eq!(ReasoningStyle::Code.render(&optimization_type, reasoning.to_string()), code);
+            assert_eq!(ReasoningStyle::Verbose.render(&optimization_type, reasoning.to_string()), reasoning);
+        }
+    }
+
+    #[test]
+    fn test_ingest_jsonl_fixture() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/feedback.jsonl");
+        let file = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+
+        let mut corpus = OptimizationCorpus::default();
+        corpus.update_priors("please", false, 0);
+        let stats = corpus.ingest_jsonl(file).unwrap();
+
+        assert_eq!(stats.applied, 5);
+        let skipped_lines: Vec<usize> = stats.skipped.iter().map(|(line, _)| *line).collect();
+        assert_eq!(skipped_lines, vec![6, 7, 8]);
+        assert!(stats.skipped[1].1.contains("expected a boolean"), "{:?}", stats.skipped);
+        assert!(stats.skipped[2].1.contains("utf-8"), "{:?}", stats.skipped);
+        assert_eq!(corpus.total_optimizations, 6);
+
+        let please = &corpus.patterns["please"];
+        assert_eq!(please.successful_optimizations, 2);
+        assert_eq!(please.failed_optimizations, 2);
+        let basically = &corpus.patterns["basically"];
+        assert_eq!(basically.successful_optimizations, 1);
+        assert_eq!(basically.avg_token_savings, 1.0);
+        assert!((corpus.success_rate - 4.0 / 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_corpus_merge() {
+        let mut a = OptimizationCorpus::default();
+        a.update_priors("please", true, 2);
+        a.update_priors("please", false, 4);
+        a.update_priors("kindly", true, 1);
+
+        let mut b = OptimizationCorpus::default();
+        b.update_priors("please", true, 6);
+        b.update_priors("basically", false, 3);
+        b.patterns.get_mut("please").unwrap().occurrences = 5;
+
+        a.merge(&b);
+
+        // Overlapping pattern: counts summed, average weighted 2:1
+        let please = &a.patterns["please"];
+        assert_eq!(please.successful_optimizations, 2);
+        assert_eq!(please.failed_optimizations, 1);
+        assert_eq!(please.occurrences, 5);
+        assert!((please.avg_token_savings - 4.0).abs() < 1e-9);
+
+        // Disjoint patterns carried over unchanged
+        assert_eq!(a.patterns["kindly"].successful_optimizations, 1);
+        assert_eq!(a.patterns["basically"].failed_optimizations, 1);
+        assert_eq!(a.patterns["basically"].avg_token_savings, 3.0);
+
+        assert_eq!(a.total_optimizations, 5);
+        assert!((a.success_rate - 3.0 / 5.0).abs() < 1e-9);
+
+        // Feedback without recorded occurrences earns no bonus
+        assert_eq!(a.get_frequency_bonus("kindly"), 0.0);
+    }
+
+    #[test]
+    fn test_diff_stats() {
+        let stats = DiffStats::between(
+            "Please analyze this code.\nThank you!",
+            "Analyze this code.\n\n[output_language: english]",
+        );
+        assert_eq!(stats.lines_before, 2);
+        assert_eq!(stats.lines_after, 3);
+        // "Please analyze" -> "Analyze", "Thank you!" -> "\n[output_language: english]"
+        assert_eq!(stats.chars_removed, "Please analyze".len() + "Thank you!".len());
+        assert_eq!(stats.chars_added, "Analyze".len() + "\n[output_language: english]".len());
+
+        // Chars, not bytes
+        let stats = DiffStats::between("医院 hospital", "hospital");
+        assert_eq!((stats.chars_removed, stats.chars_added), (3, 0));
+        assert_eq!(stats.chars_saved(), 3);
+        assert_eq!(DiffStats::between("same", "same"), DiffStats { lines_before: 1, lines_after: 1, ..Default::default() });
+    }
+
+    #[test]
+    fn test_confidence_summary() {
+        let optimization = |optimization_type, confidence| Optimization {
+            id: String::new(),
+            optimization_type,
+            original_text: String::new(),
+            optimized_text: String::new(),
+            token_savings: 1,
+            confidence: OptimizationConfidence::new(confidence, 0.0, 0.0, 0.0),
+            requires_review: true,
+            reasoning: String::new(),
+            start_pos: 0,
+            end_pos: 0,
+            pass: 0,
+        };
+        let review = vec![
+            optimization(OptimizationType::FillerRemoval, 0.8234),
+            optimization(OptimizationType::SynonymConsolidation, 0.829),
+            optimization(OptimizationType::FillerRemoval, 0.61),
+            // Always reviewed, so never part of the suggestion
+            optimization(OptimizationType::MandarinSubstitution, 0.84),
+        ];
+        let policy = ReviewPolicy::default();
+
+        let summary = ConfidenceSummary::new(&[], &review, &policy, true);
+        assert_eq!(summary.max_confidence_seen, Some(0.84));
+        assert_eq!(summary.suggested_threshold, Some(0.82));
+        assert_eq!(summary.unlocked_by_suggestion, 2);
+        let counts: Vec<_> = summary.distribution.iter().map(|b| (b.lower, b.count)).collect();
+        assert_eq!(counts.len(), 2);
+        assert!((counts[0].0 - 0.6).abs() < 1e-9 && counts[0].1 == 1);
+        assert!((counts[1].0 - 0.8).abs() < 1e-9 && counts[1].1 == 3);
+
+        // The threshold is fixed in aggressive mode
+        let fixed = ConfidenceSummary::new(&[], &review, &policy, false);
+        assert_eq!((fixed.suggested_threshold, fixed.unlocked_by_suggestion), (None, 0));
+
+        let full = ConfidenceSummary::new(&[optimization(OptimizationType::FillerRemoval, 1.0)], &[], &policy, true);
+        assert_eq!(full.distribution.len(), 1);
+        assert_eq!(full.distribution[0].count, 1);
+        assert_eq!(ConfidenceSummary::new(&[], &[], &policy, true), ConfidenceSummary::default());
+    }
+}
@@ -1,38 +1,597 @@
 use crate::confidence::{extract_context, ConfidenceCalculator};
 use crate::models::{
-    DirectiveFormat, Language, Optimization, OptimizationRequest, OptimizationResult,
+    compression_ratio, savings_percentage, ConfidenceSummary, DiffStats, DirectiveFormat, DirectivePlacement, Language,
+    Message, MessagesResult, Optimization, OptimizationRequest, OptimizationResult, OptimizationStep, PatternsConfig,
+    RejectedOptimization, RejectionReason, SavingsFloor, StageTimings,
 };
-use crate::patterns::PatternDetector;
+use crate::patterns::{detect_contractions, detect_json_keys, PatternDetector};
+use crate::protected_regions::{
+    ProtectedRegion, ProtectedRegionDetector, ProtectedRegionSummary, ProtectionPolicy, ProtectionReport,
+};
+use crate::sentence::SentenceBoundaries;
 use crate::tokenizer::Tokenizer;
-use anyhow::Result;
+use crate::error::Result;
+use std::ops::Range;
+use std::time::Instant;
 use uuid::Uuid;
 
+/// Upper bound on detection passes in `Optimizer::optimize`
+const MAX_PASSES: usize = 3;
+
+/// Connectors left dangling when the clause next to them is removed
+const DANGLING_CONJUNCTIONS: &[&str] = &["and", "but", "or"];
+
 /// Main optimization engine
 pub struct Optimizer {
     detector: PatternDetector,
+    region_detector: ProtectedRegionDetector,
+    sentences: SentenceBoundaries,
     calculator: ConfidenceCalculator,
     tokenizer: Tokenizer,
 }
 
 impl Optimizer {
+    /// Optimizer with the default pattern detector
     pub fn new(calculator: ConfidenceCalculator, tokenizer: Tokenizer) -> Self {
+        Self::with_detector(PatternDetector::new(), calculator, tokenizer)
+    }
+
+    /// Optimizer that detects patterns with `detector`
+    pub fn with_detector(
+        detector: PatternDetector,
+        calculator: ConfidenceCalculator,
+        tokenizer: Tokenizer,
+    ) -> Self {
         Self {
-            detector: PatternDetector::new(),
+            detector,
+            region_detector: ProtectedRegionDetector::default(),
+            sentences: SentenceBoundaries::default(),
             calculator,
             tokenizer,
         }
     }
 
+    /// Run only the pattern families enabled in `patterns`
+    pub fn with_patterns_config(mut self, patterns: PatternsConfig) -> Self {
+        self.detector = PatternDetector::with_config(patterns);
+        self
+    }
+
+    /// Detect protected regions with `region_detector` (e.g. custom comment markers)
+    pub fn with_region_detector(mut self, region_detector: ProtectedRegionDetector) -> Self {
+        self.region_detector = region_detector;
+        self
+    }
+
     /// Optimize a prompt according to the request
-    pub fn optimize(&mut self, request: &OptimizationRequest) -> Result<OptimizationResult> {
+    ///
+    /// Detection re-runs on the output (up to `MAX_PASSES` times) while it
+    /// keeps finding auto-applicable edits, so cascading boilerplate collapses
+    /// fully. Optimizations from later passes are positioned in the previous
+    /// pass's output, not the original prompt.
+    ///
+    /// A `protected_prefix`/`protected_suffix` found at the ends of the prompt
+    /// is split off and reattached verbatim; the directive goes inside them.
+    pub fn optimize(&self, request: &OptimizationRequest) -> Result<OptimizationResult> {
+        request.validate()?;
+        if let Some(ends) = ProtectedEnds::split(request, &self.region_detector) {
+            let result = self.optimize(ends.inner())?;
+            return Ok(ends.rejoin(result, &self.tokenizer));
+        }
+
+        let original_prompt = &request.prompt;
+        let original_tokens = self.tokenizer.count_tokens(original_prompt);
+
+        let mut timer = StageTimer::new(request.profile);
+        let protected_regions = self.protected_regions(request, original_prompt);
+        let (optimizations, rejected) = self.collect_optimizations(
+            request,
+            0..original_prompt.len(),
+            &protected_regions,
+            &mut timer,
+        );
+
+        // Split into auto-apply and requires-review
+        let (mut auto_apply, requires_review): (Vec<_>, Vec<_>) = optimizations
+            .into_iter()
+            .partition(|opt| !opt.requires_review);
+
+        // Apply auto-approved optimizations
+        let mut optimized_prompt = self.apply_optimizations(
+            original_prompt,
+            &auto_apply,
+            &protected_regions,
+            !request.preserve_case,
+        );
+        timer.mark(Stage::Apply);
+
+        // Removing one match can expose another ("please make sure to make
+        // sure to"); stop once a pass finds nothing or changes nothing
+        if !auto_apply.is_empty() {
+            for pass_index in 1..MAX_PASSES {
+                let pass_request = OptimizationRequest {
+                    prompt: optimized_prompt.clone(),
+                    include_rejected: false,
+                    ..request.clone()
+                };
+                let protected_regions = self.protected_regions(request, &optimized_prompt);
+                let (found, _) = self.collect_optimizations(
+                    &pass_request,
+                    0..optimized_prompt.len(),
+                    &protected_regions,
+                    &mut timer,
+                );
+                let pass: Vec<_> = found
+                    .into_iter()
+                    .filter(|opt| !opt.requires_review)
+                    .map(|opt| Optimization { pass: pass_index, ..opt })
+                    .collect();
+                if pass.is_empty() {
+                    break;
+                }
+
+                let next = self.apply_optimizations(
+                    &optimized_prompt,
+                    &pass,
+                    &protected_regions,
+                    !request.preserve_case,
+                );
+                timer.mark(Stage::Apply);
+                if next == optimized_prompt {
+                    break;
+                }
+                optimized_prompt = next;
+                auto_apply.extend(pass);
+            }
+        }
+
+        let confidence_summary = ConfidenceSummary::new(
+            &auto_apply,
+            &requires_review,
+            &request.review_policy,
+            !request.aggressive_mode,
+        );
+
+        // Never hand back an empty or corrupted prompt
+        let mut warnings = Vec::new();
+        let mut directive_tokens = 0;
+        let mut directive_added = false;
+        let optimized_prompt = match validate_optimized_prompt(original_prompt, &optimized_prompt) {
+            // Add output language directive
+            Ok(()) => {
+                let with_directive = if request.language_directive {
+                    add_language_directive(
+                        &optimized_prompt,
+                        &request.output_language,
+                        &request.directive_format,
+                        request.directive_placement,
+                        &request.directive_separator,
+                        request.directive_trim,
+                    )
+                } else {
+                    optimized_prompt.clone()
+                };
+                let directive_cost = self
+                    .tokenizer
+                    .count_tokens(&with_directive)
+                    .saturating_sub(self.tokenizer.count_tokens(&optimized_prompt));
+                let with_directive_len = with_directive.len();
+                let (prompt, reverted) = apply_savings_floor(
+                    request.savings_floor,
+                    &self.tokenizer,
+                    original_prompt,
+                    optimized_prompt,
+                    with_directive,
+                    &mut warnings,
+                );
+                if reverted {
+                    auto_apply.clear();
+                }
+                // The floor may have dropped the directive (or everything)
+                if !reverted && prompt.len() == with_directive_len {
+                    directive_tokens = directive_cost;
+                    directive_added = request.language_directive;
+                }
+                prompt
+            }
+            Err(warning) => {
+                warnings.push(warning);
+                auto_apply.clear();
+                original_prompt.to_string()
+            }
+        };
+        timer.mark(Stage::Directive);
+
+        let optimized_tokens = self.tokenizer.count_tokens(&optimized_prompt);
+        let token_savings = original_tokens as i64 - optimized_tokens as i64;
+        let diff_stats = DiffStats::between(original_prompt, &optimized_prompt);
+
+        Ok(OptimizationResult {
+            original_prompt: original_prompt.clone(),
+            optimized_prompt,
+            original_tokens,
+            optimized_tokens,
+            token_savings,
+            directive_tokens,
+            savings_including_directive: token_savings,
+            savings_excluding_directive: token_savings + directive_tokens as i64,
+            savings_percentage: savings_percentage(original_tokens, optimized_tokens),
+            compression_ratio: compression_ratio(original_tokens, optimized_tokens),
+            optimizations: auto_apply,
+            requires_review,
+            output_language: request.output_language.clone(),
+            rejected,
+            warnings,
+            net_savings_negative: token_savings < 0,
+            diff_stats,
+            replayable: !directive_added && !request.preserve_case && self.replayable_protection(request),
+            confidence_summary,
+            profile: timer.finish(),
+        })
+    }
+
+    /// Whether `request`'s protected regions are the ones a replay detects
+    fn replayable_protection(&self, request: &OptimizationRequest) -> bool {
+        request.protection_policy == ProtectionPolicy::Conservative
+            && !request.preserve_first_person
+            && self.region_detector.has_default_rules()
+    }
+
+    /// Optimize each message of a chat conversation independently
+    ///
+    /// Roles and order are kept and `request.prompt` is ignored. Blank
+    /// messages, and system messages with `skip_system_messages`, pass
+    /// through unchanged. The language directive is added once: to the last
+    /// user message, or as a new leading system message when there is none.
+    pub fn optimize_messages(
+        &self,
+        messages: Vec<Message>,
+        request: &OptimizationRequest,
+    ) -> Result<MessagesResult> {
+        let per_message = OptimizationRequest {
+            language_directive: false,
+            ..request.clone()
+        };
+
+        let mut original_tokens = 0;
+        let mut optimized = Vec::with_capacity(messages.len() + 1);
+        let mut results = Vec::with_capacity(messages.len() + 1);
+        for message in messages {
+            original_tokens += self.tokenizer.count_tokens(&message.content);
+            if message.content.trim().is_empty()
+                || (request.skip_system_messages && message.role == "system")
+            {
+                optimized.push(message);
+                results.push(None);
+                continue;
+            }
+
+            let result = self.optimize(&OptimizationRequest {
+                prompt: message.content.clone(),
+                ..per_message.clone()
+            })?;
+            optimized.push(Message {
+                content: result.optimized_prompt.clone(),
+                ..message
+            });
+            results.push(Some(result));
+        }
+
+        let mut directive_tokens = 0;
+        if request.language_directive {
+            match optimized.iter_mut().rev().find(|m| m.role == "user") {
+                Some(last_user) => {
+                    let before = self.tokenizer.count_tokens(&last_user.content);
+                    last_user.content = add_language_directive(
+                        &last_user.content,
+                        &request.output_language,
+                        &request.directive_format,
+                        request.directive_placement,
+                        &request.directive_separator,
+                        request.directive_trim,
+                    );
+                    directive_tokens = self
+                        .tokenizer
+                        .count_tokens(&last_user.content)
+                        .saturating_sub(before);
+                }
+                None => {
+                    let directive = language_directive(&request.output_language, &request.directive_format);
+                    directive_tokens = self.tokenizer.count_tokens(&directive);
+                    optimized.insert(0, Message::new("system", directive));
+                    results.insert(0, None);
+                }
+            }
+        }
+
+        let optimized_tokens = optimized
+            .iter()
+            .map(|m| self.tokenizer.count_tokens(&m.content))
+            .sum();
+        Ok(MessagesResult {
+            messages: optimized,
+            results,
+            original_tokens,
+            optimized_tokens,
+            token_savings: original_tokens as i64 - optimized_tokens as i64,
+            directive_tokens,
+            savings_percentage: savings_percentage(original_tokens, optimized_tokens),
+            compression_ratio: compression_ratio(original_tokens, optimized_tokens),
+        })
+    }
+
+    /// Replay the edits `optimize` auto-applies one at a time, re-tokenizing
+    /// after each
+    ///
+    /// Steps follow application order, pass by pass. Token counts cover the
+    /// whole prompt (protected ends included) but not the language directive
+    /// or savings floor, so the last `cumulative_saved` is the saving of the
+    /// edits themselves.
+    pub fn optimize_steps(&self, request: &OptimizationRequest) -> Result<Vec<OptimizationStep>> {
+        request.validate()?;
+        match ProtectedEnds::split(request, &self.region_detector) {
+            Some(ends) => {
+                let mut steps = self.replay_steps(ends.inner(), ends.head, ends.tail);
+                for step in &mut steps {
+                    step.optimization.start_pos += ends.head.len();
+                    step.optimization.end_pos += ends.head.len();
+                }
+                Ok(steps)
+            }
+            None => Ok(self.replay_steps(request, "", "")),
+        }
+    }
+
+    fn replay_steps(&self, request: &OptimizationRequest, head: &str, tail: &str) -> Vec<OptimizationStep> {
+        let count = |text: &str| self.tokenizer.count_tokens(&format!("{}{}{}", head, text, tail));
+        let original_tokens = count(&request.prompt) as i64;
+        let capitalize = !request.preserve_case;
+        let mut timer = StageTimer::new(false);
+        let mut text = request.prompt.clone();
+        let mut steps = Vec::new();
+
+        // Same passes as `optimize`, but each edit is applied on its own
+        for pass_index in 0..MAX_PASSES {
+            let pass_request = OptimizationRequest {
+                prompt: text.clone(),
+                include_rejected: false,
+                ..request.clone()
+            };
+            let protected_regions = self.protected_regions(request, &text);
+            let (found, _) =
+                self.collect_optimizations(&pass_request, 0..text.len(), &protected_regions, &mut timer);
+            let pass: Vec<_> = found
+                .into_iter()
+                .filter(|opt| !opt.requires_review)
+                .map(|opt| Optimization { pass: pass_index, ..opt })
+                .collect();
+            if pass.is_empty() {
+                break;
+            }
+
+            let next = self.apply_optimizations(&text, &pass, &protected_regions, capitalize);
+            if pass_index > 0 && next == text {
+                break;
+            }
+
+            for (i, optimization) in pass.iter().enumerate() {
+                let tokens_after =
+                    count(&self.apply_optimizations(&text, &pass[..=i], &protected_regions, capitalize));
+                steps.push(OptimizationStep {
+                    optimization: optimization.clone(),
+                    tokens_after,
+                    cumulative_saved: original_tokens - tokens_after as i64,
+                });
+            }
+            text = next;
+        }
+
+        steps
+    }
+
+    /// List the regions the detector protects in `prompt`, with the number of
+    /// tokens they cover
+    pub fn protection_report(&self, prompt: &str) -> ProtectionReport {
+        let regions = self.region_detector.detect(prompt);
+
+        ProtectionReport {
+            protected_tokens: regions
+                .iter()
+                .map(|r| self.tokenizer.count_tokens(&prompt[r.start..r.end]))
+                .sum(),
+            regions: regions
+                .iter()
+                .map(|r| ProtectedRegionSummary::new(prompt, r))
+                .collect(),
+        }
+    }
+
+    /// Optimize only the byte range `[start, end)` of the prompt (e.g. an
+    /// editor selection)
+    ///
+    /// The range is clamped to the prompt and to char boundaries. Text outside
+    /// it is returned verbatim and no language directive is added; optimization
+    /// positions are relative to the full prompt.
+    pub fn optimize_range(
+        &self,
+        request: &OptimizationRequest,
+        start: usize,
+        end: usize,
+    ) -> Result<OptimizationResult> {
+        request.validate()?;
         let original_prompt = &request.prompt;
         let original_tokens = self.tokenizer.count_tokens(original_prompt);
 
-        // Detect all patterns
-        let detected = self.detector.detect_all(original_prompt);
+        let floor_boundary = |mut pos: usize| {
+            pos = pos.min(original_prompt.len());
+            while !original_prompt.is_char_boundary(pos) {
+                pos -= 1;
+            }
+            pos
+        };
+        let end = floor_boundary(end);
+        let start = floor_boundary(start).min(end);
+
+        let mut timer = StageTimer::new(request.profile);
+        let protected_regions = self.protected_regions(request, original_prompt);
+        let (optimizations, rejected) =
+            self.collect_optimizations(request, start..end, &protected_regions, &mut timer);
+        let (mut auto_apply, requires_review): (Vec<_>, Vec<_>) = optimizations
+            .into_iter()
+            .partition(|opt| !opt.requires_review);
+
+        let confidence_summary = ConfidenceSummary::new(
+            &auto_apply,
+            &requires_review,
+            &request.review_policy,
+            !request.aggressive_mode,
+        );
+
+        // Apply within the selection, keeping its surrounding whitespace
+        let selection = &original_prompt[start..end];
+        let shifted: Vec<Optimization> = auto_apply
+            .iter()
+            .map(|opt| Optimization {
+                start_pos: opt.start_pos - start,
+                end_pos: opt.end_pos - start,
+                ..opt.clone()
+            })
+            .collect();
+        let selection_regions: Vec<ProtectedRegion> = protected_regions
+            .iter()
+            .filter(|r| r.start >= start && r.end <= end)
+            .map(|r| ProtectedRegion {
+                start: r.start - start,
+                end: r.end - start,
+                ..r.clone()
+            })
+            .collect();
+        let mut optimized_selection =
+            self.apply_optimizations(selection, &shifted, &selection_regions, false);
+        if !shifted.is_empty() {
+            let leading = &selection[..selection.len() - selection.trim_start().len()];
+            let trailing = &selection[selection.trim_end().len()..];
+            optimized_selection = format!("{}{}{}", leading, optimized_selection, trailing);
+        }
+
+        // Capitalize as part of the full text, so only true sentence starts change
+        let prefix = &original_prompt[..start];
+        if !request.preserve_case && !shifted.is_empty() {
+            let capitalize = |text: &str| {
+                let spans: Vec<Range<usize>> =
+                    self.protected_regions(request, text).iter().map(|r| r.start..r.end).collect();
+                with_spans_masked(text, &spans, |text| capitalize_unfenced(&self.sentences, text))
+            };
+            let capitalized_prefix = capitalize(prefix);
+            let capitalized = capitalize(&format!("{}{}", prefix, optimized_selection));
+            optimized_selection = capitalized[capitalized_prefix.len()..].to_string();
+        }
+
+        let optimized_prompt = format!("{}{}{}", prefix, optimized_selection, &original_prompt[end..]);
+        timer.mark(Stage::Apply);
+
+        let mut warnings = Vec::new();
+        let optimized_prompt = match validate_optimized_prompt(original_prompt, &optimized_prompt) {
+            Ok(()) => optimized_prompt,
+            Err(warning) => {
+                warnings.push(warning);
+                auto_apply.clear();
+                original_prompt.to_string()
+            }
+        };
+        timer.mark(Stage::Directive);
+
+        let optimized_tokens = self.tokenizer.count_tokens(&optimized_prompt);
+        let token_savings = original_tokens as i64 - optimized_tokens as i64;
+        let diff_stats = DiffStats::between(original_prompt, &optimized_prompt);
+
+        Ok(OptimizationResult {
+            original_prompt: original_prompt.clone(),
+            optimized_prompt,
+            original_tokens,
+            optimized_tokens,
+            token_savings,
+            directive_tokens: 0,
+            savings_including_directive: token_savings,
+            savings_excluding_directive: token_savings,
+            savings_percentage: savings_percentage(original_tokens, optimized_tokens),
+            compression_ratio: compression_ratio(original_tokens, optimized_tokens),
+            optimizations: auto_apply,
+            requires_review,
+            output_language: request.output_language.clone(),
+            rejected,
+            warnings,
+            net_savings_negative: token_savings < 0,
+            diff_stats,
+            // Cleanup stays inside the selection, unlike a replay
+            replayable: false,
+            confidence_summary,
+            profile: timer.finish(),
+        })
+    }
+
+    /// Regions of `text` no optimization may touch under `request`
+    ///
+    /// Code, templates, URLs, keywords (and, when conservative, identifiers
+    /// and quoted strings), plus example/quoted user text when requested.
+    fn protected_regions(&self, request: &OptimizationRequest, text: &str) -> Vec<ProtectedRegion> {
+        let mut regions = self
+            .region_detector
+            .detect_with_policy(text, request.protection_policy);
+        if request.preserve_first_person {
+            regions.extend(self.region_detector.detect_examples(text));
+        }
+        regions
+    }
+
+    /// Detect, score and de-conflict optimizations lying entirely within
+    /// `range`, skipping any that touch `protected_regions`
+    fn collect_optimizations(
+        &self,
+        request: &OptimizationRequest,
+        range: Range<usize>,
+        protected_regions: &[ProtectedRegion],
+        timer: &mut StageTimer,
+    ) -> (Vec<Optimization>, Vec<RejectedOptimization>) {
+        let original_prompt = &request.prompt;
+
+        // Detect all patterns (over the whole prompt, for context)
+        let mut detected =
+            self.detector
+                .detect_all_with_mode(original_prompt, request.locale, request.aggressive_mode);
+
+        // Aggressive mode also strips markdown bullets/headers, separators
+        // and articles
+        if request.aggressive_mode {
+            detected.extend(self.detector.detect_markdown(original_prompt));
+            detected.extend(self.detector.detect_separators(original_prompt));
+            detected.extend(self.detector.detect_articles(original_prompt));
+        }
+        if request.rewrite_json_keys {
+            detected.extend(detect_json_keys(original_prompt));
+        }
+        detected.extend(detect_contractions(
+            original_prompt,
+            request.contraction_policy,
+            &self.tokenizer,
+        ));
+        detected.retain(|p| {
+            p.start_pos >= range.start && p.end_pos <= range.end && request.allows_type(&p.pattern_type)
+        });
+
+        timer.mark(Stage::Detect);
 
         // Calculate confidence for each pattern
         let mut optimizations: Vec<Optimization> = Vec::new();
+        let mut rejected: Vec<RejectedOptimization> = Vec::new();
+
+        // Adjust threshold based on mode
+        let min_confidence = if request.aggressive_mode { 0.4 } else { 0.5 };
+        let auto_apply_threshold = if request.aggressive_mode {
+            0.70
+        } else {
+            request.confidence_threshold
+        };
 
         for pattern in detected {
             let context = extract_context(
@@ -53,66 +612,71 @@ impl Optimizer {
                 .tokenizer
                 .estimate_savings(&pattern.original_text, &pattern.optimized_text);
 
-            // Adjust threshold based on mode
-            let min_confidence = if request.aggressive_mode { 0.4 } else { 0.5 };
-            let auto_apply_threshold = if request.aggressive_mode { 0.70 } else { request.confidence_threshold };
+            let rejection = if self
+                .region_detector
+                .is_protected(protected_regions, pattern.start_pos, pattern.end_pos)
+            {
+                Some(RejectionReason::Protected)
+            } else if request.safe_mode && pattern.optimized_text.is_empty() {
+                // Safe mode keeps rewrites but drops pure removals
+                Some(RejectionReason::SafeMode)
+            } else if confidence.final_confidence < min_confidence {
+                Some(RejectionReason::BelowMinConfidence)
+            } else if token_savings <= 0 {
+                Some(RejectionReason::NoSavings)
+            } else {
+                None
+            };
+
+            let requires_review = request.review_policy.requires_review(
+                &pattern.pattern_type,
+                confidence.final_confidence,
+                auto_apply_threshold,
+            );
+            let reasoning = request
+                .reasoning_style
+                .render(&pattern.pattern_type, pattern.reasoning);
+            let optimization = Optimization {
+                id: Uuid::new_v4().to_string(),
+                optimization_type: pattern.pattern_type,
+                original_text: pattern.original_text,
+                optimized_text: pattern.optimized_text,
+                token_savings,
+                confidence,
+                requires_review,
+                reasoning,
+                start_pos: pattern.start_pos,
+                end_pos: pattern.end_pos,
+                pass: 0,
+            };
 
             // Only include if meets minimum confidence and saves tokens
-            if confidence.final_confidence >= min_confidence && token_savings > 0 {
-                let requires_review = confidence.final_confidence < auto_apply_threshold;
-
-                optimizations.push(Optimization {
-                    id: Uuid::new_v4().to_string(),
-                    optimization_type: pattern.pattern_type,
-                    original_text: pattern.original_text,
-                    optimized_text: pattern.optimized_text,
-                    token_savings,
-                    confidence,
-                    requires_review,
-                    reasoning: pattern.reasoning,
-                    start_pos: pattern.start_pos,
-                    end_pos: pattern.end_pos,
-                });
+            match rejection {
+                None => optimizations.push(optimization),
+                Some(reason) if request.include_rejected => {
+                    rejected.push(RejectedOptimization::new(optimization, reason));
+                }
+                Some(_) => {}
             }
         }
 
-        // Resolve conflicts (overlapping optimizations)
-        let optimizations = self.resolve_conflicts(optimizations);
-
-        // Split into auto-apply and requires-review
-        let (auto_apply, requires_review): (Vec<_>, Vec<_>) = optimizations
-            .into_iter()
-            .partition(|opt| !opt.requires_review);
-
-        // Apply auto-approved optimizations
-        let mut optimized_prompt = self.apply_optimizations(original_prompt, &auto_apply);
-
-        // Add output language directive
-        optimized_prompt = self.add_language_directive(
-            &optimized_prompt,
-            &request.output_language,
-            &request.directive_format,
-        );
+        timer.mark(Stage::Score);
 
-        let optimized_tokens = self.tokenizer.count_tokens(&optimized_prompt);
-        let token_savings = original_tokens as i64 - optimized_tokens as i64;
-        let savings_percentage = if original_tokens > 0 {
-            (token_savings as f64 / original_tokens as f64) * 100.0
-        } else {
-            0.0
-        };
+        // Resolve conflicts (overlapping optimizations)
+        let candidates = request.include_rejected.then(|| optimizations.clone());
+        let mut optimizations = self.resolve_conflicts(optimizations);
+        recount_savings_in_context(original_prompt, &mut optimizations, &self.tokenizer);
+        if let Some(candidates) = candidates {
+            rejected.extend(
+                candidates
+                    .into_iter()
+                    .filter(|c| !optimizations.iter().any(|opt| opt.id == c.id))
+                    .map(|c| RejectedOptimization::new(c, RejectionReason::ConflictLoser)),
+            );
+        }
+        timer.mark(Stage::Resolve);
 
-        Ok(OptimizationResult {
-            original_prompt: original_prompt.clone(),
-            optimized_prompt,
-            original_tokens,
-            optimized_tokens,
-            token_savings,
-            savings_percentage,
-            optimizations: auto_apply,
-            requires_review,
-            output_language: request.output_language.clone(),
-        })
+        (optimizations, rejected)
     }
 
     /// Resolve overlapping optimizations by selecting the best ones
@@ -146,159 +710,621 @@ impl Optimizer {
         resolved
     }
 
-    /// Apply optimizations to text
-    fn apply_optimizations(&self, text: &str, optimizations: &[Optimization]) -> String {
-        if optimizations.is_empty() {
-            return text.to_string();
-        }
+    /// Apply optimizations to text, leaving `protected_regions` byte-for-byte
+    fn apply_optimizations(
+        &self,
+        text: &str,
+        optimizations: &[Optimization],
+        protected_regions: &[ProtectedRegion],
+        capitalize: bool,
+    ) -> String {
+        apply_pass(text, optimizations, protected_regions, capitalize.then_some(&self.sentences))
+    }
 
-        let mut result = String::new();
-        let mut last_pos = 0;
+    /// Get reference to confidence calculator
+    pub fn calculator(&self) -> &ConfidenceCalculator {
+        &self.calculator
+    }
 
-        for opt in optimizations {
-            // Add text before this optimization
-            result.push_str(&text[last_pos..opt.start_pos]);
+    /// Get mutable reference to confidence calculator
+    pub fn calculator_mut(&mut self) -> &mut ConfidenceCalculator {
+        &mut self.calculator
+    }
+}
 
-            // Add optimized text
-            result.push_str(&opt.optimized_text);
+/// Pipeline stage charged by `StageTimer::mark`
+#[derive(Clone, Copy)]
+enum Stage {
+    Detect,
+    Score,
+    Resolve,
+    Apply,
+    Directive,
+}
+
+/// Accumulates per-stage time when profiling; a no-op otherwise
+struct StageTimer {
+    timings: Option<(StageTimings, Instant)>,
+}
 
-            last_pos = opt.end_pos;
+impl StageTimer {
+    fn new(enabled: bool) -> Self {
+        Self {
+            timings: enabled.then(|| (StageTimings::default(), Instant::now())),
         }
+    }
 
-        // Add remaining text
-        result.push_str(&text[last_pos..]);
+    /// Charge the time since the previous mark to `stage`
+    fn mark(&mut self, stage: Stage) {
+        let Some((timings, last)) = &mut self.timings else {
+            return;
+        };
+        let now = Instant::now();
+        let ms = now.duration_since(*last).as_secs_f64() * 1000.0;
+        *last = now;
+        match stage {
+            Stage::Detect => timings.detect_ms += ms,
+            Stage::Score => timings.score_ms += ms,
+            Stage::Resolve => timings.resolve_ms += ms,
+            Stage::Apply => timings.apply_ms += ms,
+            Stage::Directive => timings.directive_ms += ms,
+        }
+    }
 
-        // Clean up extra whitespace
-        self.clean_whitespace(&result)
+    fn finish(self) -> Option<StageTimings> {
+        self.timings.map(|(timings, _)| timings)
     }
+}
+
+/// A prompt split around its `protected_prefix`/`protected_suffix`
+///
+/// Only the middle is optimized; the ends, together with the whitespace
+/// separating them from the middle, are reattached byte-for-byte. Without a
+/// `protected_prefix`, conservative protection splits off YAML frontmatter
+/// the same way, so capitalization and the directive never touch it.
+pub(crate) struct ProtectedEnds<'a> {
+    head: &'a str,
+    tail: &'a str,
+    inner: OptimizationRequest,
+    warnings: Vec<String>,
+}
 
-    /// Clean up extra whitespace
-    fn clean_whitespace(&self, text: &str) -> String {
-        // Remove multiple spaces
-        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+impl<'a> ProtectedEnds<'a> {
+    /// Split `request`, or `None` when it protects neither end
+    pub(crate) fn split(
+        request: &'a OptimizationRequest,
+        region_detector: &ProtectedRegionDetector,
+    ) -> Option<Self> {
+        let non_empty = |end: &'a Option<String>| end.as_deref().filter(|s| !s.is_empty());
+        let prefix = non_empty(&request.protected_prefix);
+        let suffix = non_empty(&request.protected_suffix);
+        let prompt = request.prompt.as_str();
+        let frontmatter = match (prefix, request.protection_policy) {
+            (None, ProtectionPolicy::Conservative) => {
+                region_detector.detect_frontmatter(prompt).first().map(|r| r.end)
+            }
+            _ => None,
+        };
+        if prefix.is_none() && suffix.is_none() && frontmatter.is_none() {
+            return None;
+        }
 
-        // Clean up punctuation spacing
-        let text = text.replace(" .", ".").replace(" ,", ",");
-        let text = text.replace(" !", "!").replace(" ?", "?");
+        let mut warnings = Vec::new();
+        let mut start = frontmatter.unwrap_or(0);
+        if let Some(prefix) = prefix {
+            if prompt.starts_with(prefix) {
+                start = prefix.len();
+            } else {
+                warnings.push("protected_prefix not found at the start of the prompt".to_string());
+            }
+        }
+        let mut end = prompt.len();
+        if let Some(suffix) = suffix {
+            if prompt[start..].ends_with(suffix) {
+                end -= suffix.len();
+            } else {
+                warnings.push("protected_suffix not found at the end of the prompt".to_string());
+            }
+        }
 
-        let text = text.trim().to_string();
+        // Keep the separators too, so the ends stay where they were
+        let middle = &prompt[start..end];
+        let start = start + (middle.len() - middle.trim_start().len());
+        let end = start.max(end - (middle.len() - middle.trim_end().len()));
+
+        Some(Self {
+            head: &prompt[..start],
+            tail: &prompt[end..],
+            inner: OptimizationRequest {
+                prompt: prompt[start..end].to_string(),
+                protected_prefix: None,
+                protected_suffix: None,
+                ..request.clone()
+            },
+            warnings,
+        })
+    }
 
-        // Capitalize sentence starts
-        self.capitalize_sentences(&text)
+    /// Request covering only the middle of the prompt
+    pub(crate) fn inner(&self) -> &OptimizationRequest {
+        &self.inner
     }
 
-    /// Capitalize the first letter after sentence boundaries
-    fn capitalize_sentences(&self, text: &str) -> String {
-        if text.is_empty() {
-            return text.to_string();
+    /// Reattach the protected ends to the optimized middle, moving positions
+    /// and token counts back to the full prompt
+    pub(crate) fn rejoin(self, mut result: OptimizationResult, tokenizer: &Tokenizer) -> OptimizationResult {
+        let offset = self.head.len();
+        for opt in result.optimizations.iter_mut().chain(&mut result.requires_review) {
+            opt.start_pos += offset;
+            opt.end_pos += offset;
         }
-
-        let mut result = String::with_capacity(text.len());
-        let mut chars = text.chars().peekable();
-        let mut capitalize_next = true;  // First character should be capitalized
-
-        while let Some(ch) = chars.next() {
-            if capitalize_next && ch.is_alphabetic() {
-                result.extend(ch.to_uppercase());
-                capitalize_next = false;
-            } else {
-                result.push(ch);
-
-                // Set flag to capitalize after sentence boundaries
-                if matches!(ch, '.' | '!' | '?') {
-                    // Skip whitespace after punctuation
-                    while let Some(&next_ch) = chars.peek() {
-                        if next_ch.is_whitespace() {
-                            result.push(chars.next().unwrap());
-                        } else {
-                            break;
-                        }
-                    }
-                    capitalize_next = true;
-                }
-            }
+        for rejected in &mut result.rejected {
+            rejected.start_pos += offset;
+            rejected.end_pos += offset;
         }
 
+        result.original_prompt = format!("{}{}{}", self.head, result.original_prompt, self.tail);
+        result.optimized_prompt = format!("{}{}{}", self.head, result.optimized_prompt, self.tail);
+        result.original_tokens = tokenizer.count_tokens(&result.original_prompt);
+        result.optimized_tokens = tokenizer.count_tokens(&result.optimized_prompt);
+        result.token_savings = result.original_tokens as i64 - result.optimized_tokens as i64;
+        result.savings_including_directive = result.token_savings;
+        result.savings_excluding_directive = result.token_savings + result.directive_tokens as i64;
+        result.savings_percentage = savings_percentage(result.original_tokens, result.optimized_tokens);
+        result.compression_ratio = compression_ratio(result.original_tokens, result.optimized_tokens);
+        result.net_savings_negative = result.token_savings < 0;
+        result.diff_stats = DiffStats::between(&result.original_prompt, &result.optimized_prompt);
+        // Replays cover the whole prompt, ends included
+        result.replayable = false;
+
+        let mut warnings = self.warnings;
+        warnings.append(&mut result.warnings);
+        result.warnings = warnings;
         result
     }
+}
 
-    /// Add language directive to prompt
-    fn add_language_directive(
-        &self,
-        prompt: &str,
-        language: &Language,
-        format: &DirectiveFormat,
-    ) -> String {
-        let lang_str = match language {
-            Language::English => "english",
-            Language::Mandarin => "mandarin",
-        };
+/// Check that an optimized prompt is safe to return in place of the original
+///
+/// Rejects empty output for a non-empty prompt and output that no longer
+/// round-trips as UTF-8 or gained replacement characters (a cut multibyte char).
+pub(crate) fn validate_optimized_prompt(original: &str, optimized: &str) -> Result<(), String> {
+    if optimized.trim().is_empty() && !original.trim().is_empty() {
+        return Err("Optimization produced an empty prompt".to_string());
+    }
 
-        let directive = match format {
-            DirectiveFormat::Bracketed => format!("[output_language: {}]", lang_str),
-            DirectiveFormat::Instructive => format!("Respond in {}.", Self::capitalize(lang_str)),
-            DirectiveFormat::Xml => {
-                format!("<output_language>{}</output_language>", lang_str)
-            }
-            DirectiveFormat::Natural => {
-                format!("Please respond to me in {}.", Self::capitalize(lang_str))
-            }
-        };
+    let round_tripped = String::from_utf8_lossy(optimized.as_bytes());
+    if round_tripped != optimized
+        || optimized.matches('\u{FFFD}').count() > original.matches('\u{FFFD}').count()
+    {
+        return Err("Optimization produced invalid UTF-8".to_string());
+    }
+
+    Ok(())
+}
 
-        format!("{}\n\n{}", prompt.trim(), directive)
+/// The output language directive on its own
+pub(crate) fn language_directive(language: &Language, format: &DirectiveFormat) -> String {
+    let lang_str = match language {
+        Language::English => "english",
+        Language::Mandarin => "mandarin",
+    };
+    let lang_title = match language {
+        Language::English => "English",
+        Language::Mandarin => "Mandarin",
+    };
+
+    match format {
+        DirectiveFormat::Bracketed => format!("[output_language: {}]", lang_str),
+        DirectiveFormat::Instructive => format!("Respond in {}.", lang_title),
+        DirectiveFormat::Xml => format!("<output_language>{}</output_language>", lang_str),
+        DirectiveFormat::Natural => format!("Please respond to me in {}.", lang_title),
     }
+}
 
-    fn capitalize(s: &str) -> String {
-        let mut chars = s.chars();
-        match chars.next() {
-            None => String::new(),
-            Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
-        }
+/// Add the output language directive to a prompt
+///
+/// Idempotent: a prompt that already starts or ends with the same directive
+/// (from either placement) is returned unchanged (trimmed, when `trim` is set).
+pub(crate) fn add_language_directive(
+    prompt: &str,
+    language: &Language,
+    format: &DirectiveFormat,
+    placement: DirectivePlacement,
+    separator: &str,
+    trim: bool,
+) -> String {
+    let directive = language_directive(language, format);
+    let body = prompt.trim();
+    if body.starts_with(&directive) || body.ends_with(&directive) {
+        return if trim { body } else { prompt }.to_string();
     }
 
-    /// Get reference to confidence calculator
-    pub fn calculator(&self) -> &ConfidenceCalculator {
-        &self.calculator
+    let joined = match placement {
+        DirectivePlacement::Prepend => format!("{}{}{}", directive, separator, body),
+        DirectivePlacement::Append => format!("{}{}{}", body, separator, directive),
+    };
+    if trim {
+        return joined;
     }
 
-    /// Get mutable reference to confidence calculator
-    pub fn calculator_mut(&mut self) -> &mut ConfidenceCalculator {
-        &mut self.calculator
+    // Keep the prompt's own surrounding whitespace outside the joined text
+    let leading = &prompt[..prompt.len() - prompt.trim_start().len()];
+    let trailing = &prompt[prompt.trim_end().len()..];
+    format!("{}{}{}", leading, joined, trailing)
+}
+
+/// Enforce the request's savings floor once the directive has been added
+///
+/// Returns the prompt to hand back and whether it is the original (so the
+/// applied optimizations should be cleared). `body` is the optimized prompt
+/// without the directive.
+pub(crate) fn apply_savings_floor(
+    floor: SavingsFloor,
+    tokenizer: &Tokenizer,
+    original: &str,
+    body: String,
+    with_directive: String,
+    warnings: &mut Vec<String>,
+) -> (String, bool) {
+    let original_tokens = tokenizer.count_tokens(original);
+    if tokenizer.count_tokens(&with_directive) <= original_tokens {
+        return (with_directive, false);
+    }
+
+    match floor {
+        SavingsFloor::Flag => (with_directive, false),
+        SavingsFloor::OmitDirective if tokenizer.count_tokens(&body) <= original_tokens => {
+            warnings.push("Language directive omitted: it cost more tokens than optimization saved".to_string());
+            (body, false)
+        }
+        SavingsFloor::OmitDirective | SavingsFloor::Original => {
+            warnings.push("Optimization did not save tokens; returning original prompt".to_string());
+            (original.to_string(), true)
+        }
     }
 }
 
-impl Default for Optimizer {
-    fn default() -> Self {
-        Self::new(
-            ConfidenceCalculator::default(),
-            Tokenizer::default(),
-        )
+/// Recount each resolved optimization's savings in context
+///
+/// Savings are first estimated on the matched snippet alone, which can
+/// overstate the effect; once conflicts are resolved each surviving edit is
+/// re-measured within its surrounding words so the per-item numbers roughly
+/// sum to the total.
+pub(crate) fn recount_savings_in_context(
+    text: &str,
+    optimizations: &mut [Optimization],
+    tokenizer: &Tokenizer,
+) {
+    for opt in optimizations {
+        opt.token_savings =
+            tokenizer.savings_in_context(text, opt.start_pos, opt.end_pos, &opt.optimized_text);
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::models::Language;
+/// Replace one pass of sorted, non-overlapping spans in `text`, then clean up
+/// orphans and whitespace and, given `sentences`, capitalize sentence starts
+///
+/// Spans that are out of range, out of order or not on char boundaries are
+/// skipped. The cleanup never touches `protected` regions (given in `text`
+/// coordinates): they come out byte-for-byte. Shared by both optimizers and
+/// `OptimizationResult::apply_to`.
+pub(crate) fn apply_pass(
+    text: &str,
+    optimizations: &[Optimization],
+    protected: &[ProtectedRegion],
+    sentences: Option<&SentenceBoundaries>,
+) -> String {
+    if optimizations.is_empty() {
+        return text.to_string();
+    }
 
-    #[test]
-    fn test_basic_optimization() {
-        let mut optimizer = Optimizer::default();
+    let mut result = String::new();
+    let mut last_pos = 0;
+    let mut removals = Vec::new();
+    // Unchanged stretches of `text` and where each starts in `result`
+    let mut kept: Vec<(Range<usize>, usize)> = Vec::new();
+
+    for opt in optimizations {
+        // Skip out-of-range, overlapping, or non-char-boundary spans
+        if opt.start_pos < last_pos
+            || opt.start_pos > opt.end_pos
+            || opt.end_pos > text.len()
+            || !text.is_char_boundary(opt.start_pos)
+            || !text.is_char_boundary(opt.end_pos)
+        {
+            continue;
+        }
 
-        let request = OptimizationRequest {
-            prompt: "I would really appreciate it if you could please help me with this task."
-                .to_string(),
-            output_language: Language::English,
-            confidence_threshold: 0.85,
-            aggressive_mode: false,
-            directive_format: DirectiveFormat::Bracketed,
-        };
+        // Add text before this optimization
+        kept.push((last_pos..opt.start_pos, result.len()));
+        result.push_str(&text[last_pos..opt.start_pos]);
 
-        let result = optimizer.optimize(&request).unwrap();
+        // Add optimized text
+        result.push_str(&opt.optimized_text);
+        if opt.optimized_text.trim().is_empty() {
+            removals.push(result.len());
+        }
 
-        assert!(result.token_savings > 0);
-        assert!(result.savings_percentage > 0.0);
-        assert!(!result.optimized_prompt.is_empty());
+        last_pos = opt.end_pos;
+    }
+
+    // Add remaining text
+    kept.push((last_pos..text.len(), result.len()));
+    result.push_str(&text[last_pos..]);
+
+    // Protected regions move with the unchanged text around them
+    let mut spans: Vec<Range<usize>> = protected
+        .iter()
+        .filter_map(|r| {
+            let (source, offset) = kept
+                .iter()
+                .find(|(source, _)| source.start <= r.start && r.end <= source.end)?;
+            Some(offset + r.start - source.start..offset + r.end - source.start)
+        })
+        .collect();
+
+    // Clean up orphaned punctuation and extra whitespace
+    let result = remove_orphans(result, &removals, &mut spans);
+    with_spans_masked(&result, &spans, |text| {
+        let text = normalize_whitespace(text);
+        match sentences {
+            Some(sentences) => capitalize_unfenced(sentences, &text),
+            None => text,
+        }
+    })
+}
+
+/// Capitalize sentence starts everywhere but inside fenced code blocks,
+/// fence lines and info strings ("```python") included
+fn capitalize_unfenced(sentences: &SentenceBoundaries, text: &str) -> String {
+    with_spans_masked(text, &fenced_blocks(text), |text| sentences.capitalize(text))
+}
+
+/// Byte ranges of the ``` / ~~~ fenced blocks in `text`, from the opening
+/// fence line through the closing one (or the end of an unclosed block)
+fn fenced_blocks(text: &str) -> Vec<Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut open: Option<(usize, &str)> = None;
+    let mut pos = 0;
+
+    for line in text.split_inclusive('\n') {
+        let body = line.trim_start();
+        let fence = ["```", "~~~"].into_iter().find(|f| body.starts_with(f));
+        match (open, fence) {
+            (None, Some(fence)) => open = Some((pos, fence)),
+            (Some((start, opened)), Some(fence)) if fence == opened => {
+                blocks.push(start..pos + line.trim_end().len());
+                open = None;
+            }
+            _ => {}
+        }
+        pos += line.len();
+    }
+    if let Some((start, _)) = open {
+        blocks.push(start..text.len());
+    }
+    blocks
+}
+
+/// Drop punctuation and conjunctions orphaned by removals
+///
+/// `removals` are the byte offsets, in ascending order, where removed text
+/// used to be. At a sentence start the following `,`/`;`/`:` or terminal
+/// punctuation and a dangling "and"/"but"/"or" go ("Thank you, but keep it
+/// short" → "keep it short"); before the end of a sentence a trailing comma,
+/// semicolon or conjunction goes ("the report, thank you." → "the report.").
+/// Nothing inside `protected` is dropped; the spans are shifted to follow
+/// the text.
+pub(crate) fn remove_orphans(mut text: String, removals: &[usize], protected: &mut [Range<usize>]) -> String {
+    let mut remove = |text: &mut String, range: Range<usize>| {
+        if range.is_empty() || protected.iter().any(|p| p.start < range.end && range.start < p.end) {
+            return;
+        }
+        text.replace_range(range.clone(), "");
+        for span in protected.iter_mut().filter(|p| p.start >= range.end) {
+            *span = span.start - range.len()..span.end - range.len();
+        }
+    };
+
+    for &pos in removals.iter().rev() {
+        let before = &text[..pos];
+        let after = &text[pos..];
+
+        let at_sentence_start = before.trim_end().is_empty()
+            || before.trim_end().ends_with(['.', '!', '?'])
+            || before.trim_end_matches([' ', '\t']).ends_with('\n');
+
+        if at_sentence_start {
+            let mut rest = after;
+            let mut orphan_end = 0;
+            loop {
+                let trimmed = rest.trim_start_matches([' ', '\t']);
+                let skipped = if trimmed.starts_with([',', ';', ':', '.', '!', '?']) {
+                    1
+                } else if let Some(word) = leading_conjunction(trimmed) {
+                    word.len()
+                } else {
+                    break;
+                };
+                rest = &trimmed[skipped..];
+                orphan_end = after.len() - rest.len();
+            }
+            remove(&mut text, pos..pos + orphan_end);
+            continue;
+        }
+
+        let trailing = after.trim_start_matches([' ', '\t']);
+        let at_sentence_end = trailing.is_empty() || trailing.starts_with(['.', '!', '?', '\n']);
+        if at_sentence_end {
+            let mut kept = before;
+            loop {
+                let trimmed = kept.trim_end_matches([' ', '\t']);
+                if let Some(stripped) = trimmed.strip_suffix([',', ';']) {
+                    kept = stripped;
+                } else if let Some(word) = trailing_conjunction(trimmed) {
+                    kept = &trimmed[..trimmed.len() - word.len()];
+                } else {
+                    break;
+                }
+            }
+            let orphan_start = kept.len();
+            remove(&mut text, orphan_start..pos);
+        }
+    }
+
+    text
+}
+
+/// A dangling conjunction opening `text`, as a whole word
+fn leading_conjunction(text: &str) -> Option<&str> {
+    let word_end = text.find(|c: char| !c.is_alphabetic()).unwrap_or(text.len());
+    let word = &text[..word_end];
+    let dangling = DANGLING_CONJUNCTIONS.iter().any(|c| c.eq_ignore_ascii_case(word));
+    (dangling && word_end < text.len()).then_some(word)
+}
+
+/// A dangling conjunction closing `text`, as a whole word
+fn trailing_conjunction(text: &str) -> Option<&str> {
+    let word_start = text
+        .rfind(|c: char| !c.is_alphabetic())
+        .map_or(0, |i| i + text[i..].chars().next().map_or(1, char::len_utf8));
+    let word = &text[word_start..];
+    let dangling = DANGLING_CONJUNCTIONS.iter().any(|c| c.eq_ignore_ascii_case(word));
+    (dangling && word_start > 0).then_some(word)
+}
+
+/// Run `clean` over `text` with every span in `spans` swapped for an opaque
+/// placeholder, then put the spans back verbatim
+///
+/// A placeholder is a private-use marker character absent from `text`
+/// around the span's index: no whitespace, punctuation or letters, so
+/// neither whitespace cleanup nor capitalization can alter or split it.
+fn with_spans_masked(text: &str, spans: &[Range<usize>], clean: impl FnOnce(&str) -> String) -> String {
+    let mut spans: Vec<Range<usize>> = spans.iter().filter(|s| !s.is_empty()).cloned().collect();
+    let Some(marker) = ('\u{E000}'..='\u{F8FF}').find(|c| !text.contains(*c)) else {
+        return clean(text);
+    };
+    if spans.is_empty() {
+        return clean(text);
+    }
+
+    // Overlapping spans mask as one
+    spans.sort_by_key(|s| s.start);
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.start <= last.end => last.end = last.end.max(span.end),
+            _ => merged.push(span),
+        }
+    }
+
+    let mut masked = String::with_capacity(text.len());
+    let mut last = 0;
+    for (i, span) in merged.iter().enumerate() {
+        masked.push_str(&text[last..span.start]);
+        masked.push(marker);
+        masked.push_str(&i.to_string());
+        masked.push(marker);
+        last = span.end;
+    }
+    masked.push_str(&text[last..]);
+
+    let cleaned = clean(&masked);
+    let mut restored = String::with_capacity(text.len());
+    let mut parts = cleaned.split(marker);
+    restored.push_str(parts.next().unwrap_or_default());
+    while let (Some(index), Some(rest)) = (parts.next(), parts.next()) {
+        if let Some(span) = index.parse::<usize>().ok().and_then(|i| merged.get(i)) {
+            restored.push_str(&text[span.clone()]);
+        }
+        restored.push_str(rest);
+    }
+    restored
+}
+
+/// Tidy whitespace left behind after applying optimizations
+///
+/// Runs of spaces collapse to one and spaces before punctuation are dropped,
+/// but line breaks are kept: more than one blank line collapses to a single
+/// paragraph break. Indentation of two or more spaces (or a tab) survives; a
+/// lone leading space is a removal artifact and is trimmed. Markdown table
+/// rows keep their column padding.
+pub(crate) fn normalize_whitespace(text: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in text.lines() {
+        let body = line.trim_start();
+        if body.starts_with('|') {
+            lines.push(line.trim_end().to_string());
+            continue;
+        }
+        let indent = &line[..line.len() - body.len()];
+        let indent = if indent.starts_with('\t') || indent.len() >= 2 {
+            indent
+        } else {
+            ""
+        };
+
+        let mut cleaned = String::with_capacity(line.len());
+        for word in body.split_whitespace() {
+            let attaches = word.starts_with(['.', ',', '!', '?', ';', ':']);
+            if !cleaned.is_empty() && !attaches {
+                cleaned.push(' ');
+            }
+            cleaned.push_str(word);
+        }
+
+        // At most one blank line in a row
+        if cleaned.is_empty() {
+            if lines.last().is_some_and(|l| l.is_empty()) {
+                continue;
+            }
+            lines.push(String::new());
+        } else {
+            lines.push(format!("{}{}", indent, cleaned));
+        }
+    }
+
+    lines.join("\n").trim_matches('\n').trim_end().to_string()
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self::new(
+            ConfidenceCalculator::default(),
+            Tokenizer::default(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{reasoning_code, ContractionPolicy, Language, OptimizationType, ReasoningStyle, ReviewPolicy};
+    use crate::patterns::Pattern;
+    use crate::models::Locale;
+    use crate::protected_regions::{ProtectionPolicy, RegionType};
+
+    #[test]
+    fn test_basic_optimization() {
+        let optimizer = Optimizer::default();
+
+        let request = OptimizationRequest {
+            prompt: "I would really appreciate it if you could please help me with this task."
+                .to_string(),
+            output_language: Language::English,
+            confidence_threshold: 0.85,
+            aggressive_mode: false,
+            directive_format: DirectiveFormat::Bracketed,
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+
+        assert!(result.token_savings > 0);
+        assert!(result.savings_percentage > 0.0);
+        assert!(!result.optimized_prompt.is_empty());
     }
 
     #[test]
@@ -316,6 +1342,7 @@ mod tests {
             reasoning: "test".to_string(),
             start_pos: 0,
             end_pos: 4,
+            pass: 0,
         };
 
         let opt2 = Optimization {
@@ -329,6 +1356,7 @@ mod tests {
             reasoning: "test".to_string(),
             start_pos: 2,
             end_pos: 14,
+            pass: 0,
         };
 
         let resolved = optimizer.resolve_conflicts(vec![opt1, opt2]);
@@ -339,53 +1367,1270 @@ mod tests {
     }
 
     #[test]
-    fn test_language_directive() {
+    fn test_apply_skips_invalid_optimizations() {
         let optimizer = Optimizer::default();
+        let text = "Please summarize the report.";
+        let make = |start_pos: usize, end_pos: usize, optimized: &str| Optimization {
+            id: format!("{}-{}", start_pos, end_pos),
+            optimization_type: crate::models::OptimizationType::BoilerplateRemoval,
+            original_text: String::new(),
+            optimized_text: optimized.to_string(),
+            token_savings: 1,
+            confidence: crate::models::OptimizationConfidence::new(0.9, 0.0, 0.0, 0.0),
+            requires_review: false,
+            reasoning: "test".to_string(),
+            start_pos,
+            end_pos,
+            pass: 0,
+        };
 
+        let opts = vec![
+            make(0, 7, ""),
+            make(3, 5, "x"), // overlaps the previous edit
+            make(20, 10, "x"), // start after end
+            make(25, text.len() + 5, ""), // past the end of the text
+        ];
+
+        let result = optimizer.apply_optimizations(text, &opts, &[], true);
+        assert_eq!(result, "Summarize the report.");
+    }
+
+    #[test]
+    fn test_directive_dominated_prompt_reports_growth() {
+        let optimizer = Optimizer::default();
+        let request = OptimizationRequest {
+            prompt: "Hi.".to_string(),
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result.optimized_tokens > 2 * result.original_tokens);
+        assert!(result.net_savings_negative);
+        assert_eq!(result.savings_percentage, -100.0);
+        assert_eq!(
+            result.compression_ratio,
+            result.optimized_tokens as f64 / result.original_tokens as f64
+        );
+    }
+
+    #[test]
+    fn test_language_directive() {
         let text = "Test prompt";
 
-        let result = optimizer.add_language_directive(
+        let result = add_language_directive(
             text,
             &Language::English,
             &DirectiveFormat::Bracketed,
+            DirectivePlacement::Append,
+            "\n\n",
+            true,
         );
 
         assert!(result.contains("[output_language: english]"));
     }
 
+    #[test]
+    fn test_directive_placement() {
+        let append = add_language_directive(
+            "Test prompt",
+            &Language::Mandarin,
+            &DirectiveFormat::Instructive,
+            DirectivePlacement::Append,
+            "\n\n",
+            true,
+        );
+        assert_eq!(append, "Test prompt\n\nRespond in Mandarin.");
+
+        let prepend = add_language_directive(
+            "Test prompt",
+            &Language::Mandarin,
+            &DirectiveFormat::Instructive,
+            DirectivePlacement::Prepend,
+            "\n\n",
+            true,
+        );
+        assert_eq!(prepend, "Respond in Mandarin.\n\nTest prompt");
+
+        // Re-adding is a no-op whichever placement produced the directive
+        for placement in [DirectivePlacement::Prepend, DirectivePlacement::Append] {
+            for existing in [&append, &prepend] {
+                assert_eq!(
+                    &add_language_directive(
+                        existing,
+                        &Language::Mandarin,
+                        &DirectiveFormat::Instructive,
+                        placement,
+                        "\n\n",
+                        true,
+                    ),
+                    existing
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_directive_separator_and_trim() {
+        let join = |prompt: &str, placement, separator: &str, trim: bool| {
+            add_language_directive(
+                prompt,
+                &Language::English,
+                &DirectiveFormat::Bracketed,
+                placement,
+                separator,
+                trim,
+            )
+        };
+
+        assert_eq!(
+            join("  Test prompt\n", DirectivePlacement::Append, " ", true),
+            "Test prompt [output_language: english]"
+        );
+        // Untrimmed, the trailing newline stays last
+        assert_eq!(
+            join("  Test prompt\n", DirectivePlacement::Append, "\n", false),
+            "  Test prompt\n[output_language: english]\n"
+        );
+        assert_eq!(
+            join("Test prompt\n", DirectivePlacement::Prepend, "\n---\n", false),
+            "[output_language: english]\n---\nTest prompt\n"
+        );
+
+        // Through a request
+        let optimizer = Optimizer::default();
+        let request = OptimizationRequest::builder("Summarize the attached report.\n")
+            .directive_separator(" ")
+            .directive_trim(false)
+            .build();
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result
+            .optimized_prompt
+            .ends_with(". [output_language: english]\n"));
+    }
+
+    #[test]
+    fn test_optimize_messages() {
+        let optimizer = Optimizer::default();
+        let boilerplate = "I would really appreciate it if you could please help me analyze this code.";
+        let messages = vec![
+            Message::new("system", boilerplate),
+            Message::new("user", boilerplate),
+            Message::new("assistant", "Sure, paste it."),
+            Message::new("user", "  "),
+            Message::new("user", boilerplate),
+        ];
+        let request = OptimizationRequest::default();
+
+        let result = optimizer.optimize_messages(messages.clone(), &request).unwrap();
+        let roles: Vec<_> = result.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "user", "assistant", "user", "user"]);
+        assert_eq!(result.results.len(), 5);
+        assert!(result.results[3].is_none());
+
+        // Every message is optimized; only the last user message gets the directive
+        assert_ne!(result.messages[0].content, boilerplate);
+        assert!(!result.messages[1].content.contains("[output_language"));
+        assert!(result.messages[4].content.ends_with("[output_language: english]"));
+        assert_eq!(
+            result.messages.iter().filter(|m| m.content.contains("[output_language")).count(),
+            1
+        );
+        assert!(result.directive_tokens > 0);
+
+        // Savings add up across messages
+        let tokenizer = Tokenizer::new().unwrap();
+        let count = |messages: &[Message]| -> usize {
+            messages.iter().map(|m| tokenizer.count_tokens(&m.content)).sum()
+        };
+        assert_eq!(result.original_tokens, count(&messages));
+        assert_eq!(result.optimized_tokens, count(&result.messages));
+        assert!(result.token_savings > 0);
+
+        // System messages can be left alone
+        let request = OptimizationRequest::builder("").skip_system_messages(true).build();
+        let result = optimizer.optimize_messages(messages, &request).unwrap();
+        assert_eq!(result.messages[0].content, boilerplate);
+        assert!(result.results[0].is_none());
+    }
+
+    #[test]
+    fn test_optimize_messages_without_user_message() {
+        let optimizer = Optimizer::default();
+        let messages = vec![Message::new("assistant", "Here is the summary you asked for.")];
+
+        let result = optimizer
+            .optimize_messages(messages, &OptimizationRequest::default())
+            .unwrap();
+        assert_eq!(
+            result.messages[0],
+            Message::new("system", "[output_language: english]")
+        );
+        assert_eq!(result.messages[1].role, "assistant");
+        assert!(result.results[0].is_none());
+        assert!(result.results[1].is_some());
+
+        // No directive at all when disabled
+        let request = OptimizationRequest::builder("Summarize this.")
+            .language_directive(false)
+            .build();
+        assert_eq!(optimizer.optimize(&request).unwrap().optimized_prompt, "Summarize this.");
+        let messages = vec![Message::new("user", "Summarize this.")];
+        let result = optimizer.optimize_messages(messages.clone(), &request).unwrap();
+        assert_eq!(result.messages, messages);
+        assert_eq!(result.directive_tokens, 0);
+    }
+
+    #[test]
+    fn test_optimize_prepends_directive() {
+        let optimizer = Optimizer::default();
+        let request = OptimizationRequest {
+            prompt: "I would really appreciate it if you could analyze this code.".to_string(),
+            directive_placement: DirectivePlacement::Prepend,
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result.optimized_prompt.starts_with("[output_language: english]\n\n"));
+
+        // Optimizing the output again does not stack a second directive
+        let again = optimizer
+            .optimize(&OptimizationRequest {
+                prompt: result.optimized_prompt.clone(),
+                ..request
+            })
+            .unwrap();
+        assert_eq!(again.optimized_prompt.matches("[output_language").count(), 1);
+    }
+
+    #[test]
+    fn test_savings_floor_on_short_prompt() {
+        let optimizer = Optimizer::default();
+        let request = OptimizationRequest {
+            prompt: "List primes.".to_string(),
+            output_language: Language::English,
+            directive_format: DirectiveFormat::Natural,
+            ..Default::default()
+        };
+
+        // Default: the directive dominates and the result says so
+        let flagged = optimizer.optimize(&request).unwrap();
+        assert!(flagged.token_savings < 0);
+        assert!(flagged.net_savings_negative);
+
+        let omitted = optimizer
+            .optimize(&OptimizationRequest {
+                savings_floor: SavingsFloor::OmitDirective,
+                ..request.clone()
+            })
+            .unwrap();
+        assert_eq!(omitted.optimized_prompt, "List primes.");
+        assert!(!omitted.net_savings_negative);
+        assert_eq!(omitted.warnings.len(), 1);
+
+        let original = optimizer
+            .optimize(&OptimizationRequest {
+                savings_floor: SavingsFloor::Original,
+                ..request
+            })
+            .unwrap();
+        assert_eq!(original.optimized_prompt, "List primes.");
+        assert_eq!(original.token_savings, 0);
+        assert!(original.optimizations.is_empty());
+    }
+
+    #[test]
+    fn test_directive_tokens_accounted_separately() {
+        let optimizer = Optimizer::default();
+        let request = OptimizationRequest {
+            prompt: "I would really appreciate it if you could analyze this code. Thank you so much!".to_string(),
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+        let directive = optimizer.tokenizer.count_tokens("\n\n[output_language: english]");
+        assert!(result.directive_tokens > 0);
+        assert!(result.directive_tokens.abs_diff(directive) <= 1);
+        assert_eq!(result.savings_including_directive, result.token_savings);
+        assert_eq!(
+            result.savings_excluding_directive - result.savings_including_directive,
+            result.directive_tokens as i64
+        );
+        // Without the directive the body alone measures the excluded savings
+        let body = result.optimized_prompt.replace("\n\n[output_language: english]", "");
+        assert_eq!(
+            result.savings_excluding_directive,
+            result.original_tokens as i64 - optimizer.tokenizer.count_tokens(&body) as i64
+        );
+
+        // An omitted directive costs nothing
+        let omitted = optimizer
+            .optimize(&OptimizationRequest {
+                prompt: "List primes.".to_string(),
+                savings_floor: SavingsFloor::OmitDirective,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(omitted.directive_tokens, 0);
+        assert_eq!(omitted.savings_excluding_directive, omitted.savings_including_directive);
+    }
+
     #[test]
     fn test_capitalize_sentences() {
         let optimizer = Optimizer::default();
 
         assert_eq!(
-            optimizer.capitalize_sentences("hello. world"),
+            optimizer.sentences.capitalize("hello. world"),
             "Hello. World"
         );
         assert_eq!(
-            optimizer.capitalize_sentences("test! another. one?"),
+            optimizer.sentences.capitalize("test! another. one?"),
             "Test! Another. One?"
         );
         assert_eq!(
-            optimizer.capitalize_sentences("already Capitalized."),
+            optimizer.sentences.capitalize("already Capitalized."),
             "Already Capitalized."
         );
     }
 
     #[test]
     fn test_no_orphaned_phrases() {
-        let mut optimizer = Optimizer::default();
+        let optimizer = Optimizer::default();
 
         let request = OptimizationRequest {
-            prompt: "Thank you so much in advance for your help with this!".to_string(),
+            prompt: "Summarize the attached report. Thank you so much in advance for your help with this!".to_string(),
             output_language: Language::English,
             confidence_threshold: 0.85,
             aggressive_mode: false,
             directive_format: DirectiveFormat::Bracketed,
+            ..Default::default()
         };
 
         let result = optimizer.optimize(&request).unwrap();
 
         // Should not contain orphaned "for your help with this!"
+        assert!(result.warnings.is_empty());
         assert!(!result.optimized_prompt.contains("for your help"));
     }
+
+    #[test]
+    fn test_removal_leaves_no_orphaned_connectors() {
+        let optimizer = Optimizer::default();
+        let optimize = |prompt: &str, aggressive_mode: bool| {
+            let request = OptimizationRequest {
+                prompt: prompt.to_string(),
+                aggressive_mode,
+                directive_format: DirectiveFormat::Bracketed,
+                ..Default::default()
+            };
+            let result = optimizer.optimize(&request).unwrap();
+            result.optimized_prompt.replace("\n\n[output_language: english]", "")
+        };
+
+        assert_eq!(optimize("Also, please analyze the code.", true), "Analyze code.");
+        assert_eq!(
+            optimize("Summarize the report. Thank you so much in advance, and also analyze the code.", false),
+            "Summarize the report. Also analyze the code."
+        );
+        assert_eq!(optimize("Thank you so much in advance, but keep it short.", false), "Keep it short.");
+        assert_eq!(optimize("Summarize the report, thank you so much in advance.", false), "Summarize the report.");
+    }
+
+    #[test]
+    fn test_remove_orphans() {
+        // Leading comma (and connector) at a sentence start
+        assert_eq!(remove_orphans(", analyze the code.".to_string(), &[0], &mut []), " analyze the code.");
+        assert_eq!(remove_orphans("Done.  ; or check it.".to_string(), &[6], &mut []), "Done.  check it.");
+        // Trailing comma or connector before the end of a sentence
+        assert_eq!(remove_orphans("Summarize it, and .".to_string(), &[18], &mut []), "Summarize it.");
+        // Mid-sentence removals and untouched text are left alone
+        assert_eq!(remove_orphans("Also, analyze the code.".to_string(), &[6], &mut []), "Also, analyze the code.");
+        assert_eq!(remove_orphans("And, or so.".to_string(), &[], &mut []), "And, or so.");
+        assert_eq!(remove_orphans("Android tips.".to_string(), &[0], &mut []), "Android tips.");
+    }
+
+    #[test]
+    fn test_removed_line_leaves_one_blank_line() {
+        let optimizer = Optimizer::default();
+
+        let request = OptimizationRequest {
+            prompt: "Summarize the attached report.\n\nThank you so much in advance for your help with this!\n\nFocus on revenue.".to_string(),
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+
+        // Exactly one paragraph break where the thank-you line was
+        assert!(result
+            .optimized_prompt
+            .starts_with("Summarize the attached report.\n\nFocus on revenue."));
+    }
+
+    #[test]
+    fn test_contraction_policy_only_rewrites_when_cheaper() {
+        let mut optimizer = Optimizer::default();
+        let prompt = r#"You can't skip the tests, and you don't need to rebuild. Quote it as "can't skip"."#;
+        let run = |optimizer: &mut Optimizer, policy| {
+            optimizer
+                .optimize(&OptimizationRequest {
+                    prompt: prompt.to_string(),
+                    contraction_policy: policy,
+                    ..Default::default()
+                })
+                .unwrap()
+        };
+
+        // cl100k_base: " cannot" is one token, " can't" two; "don't" and
+        // "do not" cost the same, so they are left alone
+        let cheapest = run(&mut optimizer, ContractionPolicy::Cheapest);
+        assert!(cheapest.optimized_prompt.starts_with("You cannot skip the tests, and you don't need"));
+        assert!(cheapest.optimized_prompt.contains(r#""can't skip""#));
+        assert_eq!(
+            cheapest
+                .optimizations
+                .iter()
+                .filter(|o| o.optimization_type == OptimizationType::FormatConsolidation)
+                .count(),
+            1
+        );
+
+        let expand = run(&mut optimizer, ContractionPolicy::Expand);
+        assert_eq!(expand.optimized_prompt, cheapest.optimized_prompt);
+
+        // Contracting would cost tokens, so nothing changes
+        for policy in [ContractionPolicy::Contract, ContractionPolicy::Off] {
+            let result = run(&mut optimizer, policy);
+            assert!(result.optimized_prompt.starts_with("You can't skip the tests, and you don't need"));
+        }
+    }
+
+    #[test]
+    fn test_with_detector_uses_custom_patterns() {
+        let only_custom = PatternsConfig {
+            boilerplate_enabled: false,
+            synonym_consolidation: false,
+            filler_removal: false,
+            mandarin_substitution: false,
+            format_consolidation: false,
+            whitespace_normalization: false,
+        };
+        let detector = PatternDetector::with_config(only_custom).with_pattern(Pattern {
+            pattern_type: OptimizationType::FormatConsolidation,
+            regex: regex::Regex::new(r"(?i)\bas soon as possible\b").unwrap(),
+            replacement: "ASAP".to_string(),
+            base_confidence: 0.98,
+            reasoning: "House abbreviation".to_string(),
+            context_guard: None,
+            priority: None,
+            aggressive_only: false,
+        });
+        let optimizer =
+            Optimizer::with_detector(detector, ConfidenceCalculator::default(), Tokenizer::default());
+
+        let request = OptimizationRequest {
+            prompt: "Please reply as soon as possible with the summary.".to_string(),
+            ..Default::default()
+        };
+        let result = optimizer.optimize(&request).unwrap();
+
+        // The custom pattern applies; disabled families (boilerplate) don't
+        assert!(result.optimized_prompt.starts_with("Please reply ASAP with the summary."));
+        assert_eq!(result.optimizations.len(), 1);
+        assert_eq!(result.optimizations[0].reasoning, "House abbreviation");
+    }
+
+    #[test]
+    fn test_shared_optimizer_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<PatternDetector>();
+        assert_send_sync::<Optimizer>();
+
+        let detector = PatternDetector::new();
+        let optimizer = std::sync::Arc::new(Optimizer::with_detector(
+            detector.clone(),
+            ConfidenceCalculator::default(),
+            Tokenizer::default(),
+        ));
+        let request = OptimizationRequest {
+            prompt: "I would really appreciate it if you could summarize this report.".to_string(),
+            ..Default::default()
+        };
+        let expected = optimizer.optimize(&request).unwrap().optimized_prompt;
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let optimizer = std::sync::Arc::clone(&optimizer);
+                let detector = detector.clone();
+                let request = request.clone();
+                std::thread::spawn(move || {
+                    assert!(!detector.detect_all(&request.prompt).is_empty());
+                    optimizer.optimize(&request).unwrap().optimized_prompt
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_cascading_boilerplate_collapses() {
+        let optimizer = Optimizer::default();
+
+        // Removing the filler exposes "I would like you to" to a second pass;
+        // "I would obviously appreciate it if you could" likewise
+        for prompt in [
+            "I would actually like you to summarize this report.",
+            "I would obviously appreciate it if you could summarize this report.",
+            "Please make sure to make sure to make sure to summarize this report.",
+        ] {
+            let request = OptimizationRequest {
+                prompt: prompt.to_string(),
+                aggressive_mode: true,
+                ..Default::default()
+            };
+
+            let result = optimizer.optimize(&request).unwrap();
+            assert_eq!(
+                result.optimized_prompt,
+                "Summarize this report.\n\n[output_language: english]",
+                "{}",
+                prompt
+            );
+            assert!(result.optimizations.len() >= 2);
+        }
+    }
+
+    #[test]
+    fn test_apply_to_rederives_optimized_prompt() {
+        let optimizer = Optimizer::default();
+
+        let mut multi_pass = false;
+        for prompt in [
+            "I would actually like you to summarize this report.",
+            "Please make sure to make sure to make sure to summarize this report.",
+            "In order to basically help me, could you please analyze the data? Thanks in advance!",
+        ] {
+            let request = OptimizationRequest {
+                prompt: prompt.to_string(),
+                aggressive_mode: true,
+                language_directive: false,
+                ..Default::default()
+            };
+
+            let result = optimizer.optimize(&request).unwrap();
+            assert!(!result.optimizations.is_empty(), "{}", prompt);
+            assert_eq!(result.apply_to(&result.original_prompt).unwrap(), result.optimized_prompt, "{}", prompt);
+            multi_pass |= result.optimizations.iter().any(|opt| opt.pass > 0);
+
+            // A replay cannot add the directive
+            let request = OptimizationRequest {
+                language_directive: true,
+                ..request
+            };
+            assert_eq!(optimizer.optimize(&request).unwrap().apply_to(prompt), None);
+        }
+        assert!(multi_pass);
+    }
+
+    #[test]
+    fn test_optimize_range_leaves_rest_verbatim() {
+        let optimizer = Optimizer::default();
+
+        let first = "Thank you so much in advance for your help with this!  ";
+        let middle = "Please  summarize the attached report. Thank you so much in advance for your help with this!";
+        let last = " Thank you so much in advance for your help with this!";
+        let prompt = format!("{}{}{}", first, middle, last);
+        let start = first.len();
+        let end = start + middle.len();
+
+        let request = OptimizationRequest {
+            prompt: prompt.clone(),
+            ..Default::default()
+        };
+        let result = optimizer.optimize_range(&request, start, end).unwrap();
+
+        assert!(!result.optimizations.is_empty());
+        assert!(result
+            .optimizations
+            .iter()
+            .all(|opt| opt.start_pos >= start && opt.end_pos <= end));
+        assert!(result
+            .optimizations
+            .iter()
+            .all(|opt| prompt[opt.start_pos..opt.end_pos] == opt.original_text));
+
+        // Selection compressed, first and last sentences untouched, no directive
+        assert!(result.optimized_prompt.starts_with(first));
+        assert!(result.optimized_prompt.ends_with(last));
+        assert!(!result.optimized_prompt.contains("output_language"));
+        assert!(result.optimized_prompt.len() < prompt.len());
+        assert!(result.token_savings > 0);
+
+        // Out-of-range and mid-char bounds are clamped
+        let result = optimizer.optimize_range(&request, prompt.len() + 5, prompt.len() + 10).unwrap();
+        assert_eq!(result.optimized_prompt, prompt);
+        let request = OptimizationRequest {
+            prompt: "医院".to_string(),
+            ..Default::default()
+        };
+        assert!(optimizer.optimize_range(&request, 1, 4).is_ok());
+    }
+
+    #[test]
+    fn test_aggressive_protection_compresses_more() {
+        let optimizer = Optimizer::default();
+        let prompt = "Forward this reply: 'Thank you so much in advance for your help with this!' Summarize the thread.";
+
+        let conservative = optimizer
+            .optimize(&OptimizationRequest {
+                prompt: prompt.to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        let aggressive = optimizer
+            .optimize(&OptimizationRequest {
+                prompt: prompt.to_string(),
+                protection_policy: ProtectionPolicy::Aggressive,
+                ..Default::default()
+            })
+            .unwrap();
+
+        // The quoted string is protected unless the policy is aggressive
+        assert!(conservative.optimized_prompt.contains("Thank you so much"));
+        assert!(!aggressive.optimized_prompt.contains("Thank you so much"));
+        assert!(aggressive.token_savings > conservative.token_savings);
+    }
+
+    #[test]
+    fn test_protection_report() {
+        let optimizer = Optimizer::default();
+        let prompt = "Résumé: see https://example.com/docs and return JSON.";
+
+        let report = optimizer.protection_report(prompt);
+
+        let url = report
+            .regions
+            .iter()
+            .find(|r| r.region_type == RegionType::UrlOrPath)
+            .unwrap();
+        assert_eq!(url.snippet, "https://example.com/docs");
+        // Char offsets, not bytes ("é" is two bytes)
+        assert_eq!(url.start, 12);
+        assert_eq!(url.end, 12 + url.snippet.chars().count());
+
+        assert!(report.regions.iter().any(|r| r.region_type == RegionType::InstructionKeyword));
+        assert!(report.protected_tokens > 0);
+
+        let long = format!("`{}`", "x".repeat(60));
+        let report = optimizer.protection_report(&long);
+        assert!(report.regions[0].snippet.ends_with('…'));
+        assert_eq!(report.regions[0].end, 62);
+    }
+
+    #[test]
+    fn test_normalize_whitespace() {
+        assert_eq!(normalize_whitespace("a  b ,c\n\n\n\nd"), "a b,c\n\nd");
+        assert_eq!(normalize_whitespace(" \n  \nlead\n \t\n\ntail  \n"), "lead\n\ntail");
+
+        // Indentation survives, a stray leading space does not
+        assert_eq!(normalize_whitespace("list:\n  - item\n next"), "list:\n  - item\nnext");
+    }
+
+    #[test]
+    fn test_validate_optimized_prompt() {
+        assert!(validate_optimized_prompt("Explain this.", "Explain.").is_ok());
+        assert!(validate_optimized_prompt("", "").is_ok());
+
+        // Destructive pattern wiped the prompt
+        assert!(validate_optimized_prompt("Explain this.", "  \n").is_err());
+
+        // A cut multibyte char decoded lossily
+        let broken = String::from_utf8_lossy(&"医院".as_bytes()[..4]).into_owned();
+        assert!(validate_optimized_prompt("医院", &broken).is_err());
+        assert!(validate_optimized_prompt("bad \u{FFFD} input", "bad \u{FFFD}").is_ok());
+    }
+
+    #[test]
+    fn test_include_rejected() {
+        let optimizer = Optimizer::default();
+
+        let mut request = OptimizationRequest {
+            prompt: "I would really appreciate it if you could plan a 5 kilometers route.".to_string(),
+            ..Default::default()
+        };
+
+        // Not reported unless requested
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result.rejected.is_empty());
+
+        request.include_rejected = true;
+        let result = optimizer.optimize(&request).unwrap();
+
+        // "5 kilometers" -> "5km" saves no tokens
+        let km = result
+            .rejected
+            .iter()
+            .find(|r| r.original_text == "5 kilometers")
+            .expect("unit rewrite should be reported as rejected");
+        assert_eq!(km.reason, RejectionReason::NoSavings);
+        assert_eq!(km.optimized_text, "5km");
+
+        // Applied optimizations are never also reported as rejected
+        for opt in &result.optimizations {
+            assert!(!result.rejected.iter().any(|r| r.start_pos == opt.start_pos
+                && r.original_text == opt.original_text));
+        }
+    }
+
+    #[test]
+    fn test_safe_mode_never_removes_words() {
+        let optimizer = Optimizer::default();
+
+        let prompt = "I would really appreciate it if you could plan a route.......... I want you to  list every stop.\n\n\n\nThen return home.";
+        let request = OptimizationRequest {
+            prompt: prompt.to_string(),
+            confidence_threshold: 0.75,
+            safe_mode: true,
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+        let optimized = result.optimized_prompt.to_lowercase();
+
+        // Rewrites still apply
+        assert!(!result.optimizations.is_empty());
+        assert!(!optimized.contains(".."));
+        assert!(result
+            .optimizations
+            .iter()
+            .all(|opt| !opt.optimized_text.is_empty()));
+
+        // Structural whitespace collapses are never dropped as removals
+        let with_rejected = optimizer
+            .optimize(&OptimizationRequest {
+                include_rejected: true,
+                ..request.clone()
+            })
+            .unwrap();
+        let collapses: Vec<_> = with_rejected
+            .rejected
+            .iter()
+            .filter(|r| r.optimization_type == OptimizationType::Whitespace)
+            .collect();
+        assert_eq!(collapses.len(), 2);
+        assert!(collapses.iter().all(|r| r.reason != RejectionReason::SafeMode));
+        assert!(!optimized.contains("  ") && !optimized.contains("\n\n\n"));
+
+        // Every other word survives
+        for word in prompt.split_whitespace() {
+            let word = word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+            assert!(optimized.contains(&word), "'{}' was removed in safe mode", word);
+        }
+    }
+
+    #[test]
+    fn test_preserve_first_person_keeps_example() {
+        let optimizer = Optimizer::default();
+
+        let example = "I would really appreciate it if you could help me with my order.";
+        let prompt = format!(
+            "I would really appreciate it if you could classify user intent.\nE.g. {}",
+            example
+        );
+
+        let make_request = |preserve_first_person| OptimizationRequest {
+            prompt: prompt.clone(),
+            preserve_first_person,
+            ..Default::default()
+        };
+
+        // Without the flag, the example gets stripped too
+        let result = optimizer.optimize(&make_request(false)).unwrap();
+        assert!(!result.optimized_prompt.contains(example));
+
+        // With the flag, the example survives while the real boilerplate goes
+        let result = optimizer.optimize(&make_request(true)).unwrap();
+        assert!(result.optimized_prompt.contains(example));
+        assert!(result.optimized_prompt.starts_with("Classify user intent"));
+    }
+
+    #[test]
+    fn test_preserve_case_keeps_lowercase() {
+        let optimizer = Optimizer::default();
+
+        let mut request = OptimizationRequest {
+            prompt: "please   summarize the log. then list each errorCode found.".to_string(),
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result.optimized_prompt.starts_with("Summarize"));
+
+        request.preserve_case = true;
+        let result = optimizer.optimize(&request).unwrap();
+
+        // Whitespace is still cleaned up, casing untouched
+        assert!(result
+            .optimized_prompt
+            .starts_with("summarize the log. then list each errorCode found."));
+    }
+
+    #[test]
+    fn test_separator_kept_unless_aggressive() {
+        let optimizer = Optimizer::default();
+
+        let mut request = OptimizationRequest {
+            prompt: "Summarize the report below.\n---\nQ3 revenue grew by 12 percent.".to_string(),
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result.optimized_prompt.contains("---"));
+
+        request.aggressive_mode = true;
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(!result.optimized_prompt.contains("---"));
+    }
+
+    #[test]
+    fn test_articles_dropped_only_when_aggressive() {
+        let optimizer = Optimizer::default();
+
+        let mut request = OptimizationRequest {
+            prompt: "Summarize the report and list the open issues for the team.\nKeep `the_config` as is.".to_string(),
+            ..Default::default()
+        };
+
+        let normal = optimizer.optimize(&request).unwrap();
+        assert!(normal.optimized_prompt.contains("the report"));
+        assert!(!normal
+            .optimizations
+            .iter()
+            .any(|opt| opt.original_text.trim().eq_ignore_ascii_case("the")));
+
+        request.aggressive_mode = true;
+        let aggressive = optimizer.optimize(&request).unwrap();
+        assert!(aggressive.optimized_prompt.contains("Summarize report"), "{}", aggressive.optimized_prompt);
+        assert!(!aggressive.optimized_prompt.contains("the open issues"));
+        assert!(aggressive.optimized_tokens < normal.optimized_tokens);
+
+        // Protected code is never touched
+        assert!(aggressive.optimized_prompt.contains("`the_config`"));
+    }
+
+    #[test]
+    fn test_aggressive_only_patterns_unlocked_by_aggressive_mode() {
+        let optimizer = Optimizer::default();
+        let mut request = OptimizationRequest {
+            prompt: "To be honest, I think the parser leaks memory. Keep in mind that it runs hourly.".to_string(),
+            ..Default::default()
+        };
+
+        let normal = optimizer.optimize(&request).unwrap();
+        assert!(normal.optimized_prompt.contains("I think"));
+        assert!(normal.optimized_prompt.contains("Keep in mind that"));
+        assert!(!normal
+            .optimizations
+            .iter()
+            .chain(&normal.requires_review)
+            .any(|opt| opt.reasoning.contains("Hedge") || opt.reasoning.contains("opener")));
+
+        request.aggressive_mode = true;
+        let aggressive = optimizer.optimize(&request).unwrap();
+        assert!(!aggressive.optimized_prompt.contains("I think"), "{}", aggressive.optimized_prompt);
+        // Detected, if not confident enough to skip review
+        assert!(aggressive
+            .optimizations
+            .iter()
+            .chain(&aggressive.requires_review)
+            .any(|opt| opt.original_text == "Keep in mind that "));
+    }
+
+    #[test]
+    fn test_confidence_summary_suggests_threshold() {
+        let optimizer = Optimizer::default();
+        let mut request = OptimizationRequest {
+            prompt: "I would really appreciate it if you could analyze this code. Thank you so much!".to_string(),
+            confidence_threshold: 0.99,
+            review_policy: ReviewPolicy::threshold_only(),
+            ..Default::default()
+        };
+
+        let strict = optimizer.optimize(&request).unwrap();
+        assert!(strict.optimizations.is_empty());
+        let summary = &strict.confidence_summary;
+        let max = summary.max_confidence_seen.unwrap();
+        let suggested = summary.suggested_threshold.unwrap();
+        assert!(suggested <= max && max - suggested < 0.01);
+        assert!(summary.unlocked_by_suggestion >= 1);
+        let bucketed: usize = summary.distribution.iter().map(|b| b.count).sum();
+        assert_eq!(bucketed, strict.requires_review.len());
+
+        // Following the hint auto-applies what it promised
+        request.confidence_threshold = suggested;
+        let tuned = optimizer.optimize(&request).unwrap();
+        let first_pass = tuned.optimizations.iter().filter(|opt| opt.pass == 0).count();
+        assert_eq!(first_pass, summary.unlocked_by_suggestion);
+    }
+
+    #[test]
+    fn test_reasoning_style_code() {
+        let optimizer = Optimizer::default();
+        let mut request = OptimizationRequest {
+            prompt: "I would really appreciate it if you could analyze this code. Thank you so much!".to_string(),
+            ..Default::default()
+        };
+
+        let verbose = optimizer.optimize(&request).unwrap();
+        request.reasoning_style = ReasoningStyle::Code;
+        let coded = optimizer.optimize(&request).unwrap();
+
+        assert!(!coded.optimizations.is_empty());
+        assert_eq!(coded.optimizations.len(), verbose.optimizations.len());
+        for (code, prose) in coded.optimizations.iter().zip(&verbose.optimizations) {
+            assert_eq!(code.reasoning, reasoning_code(&prose.optimization_type, &prose.reasoning));
+            assert!(code.reasoning.starts_with(prose.optimization_type.code_prefix()));
+        }
+        assert_eq!(coded.optimized_prompt, verbose.optimized_prompt);
+    }
+
+    #[test]
+    fn test_markdown_stripping_aggressive() {
+        let optimizer = Optimizer::default();
+
+        let prompt = "## Review checklist\n- check naming\n- check errors\n- check tests\n- check docs\n- check style\n```\n- keep this\n```";
+
+        let make_request = |aggressive_mode| OptimizationRequest {
+            prompt: prompt.to_string(),
+            output_language: Language::English,
+            confidence_threshold: 0.85,
+            aggressive_mode,
+            directive_format: DirectiveFormat::Bracketed,
+            ..Default::default()
+        };
+
+        let normal = optimizer.optimize(&make_request(false)).unwrap();
+        let aggressive = optimizer.optimize(&make_request(true)).unwrap();
+
+        // Bullet-heavy input shrinks further once markers are stripped
+        assert!(aggressive.optimized_tokens < normal.optimized_tokens);
+        assert!(!aggressive.optimized_prompt.contains("## "));
+        assert!(!aggressive.optimized_prompt.contains("- check"));
+
+        // Code fence content is untouched
+        assert!(aggressive.optimized_prompt.contains("- keep this"));
+
+        // Nested items stay indented under their parent
+        let request = OptimizationRequest {
+            prompt: "Steps:\n- build\n  - debug\n  - release\n- test".to_string(),
+            ..make_request(true)
+        };
+        let nested = optimizer.optimize(&request).unwrap();
+        assert!(nested.optimized_prompt.contains("build\n  debug\n  release\ntest"), "{}", nested.optimized_prompt);
+    }
+
+    #[test]
+    fn test_profile_reports_stage_timings() {
+        let optimizer = Optimizer::default();
+        let mut request = OptimizationRequest {
+            prompt: "I would really appreciate it if you could please analyze this code.".to_string(),
+            ..Default::default()
+        };
+
+        assert!(optimizer.optimize(&request).unwrap().profile.is_none());
+
+        request.profile = true;
+        let timings = optimizer.optimize(&request).unwrap().profile.unwrap();
+        assert!(timings.detect_ms > 0.0);
+        assert!(timings.total_ms() >= timings.detect_ms);
+    }
+
+    #[test]
+    fn test_protected_suffix_kept_verbatim() {
+        let optimizer = Optimizer::default();
+        let footer = "— Generated by X, do not modify.  Please   keep.";
+        let request = OptimizationRequest {
+            prompt: format!("I would really appreciate it if you could please analyze this code.\n\n{}", footer),
+            protected_suffix: Some(footer.to_string()),
+            directive_format: DirectiveFormat::Bracketed,
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result.token_savings > 0);
+        let body = result.optimized_prompt.strip_suffix(footer).unwrap();
+
+        // Directive sits before the footer, which keeps its separator
+        assert!(body.ends_with("[output_language: english]\n\n"));
+        assert!(!body.contains("please"));
+
+        // First-pass positions still point into the full prompt
+        let first = &result.optimizations[0];
+        assert_eq!(&request.prompt[first.start_pos..first.end_pos], first.original_text);
+
+        // Prepended directive goes after a protected prefix
+        let header = "ID: 42";
+        let request = OptimizationRequest {
+            prompt: format!("{}\nPlease analyze this code.", header),
+            protected_prefix: Some(header.to_string()),
+            directive_placement: DirectivePlacement::Prepend,
+            ..request
+        };
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result.optimized_prompt.starts_with("ID: 42\n[output_language: english]\n\n"));
+        assert!(result.warnings.iter().any(|w| w.contains("protected_suffix")));
+    }
+
+    #[test]
+    fn test_json_keys_opt_in_and_fenced_keys_intact() {
+        let optimizer = Optimizer::default();
+        let fenced = "```json\n{\"configuration\": \"kept\", \"parameters\": {}}\n```";
+        let request = OptimizationRequest {
+            prompt: format!("Fill in {{\"configuration\": \"short\"}} and return:\n{}", fenced),
+            protection_policy: ProtectionPolicy::Aggressive,
+            ..Default::default()
+        };
+
+        // Off by default
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result.optimized_prompt.contains(r#"{"configuration": "short"}"#));
+
+        let request = OptimizationRequest {
+            rewrite_json_keys: true,
+            ..request
+        };
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result.optimized_prompt.contains(r#"{"config":"short"}"#));
+        assert!(result.optimized_prompt.contains(fenced));
+    }
+
+    #[test]
+    fn test_whitespace_cleanup_skips_code_and_tables() {
+        let optimizer = Optimizer::default();
+        let fenced = "```python\nx  =  1   # aligned\nprint(\"a\" , x)\n```";
+        let table = "| name   | value |\n|--------|-------|\n| a      | 1     |";
+        let request = OptimizationRequest {
+            prompt: format!(
+                "I would really appreciate it if you could basically fix this:\n\n{}\n\n{}",
+                fenced, table
+            ),
+            confidence_threshold: 0.5,
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(!result.optimizations.is_empty());
+        assert!(result.optimized_prompt.contains(fenced), "{}", result.optimized_prompt);
+        assert!(result.optimized_prompt.contains(table), "{}", result.optimized_prompt);
+    }
+
+    #[test]
+    fn test_capitalization_skips_code() {
+        let optimizer = Optimizer::default();
+        let fenced = "```python\nresult = run(). value\n```";
+        let request = OptimizationRequest {
+            prompt: format!(
+                "I would really appreciate it if you could basically fix this. see `x. y`:\n{}",
+                fenced
+            ),
+            confidence_threshold: 0.5,
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(!result.optimizations.is_empty());
+        assert!(result.optimized_prompt.contains("See `x. y`"), "{}", result.optimized_prompt);
+        assert!(result.optimized_prompt.contains(fenced), "{}", result.optimized_prompt);
+
+        // Unprotected fences are still left alone
+        let sentences = SentenceBoundaries::default();
+        assert_eq!(
+            capitalize_unfenced(&sentences, "done.\n~~~sh\nls. cd\n~~~\nthen. next."),
+            "Done.\n~~~sh\nls. cd\n~~~\nthen. Next."
+        );
+    }
+
+    #[test]
+    fn test_merged_regions_keep_their_gap() {
+        let optimizer = Optimizer::default();
+        let request = OptimizationRequest {
+            prompt: "I would really appreciate it if you could basically run `make build`  ${TARGET} now.".to_string(),
+            confidence_threshold: 0.5,
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(!result.optimizations.is_empty());
+        assert!(result.optimized_prompt.contains("`make build`  ${TARGET}"), "{}", result.optimized_prompt);
+    }
+
+    #[test]
+    fn test_only_types_restricts_detection() {
+        let optimizer = Optimizer::default();
+        let request = OptimizationRequest {
+            prompt: "I would really appreciate it if you could basically write between 5 and 10 lines.".to_string(),
+            only_types: Some(vec![OptimizationType::FormatConsolidation]),
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(!result.optimizations.is_empty());
+        assert!(result
+            .optimizations
+            .iter()
+            .chain(&result.requires_review)
+            .all(|opt| opt.optimization_type == OptimizationType::FormatConsolidation));
+        // Boilerplate and fillers stay
+        assert!(result
+            .optimized_prompt
+            .starts_with("I would really appreciate it if you could basically write 5-10 lines."));
+    }
+
+    #[test]
+    fn test_only_types_separates_whitespace_from_format() {
+        let optimizer = Optimizer::default();
+        let prompt = "Keep it between 5 and 10 lines. Really?????? Thanks";
+        let run = |only: OptimizationType| {
+            let request = OptimizationRequest {
+                prompt: prompt.to_string(),
+                only_types: Some(vec![only]),
+                ..Default::default()
+            };
+            optimizer.optimize(&request).unwrap().optimized_prompt
+        };
+
+        let whitespace = run(OptimizationType::Whitespace);
+        assert!(whitespace.starts_with("Keep it between 5 and 10 lines. Really? Thanks"));
+
+        let format = run(OptimizationType::FormatConsolidation);
+        assert!(format.starts_with("Keep it 5-10 lines. Really?????? Thanks"));
+    }
+
+    #[test]
+    fn test_locale_selects_unit_rules() {
+        let optimizer = Optimizer::default();
+        let mut request = OptimizationRequest {
+            prompt: "Prices rose 15 per cent this year.".to_string(),
+            ..Default::default()
+        };
+
+        let us = optimizer.optimize(&request).unwrap();
+        assert!(us.optimized_prompt.contains("15 per cent"));
+
+        request.locale = Locale::EnGb;
+        let gb = optimizer.optimize(&request).unwrap();
+        assert!(gb.optimized_prompt.contains("rose 15% this year"));
+    }
+
+    #[test]
+    fn test_numeric_ranges_rewritten_when_cheaper() {
+        let optimizer = Optimizer::default();
+        let request = OptimizationRequest {
+            prompt: "Write between 200 and 300 words covering events from 1990 to 2005. \
+                     Keep `between 1 and 2` as is. Quote between ₹500 and ₹2,000."
+                .to_string(),
+            include_rejected: true,
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+        assert!(result.optimized_prompt.contains("Write 200-300 words"));
+        assert!(result.optimized_prompt.contains("events 1990-2005."));
+
+        // Code is protected
+        assert!(result.optimized_prompt.contains("`between 1 and 2`"));
+
+        // "₹500-₹2,000" costs as many tokens as the words
+        assert!(result.optimized_prompt.contains("between ₹500 and ₹2,000"));
+        let rupees = result
+            .rejected
+            .iter()
+            .find(|r| r.original_text == "between ₹500 and ₹2,000")
+            .expect("range rewrite should be reported as rejected");
+        assert_eq!(rupees.reason, RejectionReason::NoSavings);
+    }
+
+    #[test]
+    fn test_frontmatter_and_comments_kept_verbatim() {
+        let frontmatter = "---\ntitle: Code review\nnote: I would really appreciate it if you could be brief\n---";
+        let prompt = format!(
+            "{}\nI would really appreciate it if you could please analyze this code.\n# Please note that this is a draft",
+            frontmatter
+        );
+        let request = OptimizationRequest {
+            prompt,
+            ..Default::default()
+        };
+
+        let optimizer = Optimizer::default()
+            .with_region_detector(ProtectedRegionDetector::default().with_comment_markers(["//", "#"]));
+        let result = optimizer.optimize(&request).unwrap();
+
+        assert!(result.optimized_prompt.starts_with(frontmatter));
+        assert!(result
+            .optimized_prompt
+            .contains("\nAnalyze this code.\n# Please note that this is a draft"));
+        assert!(result.token_savings > 0);
+    }
+
+    #[test]
+    fn test_item_savings_counted_in_context() {
+        let optimizer = Optimizer::default();
+        let tokenizer = Tokenizer::new().unwrap();
+        let prompt = "I would really appreciate it if you could please analyze this code.";
+        let request = OptimizationRequest {
+            prompt: prompt.to_string(),
+            savings_floor: SavingsFloor::OmitDirective,
+            ..Default::default()
+        };
+
+        let first_pass = optimizer
+            .collect_optimizations(&request, 0..prompt.len(), &[], &mut StageTimer::new(false))
+            .0;
+        assert!(!first_pass.is_empty());
+        for opt in &first_pass {
+            assert_eq!(
+                opt.token_savings,
+                tokenizer.savings_in_context(prompt, opt.start_pos, opt.end_pos, &opt.optimized_text)
+            );
+        }
+
+        // Per-item numbers no longer exceed the real total
+        let result = optimizer.optimize(&request).unwrap();
+        let directive_tokens = tokenizer.count_tokens("\n\n[output_language: english]") as i64;
+        let item_total: i64 = result.optimizations.iter().map(|opt| opt.token_savings).sum();
+        assert!(item_total <= result.token_savings + directive_tokens);
+    }
+
+    #[test]
+    fn test_steps_accumulate_to_total_savings() {
+        let optimizer = Optimizer::default();
+        let tokenizer = Tokenizer::new().unwrap();
+        let request = OptimizationRequest {
+            prompt: "I would really appreciate it if you could please make sure to summarize this report. Thank you so much in advance!".to_string(),
+            ..Default::default()
+        };
+
+        let result = optimizer.optimize(&request).unwrap();
+        let steps = optimizer.optimize_steps(&request).unwrap();
+
+        // Same edits, same order
+        let replayed: Vec<_> = steps.iter().map(|s| &s.optimization.original_text).collect();
+        let applied: Vec<_> = result.optimizations.iter().map(|o| &o.original_text).collect();
+        assert_eq!(replayed, applied);
+        assert!(steps.len() > 1);
+
+        // The last step lands on the optimized prompt (minus the directive)
+        let without_directive = result
+            .optimized_prompt
+            .trim_end_matches("[output_language: english]")
+            .trim_end();
+        let last = steps.last().unwrap();
+        assert_eq!(last.tokens_after, tokenizer.count_tokens(without_directive));
+        assert_eq!(
+            last.cumulative_saved,
+            result.original_tokens as i64 - last.tokens_after as i64
+        );
+    }
 }
@@ -0,0 +1,320 @@
+//! Pluggable pattern sources
+//!
+//! A `PatternSource` yields compiled regex `Pattern`s: the built-in tables
+//! (`PatternDetector`), the SQLite store (`DatabasePatternDetector`) or a TOML
+//! file (`FilePatternSource`). `detect_with_mode_indexed` is the one
+//! detection loop they all run through (`detect_with` and `detect_with_mode`
+//! wrap it), and `LayeredSource` stacks sources so a later one overrides
+//! earlier patterns with the same regex.
+
+use crate::error::Result;
+use crate::models::{Locale, OptimizationType};
+use crate::patterns::{ContextGuard, DetectedPattern, Pattern};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Something that supplies compiled patterns to detect
+pub trait PatternSource {
+    /// Patterns to run on text written for `locale`
+    fn patterns(&self, locale: Locale) -> Vec<&Pattern>;
+
+    /// Called once per match of `patterns(locale)[index]`, e.g. to keep
+    /// usage stats
+    fn on_match(&self, _locale: Locale, _index: usize) {}
+}
+
+/// Detect every pattern `source` supplies outside aggressive mode, sorted
+/// by position
+pub fn detect_with<S: PatternSource + ?Sized>(
+    source: &S,
+    text: &str,
+    locale: Locale,
+) -> Vec<DetectedPattern> {
+    detect_with_mode(source, text, locale, false)
+}
+
+/// Detect every pattern `source` supplies, sorted by position;
+/// `aggressive_only` patterns run only when `aggressive` is set
+pub fn detect_with_mode<S: PatternSource + ?Sized>(
+    source: &S,
+    text: &str,
+    locale: Locale,
+    aggressive: bool,
+) -> Vec<DetectedPattern> {
+    detect_with_mode_indexed(source, text, locale, aggressive)
+        .into_iter()
+        .map(|(_, detected)| detected)
+        .collect()
+}
+
+/// `detect_with_mode`, pairing each match with the index of its pattern in
+/// `source.patterns(locale)`
+pub fn detect_with_mode_indexed<S: PatternSource + ?Sized>(
+    source: &S,
+    text: &str,
+    locale: Locale,
+    aggressive: bool,
+) -> Vec<(usize, DetectedPattern)> {
+    let mut detected = Vec::new();
+
+    for (index, pattern) in source.patterns(locale).into_iter().enumerate() {
+        if pattern.aggressive_only && !aggressive {
+            continue;
+        }
+        for found in pattern.detect(text) {
+            source.on_match(locale, index);
+            detected.push((index, found));
+        }
+    }
+
+    // Sort by position to handle overlaps later
+    detected.sort_by_key(|(_, d)| d.start_pos);
+    detected
+}
+
+/// Map a stored pattern type name ("boilerplate", "filler", ...) to its
+/// optimization type; unknown names are format consolidation
+pub fn parse_pattern_type(name: &str) -> OptimizationType {
+    match name {
+        "boilerplate" => OptimizationType::BoilerplateRemoval,
+        "filler" => OptimizationType::FillerRemoval,
+        "instruction" => OptimizationType::InstructionCompression,
+        "redundant" => OptimizationType::FormatConsolidation,
+        "structural" => OptimizationType::FormatConsolidation,
+        "whitespace" => OptimizationType::Whitespace,
+        "synonym" => OptimizationType::SynonymConsolidation,
+        "mandarin" => OptimizationType::MandarinSubstitution,
+        "concept" => OptimizationType::ConceptSubstitution,
+        _ => OptimizationType::FormatConsolidation,
+    }
+}
+
+/// Sources stacked in order; a layer's pattern replaces every pattern with
+/// the same regex from the layers below it
+#[derive(Default)]
+pub struct LayeredSource<'a> {
+    layers: Vec<Box<dyn PatternSource + 'a>>,
+}
+
+impl<'a> LayeredSource<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `source` on top of the existing layers
+    pub fn with_source(mut self, source: impl PatternSource + 'a) -> Self {
+        self.layers.push(Box::new(source));
+        self
+    }
+
+    /// Surviving patterns for `locale`, each with its layer and its index
+    /// in that layer's `patterns`
+    fn layered(&self, locale: Locale) -> Vec<(usize, usize, &Pattern)> {
+        let mut patterns: Vec<(usize, usize, &Pattern)> = Vec::new();
+
+        for (layer, source) in self.layers.iter().enumerate() {
+            let layer_patterns = source.patterns(locale);
+            let overrides: HashSet<&str> = layer_patterns.iter().map(|p| p.regex.as_str()).collect();
+            patterns.retain(|(_, _, p)| !overrides.contains(p.regex.as_str()));
+            patterns.extend(layer_patterns.into_iter().enumerate().map(|(index, p)| (layer, index, p)));
+        }
+
+        patterns
+    }
+}
+
+impl PatternSource for LayeredSource<'_> {
+    fn patterns(&self, locale: Locale) -> Vec<&Pattern> {
+        self.layered(locale).into_iter().map(|(_, _, p)| p).collect()
+    }
+
+    fn on_match(&self, locale: Locale, index: usize) {
+        if let Some(&(layer, layer_index, _)) = self.layered(locale).get(index) {
+            self.layers[layer].on_match(locale, layer_index);
+        }
+    }
+}
+
+impl<S: PatternSource + ?Sized> PatternSource for &S {
+    fn patterns(&self, locale: Locale) -> Vec<&Pattern> {
+        (**self).patterns(locale)
+    }
+
+    fn on_match(&self, locale: Locale, index: usize) {
+        (**self).on_match(locale, index)
+    }
+}
+
+/// Patterns loaded from a TOML file of `[[patterns]]` tables
+///
+/// ```toml
+/// [[patterns]]
+/// type = "boilerplate"
+/// regex = '(?i)as per my last email,?\s*'
+/// replacement = ""
+/// confidence = 0.9
+/// reasoning = "Passive-aggressive preamble"
+/// context_guard = "!(?i)quote"  # optional, same syntax as the database
+/// priority = 5                  # optional
+/// aggressive_only = false        # optional; true = aggressive mode only
+/// ```
+pub struct FilePatternSource {
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Deserialize)]
+struct PatternFile {
+    #[serde(default)]
+    patterns: Vec<PatternEntry>,
+}
+
+#[derive(Deserialize)]
+struct PatternEntry {
+    #[serde(rename = "type")]
+    pattern_type: String,
+    regex: String,
+    #[serde(default)]
+    replacement: String,
+    confidence: f64,
+    #[serde(default)]
+    reasoning: String,
+    #[serde(default)]
+    context_guard: Option<String>,
+    #[serde(default)]
+    priority: Option<i64>,
+    #[serde(default)]
+    aggressive_only: bool,
+}
+
+impl FilePatternSource {
+    /// Load patterns from a TOML file; any invalid regex fails the load
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse patterns from TOML text
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let file: PatternFile = toml::from_str(text)?;
+        let patterns = file
+            .patterns
+            .into_iter()
+            .map(|entry| {
+                Ok(Pattern {
+                    pattern_type: parse_pattern_type(&entry.pattern_type),
+                    regex: Regex::new(&entry.regex)?,
+                    replacement: entry.replacement,
+                    base_confidence: entry.confidence,
+                    reasoning: entry.reasoning,
+                    context_guard: entry.context_guard.as_deref().map(ContextGuard::parse).transpose()?,
+                    priority: entry.priority,
+                    aggressive_only: entry.aggressive_only,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { patterns })
+    }
+
+    /// Get number of loaded patterns
+    pub fn pattern_count(&self) -> usize {
+        self.patterns.len()
+    }
+}
+
+impl PatternSource for FilePatternSource {
+    fn patterns(&self, _locale: Locale) -> Vec<&Pattern> {
+        self.patterns.iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::database_pattern_detector::DatabasePatternDetector;
+    use crate::patterns::PatternDetector;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_layered_sources_override_by_regex() {
+        // Database layer: overrides the built-in "Please make sure to" rule
+        // and adds one of its own
+        let db = Database::in_memory().unwrap();
+        for (regex, replacement, confidence) in [
+            (r"(?i)Please make sure to\s*", "Ensure ", 0.99),
+            (r"(?i)\bper my last email,?\s*", "", 0.93),
+        ] {
+            db.connection()
+                .unwrap()
+                .execute(
+                    "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
+                     VALUES ('boilerplate', ?1, ?2, ?3, 'Override')",
+                    rusqlite::params![regex, replacement, confidence],
+                )
+                .unwrap();
+        }
+        let db = Arc::new(db);
+        let database = DatabasePatternDetector::new(db.clone()).unwrap();
+        let hardcoded = PatternDetector::default();
+
+        let layered = LayeredSource::new().with_source(&hardcoded).with_source(&database);
+        let base_count = hardcoded.patterns(Locale::default()).len();
+        assert_eq!(layered.patterns(Locale::default()).len(), base_count + 1);
+
+        let text = "Per my last email, please make sure to really check the logs.";
+        let detected = detect_with(&layered, text, Locale::default());
+
+        let make_sure = detected
+            .iter()
+            .find(|d| d.original_text == "please make sure to ")
+            .unwrap();
+        assert_eq!(make_sure.optimized_text, "Ensure ");
+        assert_eq!(make_sure.base_confidence, 0.99);
+        assert!(detected.iter().any(|d| d.original_text == "Per my last email, "));
+        // Built-in families still run underneath
+        assert!(detected.iter().any(|d| d.original_text == "really"));
+        assert!(detected.windows(2).all(|w| w[0].start_pos <= w[1].start_pos));
+
+        // Matches through the layer still reach the database's usage stats
+        let applied: i64 = db
+            .connection()
+            .unwrap()
+            .query_row("SELECT SUM(applied_count) FROM patterns", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(applied, 2);
+    }
+
+    #[test]
+    fn test_file_source() {
+        let source = FilePatternSource::from_toml(
+            r#"
+            [[patterns]]
+            type = "boilerplate"
+            regex = '(?i)\bkindly\s+'
+            confidence = 0.9
+            reasoning = "Politeness"
+            context_guard = '!(?i)policy'
+            priority = 3
+
+            [[patterns]]
+            type = "structural"
+            regex = '(\d+)\s*hours?\b'
+            replacement = "${1}h"
+            confidence = 0.92
+            "#,
+        )
+        .unwrap();
+        assert_eq!(source.pattern_count(), 2);
+
+        let detected = detect_with(&source, "Kindly finish in 3 hours.", Locale::default());
+        assert_eq!(detected.len(), 2);
+        assert_eq!(detected[0].pattern_type, OptimizationType::BoilerplateRemoval);
+        assert_eq!(detected[0].priority, Some(3));
+        assert_eq!(detected[1].optimized_text, "3h");
+
+        assert!(detect_with(&source, "Our policy: kindly wait.", Locale::default()).is_empty());
+        assert!(FilePatternSource::from_toml("[[patterns]]\ntype = \"filler\"\nregex = \"(\"\nconfidence = 0.5\n").is_err());
+    }
+}
@@ -1,7 +1,17 @@
-use crate::models::OptimizationType;
+use crate::confidence::{extract_context, Context};
+use crate::error::Result;
+use crate::models::{ContractionPolicy, Locale, OptimizationType, PatternsConfig};
+use crate::pattern_source::{detect_with_mode, PatternSource};
+use crate::protected_regions::ProtectedRegionDetector;
+use crate::tokenizer::Tokenizer;
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::Serialize;
 use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Context window (bytes either side of a match) checked by context guards
+pub const GUARD_CONTEXT_WINDOW: usize = 50;
 
 /// A pattern for detection
 #[derive(Debug, Clone)]
@@ -11,6 +21,79 @@ pub struct Pattern {
     pub replacement: String,
     pub base_confidence: f64,
     pub reasoning: String,
+    pub context_guard: Option<ContextGuard>,
+    /// Curated priority; breaks confidence ties between overlapping matches
+    pub priority: Option<i64>,
+    /// Higher-risk pattern only detected in aggressive mode
+    pub aggressive_only: bool,
+}
+
+impl Pattern {
+    /// Check the context guard (if any) for a match at `start..end`
+    pub fn allows_match(&self, text: &str, start: usize, end: usize) -> bool {
+        self.context_guard
+            .as_ref()
+            .is_none_or(|guard| guard.allows_match(text, start, end))
+    }
+
+    /// Every match in `text` that passes the context guard
+    ///
+    /// Capture groups (`$1`) in the replacement are expanded per match.
+    pub fn detect(&self, text: &str) -> Vec<DetectedPattern> {
+        self.regex
+            .find_iter(text)
+            .filter(|mat| self.allows_match(text, mat.start(), mat.end()))
+            .map(|mat| {
+                let optimized_text = if self.replacement.contains('$') {
+                    self.regex.replace(mat.as_str(), &self.replacement).into_owned()
+                } else {
+                    self.replacement.clone()
+                };
+                DetectedPattern {
+                    pattern_type: self.pattern_type.clone(),
+                    original_text: mat.as_str().to_string(),
+                    optimized_text,
+                    start_pos: mat.start(),
+                    end_pos: mat.end(),
+                    base_confidence: self.base_confidence,
+                    reasoning: self.reasoning.clone(),
+                    priority: self.priority,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Condition on the text surrounding a match
+#[derive(Debug, Clone)]
+pub enum ContextGuard {
+    /// Only match when the surrounding window matches
+    Require(Regex),
+    /// Never match when the surrounding window matches
+    Forbid(Regex),
+}
+
+impl ContextGuard {
+    /// Parse a guard spec: a regex, or `!regex` to forbid instead of require
+    pub fn parse(spec: &str) -> Result<Self, regex::Error> {
+        match spec.strip_prefix('!') {
+            Some(forbidden) => Ok(Self::Forbid(Regex::new(forbidden)?)),
+            None => Ok(Self::Require(Regex::new(spec)?)),
+        }
+    }
+
+    /// Check the guard against an extracted context
+    pub fn allows(&self, context: &Context) -> bool {
+        match self {
+            Self::Require(regex) => regex.is_match(&context.surrounding_text),
+            Self::Forbid(regex) => !regex.is_match(&context.surrounding_text),
+        }
+    }
+
+    /// Check the guard for a match at `start..end` in `text`
+    pub fn allows_match(&self, text: &str, start: usize, end: usize) -> bool {
+        self.allows(&extract_context(text, start, end, GUARD_CONTEXT_WINDOW))
+    }
 }
 
 /// A detected pattern match in text
@@ -23,6 +106,8 @@ pub struct DetectedPattern {
     pub end_pos: usize,
     pub base_confidence: f64,
     pub reasoning: String,
+    /// Curated priority (database patterns only); breaks confidence ties
+    pub priority: Option<i64>,
 }
 
 /// Boilerplate patterns with high confidence
@@ -404,60 +489,165 @@ pub static MANDARIN_SUBSTITUTIONS: &[(&str, &str, usize, usize, f64, &str)] = &[
     // These hurt token efficiency and were removed based on test evidence.
 ];
 
-/// Structural optimizations - Units, numbers, formatting
-/// Based on empirical findings: "10km" is more token-efficient than "ten kilometers"
+/// Structural optimizations - whitespace and punctuation collapsing (units
+/// live in the locale tables)
 pub static STRUCTURAL_PATTERNS: &[(&str, &str, f64, &str)] = &[
-    // Units - normalize to compact form
+    // Excess whitespace and formatting
     (
-        r"\b(\d+)\s*kilometers?\b",
-        "${1}km",
-        0.93,
-        "Normalize kilometers to km (3 tokens → 2 tokens)"
+        r"\n\n\n+",
+        "\n\n",
+        0.95,
+        "Collapse excessive newlines (>2 → 2)"
     ),
     (
-        r"\b(\d+)\s*meters?\b",
-        "${1}m",
-        0.93,
-        "Normalize meters to m"
+        r"  +",
+        " ",
+        0.94,
+        "Collapse multiple spaces to single space"
     ),
+
+    // Excessive punctuation
     (
-        r"\b(\d+)\s*minutes?\b",
-        "${1}min",
-        0.92,
-        "Normalize minutes to min (3 tokens → 2 tokens)"
+        r"\.{2,}",
+        ".",
+        0.90,
+        "Normalize ellipsis to single period"
     ),
     (
-        r"\b(\d+)\s*seconds?\b",
-        "${1}s",
-        0.92,
-        "Normalize seconds to s"
+        r"!{2,}",
+        "!",
+        0.90,
+        "Collapse multiple exclamation marks"
     ),
     (
-        r"\b(\d+)\s*percent\b",
-        "${1}%",
-        0.95,
-        "Normalize percent to % (3 tokens → 2 tokens)"
+        r"\?{2,}",
+        "?",
+        0.90,
+        "Collapse multiple question marks"
     ),
+];
+
+/// Numeric ranges spelled out in words, as (regex, replacement, confidence,
+/// reasoning, context guard)
+///
+/// Operands are numbers, optionally with a currency prefix or a `%` suffix;
+/// negative numbers never match, as "5--10" would be ambiguous. "from X to
+/// Y" after a verb of change ("increase from 5 to 10") is a transition, not
+/// a range, so it is guarded. Not every rewrite is cheaper ("₹500-₹2,000"
+/// costs as much as the words); the optimizer's savings check drops those.
+pub static RANGE_PATTERNS: &[(&str, &str, f64, &str, Option<&str>)] = &[
     (
-        r"\b(\d+)\s*dollars?\b",
-        "$${1}",
-        0.90,
-        "Normalize dollars to $ prefix"
+        r"(?i)\bbetween\s+(\p{Sc}?\d+(?:[.,:]\d+)*%?)\s+and\s+(\p{Sc}?\d+(?:[.,:]\d+)*(?:%|\b))",
+        "${1}-${2}",
+        0.88,
+        "Write 'between X and Y' as a range (X-Y)",
+        None,
+    ),
+    (
+        r"(?i)\bfrom\s+(\p{Sc}?\d+(?:[.,:]\d+)*%?)\s+to\s+(\p{Sc}?\d+(?:[.,:]\d+)*(?:%|\b))",
+        "${1}-${2}",
+        0.86,
+        "Write 'from X to Y' as a range (X-Y)",
+        Some(r"!(?i)\b(?:increas|decreas|rais|rise|rising|rose|lower|reduc|grow|grew|fell|fall|drop|chang|mov|went|jump|climb|scal|bump|updat|shift|upgrad|downgrad|migrat)\w*(?:\s+\w+){0,3}\s+from\s+\p{Sc}?\d"),
     ),
+];
 
-    // Excess whitespace and formatting
+/// Unit and currency rules for `Locale::EnUs`
+///
+/// Based on empirical findings: "10km" is more token-efficient than "ten
+/// kilometers". `{n}` stands for the locale's number format and is capture
+/// group 1 in the replacement.
+pub static UNIT_PATTERNS_EN_US: &[(&str, &str, f64, &str)] = &[
+    (r"\b{n}\s*kilometers?\b", "${1}km", 0.93, "Normalize kilometers to km (3 tokens → 2 tokens)"),
+    (r"\b{n}\s*meters?\b", "${1}m", 0.93, "Normalize meters to m"),
+    (r"\b{n}\s*minutes?\b", "${1}min", 0.92, "Normalize minutes to min (3 tokens → 2 tokens)"),
+    (r"\b{n}\s*seconds?\b", "${1}s", 0.92, "Normalize seconds to s"),
+    (r"\b{n}\s*percent\b", "${1}%", 0.95, "Normalize percent to % (3 tokens → 2 tokens)"),
+    (r"\b{n}\s*dollars?\b", "$$${1}", 0.90, "Normalize dollars to $ prefix"),
+    (r"\b{n}\s*euros?\b", "€${1}", 0.90, "Normalize euros to € prefix"),
+];
+
+/// Unit and currency rules for `Locale::EnGb`: British and US spellings
+pub static UNIT_PATTERNS_EN_GB: &[(&str, &str, f64, &str)] = &[
+    (r"\b{n}\s*kilomet(?:re|er)s?\b", "${1}km", 0.93, "Normalize kilometres to km"),
+    (r"\b{n}\s*met(?:re|er)s?\b", "${1}m", 0.93, "Normalize metres to m"),
+    (r"\b{n}\s*minutes?\b", "${1}min", 0.92, "Normalize minutes to min (3 tokens → 2 tokens)"),
+    (r"\b{n}\s*seconds?\b", "${1}s", 0.92, "Normalize seconds to s"),
+    (r"\b{n}\s*per\s?cent\b", "${1}%", 0.95, "Normalize per cent to %"),
+    (r"\b{n}\s*pounds sterling\b", "£${1}", 0.90, "Normalize pounds sterling to £ prefix"),
+    (r"\b{n}\s*dollars?\b", "$$${1}", 0.90, "Normalize dollars to $ prefix"),
+    (r"\b{n}\s*euros?\b", "€${1}", 0.90, "Normalize euros to € prefix"),
+];
+
+/// Unit and currency rules for `Locale::DeDe`
+pub static UNIT_PATTERNS_DE_DE: &[(&str, &str, f64, &str)] = &[
+    (r"\b{n}\s*Kilometern?\b", "${1}km", 0.93, "Normalize Kilometer to km"),
+    (r"\b{n}\s*Metern?\b", "${1}m", 0.93, "Normalize Meter to m"),
+    (r"\b{n}\s*Minuten?\b", "${1}min", 0.92, "Normalize Minuten to min"),
+    (r"\b{n}\s*Sekunden?\b", "${1}s", 0.92, "Normalize Sekunden to s"),
+    (r"\b{n}\s*Prozent\b", "${1}%", 0.95, "Normalize Prozent to %"),
+    (r"\b{n}\s*Euro\b", "${1} €", 0.90, "Normalize Euro to trailing €"),
+];
+
+impl Locale {
+    /// Unit and currency rules, with `{n}` still to be expanded
+    pub fn unit_patterns(self) -> &'static [(&'static str, &'static str, f64, &'static str)] {
+        match self {
+            Locale::EnUs => UNIT_PATTERNS_EN_US,
+            Locale::EnGb => UNIT_PATTERNS_EN_GB,
+            Locale::DeDe => UNIT_PATTERNS_DE_DE,
+        }
+    }
+
+    /// Regex capturing one number, thousands separators included
+    /// ("1,000.5" in English, "1.000,5" in German)
+    pub fn number_regex(self) -> &'static str {
+        match self {
+            Locale::EnUs | Locale::EnGb => r"(\d{1,3}(?:,\d{3})+(?:\.\d+)?|\d+(?:\.\d+)?)",
+            Locale::DeDe => r"(\d{1,3}(?:\.\d{3})+(?:,\d+)?|\d+(?:,\d+)?)",
+        }
+    }
+
+    /// Unit patterns with the number format filled in
+    pub fn expanded_unit_patterns(self) -> Vec<(String, &'static str, f64, &'static str)> {
+        self.unit_patterns()
+            .iter()
+            .map(|(pattern, replacement, confidence, reasoning)| {
+                (pattern.replace("{n}", self.number_regex()), *replacement, *confidence, *reasoning)
+            })
+            .collect()
+    }
+}
+
+/// Verbose JSON keys and their short forms
+///
+/// Opt-in (`rewrite_json_keys`): a model parsing real JSON needs the exact
+/// keys, so these are never part of the structural patterns.
+pub static JSON_KEY_PATTERNS: &[(&str, &str, f64, &str)] = &[
     (
-        r"\n\n\n+",
-        "\n\n",
-        0.95,
-        "Collapse excessive newlines (>2 → 2)"
+        r#""description":\s*"#,
+        r#""desc":"#,
+        0.85,
+        "Shorten JSON key: description → desc"
     ),
     (
-        r"  +",
-        " ",
-        0.94,
-        "Collapse multiple spaces to single space"
+        r#""configuration":\s*"#,
+        r#""config":"#,
+        0.85,
+        "Shorten JSON key: configuration → config"
     ),
+    (
+        r#""parameters":\s*"#,
+        r#""params":"#,
+        0.85,
+        "Shorten JSON key: parameters → params"
+    ),
+];
+
+/// Markdown markers that can be stripped for models that don't need markdown
+/// Only applied in aggressive mode, and never inside code blocks
+/// Decorative separator runs (opt-in: often meaningful section delimiters)
+pub static SEPARATOR_PATTERNS: &[(&str, &str, f64, &str)] = &[
     (
         r"={3,}",
         "",
@@ -476,49 +666,137 @@ pub static STRUCTURAL_PATTERNS: &[(&str, &str, f64, &str)] = &[
         0.88,
         "Remove decorative separators (***)"
     ),
+];
 
-    // Verbose JSON/structure keywords
+pub static MARKDOWN_PATTERNS: &[(&str, &str, f64, &str)] = &[
     (
-        r#""description":\s*"#,
-        r#""desc":"#,
-        0.85,
-        "Shorten JSON key: description → desc"
+        r"(?m)^[ \t]*[-*+][ \t]+",
+        "",
+        0.92,
+        "Strip markdown bullet marker (plain line)"
     ),
     (
-        r#""configuration":\s*"#,
-        r#""config":"#,
-        0.85,
-        "Shorten JSON key: configuration → config"
+        r"(?m)^#{1,6}[ \t]+",
+        "",
+        0.92,
+        "Strip markdown header marker (plain text)"
     ),
+];
+
+/// Hedge phrases that soften an instruction without changing it
+/// (aggressive mode only: in opinion or analysis prompts they carry meaning)
+pub static HEDGE_PHRASES: &[(&str, f64, &str)] = &[
+    (r"(?i)\bI think(?: that)?\s+", 0.74, "Hedge phrase"),
+    (r"(?i)\bI believe(?: that)?\s+", 0.74, "Hedge phrase"),
+    (r"(?i)\bin my opinion,?\s*", 0.76, "Opinion hedge"),
+    (r"(?i)\bit seems (?:like|that)\s+", 0.72, "Uncertainty hedge"),
+    (r"(?i)\bto be honest,?\s*", 0.80, "Candor filler"),
+];
+
+/// Verbose sentence openers (aggressive mode only)
+pub static SENTENCE_OPENERS: &[(&str, &str, f64, &str)] = &[
     (
-        r#""parameters":\s*"#,
-        r#""params":"#,
-        0.85,
-        "Shorten JSON key: parameters → params"
+        r"(?i)\bit is important to note that\s+",
+        "",
+        0.76,
+        "Verbose sentence opener"
     ),
-
-    // Excessive punctuation
     (
-        r"\.{2,}",
-        ".",
-        0.90,
-        "Normalize ellipsis to single period"
+        r"(?i)\bplease note that\s+",
+        "",
+        0.76,
+        "Verbose sentence opener"
     ),
     (
-        r"!{2,}",
-        "!",
-        0.90,
-        "Collapse multiple exclamation marks"
+        r"(?i)\bkeep in mind that\s+",
+        "",
+        0.74,
+        "Verbose sentence opener"
     ),
     (
-        r"\?{2,}",
-        "?",
-        0.90,
-        "Collapse multiple question marks"
+        r"(?i)\bas you (?:may )?know,?\s*",
+        "",
+        0.75,
+        "Assumed-knowledge opener"
+    ),
+    (
+        r"(?i)\bfirst of all,?\s*",
+        "First, ",
+        0.78,
+        "Compress sentence opener"
+    ),
+];
+
+/// Articles dropped before a following word ("analyze the code" → "analyze
+/// code"). Only applied in aggressive mode; confidence is kept low so any
+/// technical or ambiguous context pushes them into review
+pub static ARTICLE_PATTERNS: &[(&str, &str, f64, &str)] = &[
+    (
+        r"(?i)\bthe[ \t]+",
+        "",
+        0.80,
+        "Drop definite article (telegraphic instruction)"
+    ),
+    (
+        r"(?i)\ban?[ \t]+",
+        "",
+        0.80,
+        "Drop indefinite article (telegraphic instruction)"
     ),
 ];
 
+/// Words whose meaning changes without the article ("a few" vs "few")
+const ARTICLE_KEEP_BEFORE: &[&str] = &[
+    "few", "little", "lot", "bit", "couple", "while", "number", "total",
+];
+
+/// Contractions and their expansions (ambiguous 's/'d forms are left out)
+pub static CONTRACTIONS: &[(&str, &str)] = &[
+    ("don't", "do not"),
+    ("doesn't", "does not"),
+    ("didn't", "did not"),
+    ("can't", "cannot"),
+    ("won't", "will not"),
+    ("isn't", "is not"),
+    ("aren't", "are not"),
+    ("wasn't", "was not"),
+    ("weren't", "were not"),
+    ("shouldn't", "should not"),
+    ("wouldn't", "would not"),
+    ("couldn't", "could not"),
+    ("haven't", "have not"),
+    ("hasn't", "has not"),
+    ("I'm", "I am"),
+    ("you're", "you are"),
+    ("we're", "we are"),
+    ("they're", "they are"),
+    ("I'll", "I will"),
+    ("you'll", "you will"),
+    ("we'll", "we will"),
+    ("I've", "I have"),
+    ("you've", "you have"),
+    ("let's", "let us"),
+];
+
 lazy_static! {
+    /// Contraction regexes: (contracted, expanded, contraction, expansion);
+    /// straight and curly apostrophes both match
+    static ref CONTRACTION_REGEXES: Vec<(Regex, Regex, &'static str, &'static str)> = {
+        CONTRACTIONS
+            .iter()
+            .filter_map(|(contraction, expansion)| {
+                let contracted = regex::escape(contraction).replace('\'', "['’]");
+                let expanded = regex::escape(expansion).replace(' ', r"\s+");
+                Some((
+                    Regex::new(&format!(r"(?i)\b{}\b", contracted)).ok()?,
+                    Regex::new(&format!(r"(?i)\b{}\b", expanded)).ok()?,
+                    *contraction,
+                    *expansion,
+                ))
+            })
+            .collect()
+    };
+
     /// Compiled boilerplate patterns
     pub static ref BOILERPLATE_REGEXES: Vec<Pattern> = {
         BOILERPLATE_PATTERNS
@@ -530,6 +808,9 @@ lazy_static! {
                     replacement: replacement.to_string(),
                     base_confidence: *confidence,
                     reasoning: reasoning.to_string(),
+                    context_guard: None,
+                    priority: None,
+                    aggressive_only: false,
                 })
             })
             .collect()
@@ -546,11 +827,84 @@ lazy_static! {
                     replacement: String::new(),
                     base_confidence: *confidence,
                     reasoning: reasoning.to_string(),
+                    context_guard: None,
+                    priority: None,
+                    aggressive_only: false,
                 })
             })
             .collect()
     };
 
+    /// Compiled instruction compression patterns
+    pub static ref INSTRUCTION_REGEXES: Vec<Pattern> = {
+        INSTRUCTION_PATTERNS
+            .iter()
+            .filter_map(|(pattern, replacement, confidence, reasoning)| {
+                Regex::new(pattern).ok().map(|regex| Pattern {
+                    pattern_type: OptimizationType::InstructionCompression,
+                    regex,
+                    replacement: replacement.to_string(),
+                    base_confidence: *confidence,
+                    reasoning: reasoning.to_string(),
+                    context_guard: None,
+                    priority: None,
+                    aggressive_only: false,
+                })
+            })
+            .collect()
+    };
+
+    /// Compiled redundant phrase patterns
+    pub static ref REDUNDANT_REGEXES: Vec<Pattern> = {
+        REDUNDANT_PHRASES
+            .iter()
+            .filter_map(|(pattern, replacement, confidence, reasoning)| {
+                Regex::new(pattern).ok().map(|regex| Pattern {
+                    pattern_type: OptimizationType::FormatConsolidation,
+                    regex,
+                    replacement: replacement.to_string(),
+                    base_confidence: *confidence,
+                    reasoning: reasoning.to_string(),
+                    context_guard: None,
+                    priority: None,
+                    aggressive_only: false,
+                })
+            })
+            .collect()
+    };
+
+    /// Compiled synonym pairs: "X and/or Y" in either order becomes the
+    /// preferred term
+    pub static ref SYNONYM_REGEXES: Vec<Pattern> = {
+        let mut patterns = Vec::new();
+        for (preferred, alternatives, confidence, reasoning) in SYNONYM_PAIRS {
+            for alt in *alternatives {
+                let orders = [
+                    (*alt, "and", *preferred),
+                    (*preferred, "and", *alt),
+                    (*alt, "or", *preferred),
+                    (*preferred, "or", *alt),
+                ];
+                for (first, conjunction, second) in orders {
+                    let pattern = format!(r"(?i)\b{}\s+{}\s+{}\b", first, conjunction, second);
+                    if let Ok(regex) = Regex::new(&pattern) {
+                        patterns.push(Pattern {
+                            pattern_type: OptimizationType::SynonymConsolidation,
+                            regex,
+                            replacement: preferred.to_string(),
+                            base_confidence: *confidence,
+                            reasoning: reasoning.to_string(),
+                            context_guard: None,
+                            priority: None,
+                            aggressive_only: false,
+                        });
+                    }
+                }
+            }
+        }
+        patterns
+    };
+
     /// Mandarin substitution lookup
     pub static ref MANDARIN_MAP: HashMap<String, (String, f64, String)> = {
         MANDARIN_SUBSTITUTIONS
@@ -567,6 +921,89 @@ lazy_static! {
     /// Compiled structural optimization patterns
     pub static ref STRUCTURAL_REGEXES: Vec<Pattern> = {
         STRUCTURAL_PATTERNS
+            .iter()
+            .filter_map(|(pattern, replacement, confidence, reasoning)| {
+                Regex::new(pattern).ok().map(|regex| Pattern {
+                    pattern_type: OptimizationType::Whitespace,
+                    regex,
+                    replacement: replacement.to_string(),
+                    base_confidence: *confidence,
+                    reasoning: reasoning.to_string(),
+                    context_guard: None,
+                    priority: None,
+                    aggressive_only: false,
+                })
+            })
+            .collect()
+    };
+
+    /// Compiled numeric range patterns
+    pub static ref RANGE_REGEXES: Vec<Pattern> = {
+        RANGE_PATTERNS
+            .iter()
+            .filter_map(|(pattern, replacement, confidence, reasoning, guard)| {
+                Some(Pattern {
+                    pattern_type: OptimizationType::FormatConsolidation,
+                    regex: Regex::new(pattern).ok()?,
+                    replacement: replacement.to_string(),
+                    base_confidence: *confidence,
+                    reasoning: reasoning.to_string(),
+                    context_guard: guard.map(ContextGuard::parse).transpose().ok()?,
+                    priority: None,
+                    aggressive_only: false,
+                })
+            })
+            .collect()
+    };
+
+    /// Compiled unit and currency patterns per locale
+    pub static ref UNIT_REGEXES: HashMap<Locale, Vec<Pattern>> = {
+        [Locale::EnUs, Locale::EnGb, Locale::DeDe]
+            .into_iter()
+            .map(|locale| {
+                let patterns = locale
+                    .expanded_unit_patterns()
+                    .into_iter()
+                    .filter_map(|(pattern, replacement, confidence, reasoning)| {
+                        Regex::new(&pattern).ok().map(|regex| Pattern {
+                            pattern_type: OptimizationType::FormatConsolidation,
+                            regex,
+                            replacement: replacement.to_string(),
+                            base_confidence: confidence,
+                            reasoning: reasoning.to_string(),
+                            context_guard: None,
+                            priority: None,
+                            aggressive_only: false,
+                        })
+                    })
+                    .collect();
+                (locale, patterns)
+            })
+            .collect()
+    };
+
+    /// Compiled JSON key patterns
+    pub static ref JSON_KEY_REGEXES: Vec<Pattern> = {
+        JSON_KEY_PATTERNS
+            .iter()
+            .filter_map(|(pattern, replacement, confidence, reasoning)| {
+                Regex::new(pattern).ok().map(|regex| Pattern {
+                    pattern_type: OptimizationType::FormatConsolidation,
+                    regex,
+                    replacement: replacement.to_string(),
+                    base_confidence: *confidence,
+                    reasoning: reasoning.to_string(),
+                    context_guard: None,
+                    priority: None,
+                    aggressive_only: false,
+                })
+            })
+            .collect()
+    };
+
+    /// Compiled decorative separator patterns
+    pub static ref SEPARATOR_REGEXES: Vec<Pattern> = {
+        SEPARATOR_PATTERNS
             .iter()
             .filter_map(|(pattern, replacement, confidence, reasoning)| {
                 Regex::new(pattern).ok().map(|regex| Pattern {
@@ -575,182 +1012,333 @@ lazy_static! {
                     replacement: replacement.to_string(),
                     base_confidence: *confidence,
                     reasoning: reasoning.to_string(),
+                    context_guard: None,
+                    priority: None,
+                    aggressive_only: false,
+                })
+            })
+            .collect()
+    };
+
+    /// Compiled hedge phrase patterns (aggressive only)
+    pub static ref HEDGE_REGEXES: Vec<Pattern> = {
+        HEDGE_PHRASES
+            .iter()
+            .filter_map(|(pattern, confidence, reasoning)| {
+                Regex::new(pattern).ok().map(|regex| Pattern {
+                    pattern_type: OptimizationType::FillerRemoval,
+                    regex,
+                    replacement: String::new(),
+                    base_confidence: *confidence,
+                    reasoning: reasoning.to_string(),
+                    context_guard: None,
+                    priority: None,
+                    aggressive_only: true,
+                })
+            })
+            .collect()
+    };
+
+    /// Compiled sentence opener patterns (aggressive only)
+    pub static ref OPENER_REGEXES: Vec<Pattern> = {
+        SENTENCE_OPENERS
+            .iter()
+            .filter_map(|(pattern, replacement, confidence, reasoning)| {
+                Regex::new(pattern).ok().map(|regex| Pattern {
+                    pattern_type: OptimizationType::InstructionCompression,
+                    regex,
+                    replacement: replacement.to_string(),
+                    base_confidence: *confidence,
+                    reasoning: reasoning.to_string(),
+                    context_guard: None,
+                    priority: None,
+                    aggressive_only: true,
+                })
+            })
+            .collect()
+    };
+
+    /// Compiled article patterns
+    pub static ref ARTICLE_REGEXES: Vec<Pattern> = {
+        ARTICLE_PATTERNS
+            .iter()
+            .filter_map(|(pattern, replacement, confidence, reasoning)| {
+                Regex::new(pattern).ok().map(|regex| Pattern {
+                    pattern_type: OptimizationType::FillerRemoval,
+                    regex,
+                    replacement: replacement.to_string(),
+                    base_confidence: *confidence,
+                    reasoning: reasoning.to_string(),
+                    context_guard: None,
+                    priority: None,
+                    aggressive_only: true,
+                })
+            })
+            .collect()
+    };
+
+    /// Compiled markdown marker patterns
+    pub static ref MARKDOWN_REGEXES: Vec<Pattern> = {
+        MARKDOWN_PATTERNS
+            .iter()
+            .filter_map(|(pattern, replacement, confidence, reasoning)| {
+                Regex::new(pattern).ok().map(|regex| Pattern {
+                    pattern_type: OptimizationType::FormatConsolidation,
+                    regex,
+                    replacement: replacement.to_string(),
+                    base_confidence: *confidence,
+                    reasoning: reasoning.to_string(),
+                    context_guard: None,
+                    priority: None,
+                    aggressive_only: false,
                 })
             })
             .collect()
     };
 }
 
-/// Pattern detector engine
-pub struct PatternDetector;
+/// Whether the article matched by `detected` can go without changing what
+/// follows it
+fn is_droppable_article(text: &str, detected: &DetectedPattern) -> bool {
+    let next_word: String = text[detected.end_pos..]
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
+        .collect();
+    if !next_word.starts_with(char::is_alphabetic)
+        || ARTICLE_KEEP_BEFORE.contains(&next_word.as_str())
+    {
+        return false;
+    }
 
-impl PatternDetector {
-    pub fn new() -> Self {
-        Self
+    // Attached to the previous token ("x-a b", "'the list'")
+    let attached = text[..detected.start_pos].ends_with(['-', '\'', '"', '/', '.']);
+    if attached {
+        return false;
     }
 
-    /// Detect all patterns in text
-    pub fn detect_all(&self, text: &str) -> Vec<DetectedPattern> {
-        let mut detected = Vec::new();
+    // "A" mid-sentence is a label ("option A uses"), not an article
+    if detected.original_text.trim_end() == "A" {
+        let before = text[..detected.start_pos].trim_end_matches([' ', '\t']);
+        return before.is_empty() || before.ends_with(['.', '!', '?', ':', '\n']);
+    }
+    true
+}
 
-        detected.extend(self.detect_structural(text));
-        detected.extend(self.detect_boilerplate(text));
-        detected.extend(self.detect_instructions(text));
-        detected.extend(self.detect_redundant_phrases(text));
-        detected.extend(self.detect_fillers(text));
-        detected.extend(self.detect_synonyms(text));
-        detected.extend(self.detect_mandarin(text));
+/// Detect contractions (or expanded forms) that the tokenizer encodes more
+/// cheaply the other way
+///
+/// Each phrase is counted with a leading space, as it appears mid-sentence,
+/// and only strictly cheaper rewrites are reported. Quoted strings and code
+/// are skipped whatever the protection policy.
+pub fn detect_contractions(
+    text: &str,
+    policy: ContractionPolicy,
+    tokenizer: &Tokenizer,
+) -> Vec<DetectedPattern> {
+    let (expand, contract) = match policy {
+        ContractionPolicy::Off => return Vec::new(),
+        ContractionPolicy::Cheapest => (true, true),
+        ContractionPolicy::Expand => (true, false),
+        ContractionPolicy::Contract => (false, true),
+    };
 
-        // Sort by position to handle overlaps later
-        detected.sort_by_key(|d| d.start_pos);
-        detected
-    }
+    let region_detector = ProtectedRegionDetector::default();
+    let mut skipped = region_detector.detect_quoted_strings(text);
+    skipped.extend(region_detector.detect_code_blocks(text));
 
-    /// Detect structural optimization opportunities
-    fn detect_structural(&self, text: &str) -> Vec<DetectedPattern> {
-        let mut detected = Vec::new();
+    let mut detected = Vec::new();
+    for (contracted, expanded, contraction, expansion) in CONTRACTION_REGEXES.iter() {
+        let directions = [
+            (expand, contracted, *expansion, "Expand contraction"),
+            (contract, expanded, *contraction, "Contract phrase"),
+        ];
 
-        for pattern in STRUCTURAL_REGEXES.iter() {
-            for mat in pattern.regex.find_iter(text) {
-                let optimized = pattern.regex.replace(mat.as_str(), &pattern.replacement);
-                detected.push(DetectedPattern {
-                    pattern_type: OptimizationType::FormatConsolidation,
-                    original_text: mat.as_str().to_string(),
-                    optimized_text: optimized.to_string(),
-                    start_pos: mat.start(),
-                    end_pos: mat.end(),
-                    base_confidence: pattern.base_confidence,
-                    reasoning: pattern.reasoning.clone(),
-                });
+        for (enabled, regex, replacement, reasoning) in directions {
+            if !enabled {
+                continue;
             }
-        }
 
-        detected
-    }
+            for mat in regex.find_iter(text) {
+                if region_detector.is_protected(&skipped, mat.start(), mat.end()) {
+                    continue;
+                }
 
-    /// Detect boilerplate patterns
-    fn detect_boilerplate(&self, text: &str) -> Vec<DetectedPattern> {
-        let mut detected = Vec::new();
+                let replacement = match_leading_case(mat.as_str(), replacement);
+                let before = tokenizer.count_tokens(&format!(" {}", mat.as_str()));
+                let after = tokenizer.count_tokens(&format!(" {}", replacement));
+                if after >= before {
+                    continue;
+                }
 
-        for pattern in BOILERPLATE_REGEXES.iter() {
-            for mat in pattern.regex.find_iter(text) {
                 detected.push(DetectedPattern {
-                    pattern_type: OptimizationType::BoilerplateRemoval,
+                    pattern_type: OptimizationType::FormatConsolidation,
                     original_text: mat.as_str().to_string(),
-                    optimized_text: pattern.replacement.clone(),
+                    optimized_text: replacement,
                     start_pos: mat.start(),
                     end_pos: mat.end(),
-                    base_confidence: pattern.base_confidence,
-                    reasoning: pattern.reasoning.clone(),
+                    base_confidence: 0.93,
+                    reasoning: format!("{} ({} → {} tokens)", reasoning, before, after),
+                    priority: None,
                 });
             }
         }
-
-        detected
     }
 
-    /// Detect filler words
-    fn detect_fillers(&self, text: &str) -> Vec<DetectedPattern> {
-        let mut detected = Vec::new();
+    detected.sort_by_key(|d| d.start_pos);
+    detected
+}
 
-        for pattern in FILLER_REGEXES.iter() {
-            for mat in pattern.regex.find_iter(text) {
-                detected.push(DetectedPattern {
-                    pattern_type: OptimizationType::FillerRemoval,
-                    original_text: mat.as_str().to_string(),
-                    optimized_text: String::new(),
-                    start_pos: mat.start(),
-                    end_pos: mat.end(),
-                    base_confidence: pattern.base_confidence,
-                    reasoning: pattern.reasoning.clone(),
-                });
+/// Detect verbose JSON keys that can be shortened
+///
+/// Keys in code, or inside a larger quoted string (a JSON payload embedded
+/// in quotes), are skipped whatever the protection policy.
+pub fn detect_json_keys(text: &str) -> Vec<DetectedPattern> {
+    let region_detector = ProtectedRegionDetector::default();
+    let code = region_detector.detect_code_blocks(text);
+    let quoted = region_detector.detect_quoted_strings(text);
+
+    let mut detected = Vec::new();
+    for pattern in JSON_KEY_REGEXES.iter() {
+        for mat in pattern.regex.find_iter(text) {
+            // The key's own quotes are a quoted string too; only an
+            // enclosing one marks an embedded payload
+            let key_end = mat.as_str().trim_end().trim_end_matches(':').len() + mat.start();
+            let enclosed = quoted.iter().any(|r| {
+                r.start <= mat.start() && key_end <= r.end && (r.start, r.end) != (mat.start(), key_end)
+            });
+            if enclosed || region_detector.is_protected(&code, mat.start(), mat.end()) {
+                continue;
             }
+
+            detected.push(DetectedPattern {
+                pattern_type: OptimizationType::FormatConsolidation,
+                original_text: mat.as_str().to_string(),
+                optimized_text: pattern.regex.replace(mat.as_str(), &pattern.replacement).to_string(),
+                start_pos: mat.start(),
+                end_pos: mat.end(),
+                base_confidence: pattern.base_confidence,
+                reasoning: pattern.reasoning.clone(),
+                priority: None,
+            });
         }
+    }
 
-        detected
+    detected.sort_by_key(|d| d.start_pos);
+    detected
+}
+
+/// Capitalize `replacement` when `original` starts with a capital
+fn match_leading_case(original: &str, replacement: &str) -> String {
+    let mut chars = replacement.chars();
+    match (original.chars().next(), chars.next()) {
+        (Some(o), Some(r)) if o.is_uppercase() && r.is_lowercase() => {
+            r.to_uppercase().collect::<String>() + chars.as_str()
+        }
+        _ => replacement.to_string(),
     }
+}
 
-    /// Detect synonym consolidation opportunities
-    fn detect_synonyms(&self, text: &str) -> Vec<DetectedPattern> {
-        let mut detected = Vec::new();
-        let text_lower = text.to_lowercase();
+/// Pattern detector over the built-in tables (the hardcoded `PatternSource`)
+///
+/// Built-in patterns are compiled once into shared statics and custom ones
+/// sit behind an `Arc`, so the detector is `Send + Sync` and cheap to clone.
+#[derive(Clone)]
+pub struct PatternDetector {
+    enabled: PatternsConfig,
+    custom: Arc<Vec<Pattern>>,
+}
 
-        for (preferred, alternatives, confidence, reasoning) in SYNONYM_PAIRS {
-            // Look for patterns like "X and Y" where Y is the alternative
-            for alt in *alternatives {
-                // Pattern: "alternative and/or preferred" or "preferred and/or alternative"
-                let patterns = [
-                    format!(r"\b{}\s+and\s+{}\b", alt, preferred),
-                    format!(r"\b{}\s+and\s+{}\b", preferred, alt),
-                    format!(r"\b{}\s+or\s+{}\b", alt, preferred),
-                    format!(r"\b{}\s+or\s+{}\b", preferred, alt),
-                ];
+impl PatternDetector {
+    pub fn new() -> Self {
+        Self::with_config(PatternsConfig::default())
+    }
 
-                for pattern_str in &patterns {
-                    if let Ok(regex) = Regex::new(pattern_str) {
-                        for mat in regex.find_iter(&text_lower) {
-                            detected.push(DetectedPattern {
-                                pattern_type: OptimizationType::SynonymConsolidation,
-                                original_text: text[mat.start()..mat.end()].to_string(),
-                                optimized_text: preferred.to_string(),
-                                start_pos: mat.start(),
-                                end_pos: mat.end(),
-                                base_confidence: *confidence,
-                                reasoning: reasoning.to_string(),
-                            });
-                        }
-                    }
-                }
-            }
+    /// Run only the pattern families enabled in `enabled` (instruction
+    /// compression always runs)
+    pub fn with_config(enabled: PatternsConfig) -> Self {
+        Self {
+            enabled,
+            custom: Arc::new(Vec::new()),
         }
+    }
 
-        detected
+    /// Also detect `pattern`, whatever families are enabled
+    ///
+    /// The replacement may reference capture groups (`$1`).
+    pub fn with_pattern(mut self, pattern: Pattern) -> Self {
+        Arc::make_mut(&mut self.custom).push(pattern);
+        self
     }
 
-    /// Detect instruction compression opportunities
-    fn detect_instructions(&self, text: &str) -> Vec<DetectedPattern> {
-        let mut detected = Vec::new();
+    /// Detect all patterns in text, with `en-US` unit rules
+    pub fn detect_all(&self, text: &str) -> Vec<DetectedPattern> {
+        self.detect_all_for(text, Locale::default())
+    }
 
-        for (pattern_str, replacement, confidence, reasoning) in INSTRUCTION_PATTERNS {
-            if let Ok(regex) = Regex::new(pattern_str) {
-                for mat in regex.find_iter(text) {
-                    detected.push(DetectedPattern {
-                        pattern_type: OptimizationType::InstructionCompression,
-                        original_text: mat.as_str().to_string(),
-                        optimized_text: replacement.to_string(),
-                        start_pos: mat.start(),
-                        end_pos: mat.end(),
-                        base_confidence: *confidence,
-                        reasoning: reasoning.to_string(),
-                    });
-                }
-            }
-        }
+    /// Detect all patterns in text, with `locale`'s unit, currency and number
+    /// rules
+    pub fn detect_all_for(&self, text: &str, locale: Locale) -> Vec<DetectedPattern> {
+        self.detect_all_with_mode(text, locale, false)
+    }
 
+    /// Detect all patterns in text, including the `aggressive_only` ones
+    /// (hedges, verbose sentence openers) when `aggressive` is set
+    pub fn detect_all_with_mode(&self, text: &str, locale: Locale, aggressive: bool) -> Vec<DetectedPattern> {
+        let mut detected = detect_with_mode(self, text, locale, aggressive);
+
+        // Mandarin substitution is a lookup, not a regex family
+        if self.enabled.mandarin_substitution {
+            detected.extend(self.detect_mandarin(text));
+            detected.sort_by_key(|d| d.start_pos);
+        }
         detected
     }
 
-    /// Detect redundant phrases
-    fn detect_redundant_phrases(&self, text: &str) -> Vec<DetectedPattern> {
-        let mut detected = Vec::new();
+    /// Detect decorative separator runs (`===`, `---`, `***`)
+    ///
+    /// Not part of `detect_all`: separators often delimit sections, so callers
+    /// opt in (aggressive mode only).
+    pub fn detect_separators(&self, text: &str) -> Vec<DetectedPattern> {
+        SEPARATOR_REGEXES.iter().flat_map(|pattern| pattern.detect(text)).collect()
+    }
 
-        for (pattern_str, replacement, confidence, reasoning) in REDUNDANT_PHRASES {
-            if let Ok(regex) = Regex::new(pattern_str) {
-                for mat in regex.find_iter(text) {
-                    detected.push(DetectedPattern {
-                        pattern_type: OptimizationType::FormatConsolidation,
-                        original_text: mat.as_str().to_string(),
-                        optimized_text: replacement.to_string(),
-                        start_pos: mat.start(),
-                        end_pos: mat.end(),
-                        base_confidence: *confidence,
-                        reasoning: reasoning.to_string(),
-                    });
-                }
-            }
-        }
+    /// Detect articles ("the", "a", "an") that can be dropped before a word
+    ///
+    /// Not part of `detect_all`: callers opt in (aggressive mode only). An
+    /// article is kept unless a word follows it, so "a.m.", "plan A." and
+    /// "a few" are left alone; a capital "A" only counts as an article at the
+    /// start of a sentence.
+    pub fn detect_articles(&self, text: &str) -> Vec<DetectedPattern> {
+        ARTICLE_REGEXES
+            .iter()
+            .flat_map(|pattern| pattern.detect(text))
+            .filter(|d| is_droppable_article(text, d))
+            .collect()
+    }
 
-        detected
+    /// Detect markdown bullet/header markers outside code blocks
+    ///
+    /// Not part of `detect_all`: callers opt in (aggressive mode only). Only
+    /// the marker is matched: a bullet's indentation is kept, so nested
+    /// lists stay nested.
+    pub fn detect_markdown(&self, text: &str) -> Vec<DetectedPattern> {
+        let code_regions = ProtectedRegionDetector::default().detect_code_blocks(text);
+
+        MARKDOWN_REGEXES
+            .iter()
+            .flat_map(|pattern| pattern.detect(text))
+            .filter(|d| {
+                !code_regions
+                    .iter()
+                    .any(|r| d.start_pos < r.end && d.end_pos > r.start)
+            })
+            .map(|mut d| {
+                let indent = d.original_text.len() - d.original_text.trim_start_matches([' ', '\t']).len();
+                d.original_text.drain(..indent);
+                d.start_pos += indent;
+                d
+            })
+            .collect()
     }
 
     /// Detect Mandarin substitution opportunities
@@ -768,12 +1356,131 @@ impl PatternDetector {
                     end_pos: pos + english.len(),
                     base_confidence: *confidence,
                     reasoning: reasoning.clone(),
+                    priority: None,
                 });
             }
         }
 
         detected
     }
+
+    /// Measure what a candidate pattern would do across `prompts` before
+    /// adding it
+    ///
+    /// Savings are counted in context with the default tokenizer, and matches
+    /// overlapping one of this detector's own patterns are counted separately
+    /// so over-matching or redundant rules stand out. The replacement may
+    /// reference capture groups (`$1`).
+    pub fn dry_run_pattern(&self, regex: &str, replacement: &str, prompts: &[String]) -> Result<PatternImpact> {
+        let pattern = Pattern {
+            pattern_type: OptimizationType::FormatConsolidation,
+            regex: Regex::new(regex)?,
+            replacement: replacement.to_string(),
+            base_confidence: 1.0,
+            reasoning: String::new(),
+            context_guard: None,
+            priority: None,
+            aggressive_only: false,
+        };
+        let tokenizer = Tokenizer::new()?;
+
+        let mut impact = PatternImpact {
+            prompts: prompts.len(),
+            ..Default::default()
+        };
+        for (prompt_index, prompt) in prompts.iter().enumerate() {
+            let matches = pattern.detect(prompt);
+            if matches.is_empty() {
+                continue;
+            }
+            impact.prompts_matched += 1;
+
+            let existing = self.detect_all(prompt);
+            for found in matches {
+                let token_savings =
+                    tokenizer.savings_in_context(prompt, found.start_pos, found.end_pos, &found.optimized_text);
+                impact.match_count += 1;
+                impact.token_savings += token_savings;
+                if existing
+                    .iter()
+                    .any(|d| d.start_pos < found.end_pos && d.end_pos > found.start_pos)
+                {
+                    impact.overlapping_existing += 1;
+                }
+                if impact.examples.len() < DRY_RUN_EXAMPLES {
+                    impact.examples.push(PatternMatchExample {
+                        prompt_index,
+                        original_text: found.original_text,
+                        optimized_text: found.optimized_text,
+                        token_savings,
+                    });
+                }
+            }
+        }
+
+        Ok(impact)
+    }
+}
+
+/// Example matches kept by `PatternDetector::dry_run_pattern`
+pub const DRY_RUN_EXAMPLES: usize = 5;
+
+/// What a candidate pattern would do across a corpus
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PatternImpact {
+    /// Prompts searched
+    pub prompts: usize,
+    /// Prompts with at least one match
+    pub prompts_matched: usize,
+    pub match_count: usize,
+    /// Estimated tokens saved by replacing every match
+    pub token_savings: i64,
+    /// Matches overlapping what the existing patterns already catch
+    pub overlapping_existing: usize,
+    /// The first few matches, in corpus order
+    pub examples: Vec<PatternMatchExample>,
+}
+
+/// One match found by a dry run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PatternMatchExample {
+    /// Index into the prompts passed to the dry run
+    pub prompt_index: usize,
+    pub original_text: String,
+    pub optimized_text: String,
+    pub token_savings: i64,
+}
+
+impl PatternSource for PatternDetector {
+    /// The enabled built-in families (instruction compression always), then
+    /// the `with_pattern` ones; hedges and sentence openers are
+    /// `aggressive_only`
+    fn patterns(&self, locale: Locale) -> Vec<&Pattern> {
+        let mut patterns: Vec<&Pattern> = Vec::new();
+
+        if self.enabled.whitespace_normalization {
+            patterns.extend(STRUCTURAL_REGEXES.iter());
+        }
+        if self.enabled.format_consolidation {
+            patterns.extend(&UNIT_REGEXES[&locale]);
+            patterns.extend(RANGE_REGEXES.iter());
+            patterns.extend(REDUNDANT_REGEXES.iter());
+        }
+        if self.enabled.boilerplate_enabled {
+            patterns.extend(BOILERPLATE_REGEXES.iter());
+        }
+        patterns.extend(INSTRUCTION_REGEXES.iter());
+        patterns.extend(OPENER_REGEXES.iter());
+        if self.enabled.filler_removal {
+            patterns.extend(FILLER_REGEXES.iter());
+            patterns.extend(HEDGE_REGEXES.iter());
+        }
+        if self.enabled.synonym_consolidation {
+            patterns.extend(SYNONYM_REGEXES.iter());
+        }
+        patterns.extend(self.custom.iter());
+        patterns
+    }
 }
 
 impl Default for PatternDetector {
@@ -786,31 +1493,87 @@ impl Default for PatternDetector {
 mod tests {
     use super::*;
 
+    /// Run one family's patterns, in table order
+    fn detect_family<'a>(patterns: impl IntoIterator<Item = &'a Pattern>, text: &str) -> Vec<DetectedPattern> {
+        patterns.into_iter().flat_map(|pattern| pattern.detect(text)).collect()
+    }
+
+    fn detect_structural(text: &str, locale: Locale) -> Vec<DetectedPattern> {
+        detect_family(STRUCTURAL_REGEXES.iter().chain(&UNIT_REGEXES[&locale]), text)
+    }
+
     #[test]
     fn test_boilerplate_detection() {
-        let detector = PatternDetector::new();
         let text = "I would really appreciate it if you could help me with this task.";
 
-        let detected = detector.detect_boilerplate(text);
+        let detected = detect_family(BOILERPLATE_REGEXES.iter(), text);
         assert!(!detected.is_empty());
         assert!(detected[0].base_confidence > 0.9);
     }
 
+    #[test]
+    fn test_disabled_family_not_detected() {
+        let text = "This is really important. Please provide a summary.";
+        let has_filler = |detector: &PatternDetector| {
+            detector
+                .detect_all(text)
+                .iter()
+                .any(|d| d.pattern_type == OptimizationType::FillerRemoval)
+        };
+
+        assert!(has_filler(&PatternDetector::new()));
+        assert!(!has_filler(&PatternDetector::with_config(PatternsConfig {
+            filler_removal: false,
+            ..Default::default()
+        })));
+    }
+
+    #[test]
+    fn test_whitespace_and_format_toggle_independently() {
+        let text = "Wait...\n\n\n\nRun it for 5 minutes  or between 5 and 10 times.";
+        let types = |config: PatternsConfig| {
+            let mut types: Vec<OptimizationType> = PatternDetector::with_config(config)
+                .detect_all(text)
+                .into_iter()
+                .map(|d| d.pattern_type)
+                .filter(|t| matches!(t, OptimizationType::Whitespace | OptimizationType::FormatConsolidation))
+                .collect();
+            types.sort();
+            types.dedup();
+            types
+        };
+
+        assert_eq!(
+            types(PatternsConfig::default()),
+            vec![OptimizationType::FormatConsolidation, OptimizationType::Whitespace]
+        );
+        assert_eq!(
+            types(PatternsConfig { format_consolidation: false, ..Default::default() }),
+            vec![OptimizationType::Whitespace]
+        );
+        assert_eq!(
+            types(PatternsConfig { whitespace_normalization: false, ..Default::default() }),
+            vec![OptimizationType::FormatConsolidation]
+        );
+
+        // Configs written before the split still collapse whitespace
+        let config: PatternsConfig = toml::from_str("format_consolidation = false").unwrap();
+        assert!(config.whitespace_normalization);
+    }
+
     #[test]
     fn test_filler_detection() {
-        let detector = PatternDetector::new();
         let text = "This is really very important and definitely needs attention.";
 
-        let detected = detector.detect_fillers(text);
+        let detected = detect_family(FILLER_REGEXES.iter(), text);
         assert!(detected.len() >= 3); // really, very, definitely
     }
 
     #[test]
     fn test_synonym_detection() {
-        let detector = PatternDetector::new();
         let text = "Please analyze and examine this code carefully.";
 
-        let detected = detector.detect_synonyms(text);
+        let detected = detect_family(SYNONYM_REGEXES.iter(), text);
         assert!(!detected.is_empty());
     }
 
@@ -827,10 +1590,9 @@ mod tests {
 
     #[test]
     fn test_structural_detection() {
-        let detector = PatternDetector::new();
         let text = "The distance is 10 kilometers and it takes 5 minutes at 50 percent speed.";
 
-        let detected = detector.detect_structural(text);
+        let detected = detect_structural(text, Locale::EnUs);
         // Should detect: "10 kilometers" → "10km", "5 minutes" → "5min", "50 percent" → "50%"
         assert!(detected.len() >= 3, "Should detect at least 3 structural optimizations");
 
@@ -840,23 +1602,215 @@ mod tests {
         assert!(km_opt.unwrap().optimized_text.contains("km"));
     }
 
+    #[test]
+    fn test_locale_unit_rules() {
+        let rewrites = |text: &str, locale| {
+            detect_structural(text, locale)
+                .into_iter()
+                .map(|d| (d.original_text, d.optimized_text))
+                .collect::<Vec<_>>()
+        };
+
+        // British spelling only under en-GB
+        let text = "It is 10 kilometres away.";
+        assert!(rewrites(text, Locale::EnUs).is_empty());
+        assert_eq!(
+            rewrites(text, Locale::EnGb),
+            vec![("10 kilometres".to_string(), "10km".to_string())]
+        );
+
+        // Euro amounts, thousands separators kept with the number
+        assert_eq!(
+            rewrites("It costs 1,250.50 euros.", Locale::EnGb),
+            vec![("1,250.50 euros".to_string(), "€1,250.50".to_string())]
+        );
+        assert_eq!(
+            rewrites("It costs 1,000 dollars.", Locale::EnUs),
+            vec![("1,000 dollars".to_string(), "$1,000".to_string())]
+        );
+
+        // Comma decimals under de-DE
+        assert_eq!(
+            rewrites("Es kostet 1.250,50 Euro für 2,5 Kilometer.", Locale::DeDe),
+            vec![
+                ("2,5 Kilometer".to_string(), "2,5km".to_string()),
+                ("1.250,50 Euro".to_string(), "1.250,50 €".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_range_detection() {
+        let rewrites = |text: &str| {
+            detect_family(RANGE_REGEXES.iter(), text)
+                .into_iter()
+                .map(|d| (d.original_text, d.optimized_text))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            rewrites("Retry between 3 and 5 times, waiting from 1.5 to 10% longer."),
+            vec![
+                ("between 3 and 5".to_string(), "3-5".to_string()),
+                ("from 1.5 to 10%".to_string(), "1.5-10%".to_string()),
+            ]
+        );
+        assert_eq!(
+            rewrites("Open From 9:00 to 17:00, budget between $1,000 and $2,500."),
+            vec![
+                ("between $1,000 and $2,500".to_string(), "$1,000-$2,500".to_string()),
+                ("From 9:00 to 17:00".to_string(), "9:00-17:00".to_string()),
+            ]
+        );
+
+        // Negative bounds, partial numbers and changes are not ranges
+        assert!(rewrites("Clamp between -5 and 5, or between 5 and -5.").is_empty());
+        assert!(rewrites("Pick from 5 to 10x.").is_empty());
+        assert!(rewrites("Increase the limit from 5 to 10.").is_empty());
+        assert!(rewrites("Prices rose from $5 to $8 last year.").is_empty());
+    }
+
     #[test]
     fn test_structural_formatting() {
         let detector = PatternDetector::new();
         let text = "===\nCheck this!!!\nIs this right???\nWait...\n\n\n\nNext section.";
 
-        let detected = detector.detect_structural(text);
-        // Should detect: ===, !!!, ???, ..., \n\n\n+
+        let detected = detect_structural(text, Locale::EnUs);
+        // Should detect: !!!, ???, ..., \n\n\n+
         assert!(detected.len() >= 4, "Should detect formatting optimizations: found {}", detected.len());
+
+        // Separators are opt-in
+        assert!(!detected.iter().any(|d| d.original_text == "==="));
+        let separators = detector.detect_separators(text);
+        assert_eq!(separators.len(), 1);
+        assert_eq!(separators[0].original_text, "===");
     }
 
     #[test]
     fn test_structural_json_keys() {
-        let detector = PatternDetector::new();
         let text = r#"{"description": "test", "configuration": "prod", "parameters": {}}"#;
 
-        let detected = detector.detect_structural(text);
+        let detected = detect_json_keys(text);
         // Should detect: description → desc, configuration → config, parameters → params
         assert!(detected.len() >= 3, "Should detect JSON key shortenings");
+
+        // Opt-in only, and never inside code or an enclosing quoted string
+        assert!(detect_structural(text, Locale::EnUs).is_empty());
+        assert!(detect_json_keys(&format!("```json\n{}\n```", text)).is_empty());
+        assert!(detect_json_keys(r#"Send '{"description": "test"}' as is"#).is_empty());
+    }
+
+    #[test]
+    fn test_markdown_detection() {
+        let detector = PatternDetector::new();
+        let text = "## Tasks\n- first item\n- second item\n* third item\n```\n- not a bullet\n# comment\n```";
+
+        let detected = detector.detect_markdown(text);
+        // Should detect: header + 3 bullets, but nothing inside the code fence
+        assert_eq!(detected.len(), 4, "Found {:?}", detected);
+
+        let fence_start = text.find("```").unwrap();
+        assert!(detected.iter().all(|d| d.end_pos <= fence_start));
+
+        // Nested bullets keep their indentation; only the marker is matched
+        let text = "- top\n  - nested\n\t* tab nested";
+        let detected = detector.detect_markdown(text);
+        let markers: Vec<_> = detected.iter().map(|d| (d.start_pos, d.original_text.as_str())).collect();
+        assert_eq!(markers, vec![(0, "- "), (8, "- "), (18, "* ")]);
+    }
+
+    #[test]
+    fn test_aggressive_only_patterns_never_fire_in_normal_mode() {
+        let custom = Pattern {
+            pattern_type: OptimizationType::FillerRemoval,
+            regex: Regex::new(r"(?i)\bhonestly,?\s*").unwrap(),
+            replacement: String::new(),
+            base_confidence: 0.9,
+            reasoning: "Candor filler".to_string(),
+            context_guard: None,
+            priority: None,
+            aggressive_only: true,
+        };
+        let detector = PatternDetector::new().with_pattern(custom);
+        let text = "Honestly, I think the cache is stale. First of all, it is important to note that reads are slow.";
+
+        let normal = detector.detect_all(text);
+        assert!(normal.iter().all(|d| !["Honestly, ", "I think ", "First of all, "].contains(&d.original_text.as_str())));
+        assert!(!normal.iter().any(|d| d.original_text.starts_with("it is important")));
+        assert_eq!(normal.len(), detector.detect_all_with_mode(text, Locale::default(), false).len());
+
+        let aggressive = detector.detect_all_with_mode(text, Locale::default(), true);
+        for expected in ["Honestly, ", "I think ", "First of all, ", "it is important to note that "] {
+            assert!(aggressive.iter().any(|d| d.original_text == expected), "missing {:?}", expected);
+        }
+        let opener = aggressive.iter().find(|d| d.original_text == "First of all, ").unwrap();
+        assert_eq!(opener.optimized_text, "First, ");
+    }
+
+    #[test]
+    fn test_dry_run_pattern() {
+        let detector = PatternDetector::default();
+        let prompts = vec![
+            "As per my last email, send the report.".to_string(),
+            "Nothing to see here.".to_string(),
+            "as per my last email, please make sure to check the logs".to_string(),
+        ];
+
+        let impact = detector
+            .dry_run_pattern(r"(?i)as per my last email,?\s*", "", &prompts)
+            .unwrap();
+        assert_eq!(impact.prompts, 3);
+        assert_eq!(impact.prompts_matched, 2);
+        assert_eq!(impact.match_count, 2);
+        assert!(impact.token_savings > 0);
+        assert_eq!(impact.overlapping_existing, 0);
+        assert_eq!(impact.examples[1].prompt_index, 2);
+        assert_eq!(impact.examples[0].original_text, "As per my last email, ");
+
+        // Capture references, over-matching and overlap with built-in rules
+        let impact = detector
+            .dry_run_pattern(r"(?i)make sure to (\w+)", "$1", &prompts)
+            .unwrap();
+        assert_eq!(impact.examples[0].optimized_text, "check");
+        assert_eq!(impact.overlapping_existing, 1);
+
+        let noisy: Vec<String> = (0..10).map(|i| format!("item {} and item {}", i, i + 1)).collect();
+        let impact = detector.dry_run_pattern(r"item", "it", &noisy).unwrap();
+        assert_eq!(impact.match_count, 20);
+        assert_eq!(impact.examples.len(), DRY_RUN_EXAMPLES);
+
+        assert!(detector.dry_run_pattern("(", "", &prompts).is_err());
+    }
+
+    #[test]
+    fn test_article_detection() {
+        let detector = PatternDetector::new();
+        let text = "Analyze the code and write an summary. A test covers it.";
+
+        let detected = detector.detect_articles(text);
+        let originals: Vec<&str> = detected.iter().map(|d| d.original_text.as_str()).collect();
+        assert_eq!(originals, vec!["the ", "an ", "A "]);
+        assert!(detected.iter().all(|d| d.optimized_text.is_empty()));
+
+        // Grammatical edges are left alone
+        for text in [
+            "Meet at 9 a.m. tomorrow",
+            "Use option A instead of B",
+            "Keep a few spare rows",
+            "Read the. Then stop",
+            "Set a=1 first",
+            "x-a value",
+        ] {
+            assert!(detector.detect_articles(text).is_empty(), "{}: {:?}", text, detector.detect_articles(text));
+        }
+    }
+
+    #[test]
+    fn test_markdown_ignores_separators_and_bold() {
+        let detector = PatternDetector::new();
+        let text = "---\n**Bold** start\n| a | b |";
+
+        let detected = detector.detect_markdown(text);
+        assert!(detected.is_empty(), "Found {:?}", detected);
     }
 }
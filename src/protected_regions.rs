@@ -1,13 +1,14 @@
-/// Phase 3: Protected Regions - Detect areas that should NEVER be optimized
-///
-/// Purpose: Identify code blocks, template variables, technical terms, URLs,
-/// and other regions where compression could break functionality.
-///
-/// Philosophy: "Efficiency without comprehension is a loop, not a shortcut"
-/// → Never optimize instructions, code, or domain-specific terms
+//! Phase 3: Protected Regions - Detect areas that should NEVER be optimized
+//!
+//! Purpose: Identify code blocks, template variables, technical terms, URLs,
+//! and other regions where compression could break functionality.
+//!
+//! Philosophy: "Efficiency without comprehension is a loop, not a shortcut"
+//! → Never optimize instructions, code, or domain-specific terms
 
 use regex::Regex;
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 
 /// A protected region in the text (byte range)
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -19,11 +20,12 @@ pub struct ProtectedRegion {
 }
 
 /// Type of protected region
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RegionType {
     /// Code block (```...``` or indented)
     CodeBlock,
-    /// Template variable ({{...}}, ${...}, {%...%})
+    /// Template variable ({{...}}, {{{...}}}, ${...}, {%...%}, <|...|>, [[...]])
     TemplateVariable,
     /// URL or file path
     UrlOrPath,
@@ -33,33 +35,72 @@ pub enum RegionType {
     QuotedString,
     /// Instruction keyword (MUST, REQUIRED, FORMAT, OUTPUT)
     InstructionKeyword,
+    /// Example or quoted user text (blockquote, "example:", "e.g.")
+    Example,
+    /// YAML frontmatter block (`---` ... `---`) at the start of the prompt
+    Frontmatter,
+    /// Whole line starting with a comment marker (`// ...`)
+    Comment,
+}
+
+/// Protected region as reported to users (char offsets, short snippet)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProtectedRegionSummary {
+    pub region_type: RegionType,
+    /// Char offset of the region start
+    pub start: usize,
+    /// Char offset just past the region end
+    pub end: usize,
+    pub snippet: String,
+}
+
+/// Every protected region in a prompt and the tokens they cover
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ProtectionReport {
+    pub regions: Vec<ProtectedRegionSummary>,
+    pub protected_tokens: usize,
+}
+
+/// Max chars shown per region snippet
+const SNIPPET_CHARS: usize = 40;
+
+impl ProtectedRegionSummary {
+    /// Summarize a region of `text` (byte offsets converted to chars)
+    pub fn new(text: &str, region: &ProtectedRegion) -> Self {
+        let start = text[..region.start].chars().count();
+        let content = &text[region.start..region.end];
+        let len = content.chars().count();
+
+        let mut snippet: String = content.chars().take(SNIPPET_CHARS).collect();
+        if len > SNIPPET_CHARS {
+            snippet.push('…');
+        }
+
+        Self {
+            region_type: region.region_type,
+            start,
+            end: start + len,
+            snippet,
+        }
+    }
 }
 
 /// Policy for protected region detection
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ProtectionPolicy {
     /// Protect more regions (safer, less compression)
+    #[default]
     Conservative,
     /// Protect fewer regions (more compression, higher risk)
     Aggressive,
 }
 
-impl Default for ProtectionPolicy {
-    fn default() -> Self {
-        Self::Conservative
-    }
-}
-
 lazy_static! {
     // Code blocks
     static ref FENCED_CODE_BLOCK: Regex = Regex::new(r"```[\s\S]*?```").unwrap();
     static ref INLINE_CODE: Regex = Regex::new(r"`[^`]+`").unwrap();
 
-    // Template variables
-    static ref MUSTACHE_VAR: Regex = Regex::new(r"\{\{[^}]+\}\}").unwrap();
-    static ref DOLLAR_VAR: Regex = Regex::new(r"\$\{[^}]+\}").unwrap();
-    static ref JINJA_VAR: Regex = Regex::new(r"\{%[^%]+%\}").unwrap();
-
     // URLs and paths
     static ref URL: Regex = Regex::new(r"https?://[^\s]+").unwrap();
     static ref FILE_PATH: Regex = Regex::new(r"(?:/[a-zA-Z0-9_.-]+)+|(?:[a-zA-Z]:\\[a-zA-Z0-9_.\\\-]+)").unwrap();
@@ -71,27 +112,230 @@ lazy_static! {
 
     // Quoted strings
     static ref DOUBLE_QUOTED: Regex = Regex::new(r#""[^"]*""#).unwrap();
-    static ref SINGLE_QUOTED: Regex = Regex::new(r"'[^']*'").unwrap();
+    // The opening quote must not follow a letter, so apostrophes in
+    // contractions ("don't ... can't") do not pair up as quotes
+    static ref SINGLE_QUOTED: Regex = Regex::new(r"(?:^|[^\w'])('[^'\n]*')").unwrap();
+
+    // Example user text
+    static ref BLOCKQUOTE_LINE: Regex = Regex::new(r"(?m)^[ \t]*>[^\n]*").unwrap();
+    static ref EXAMPLE_SPAN: Regex = Regex::new(r"(?i)(?:\bexamples?:|\be\.g\.)[^\n]*").unwrap();
 
-    // Instruction keywords
-    static ref INSTRUCTION_KEYWORDS: Regex = Regex::new(
-        r"(?i)\b(MUST|REQUIRED|MANDATORY|FORMAT|OUTPUT|RETURN|RESPOND|JSON|XML|YAML|CSV)\b"
-    ).unwrap();
+    // Line-prefix regions
+    static ref FRONTMATTER: Regex = Regex::new(r"\A---[ \t]*\r?\n(?s:.*?)\n---[ \t]*(?:\r?\n|\z)").unwrap();
 }
 
+/// Instruction keywords protected by default
+pub const DEFAULT_INSTRUCTION_KEYWORDS: &[&str] = &[
+    "MUST", "REQUIRED", "MANDATORY", "FORMAT", "OUTPUT", "RETURN", "RESPOND", "JSON", "XML",
+    "YAML", "CSV",
+];
+
+/// Line comment markers protected by default
+///
+/// `#` is left out because it would also protect markdown headings; add it
+/// with [`ProtectedRegionDetector::with_comment_markers`] for shell- or
+/// YAML-style prompts.
+pub const DEFAULT_COMMENT_MARKERS: &[&str] = &["//"];
+
+/// Template variable delimiters (open, close) protected by default: Mustache
+/// (escaped and unescaped), shell/JS `${}`, Jinja tags, chat special tokens
+/// and wiki-style double brackets
+pub const DEFAULT_TEMPLATE_DELIMITERS: &[(&str, &str)] = &[
+    ("{{", "}}"),
+    ("{{{", "}}}"),
+    ("${", "}"),
+    ("{%", "%}"),
+    ("<|", "|>"),
+    ("[[", "]]"),
+];
+
+/// Data formats: never ordinary prose, so protected in any case
+const DATA_FORMATS: &[&str] = &["json", "xml", "yaml", "csv", "markdown"];
+
+/// Nouns that mark a preceding keyword as structural ("return a list")
+const STRUCTURAL_NOUNS: &[&str] = &["table", "list", "array", "object", "schema", "format"];
+
+/// How many words after a keyword are searched for a structural noun
+const KEYWORD_CONTEXT_WORDS: usize = 4;
+
 /// Protected region detector
 pub struct ProtectedRegionDetector {
     policy: ProtectionPolicy,
+    instruction_keywords: Vec<String>,
+    /// Compiled from `instruction_keywords` (None when the list is empty)
+    keyword_regex: Option<Regex>,
+    comment_markers: Vec<String>,
+    /// Compiled from `comment_markers` (None when the list is empty)
+    comment_regex: Option<Regex>,
+    template_delimiters: Vec<(String, String)>,
+    /// Compiled from `template_delimiters` (None when the list is empty)
+    template_regex: Option<Regex>,
 }
 
 impl ProtectedRegionDetector {
     /// Create new detector with policy
     pub fn new(policy: ProtectionPolicy) -> Self {
-        Self { policy }
+        Self::with_instruction_keywords(policy, DEFAULT_INSTRUCTION_KEYWORDS)
+    }
+
+    /// Create a detector protecting a custom instruction keyword list
+    pub fn with_instruction_keywords<I, S>(policy: ProtectionPolicy, keywords: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut detector = Self {
+            policy,
+            instruction_keywords: Vec::new(),
+            keyword_regex: None,
+            comment_markers: Vec::new(),
+            comment_regex: None,
+            template_delimiters: Vec::new(),
+            template_regex: None,
+        };
+        detector.instruction_keywords = keywords
+            .into_iter()
+            .map(|k| k.as_ref().trim().to_string())
+            .filter(|k| !k.is_empty())
+            .collect();
+        detector.compile_keywords();
+        detector
+            .with_comment_markers(DEFAULT_COMMENT_MARKERS)
+            .with_template_delimiters(DEFAULT_TEMPLATE_DELIMITERS.iter().copied())
+    }
+
+    /// Protect whole lines starting with any of `markers` instead of the defaults
+    pub fn with_comment_markers<I, S>(mut self, markers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.comment_markers = markers
+            .into_iter()
+            .map(|m| m.as_ref().trim().to_string())
+            .filter(|m| !m.is_empty())
+            .collect();
+        self.comment_regex = if self.comment_markers.is_empty() {
+            None
+        } else {
+            let alternation = self
+                .comment_markers
+                .iter()
+                .map(|m| regex::escape(m))
+                .collect::<Vec<_>>()
+                .join("|");
+            Some(Regex::new(&format!(r"(?m)^[ \t]*(?:{})[^\n]*", alternation)).unwrap())
+        };
+        self
+    }
+
+    /// Whether the keyword, comment marker and template delimiter lists are
+    /// the stock ones, so a default detector finds the same regions under
+    /// the same policy
+    pub(crate) fn has_default_rules(&self) -> bool {
+        self.instruction_keywords.iter().eq(DEFAULT_INSTRUCTION_KEYWORDS)
+            && self.comment_markers.iter().eq(DEFAULT_COMMENT_MARKERS)
+            && self
+                .template_delimiters
+                .iter()
+                .map(|(open, close)| (open.as_str(), close.as_str()))
+                .eq(DEFAULT_TEMPLATE_DELIMITERS.iter().copied())
+    }
+
+    /// Line comment markers currently protected
+    pub fn comment_markers(&self) -> &[String] {
+        &self.comment_markers
+    }
+
+    /// Protect text between any of the `(open, close)` pairs instead of the
+    /// default template delimiters
+    ///
+    /// A variable never spans lines. Where delimiters share a prefix the
+    /// longest opening wins, so `{{{x}}}` is one region rather than `{{{x}}`.
+    pub fn with_template_delimiters<I, O, C>(mut self, delimiters: I) -> Self
+    where
+        I: IntoIterator<Item = (O, C)>,
+        O: AsRef<str>,
+        C: AsRef<str>,
+    {
+        self.template_delimiters = delimiters
+            .into_iter()
+            .map(|(open, close)| (open.as_ref().trim().to_string(), close.as_ref().trim().to_string()))
+            .filter(|(open, close)| !open.is_empty() && !close.is_empty())
+            .collect();
+        self.template_regex = if self.template_delimiters.is_empty() {
+            None
+        } else {
+            let mut pairs: Vec<_> = self.template_delimiters.iter().collect();
+            // The regex takes the first alternative that matches, so try
+            // longer openings ("{{{") before their prefixes ("{{")
+            pairs.sort_by_key(|(open, _)| std::cmp::Reverse(open.len()));
+            let alternation = pairs
+                .iter()
+                .map(|(open, close)| format!(r"{}[^\n]+?{}", regex::escape(open), regex::escape(close)))
+                .collect::<Vec<_>>()
+                .join("|");
+            Some(Regex::new(&alternation).unwrap())
+        };
+        self
+    }
+
+    /// Template variable delimiters currently protected
+    pub fn template_delimiters(&self) -> &[(String, String)] {
+        &self.template_delimiters
+    }
+
+    /// Also protect `keyword`
+    pub fn add_instruction_keyword(mut self, keyword: &str) -> Self {
+        let keyword = keyword.trim();
+        if !keyword.is_empty() && !self.has_keyword(keyword) {
+            self.instruction_keywords.push(keyword.to_string());
+            self.compile_keywords();
+        }
+        self
+    }
+
+    /// Stop protecting `keyword`
+    pub fn remove_instruction_keyword(mut self, keyword: &str) -> Self {
+        self.instruction_keywords
+            .retain(|k| !k.eq_ignore_ascii_case(keyword.trim()));
+        self.compile_keywords();
+        self
+    }
+
+    /// Instruction keywords currently protected
+    pub fn instruction_keywords(&self) -> &[String] {
+        &self.instruction_keywords
+    }
+
+    fn has_keyword(&self, keyword: &str) -> bool {
+        self.instruction_keywords
+            .iter()
+            .any(|k| k.eq_ignore_ascii_case(keyword))
+    }
+
+    fn compile_keywords(&mut self) {
+        self.keyword_regex = if self.instruction_keywords.is_empty() {
+            None
+        } else {
+            let alternation = self
+                .instruction_keywords
+                .iter()
+                .map(|k| regex::escape(k))
+                .collect::<Vec<_>>()
+                .join("|");
+            // Escaped literals always compile
+            Some(Regex::new(&format!(r"(?i)\b({})\b", alternation)).unwrap())
+        };
     }
 
     /// Detect all protected regions in text
     pub fn detect(&self, text: &str) -> Vec<ProtectedRegion> {
+        self.detect_with_policy(text, self.policy)
+    }
+
+    /// Detect protected regions using `policy` instead of the detector's own
+    pub fn detect_with_policy(&self, text: &str, policy: ProtectionPolicy) -> Vec<ProtectedRegion> {
         let mut regions = Vec::new();
 
         // Always protect these (both policies)
@@ -101,14 +345,16 @@ impl ProtectedRegionDetector {
         regions.extend(self.detect_instruction_keywords(text));
 
         // Conservative policy protects more
-        if matches!(self.policy, ProtectionPolicy::Conservative) {
+        if matches!(policy, ProtectionPolicy::Conservative) {
             regions.extend(self.detect_identifiers(text));
             regions.extend(self.detect_quoted_strings(text));
+            regions.extend(self.detect_frontmatter(text));
+            regions.extend(self.detect_comments(text));
         }
 
         // Sort by start position and merge overlapping regions
         regions.sort_by_key(|r| r.start);
-        self.merge_overlapping(regions)
+        self.merge_overlapping(text, regions)
     }
 
     /// Check if a byte range overlaps with any protected region
@@ -120,7 +366,7 @@ impl ProtectedRegionDetector {
     }
 
     /// Detect fenced and inline code blocks
-    fn detect_code_blocks(&self, text: &str) -> Vec<ProtectedRegion> {
+    pub(crate) fn detect_code_blocks(&self, text: &str) -> Vec<ProtectedRegion> {
         let mut regions = Vec::new();
 
         // Fenced code blocks (```...```)
@@ -162,36 +408,19 @@ impl ProtectedRegionDetector {
 
     /// Detect template variables
     fn detect_template_variables(&self, text: &str) -> Vec<ProtectedRegion> {
-        let mut regions = Vec::new();
-
-        for mat in MUSTACHE_VAR.find_iter(text) {
-            regions.push(ProtectedRegion {
-                start: mat.start(),
-                end: mat.end(),
-                region_type: RegionType::TemplateVariable,
-                content: mat.as_str().to_string(),
-            });
-        }
-
-        for mat in DOLLAR_VAR.find_iter(text) {
-            regions.push(ProtectedRegion {
-                start: mat.start(),
-                end: mat.end(),
-                region_type: RegionType::TemplateVariable,
-                content: mat.as_str().to_string(),
-            });
-        }
+        let Some(template_regex) = &self.template_regex else {
+            return Vec::new();
+        };
 
-        for mat in JINJA_VAR.find_iter(text) {
-            regions.push(ProtectedRegion {
+        template_regex
+            .find_iter(text)
+            .map(|mat| ProtectedRegion {
                 start: mat.start(),
                 end: mat.end(),
                 region_type: RegionType::TemplateVariable,
                 content: mat.as_str().to_string(),
-            });
-        }
-
-        regions
+            })
+            .collect()
     }
 
     /// Detect URLs and file paths
@@ -219,6 +448,43 @@ impl ProtectedRegionDetector {
         regions
     }
 
+    /// Detect a YAML frontmatter block opening the prompt
+    pub(crate) fn detect_frontmatter(&self, text: &str) -> Vec<ProtectedRegion> {
+        FRONTMATTER
+            .find(text)
+            .map(|mat| {
+                let content = mat.as_str().trim_end_matches(['\r', '\n']);
+                ProtectedRegion {
+                    start: mat.start(),
+                    end: mat.start() + content.len(),
+                    region_type: RegionType::Frontmatter,
+                    content: content.to_string(),
+                }
+            })
+            .into_iter()
+            .collect()
+    }
+
+    /// Detect whole lines starting with a comment marker
+    pub(crate) fn detect_comments(&self, text: &str) -> Vec<ProtectedRegion> {
+        let Some(regex) = &self.comment_regex else {
+            return Vec::new();
+        };
+
+        regex
+            .find_iter(text)
+            .map(|mat| {
+                let content = mat.as_str().trim_end_matches('\r');
+                ProtectedRegion {
+                    start: mat.start(),
+                    end: mat.start() + content.len(),
+                    region_type: RegionType::Comment,
+                    content: content.to_string(),
+                }
+            })
+            .collect()
+    }
+
     /// Detect programming identifiers
     fn detect_identifiers(&self, text: &str) -> Vec<ProtectedRegion> {
         let mut regions = Vec::new();
@@ -254,7 +520,7 @@ impl ProtectedRegionDetector {
     }
 
     /// Detect quoted strings
-    fn detect_quoted_strings(&self, text: &str) -> Vec<ProtectedRegion> {
+    pub(crate) fn detect_quoted_strings(&self, text: &str) -> Vec<ProtectedRegion> {
         let mut regions = Vec::new();
 
         for mat in DOUBLE_QUOTED.find_iter(text) {
@@ -266,7 +532,7 @@ impl ProtectedRegionDetector {
             });
         }
 
-        for mat in SINGLE_QUOTED.find_iter(text) {
+        for mat in SINGLE_QUOTED.captures_iter(text).filter_map(|c| c.get(1)) {
             regions.push(ProtectedRegion {
                 start: mat.start(),
                 end: mat.end(),
@@ -278,11 +544,48 @@ impl ProtectedRegionDetector {
         regions
     }
 
+    /// Detect example user text: blockquote lines, "example:"/"e.g." spans
+    /// (to end of line), and quoted strings
+    ///
+    /// Not part of `detect`: callers opt in via `preserve_first_person`.
+    pub fn detect_examples(&self, text: &str) -> Vec<ProtectedRegion> {
+        let mut regions = Vec::new();
+
+        for mat in BLOCKQUOTE_LINE.find_iter(text).chain(EXAMPLE_SPAN.find_iter(text)) {
+            regions.push(ProtectedRegion {
+                start: mat.start(),
+                end: mat.end(),
+                region_type: RegionType::Example,
+                content: mat.as_str().to_string(),
+            });
+        }
+
+        regions.extend(self.detect_quoted_strings(text).into_iter().map(|r| ProtectedRegion {
+            region_type: RegionType::Example,
+            ..r
+        }));
+
+        regions.sort_by_key(|r| r.start);
+        self.merge_overlapping(text, regions)
+    }
+
     /// Detect instruction keywords
+    ///
+    /// Keywords that double as prose words ("return", "respond", "format") are
+    /// only protected when written in caps or used structurally, i.e. followed
+    /// in the same sentence by a data format or structural noun.
     fn detect_instruction_keywords(&self, text: &str) -> Vec<ProtectedRegion> {
         let mut regions = Vec::new();
 
-        for mat in INSTRUCTION_KEYWORDS.find_iter(text) {
+        let Some(keyword_regex) = &self.keyword_regex else {
+            return regions;
+        };
+
+        for mat in keyword_regex.find_iter(text) {
+            if !Self::is_instruction_use(mat.as_str(), &text[mat.end()..]) {
+                continue;
+            }
+
             regions.push(ProtectedRegion {
                 start: mat.start(),
                 end: mat.end(),
@@ -294,8 +597,32 @@ impl ProtectedRegionDetector {
         regions
     }
 
+    /// Whether a keyword match reads as an instruction rather than prose
+    fn is_instruction_use(keyword: &str, after: &str) -> bool {
+        let is_caps = keyword.chars().any(char::is_alphabetic)
+            && !keyword.chars().any(char::is_lowercase);
+        let lower = keyword.to_lowercase();
+        if is_caps || DATA_FORMATS.contains(&lower.as_str()) {
+            return true;
+        }
+
+        // Look ahead a few words, stopping at the end of the sentence
+        let sentence_end = after.find(['.', '!', '?', '\n']).unwrap_or(after.len());
+        after[..sentence_end]
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .take(KEYWORD_CONTEXT_WORDS)
+            .map(str::to_lowercase)
+            .any(|w| DATA_FORMATS.contains(&w.as_str()) || STRUCTURAL_NOUNS.contains(&w.as_str()))
+    }
+
     /// Merge overlapping regions
-    fn merge_overlapping(&self, regions: Vec<ProtectedRegion>) -> Vec<ProtectedRegion> {
+    ///
+    /// Regions of different types separated only by whitespace are merged too,
+    /// so the whitespace between back-to-back protected spans (e.g. inline code
+    /// followed by a template variable) is itself protected. A merged region keeps
+    /// the `region_type` of the first region in it.
+    fn merge_overlapping(&self, text: &str, regions: Vec<ProtectedRegion>) -> Vec<ProtectedRegion> {
         if regions.is_empty() {
             return regions;
         }
@@ -304,8 +631,14 @@ impl ProtectedRegionDetector {
         let mut current = regions[0].clone();
 
         for region in regions.into_iter().skip(1) {
-            if region.start <= current.end {
-                // Overlapping or adjacent - merge
+            let whitespace_gap = region.start > current.end
+                && region.region_type != current.region_type
+                && text[current.end..region.start].chars().all(char::is_whitespace);
+
+            if region.start <= current.end || whitespace_gap {
+                // Overlapping, adjacent, or whitespace-separated - merge. The
+                // merged span keeps the leading region's type: protection only
+                // checks the span, and a region reads as what it starts with
                 current.end = current.end.max(region.end);
                 current.content = format!("{}...{}",
                     current.content.chars().take(20).collect::<String>(),
@@ -353,7 +686,7 @@ And inline `code` too.
             .filter(|r| r.region_type == RegionType::CodeBlock)
             .collect();
 
-        assert!(code_blocks.len() >= 1, "Should find at least the fenced code block");
+        assert!(!code_blocks.is_empty(), "Should find at least the fenced code block");
     }
 
     #[test]
@@ -372,6 +705,51 @@ And inline `code` too.
         assert!(template_vars.len() >= 3, "Expected at least 3 template vars, found {}", template_vars.len());
     }
 
+    fn template_contents(detector: &ProtectedRegionDetector, text: &str) -> Vec<String> {
+        detector
+            .detect(text)
+            .into_iter()
+            .filter(|r| r.region_type == RegionType::TemplateVariable)
+            .map(|r| r.content)
+            .collect()
+    }
+
+    #[test]
+    fn test_additional_template_delimiters() {
+        let detector = ProtectedRegionDetector::default();
+
+        // Mustache unescaped: the triple brace is one region, not `{{{x}}`
+        assert_eq!(template_contents(&detector, "Body: {{{html}}} ends"), vec!["{{{html}}}"]);
+        assert_eq!(
+            template_contents(&detector, "Say {{ greeting }} then {{{ body }}}."),
+            vec!["{{ greeting }}", "{{{ body }}}"]
+        );
+        // Chat special tokens
+        assert_eq!(
+            template_contents(&detector, "<|system|>\nBe brief<|endoftext|>"),
+            vec!["<|system|>", "<|endoftext|>"]
+        );
+        // Wiki-style double brackets
+        assert_eq!(template_contents(&detector, "See [[Main Page]] for details"), vec!["[[Main Page]]"]);
+        // Braces inside a variable don't end it early
+        assert_eq!(template_contents(&detector, "Use {{ fmt({a}) }} here"), vec!["{{ fmt({a}) }}"]);
+        // Variables never span lines
+        assert!(template_contents(&detector, "[[ not\nclosed ]]").is_empty());
+    }
+
+    #[test]
+    fn test_custom_template_delimiters() {
+        let detector = ProtectedRegionDetector::default().with_template_delimiters([("<<", ">>"), ("%(", ")s")]);
+        assert_eq!(detector.template_delimiters().len(), 2);
+        assert_eq!(
+            template_contents(&detector, "Dear <<name>>, total %(amount)s, {{ignored}}"),
+            vec!["<<name>>", "%(amount)s"]
+        );
+
+        let none = ProtectedRegionDetector::default().with_template_delimiters(Vec::<(&str, &str)>::new());
+        assert!(template_contents(&none, "Hello {{name}}").is_empty());
+    }
+
     #[test]
     fn test_detect_urls() {
         let text = "Visit https://example.com or check /usr/local/bin/file.txt";
@@ -429,10 +807,136 @@ And inline `code` too.
             .filter(|r| r.region_type == RegionType::InstructionKeyword)
             .collect();
 
-        // MUST, return, JSON, format, OUTPUT, YAML are all matched
+        // MUST, return (followed by JSON), JSON, OUTPUT, YAML are matched
         assert!(keywords.len() >= 4, "Expected at least 4 instruction keywords, found {}", keywords.len());
     }
 
+    #[test]
+    fn test_prose_keywords_not_protected() {
+        let detector = ProtectedRegionDetector::new(ProtectionPolicy::Aggressive);
+        let keywords = |text: &str| -> Vec<String> {
+            detector
+                .detect_instruction_keywords(text)
+                .into_iter()
+                .map(|r| r.content)
+                .collect()
+        };
+
+        assert!(keywords("Please return the favor and respond politely.").is_empty());
+        assert!(keywords("Output quality matters. Use a nice format").is_empty());
+
+        // Caps, data formats and structural use stay protected
+        assert_eq!(keywords("RESPOND in English."), vec!["RESPOND"]);
+        assert_eq!(keywords("Return the answer as json."), vec!["Return", "json"]);
+        assert_eq!(keywords("respond with a markdown table"), vec!["respond"]);
+    }
+
+    #[test]
+    fn test_custom_instruction_keywords() {
+        let text = "Follow the SCHEMA and RETURN the result.";
+        let keywords = |detector: &ProtectedRegionDetector| -> Vec<String> {
+            detector
+                .detect(text)
+                .into_iter()
+                .filter(|r| r.region_type == RegionType::InstructionKeyword)
+                .map(|r| r.content)
+                .collect()
+        };
+
+        let detector = ProtectedRegionDetector::new(ProtectionPolicy::Aggressive);
+        assert_eq!(keywords(&detector), vec!["RETURN"]);
+
+        let detector = detector
+            .add_instruction_keyword("schema")
+            .remove_instruction_keyword("return");
+        assert_eq!(keywords(&detector), vec!["SCHEMA"]);
+
+        let detector = ProtectedRegionDetector::with_instruction_keywords(
+            ProtectionPolicy::Aggressive,
+            ["CONTRACT", "C++"],
+        );
+        assert!(keywords(&detector).is_empty());
+        assert_eq!(detector.instruction_keywords(), ["CONTRACT", "C++"]);
+
+        let detector = ProtectedRegionDetector::with_instruction_keywords(
+            ProtectionPolicy::Aggressive,
+            Vec::<String>::new(),
+        );
+        assert!(keywords(&detector).is_empty());
+    }
+
+    #[test]
+    fn test_detect_frontmatter_and_comments() {
+        let text = "---\ntitle: Review\ntags: [rust]\n---\n// keep this note verbatim\n# Heading\nPlease analyze the code.";
+        let detector = ProtectedRegionDetector::default();
+
+        let frontmatter = detector.detect_frontmatter(text);
+        assert_eq!(frontmatter.len(), 1);
+        assert_eq!(frontmatter[0].start, 0);
+        assert_eq!(frontmatter[0].content, "---\ntitle: Review\ntags: [rust]\n---");
+
+        // A `---` separator later in the prompt is not frontmatter
+        assert!(detector.detect_frontmatter("Intro\n---\na: b\n---\n").is_empty());
+
+        let comments: Vec<_> = detector.detect_comments(text).into_iter().map(|r| r.content).collect();
+        assert_eq!(comments, vec!["// keep this note verbatim"]);
+
+        let detector = detector.with_comment_markers(["#", ";"]);
+        let comments: Vec<_> = detector.detect_comments(text).into_iter().map(|r| r.content).collect();
+        assert_eq!(comments, vec!["# Heading"]);
+        assert_eq!(detector.comment_markers(), ["#", ";"]);
+
+        // Only the conservative policy protects them
+        let aggressive = ProtectedRegionDetector::new(ProtectionPolicy::Aggressive);
+        assert!(!aggressive.detect(text).iter().any(|r| matches!(
+            r.region_type,
+            RegionType::Frontmatter | RegionType::Comment
+        )));
+    }
+
+    #[test]
+    fn test_detect_examples() {
+        let text = "Be concise.\n> I would really appreciate your help!\nExample: thank you so much in advance.\nOther text, e.g. kindly note this.";
+
+        let detector = ProtectedRegionDetector::default();
+        let regions = detector.detect_examples(text);
+
+        assert_eq!(regions.len(), 3);
+        assert!(regions.iter().all(|r| r.region_type == RegionType::Example));
+        assert!(regions[0].content.starts_with("> I would"));
+        assert!(regions[1].content.starts_with("Example:"));
+        assert!(regions[2].content.starts_with("e.g."));
+
+        // Examples are not protected by default detection
+        assert!(!detector.detect(text).iter().any(|r| r.region_type == RegionType::Example));
+    }
+
+    #[test]
+    fn test_merge_back_to_back_regions() {
+        let text = "Run `make build`  ${TARGET} now, then check `status`.";
+
+        let detector = ProtectedRegionDetector::default();
+        let regions = detector.detect(text);
+
+        // Inline code and template variable merge into one region
+        let code_start = text.find("`make").unwrap();
+        let var_end = text.find("} now").unwrap() + 1;
+        let merged = regions
+            .iter()
+            .find(|r| r.start == code_start)
+            .expect("inline code should be protected");
+        assert_eq!(merged.end, var_end);
+
+        // The whitespace between them is protected
+        let gap = text.find("`  $").unwrap() + 1;
+        assert!(detector.is_protected(&regions, gap, gap + 2));
+
+        // Regions separated by real text stay apart
+        let status_start = text.find("`status`").unwrap();
+        assert!(regions.iter().any(|r| r.start == status_start));
+        assert!(!detector.is_protected(&regions, var_end + 1, var_end + 4));
+    }
+
     #[test]
     fn test_is_protected() {
         let text = "Here is `code` and normal text";
@@ -476,7 +980,8 @@ And inline `code` too.
             },
         ];
 
-        let merged = detector.merge_overlapping(regions);
+        let text = "x".repeat(30);
+        let merged = detector.merge_overlapping(&text, regions);
 
         assert_eq!(merged.len(), 2); // First two merged, third separate
         assert_eq!(merged[0].start, 0);
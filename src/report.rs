@@ -0,0 +1,195 @@
+//! Markdown reports of an optimization
+//!
+//! `render_markdown` turns an `OptimizationResult` into a self-contained
+//! GitHub-flavoured Markdown document: a savings summary table, the prompt
+//! before and after in fenced blocks, and a table of the applied
+//! optimizations with their confidence.
+
+use crate::models::{Optimization, OptimizationResult};
+use std::fmt::Write;
+
+/// Render `result` as a Markdown report
+pub fn render_markdown(result: &OptimizationResult) -> String {
+    let mut out = String::new();
+    let diff = &result.diff_stats;
+
+    // Writing to a String cannot fail
+    let _ = writeln!(out, "# Prompt optimization report\n");
+    let _ = writeln!(out, "| Metric | Value |");
+    let _ = writeln!(out, "| --- | ---: |");
+    let _ = writeln!(out, "| Original tokens | {} |", result.original_tokens);
+    let _ = writeln!(out, "| Optimized tokens | {} |", result.optimized_tokens);
+    let _ = writeln!(
+        out,
+        "| Token savings | {} ({:.1}%) |",
+        result.token_savings, result.savings_percentage
+    );
+    let _ = writeln!(
+        out,
+        "| Characters | -{} +{} ({} saved) |",
+        diff.chars_removed,
+        diff.chars_added,
+        diff.chars_saved()
+    );
+    let _ = writeln!(out, "| Lines | {} → {} |", diff.lines_before, diff.lines_after);
+    let _ = writeln!(out, "| Auto-applied | {} |", result.optimizations.len());
+    let _ = writeln!(out, "| Requires review | {} |", result.requires_review.len());
+
+    if !result.warnings.is_empty() || result.net_savings_negative {
+        out.push('\n');
+        if result.net_savings_negative {
+            let _ = writeln!(out, "> ⚠ The optimized prompt is larger than the original.");
+        }
+        for warning in &result.warnings {
+            let _ = writeln!(out, "> ⚠ {}", warning.replace('\n', " "));
+        }
+    }
+
+    for (heading, text) in [("Before", &result.original_prompt), ("After", &result.optimized_prompt)] {
+        let fence = fence_for(text);
+        let _ = writeln!(out, "\n## {}\n", heading);
+        let _ = writeln!(out, "{}text\n{}\n{}", fence, text.trim_end_matches('\n'), fence);
+    }
+
+    let _ = writeln!(out, "\n## Applied optimizations\n");
+    if result.optimizations.is_empty() {
+        let _ = writeln!(out, "_No optimizations applied._");
+    } else {
+        let _ = writeln!(out, "| # | Type | Change | Reasoning | Savings | Confidence |");
+        let _ = writeln!(out, "| ---: | --- | --- | --- | ---: | ---: |");
+        for (index, optimization) in result.optimizations.iter().enumerate() {
+            let _ = writeln!(out, "{}", table_row(index + 1, optimization));
+        }
+    }
+
+    out
+}
+
+fn table_row(number: usize, optimization: &Optimization) -> String {
+    let replacement = if optimization.optimized_text.trim().is_empty() {
+        "_(removed)_".to_string()
+    } else {
+        code_span(&optimization.optimized_text)
+    };
+    format!(
+        "| {} | {} | {} → {} | {} | {} | {:.2} |",
+        number,
+        code_span(optimization.optimization_type.code_prefix().to_lowercase().as_str()),
+        code_span(&optimization.original_text),
+        replacement,
+        escape_cell(&optimization.reasoning),
+        optimization.token_savings,
+        optimization.confidence.final_confidence
+    )
+}
+
+/// A backtick fence longer than any backtick run in `text`
+fn fence_for(text: &str) -> String {
+    "`".repeat((longest_backtick_run(text) + 1).max(3))
+}
+
+/// Inline code for a table cell; pipes are escaped so they don't split it
+fn code_span(text: &str) -> String {
+    let text = escape_cell(text.trim());
+    let ticks = "`".repeat(longest_backtick_run(&text) + 1);
+    // Padding keeps a leading or trailing backtick from merging with the
+    // delimiters
+    if text.starts_with('`') || text.ends_with('`') {
+        format!("{} {} {}", ticks, text, ticks)
+    } else {
+        format!("{}{}{}", ticks, text, ticks)
+    }
+}
+
+fn escape_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+fn longest_backtick_run(text: &str) -> usize {
+    text.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{DiffStats, Language, OptimizationConfidence, OptimizationType};
+
+    fn optimization(
+        optimization_type: OptimizationType,
+        original: &str,
+        optimized: &str,
+        savings: i64,
+        confidence: f64,
+        reasoning: &str,
+    ) -> Optimization {
+        Optimization {
+            id: String::new(),
+            optimization_type,
+            original_text: original.to_string(),
+            optimized_text: optimized.to_string(),
+            token_savings: savings,
+            confidence: OptimizationConfidence::new(confidence, 0.0, 0.0, 0.0),
+            requires_review: false,
+            reasoning: reasoning.to_string(),
+            start_pos: 0,
+            end_pos: original.len(),
+            pass: 0,
+        }
+    }
+
+    #[test]
+    fn test_markdown_report_matches_golden_file() {
+        let original = "I would like you to please make sure to review `a | b` carefully.\n```\nlet x = 1;\n```\n";
+        let optimized = "Review `a | b`.\n```\nlet x = 1;\n```\n";
+        let result = OptimizationResult {
+            original_prompt: original.to_string(),
+            optimized_prompt: optimized.to_string(),
+            original_tokens: 28,
+            optimized_tokens: 17,
+            token_savings: 11,
+            directive_tokens: 0,
+            savings_including_directive: 11,
+            savings_excluding_directive: 11,
+            savings_percentage: 39.285714,
+            compression_ratio: 17.0 / 28.0,
+            optimizations: vec![
+                optimization(
+                    OptimizationType::BoilerplateRemoval,
+                    "I would like you to please make sure to ",
+                    "",
+                    9,
+                    0.95,
+                    "Polite request preamble",
+                ),
+                optimization(OptimizationType::FillerRemoval, " carefully", "", 1, 0.88, "Filler adverb"),
+            ],
+            requires_review: vec![optimization(
+                OptimizationType::SynonymConsolidation,
+                "review",
+                "check",
+                0,
+                0.6,
+                "Shorter synonym",
+            )],
+            output_language: Language::English,
+            rejected: Vec::new(),
+            warnings: vec!["Directive omitted: it would cost more than it saves".to_string()],
+            net_savings_negative: false,
+            profile: None,
+            diff_stats: DiffStats::between(original, optimized),
+            replayable: false,
+            confidence_summary: Default::default(),
+        };
+
+        let rendered = render_markdown(&result);
+        assert_eq!(rendered, include_str!("../tests/fixtures/optimization_report.md"));
+    }
+
+    #[test]
+    fn test_fences_and_code_spans_outlast_backticks() {
+        assert_eq!(fence_for("plain"), "```");
+        assert_eq!(fence_for("```rust\n```"), "````");
+        assert_eq!(code_span("a|b"), "`a\\|b`");
+        assert_eq!(code_span("`x`"), "`` `x` ``");
+    }
+}
@@ -0,0 +1,160 @@
+//! Sentence-boundary detection shared by the optimizers
+//!
+//! A `.`, `!` or `?` only ends a sentence when followed by whitespace (or the
+//! end of the text), so decimals ("3.14") and versions ("v1.2.3") never do.
+//! Periods closing a known abbreviation ("e.g.", "etc.") or a dotted acronym
+//! ("U.S.A.") are not boundaries either.
+
+use std::collections::HashSet;
+
+/// Abbreviations (lowercase, without the trailing period) that don't end a sentence
+pub const DEFAULT_ABBREVIATIONS: &[&str] = &[
+    "e.g", "i.e", "eg", "ie", "etc", "vs", "cf", "approx", "al", "fig", "mr", "mrs", "ms", "dr",
+    "prof", "jr", "sr", "inc", "ltd",
+];
+
+/// Decides where sentences end
+#[derive(Debug, Clone)]
+pub struct SentenceBoundaries {
+    abbreviations: HashSet<String>,
+}
+
+impl SentenceBoundaries {
+    /// Create with a custom abbreviation list
+    pub fn new<I, S>(abbreviations: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            abbreviations: abbreviations
+                .into_iter()
+                .map(|a| a.as_ref().trim_end_matches('.').to_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Add an abbreviation to the default list
+    pub fn with_abbreviation(mut self, abbreviation: &str) -> Self {
+        self.abbreviations
+            .insert(abbreviation.trim_end_matches('.').to_lowercase());
+        self
+    }
+
+    /// Check whether the punctuation at byte offset `pos` ends a sentence
+    pub fn is_boundary(&self, text: &str, pos: usize) -> bool {
+        let Some(ch) = text[pos..].chars().next() else {
+            return false;
+        };
+        if !matches!(ch, '.' | '!' | '?') {
+            return false;
+        }
+
+        // Must be followed by whitespace or end of text
+        let after = &text[pos + ch.len_utf8()..];
+        if !after.chars().next().is_none_or(char::is_whitespace) {
+            return false;
+        }
+
+        if ch != '.' {
+            return true;
+        }
+
+        // Word ending at this period
+        let before = &text[..pos];
+        let word = before
+            .rsplit(|c: char| c.is_whitespace() || c == '(' || c == '"')
+            .next()
+            .unwrap_or("");
+
+        // Dotted acronyms ("U.S.A") and known abbreviations
+        !(word.contains('.') || self.abbreviations.contains(&word.to_lowercase()))
+    }
+
+    /// Capitalize the first letter of the text and after each sentence boundary
+    pub fn capitalize(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut capitalize_next = true; // First character should be capitalized
+
+        for (pos, ch) in text.char_indices() {
+            if capitalize_next && ch.is_alphabetic() {
+                result.extend(ch.to_uppercase());
+                capitalize_next = false;
+            } else {
+                result.push(ch);
+
+                if self.is_boundary(text, pos) {
+                    capitalize_next = true;
+                } else if ch.is_numeric() {
+                    // A sentence starting with a number keeps its case
+                    capitalize_next = false;
+                }
+            }
+        }
+
+        result
+    }
+}
+
+impl Default for SentenceBoundaries {
+    fn default() -> Self {
+        Self::new(DEFAULT_ABBREVIATIONS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basic_boundaries() {
+        let boundaries = SentenceBoundaries::default();
+
+        assert_eq!(boundaries.capitalize("hello. world"), "Hello. World");
+        assert_eq!(
+            boundaries.capitalize("test! another. one?"),
+            "Test! Another. One?"
+        );
+        // Unicode-aware, not ASCII-only
+        assert_eq!(boundaries.capitalize("évaluer. über alles"), "Évaluer. Über alles");
+    }
+
+    #[test]
+    fn test_decimals_and_versions() {
+        let boundaries = SentenceBoundaries::default();
+
+        assert_eq!(
+            boundaries.capitalize("pi is 3.14 approximately"),
+            "Pi is 3.14 approximately"
+        );
+        assert_eq!(
+            boundaries.capitalize("use version 1.2.three here"),
+            "Use version 1.2.three here"
+        );
+    }
+
+    #[test]
+    fn test_abbreviations() {
+        let boundaries = SentenceBoundaries::default();
+
+        assert_eq!(boundaries.capitalize("e.g. this"), "E.g. this");
+        assert_eq!(
+            boundaries.capitalize("list fruit, e.g. apples, i.e. food"),
+            "List fruit, e.g. apples, i.e. food"
+        );
+        assert_eq!(
+            boundaries.capitalize("made in the U.S.A. today"),
+            "Made in the U.S.A. today"
+        );
+        assert_eq!(boundaries.capitalize("see fig. two"), "See fig. two");
+    }
+
+    #[test]
+    fn test_custom_abbreviation() {
+        let boundaries = SentenceBoundaries::default();
+        assert_eq!(boundaries.capitalize("see sec. four"), "See sec. Four");
+
+        let boundaries = boundaries.with_abbreviation("sec.");
+        assert_eq!(boundaries.capitalize("see sec. four"), "See sec. four");
+    }
+}
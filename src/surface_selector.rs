@@ -1,19 +1,20 @@
-/// Phase 3: Surface Form Selector - Pick optimal variant for a concept
-///
-/// Purpose: Given a concept (Q-ID) and tokenizer, select the surface form
-/// with minimum token count, respecting policy constraints.
-///
-/// Example: Q16917 with cl100k_base → "hospital" (1 token) vs "医院" (4 tokens)
+//! Phase 3: Surface Form Selector - Pick optimal variant for a concept
+//!
+//! Purpose: Given a concept (Q-ID) and tokenizer, select the surface form
+//! with minimum token count, respecting policy constraints.
+//!
+//! Example: Q16917 with cl100k_base → "hospital" (1 token) vs "医院" (4 tokens)
 
 use crate::database::{Database, SurfaceForm};
 use crate::tokenizer_registry::TokenizerId;
-use anyhow::Result;
+use crate::error::Result;
 use std::sync::Arc;
 
 /// Policy for selecting surface forms
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub enum SelectionPolicy {
     /// Always pick minimum tokens, any language
+    #[default]
     MinTokens,
     /// Minimum tokens, but only within same language as original
     SameLanguage { lang: String },
@@ -21,11 +22,34 @@ pub enum SelectionPolicy {
     AllowedLanguages { langs: Vec<String> },
     /// Minimum tokens, but prefer original language (tie-breaker)
     PreferOriginalLanguage { lang: String },
+    /// Apply the inner policy to whole multi-word phrases: a resolved phrase
+    /// is swapped for a single-script form or left as is, and its words are
+    /// never swapped on their own
+    AtomicPhrase(Box<SelectionPolicy>),
 }
 
-impl Default for SelectionPolicy {
-    fn default() -> Self {
-        Self::MinTokens
+impl SelectionPolicy {
+    /// Wrap this policy so multi-word phrases are selected as a unit
+    pub fn atomic(self) -> Self {
+        match self {
+            SelectionPolicy::AtomicPhrase(_) => self,
+            other => SelectionPolicy::AtomicPhrase(Box::new(other)),
+        }
+    }
+
+    /// Whether multi-word phrases are selected as a unit
+    pub fn is_atomic(&self) -> bool {
+        matches!(self, SelectionPolicy::AtomicPhrase(_))
+    }
+
+    /// The only languages this policy can ever pick from, if restricted
+    fn languages(&self) -> Option<&[String]> {
+        match self {
+            SelectionPolicy::SameLanguage { lang } => Some(std::slice::from_ref(lang)),
+            SelectionPolicy::AllowedLanguages { langs } => Some(langs),
+            SelectionPolicy::AtomicPhrase(inner) => inner.languages(),
+            SelectionPolicy::MinTokens | SelectionPolicy::PreferOriginalLanguage { .. } => None,
+        }
     }
 }
 
@@ -47,30 +71,42 @@ impl SurfaceSelector {
         tokenizer_id: TokenizerId,
         policy: &SelectionPolicy,
     ) -> Result<Option<SurfaceForm>> {
-        // Get all surface forms for this concept and tokenizer
+        // Get the surface forms for this concept and tokenizer, in the
+        // policy's languages only
         let forms = self
             .db
-            .get_surface_forms(qid, tokenizer_id.as_str())?;
+            .get_surface_forms_filtered(qid, tokenizer_id.as_str(), policy.languages())?;
 
         if forms.is_empty() {
             return Ok(None);
         }
 
-        // Apply policy to select best form
-        let selected = match policy {
-            SelectionPolicy::MinTokens => self.select_min_tokens(&forms),
+        Ok(self.apply_policy(&forms, policy))
+    }
+
+    /// Apply policy to select best form
+    fn apply_policy(&self, forms: &[SurfaceForm], policy: &SelectionPolicy) -> Option<SurfaceForm> {
+        match policy {
+            SelectionPolicy::MinTokens => self.select_min_tokens(forms),
             SelectionPolicy::SameLanguage { lang } => {
-                self.select_same_language(&forms, lang)
+                self.select_same_language(forms, lang)
             }
             SelectionPolicy::AllowedLanguages { langs } => {
-                self.select_allowed_languages(&forms, langs)
+                self.select_allowed_languages(forms, langs)
             }
             SelectionPolicy::PreferOriginalLanguage { lang } => {
-                self.select_prefer_language(&forms, lang)
+                self.select_prefer_language(forms, lang)
             }
-        };
-
-        Ok(selected)
+            SelectionPolicy::AtomicPhrase(inner) => {
+                // Mixed-script forms read as half-translated fragments
+                let single_script: Vec<SurfaceForm> = forms
+                    .iter()
+                    .filter(|f| !is_mixed_script(&f.form))
+                    .cloned()
+                    .collect();
+                self.apply_policy(&single_script, inner)
+            }
+        }
     }
 
     /// Select form with minimum token count
@@ -159,6 +195,13 @@ impl SurfaceSelector {
     }
 }
 
+/// Whether `form` mixes Latin letters with letters from another script
+fn is_mixed_script(form: &str) -> bool {
+    let is_latin = |c: char| c.is_ascii_alphabetic() || ('\u{00C0}'..='\u{024F}').contains(&c);
+    let letters = || form.chars().filter(|c| c.is_alphabetic());
+    letters().any(is_latin) && letters().any(|c| !is_latin(c))
+}
+
 /// Optimization candidate with token savings
 #[derive(Debug, Clone, PartialEq)]
 pub struct OptimizationCandidate {
@@ -318,6 +361,37 @@ mod tests {
         assert_eq!(form.token_count, 1);
     }
 
+    #[test]
+    fn test_atomic_phrase_skips_mixed_script() {
+        let db = setup_test_db();
+        db.insert_surface_form(&SurfaceForm {
+            qid: "Q16917".to_string(),
+            tokenizer_id: "cl100k_base".to_string(),
+            lang: "zh".to_string(),
+            form: "hospital院".to_string(),
+            token_count: 0,
+            char_count: 9,
+        })
+        .unwrap();
+        let selector = SurfaceSelector::new(db);
+
+        let plain = selector
+            .select("Q16917", TokenizerId::Cl100kBase, &SelectionPolicy::MinTokens)
+            .unwrap()
+            .unwrap();
+        assert_eq!(plain.form, "hospital院");
+
+        let policy = SelectionPolicy::MinTokens.atomic();
+        assert!(policy.is_atomic());
+        assert_eq!(policy.clone().atomic(), policy);
+        let atomic = selector
+            .select("Q16917", TokenizerId::Cl100kBase, &policy)
+            .unwrap()
+            .unwrap();
+        assert_eq!(atomic.form, "hospital");
+        assert_eq!(atomic.token_count, 1);
+    }
+
     #[test]
     fn test_calculate_savings() {
         let db = setup_test_db();
@@ -1,22 +1,65 @@
-use anyhow::{Context, Result};
-use tiktoken_rs::{cl100k_base, CoreBPE};
+use crate::error::{PromptCompressError, Result};
+use crate::tokenizer_registry::TokenizerId;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tiktoken_rs::{cl100k_base, p50k_base, r50k_base, CoreBPE};
+
+/// Longest whitespace-only string whose token count is cached
+const MAX_CACHED_WHITESPACE: usize = 32;
 
 /// Tokenizer for counting tokens in prompts
 /// Uses tiktoken-rs (OpenAI's cl100k_base tokenizer)
 pub struct Tokenizer {
     bpe: CoreBPE,
+    /// Token counts of short whitespace-only strings (" ", "\n\n", ...)
+    whitespace_counts: Mutex<HashMap<String, usize>>,
 }
 
 impl Tokenizer {
     /// Create a new tokenizer instance
     pub fn new() -> Result<Self> {
-        let bpe = cl100k_base().context("Failed to load tokenizer")?;
-        Ok(Self { bpe })
+        Self::for_id(TokenizerId::Cl100kBase)
+    }
+
+    /// Create a tokenizer with `id`'s tiktoken encoding
+    ///
+    /// Claude counts with cl100k_base; HuggingFace tokenizers (llama3) have
+    /// no tiktoken encoding and are rejected.
+    pub fn for_id(id: TokenizerId) -> Result<Self> {
+        let bpe = match id {
+            TokenizerId::Cl100kBase | TokenizerId::Claude => cl100k_base(),
+            TokenizerId::R50kBase => r50k_base(),
+            TokenizerId::P50kBase => p50k_base(),
+            TokenizerId::Llama3 => {
+                return Err(PromptCompressError::Tokenizer(format!("{} has no tiktoken encoding", id)))
+            }
+        }
+        .map_err(|e| PromptCompressError::Tokenizer(format!("Failed to load tokenizer: {}", e)))?;
+        Ok(Self {
+            bpe,
+            whitespace_counts: Mutex::new(HashMap::new()),
+        })
     }
 
     /// Count tokens in a text string
+    ///
+    /// Empty strings skip the encoder, and short whitespace-only strings
+    /// (counted constantly while estimating savings) are cached.
     pub fn count_tokens(&self, text: &str) -> usize {
-        self.bpe.encode_with_special_tokens(text).len()
+        if text.is_empty() {
+            return 0;
+        }
+        if text.len() > MAX_CACHED_WHITESPACE || !text.chars().all(char::is_whitespace) {
+            return self.bpe.encode_with_special_tokens(text).len();
+        }
+
+        let mut counts = self.whitespace_counts.lock().unwrap();
+        if let Some(&count) = counts.get(text) {
+            return count;
+        }
+        let count = self.bpe.encode_with_special_tokens(text).len();
+        counts.insert(text.to_string(), count);
+        count
     }
 
     /// Encode text to tokens
@@ -47,6 +90,45 @@ impl Tokenizer {
     pub fn estimate_savings(&self, original_text: &str, replacement_text: &str) -> i64 {
         self.calculate_savings(original_text, replacement_text)
     }
+
+    /// Token savings of replacing `text[start..end]` with `replacement`,
+    /// counted in context
+    ///
+    /// The window takes in the neighbouring word on each side, so tokens
+    /// that merge across the edit (a removed trailing space, for example)
+    /// are counted once rather than per isolated snippet. Removing a
+    /// capitalized sentence start capitalizes the word that moves up, as
+    /// the optimizers do.
+    pub fn savings_in_context(&self, text: &str, start: usize, end: usize, replacement: &str) -> i64 {
+        let before = text[..start].trim_end();
+        let window_start = before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map_or(0, |(i, c)| i + c.len_utf8());
+
+        let after = &text[end..];
+        let lead = after.len() - after.trim_start().len();
+        let window_end = end
+            + lead
+            + after[lead..]
+                .find(char::is_whitespace)
+                .unwrap_or(after.len() - lead);
+
+        // A removed sentence start hands its capital to the next word
+        let mut rest = text[end..window_end].to_string();
+        let capitalized = text[start..end].starts_with(char::is_uppercase);
+        if capitalized && replacement.is_empty() {
+            let trimmed = rest.trim_start();
+            if let Some(first) = trimmed.chars().next() {
+                let tail = &trimmed[first.len_utf8()..];
+                rest = first.to_uppercase().chain(tail.chars()).collect();
+            }
+        }
+
+        let edited = format!("{}{}{}", &text[window_start..start], replacement, rest);
+        self.calculate_savings(&text[window_start..window_end], &edited)
+    }
 }
 
 impl Default for Tokenizer {
@@ -68,6 +150,32 @@ mod tests {
         assert!(count > 0);
     }
 
+    #[test]
+    fn test_tokenizer_for_id() {
+        let code = "def f():\n        return 1";
+        let cl100k = Tokenizer::for_id(TokenizerId::Cl100kBase).unwrap();
+        let r50k = Tokenizer::for_id(TokenizerId::R50kBase).unwrap();
+        let p50k = Tokenizer::for_id(TokenizerId::P50kBase).unwrap();
+
+        assert_eq!(Tokenizer::for_id(TokenizerId::Claude).unwrap().count_tokens(code), cl100k.count_tokens(code));
+        assert!(p50k.count_tokens(code) < r50k.count_tokens(code));
+        assert!(Tokenizer::for_id(TokenizerId::Llama3).is_err());
+    }
+
+    #[test]
+    fn test_empty_and_whitespace_counts() {
+        let tokenizer = Tokenizer::new().unwrap();
+        let bpe = cl100k_base().unwrap();
+
+        assert_eq!(tokenizer.count_tokens(""), 0);
+        for text in [" ", "  ", "\n", "\n\n", " \t\n ", "\u{3000}", &" ".repeat(40)] {
+            let expected = bpe.encode_with_special_tokens(text).len();
+            // Cold, then cached
+            assert_eq!(tokenizer.count_tokens(text), expected, "{:?}", text);
+            assert_eq!(tokenizer.count_tokens(text), expected, "{:?}", text);
+        }
+    }
+
     #[test]
     fn test_savings_calculation() {
         let tokenizer = Tokenizer::new().unwrap();
@@ -83,6 +191,28 @@ mod tests {
         assert!(percentage < 100.0);
     }
 
+    #[test]
+    fn test_savings_in_context() {
+        let tokenizer = Tokenizer::new().unwrap();
+
+        // "please " alone is two tokens, but in context only " please" goes
+        let text = "Can you please summarize the report?";
+        assert_eq!(tokenizer.estimate_savings("please ", ""), 2);
+        assert_eq!(tokenizer.savings_in_context(text, 8, 15, ""), 1);
+
+        // The next word takes over the capital
+        assert_eq!(
+            tokenizer.savings_in_context("Please summarize it.", 0, 7, ""),
+            tokenizer.calculate_savings("Please summarize", "Summarize")
+        );
+
+        // Whole text when there is no neighbouring word
+        assert_eq!(
+            tokenizer.savings_in_context("really", 0, 6, ""),
+            tokenizer.estimate_savings("really", "")
+        );
+    }
+
     #[test]
     fn test_mandarin_efficiency() {
         let tokenizer = Tokenizer::new().unwrap();
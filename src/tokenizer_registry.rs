@@ -1,11 +1,13 @@
-/// Phase 3: Tokenizer Registry - Multi-tokenizer abstraction layer
-///
-/// Purpose: Abstract over different tokenizer backends (tiktoken, HuggingFace)
-/// to enable token cost comparison across models (GPT, Claude, Llama, etc.)
+//! Phase 3: Tokenizer Registry - Multi-tokenizer abstraction layer
+//!
+//! Purpose: Abstract over different tokenizer backends (tiktoken, HuggingFace)
+//! to enable token cost comparison across models (GPT, Claude, Llama, etc.)
 
-use anyhow::{anyhow, Result};
+use crate::error::{PromptCompressError, Result};
 use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::Arc;
+use unicode_normalization::UnicodeNormalization;
 
 /// Tokenizer identifier for database lookups
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -16,6 +18,10 @@ pub enum TokenizerId {
     Llama3,
     /// Anthropic Claude (uses cl100k_base)
     Claude,
+    /// Legacy GPT-3 (davinci) and GPT-2 (r50k_base, a.k.a. gpt2)
+    R50kBase,
+    /// Legacy Codex and text-davinci-002/003 (p50k_base)
+    P50kBase,
 }
 
 impl TokenizerId {
@@ -25,23 +31,31 @@ impl TokenizerId {
             Self::Cl100kBase => "cl100k_base",
             Self::Llama3 => "llama3",
             Self::Claude => "claude",
+            Self::R50kBase => "r50k_base",
+            Self::P50kBase => "p50k_base",
         }
     }
 
+    /// Get all supported tokenizer IDs
+    pub fn all() -> Vec<Self> {
+        vec![Self::Cl100kBase, Self::Llama3, Self::Claude, Self::R50kBase, Self::P50kBase]
+    }
+}
+
+impl FromStr for TokenizerId {
+    type Err = PromptCompressError;
+
     /// Parse from database string
-    pub fn from_str(s: &str) -> Result<Self> {
+    fn from_str(s: &str) -> Result<Self> {
         match s {
             "cl100k_base" => Ok(Self::Cl100kBase),
             "llama3" => Ok(Self::Llama3),
             "claude" => Ok(Self::Claude),
-            _ => Err(anyhow!("Unknown tokenizer ID: {}", s)),
+            "r50k_base" | "gpt2" => Ok(Self::R50kBase),
+            "p50k_base" => Ok(Self::P50kBase),
+            _ => Err(PromptCompressError::InvalidRequest(format!("Unknown tokenizer ID: {}", s))),
         }
     }
-
-    /// Get all supported tokenizer IDs
-    pub fn all() -> Vec<Self> {
-        vec![Self::Cl100kBase, Self::Llama3, Self::Claude]
-    }
 }
 
 impl std::fmt::Display for TokenizerId {
@@ -55,12 +69,29 @@ pub trait TokenizerBackend: Send + Sync {
     /// Count tokens in text
     fn count_tokens(&self, text: &str) -> usize;
 
+    /// Count tokens for many strings at once, in input order
+    ///
+    /// Defaults to counting each string individually; backends with native
+    /// batch encoding should override this.
+    fn count_tokens_batch(&self, texts: &[&str]) -> Vec<usize> {
+        texts.iter().map(|text| self.count_tokens(text)).collect()
+    }
+
     /// Encode text to token IDs (for debugging/analysis)
     fn encode(&self, text: &str) -> Vec<u32>;
 
     /// Decode token IDs back to text (for debugging/analysis)
     fn decode(&self, tokens: &[u32]) -> Result<String>;
 
+    /// Check that encoding then decoding gives back the same text
+    /// (compared after Unicode NFC normalization)
+    fn verify_roundtrip(&self, text: &str) -> bool {
+        match self.decode(&self.encode(text)) {
+            Ok(decoded) => decoded.nfc().eq(text.nfc()),
+            Err(_) => false,
+        }
+    }
+
     /// Get tokenizer ID
     fn id(&self) -> TokenizerId;
 }
@@ -75,17 +106,37 @@ impl TiktokenBackend {
     /// Create cl100k_base tokenizer (GPT-4, GPT-3.5-turbo)
     pub fn cl100k_base() -> Result<Self> {
         let bpe = tiktoken_rs::cl100k_base()
-            .map_err(|e| anyhow!("Failed to load cl100k_base: {}", e))?;
+            .map_err(|e| PromptCompressError::Tokenizer(format!("Failed to load cl100k_base: {}", e)))?;
         Ok(Self {
             bpe,
             id: TokenizerId::Cl100kBase,
         })
     }
 
+    /// Create r50k_base tokenizer (GPT-3 davinci, GPT-2)
+    pub fn r50k_base() -> Result<Self> {
+        let bpe = tiktoken_rs::r50k_base()
+            .map_err(|e| PromptCompressError::Tokenizer(format!("Failed to load r50k_base: {}", e)))?;
+        Ok(Self {
+            bpe,
+            id: TokenizerId::R50kBase,
+        })
+    }
+
+    /// Create p50k_base tokenizer (Codex, text-davinci-002/003)
+    pub fn p50k_base() -> Result<Self> {
+        let bpe = tiktoken_rs::p50k_base()
+            .map_err(|e| PromptCompressError::Tokenizer(format!("Failed to load p50k_base: {}", e)))?;
+        Ok(Self {
+            bpe,
+            id: TokenizerId::P50kBase,
+        })
+    }
+
     /// Create Claude tokenizer (uses cl100k_base)
     pub fn claude() -> Result<Self> {
         let bpe = tiktoken_rs::cl100k_base()
-            .map_err(|e| anyhow!("Failed to load claude tokenizer: {}", e))?;
+            .map_err(|e| PromptCompressError::Tokenizer(format!("Failed to load claude tokenizer: {}", e)))?;
         Ok(Self {
             bpe,
             id: TokenizerId::Claude,
@@ -110,7 +161,7 @@ impl TokenizerBackend for TiktokenBackend {
         let tokens_usize: Vec<usize> = tokens.iter().map(|&t| t as usize).collect();
         self.bpe
             .decode(tokens_usize)
-            .map_err(|e| anyhow!("Decode failed: {}", e))
+            .map_err(|e| PromptCompressError::Tokenizer(format!("Decode failed: {}", e)))
     }
 
     fn id(&self) -> TokenizerId {
@@ -129,15 +180,17 @@ impl HuggingFaceBackend {
     pub fn llama3() -> Result<Self> {
         // Note: In production, load from HuggingFace model hub or local file
         // For now, we'll use a placeholder that needs to be configured
-        Err(anyhow!(
-            "Llama3 tokenizer not yet configured. Please provide tokenizer.json path."
+        Err(PromptCompressError::Tokenizer(
+            "Llama3 tokenizer not yet configured. Please provide tokenizer.json path.".to_string(),
         ))
     }
 
     /// Create from tokenizer.json file
     pub fn from_file(path: &str, id: TokenizerId) -> Result<Self> {
         let tokenizer = tokenizers::Tokenizer::from_file(path)
-            .map_err(|e| anyhow!("Failed to load tokenizer from {}: {}", path, e))?;
+            .map_err(|e| {
+                PromptCompressError::Tokenizer(format!("Failed to load tokenizer from {}: {}", path, e))
+            })?;
         Ok(Self { tokenizer, id })
     }
 }
@@ -150,6 +203,14 @@ impl TokenizerBackend for HuggingFaceBackend {
             .unwrap_or(0)
     }
 
+    fn count_tokens_batch(&self, texts: &[&str]) -> Vec<usize> {
+        match self.tokenizer.encode_batch(texts.to_vec(), false) {
+            Ok(encodings) => encodings.iter().map(|enc| enc.len()).collect(),
+            // Fall back to per-item counting so one bad input doesn't zero the batch
+            Err(_) => texts.iter().map(|text| self.count_tokens(text)).collect(),
+        }
+    }
+
     fn encode(&self, text: &str) -> Vec<u32> {
         self.tokenizer
             .encode(text, false)
@@ -160,7 +221,7 @@ impl TokenizerBackend for HuggingFaceBackend {
     fn decode(&self, tokens: &[u32]) -> Result<String> {
         self.tokenizer
             .decode(tokens, false)
-            .map_err(|e| anyhow!("Decode failed: {}", e))
+            .map_err(|e| PromptCompressError::Tokenizer(format!("Decode failed: {}", e)))
     }
 
     fn id(&self) -> TokenizerId {
@@ -188,11 +249,21 @@ impl TokenizerRegistry {
             backends.insert(TokenizerId::Claude, Arc::new(backend));
         }
 
+        // Legacy OpenAI encodings, for cost comparison against older models
+        if let Ok(backend) = TiktokenBackend::r50k_base() {
+            backends.insert(TokenizerId::R50kBase, Arc::new(backend));
+        }
+        if let Ok(backend) = TiktokenBackend::p50k_base() {
+            backends.insert(TokenizerId::P50kBase, Arc::new(backend));
+        }
+
         // Note: Llama3 requires external tokenizer.json file
         // Users can add via register_backend()
 
         if backends.is_empty() {
-            return Err(anyhow!("No tokenizers loaded successfully"));
+            return Err(PromptCompressError::Tokenizer(
+                "No tokenizers loaded successfully".to_string(),
+            ));
         }
 
         Ok(Self { backends })
@@ -265,6 +336,36 @@ mod tests {
         );
 
         assert!(TokenizerId::from_str("unknown").is_err());
+
+        // Every ID round-trips, and gpt2 is an alias of r50k_base
+        for id in TokenizerId::all() {
+            assert_eq!(TokenizerId::from_str(id.as_str()).unwrap(), id);
+        }
+        assert_eq!(TokenizerId::from_str("gpt2").unwrap(), TokenizerId::R50kBase);
+    }
+
+    #[test]
+    fn test_legacy_tokenizers() {
+        let r50k = TiktokenBackend::r50k_base().unwrap();
+        let p50k = TiktokenBackend::p50k_base().unwrap();
+        assert_eq!(r50k.id(), TokenizerId::R50kBase);
+        assert_eq!(p50k.id(), TokenizerId::P50kBase);
+
+        // Same vocabulary for prose: "Hello", ",", " world", "!"
+        assert_eq!(r50k.count_tokens("Hello, world!"), 4);
+        assert_eq!(p50k.count_tokens("Hello, world!"), 4);
+
+        // p50k adds tokens for runs of spaces, so indented code is cheaper
+        let code = "def f():\n        return 1";
+        assert!(p50k.count_tokens(code) < r50k.count_tokens(code));
+
+        assert!(r50k.verify_roundtrip("Analyze this code thoroughly."));
+        assert!(p50k.verify_roundtrip("请分析这段代码。"));
+
+        let registry = TokenizerRegistry::new().unwrap();
+        assert!(registry.has(TokenizerId::R50kBase));
+        assert!(registry.has(TokenizerId::P50kBase));
+        assert_eq!(registry.count_all("Hello, world!")[&TokenizerId::R50kBase], 4);
     }
 
     #[test]
@@ -284,6 +385,64 @@ mod tests {
         assert_eq!(decoded, "Hello");
     }
 
+    #[test]
+    fn test_count_tokens_batch_matches_per_item() {
+        let texts = ["Hello, world!", "", "Analyze this code thoroughly.", "医院"];
+
+        let tiktoken = TiktokenBackend::cl100k_base().unwrap();
+        let expected: Vec<usize> = texts.iter().map(|t| tiktoken.count_tokens(t)).collect();
+        assert_eq!(tiktoken.count_tokens_batch(&texts), expected);
+
+        // Minimal word-level HuggingFace tokenizer to exercise encode_batch
+        let vocab: HashMap<String, u32> = ["[UNK]", "analyze", "this", "code", "thoroughly"]
+            .iter()
+            .enumerate()
+            .map(|(i, w)| (w.to_string(), i as u32))
+            .collect();
+        let model = tokenizers::models::wordlevel::WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("[UNK]".to_string())
+            .build()
+            .unwrap();
+        let mut tokenizer = tokenizers::Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(Some(tokenizers::pre_tokenizers::whitespace::Whitespace {}));
+        let hf = HuggingFaceBackend {
+            tokenizer,
+            id: TokenizerId::Llama3,
+        };
+
+        let expected: Vec<usize> = texts.iter().map(|t| hf.count_tokens(t)).collect();
+        assert_eq!(hf.count_tokens_batch(&texts), expected);
+        assert_eq!(hf.count_tokens_batch(&[]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_verify_roundtrip() {
+        let tiktoken = TiktokenBackend::cl100k_base().unwrap();
+        assert!(tiktoken.verify_roundtrip("Analyze this code thoroughly.\n  Then explain it."));
+        assert!(tiktoken.verify_roundtrip("请分析这段代码。医院、患者、診断"));
+        assert!(tiktoken.verify_roundtrip(""));
+
+        // Word-level decoding loses unknown words
+        let vocab: HashMap<String, u32> = ["[UNK]", "analyze", "this"]
+            .iter()
+            .enumerate()
+            .map(|(i, w)| (w.to_string(), i as u32))
+            .collect();
+        let model = tokenizers::models::wordlevel::WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("[UNK]".to_string())
+            .build()
+            .unwrap();
+        let mut tokenizer = tokenizers::Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(Some(tokenizers::pre_tokenizers::whitespace::Whitespace {}));
+        let hf = HuggingFaceBackend {
+            tokenizer,
+            id: TokenizerId::Llama3,
+        };
+        assert!(!hf.verify_roundtrip("analyze this code"));
+    }
+
     #[test]
     fn test_tokenizer_registry() {
         let registry = TokenizerRegistry::new().unwrap();
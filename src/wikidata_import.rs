@@ -0,0 +1,201 @@
+//! Phase 3: Wikidata Import - Grow the Concept Atlas from Wikidata labels
+//!
+//! Purpose: Load multilingual labels exported from Wikidata (offline, no
+//! network access) and store them as concepts plus token-counted surface forms.
+//!
+//! Input format: UTF-8 TSV, one label per line:
+//!
+//! ```text
+//! # qid    lang  label     description (optional)
+//! Q16917   en    hospital  healthcare facility
+//! Q16917   zh    医院
+//! Q16917   es    hospital
+//! ```
+//!
+//! Blank lines and lines starting with `#` are ignored. A Q-ID may have several
+//! labels per language (aliases); each becomes a surface form. The first English
+//! label becomes the concept's `label_en`, so concepts without one are skipped.
+
+use crate::concept_optimizer::count_surface_forms;
+use crate::database::{Concept, Database};
+use crate::tokenizer_registry::TokenizerRegistry;
+use crate::error::{PromptCompressError, Result};
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::path::Path;
+
+/// One row of a Wikidata labels file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WikidataLabel {
+    pub qid: String,
+    pub lang: String,
+    pub label: String,
+    pub description: Option<String>,
+}
+
+/// Outcome of an import
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportStats {
+    /// Concepts inserted or updated
+    pub concepts: usize,
+    /// Surface forms written (labels × tokenizers)
+    pub surface_forms: usize,
+    /// Q-IDs skipped because they have no English label
+    pub skipped: Vec<String>,
+}
+
+/// Parse a Wikidata labels TSV
+pub fn parse_labels_tsv<R: BufRead>(reader: R) -> Result<Vec<WikidataLabel>> {
+    let mut labels = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_num = index + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = trimmed.split('\t').map(str::trim).collect();
+        if fields.len() < 3 || fields[2].is_empty() {
+            return Err(PromptCompressError::Serde(format!(
+                "line {}: expected qid<TAB>lang<TAB>label[<TAB>description]",
+                line_num
+            )));
+        }
+
+        let qid = fields[0];
+        if !is_qid(qid) {
+            return Err(PromptCompressError::Serde(format!(
+                "line {}: invalid Q-ID {:?}",
+                line_num, qid
+            )));
+        }
+
+        labels.push(WikidataLabel {
+            qid: qid.to_string(),
+            lang: fields[1].to_lowercase(),
+            label: fields[2].to_string(),
+            description: fields.get(3).filter(|d| !d.is_empty()).map(|d| d.to_string()),
+        });
+    }
+
+    Ok(labels)
+}
+
+/// Import labels into the atlas
+///
+/// Only Q-IDs in `qids` are imported (all when empty). An existing concept
+/// keeps its category.
+pub fn import_labels(
+    db: &Database,
+    registry: &TokenizerRegistry,
+    labels: &[WikidataLabel],
+    qids: &[String],
+) -> Result<ImportStats> {
+    // Group by Q-ID, keeping file order within each concept
+    let mut by_qid: BTreeMap<&str, Vec<&WikidataLabel>> = BTreeMap::new();
+    for label in labels {
+        if qids.is_empty() || qids.iter().any(|q| q == &label.qid) {
+            by_qid.entry(&label.qid).or_default().push(label);
+        }
+    }
+
+    let mut stats = ImportStats::default();
+    for (qid, rows) in by_qid {
+        let Some(english) = rows.iter().find(|r| r.lang == "en") else {
+            stats.skipped.push(qid.to_string());
+            continue;
+        };
+
+        let description = english
+            .description
+            .clone()
+            .or_else(|| rows.iter().find_map(|r| r.description.clone()));
+        let category = db.get_concept(qid)?.and_then(|c| c.category);
+
+        db.upsert_concept(&Concept {
+            qid: qid.to_string(),
+            label_en: english.label.clone(),
+            description,
+            category,
+        })?;
+        stats.concepts += 1;
+
+        let forms: Vec<(&str, &str)> = rows
+            .iter()
+            .map(|r| (r.lang.as_str(), r.label.as_str()))
+            .collect();
+        for form in count_surface_forms(registry, qid, &forms) {
+            db.insert_surface_form(&form)?;
+            stats.surface_forms += 1;
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Parse a labels TSV file and import it into the atlas
+pub fn import_labels_file(
+    db: &Database,
+    registry: &TokenizerRegistry,
+    path: &Path,
+    qids: &[String],
+) -> Result<ImportStats> {
+    let file = std::fs::File::open(path)?;
+    let labels = parse_labels_tsv(std::io::BufReader::new(file))?;
+    import_labels(db, registry, &labels, qids)
+}
+
+fn is_qid(s: &str) -> bool {
+    s.len() > 1 && s.starts_with('Q') && s[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/wikidata_labels.tsv");
+
+    #[test]
+    fn test_parse_labels_tsv() {
+        let labels = parse_labels_tsv("# header\n\nQ16917\ten\thospital\thealthcare facility\nQ16917\tzh\t医院\n".as_bytes()).unwrap();
+
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels[0].description.as_deref(), Some("healthcare facility"));
+        assert_eq!(labels[1].lang, "zh");
+        assert_eq!(labels[1].description, None);
+
+        assert!(parse_labels_tsv("Q1\ten\n".as_bytes()).is_err());
+        assert!(parse_labels_tsv("hospital\ten\thospital\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_import_fixture() {
+        let db = Database::in_memory().unwrap();
+        let registry = TokenizerRegistry::new().unwrap();
+        let tokenizers = registry.available().len();
+
+        let stats = import_labels_file(&db, &registry, Path::new(FIXTURE), &[]).unwrap();
+
+        // Q8513 has no English label
+        assert_eq!(stats.concepts, 2);
+        assert_eq!(stats.skipped, vec!["Q8513"]);
+        assert_eq!(stats.surface_forms, 7 * tokenizers);
+
+        let hospital = db.get_concept("Q16917").unwrap().unwrap();
+        assert_eq!(hospital.label_en, "hospital");
+        assert_eq!(hospital.description.as_deref(), Some("healthcare facility"));
+
+        let forms = db.get_surface_forms("Q16917", "cl100k_base").unwrap();
+        assert_eq!(forms.len(), 4);
+        assert!(forms.iter().any(|f| f.lang == "zh" && f.form == "医院"));
+
+        // Q-ID filter
+        let db = Database::in_memory().unwrap();
+        let stats =
+            import_labels_file(&db, &registry, Path::new(FIXTURE), &["Q131512".to_string()]).unwrap();
+        assert_eq!(stats.concepts, 1);
+        assert!(db.get_concept("Q16917").unwrap().is_none());
+    }
+}
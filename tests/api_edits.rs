@@ -0,0 +1,59 @@
+//! Integration test for `POST /api/v1/optimize?format=edits`
+//!
+//! A thin client applies the returned edits to its own copy of the prompt
+//! and must end up with the same text the full response carries.
+
+use actix_web::{test, web, App};
+use prompt_compress::api::{configure_routes, AppState};
+use prompt_compress::{apply_edits, Optimizer, TextEdit};
+
+#[actix_web::test]
+async fn test_edits_rebuild_optimized_prompt() {
+    // Cached so both calls share one result (and optimization ids)
+    let state = web::Data::new(AppState::new(Optimizer::default()).with_result_cache(4));
+    let app = test::init_service(App::new().app_data(state).configure(configure_routes)).await;
+
+    let prompt = "I would really appreciate it if you could please analyze this code. \
+                  Basically, I want you to provide a detailed explanation. Thank you!";
+    let body = serde_json::json!({ "prompt": prompt, "output_language": "english" });
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/optimize")
+        .set_json(&body)
+        .to_request();
+    let full: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/v1/optimize?format=edits")
+        .set_json(&body)
+        .to_request();
+    let response: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+    // Only edits and token counts, no full prompt
+    assert!(response.get("result").is_none());
+    assert_eq!(response["token_savings"], full["result"]["token_savings"]);
+
+    let edits: Vec<TextEdit> = serde_json::from_value(response["edits"].clone()).unwrap();
+    assert!(!edits.is_empty());
+    assert!(edits.windows(2).all(|pair| pair[0].end <= pair[1].start));
+    assert!(edits.iter().all(|edit| edit.end <= prompt.len()));
+
+    assert_eq!(
+        apply_edits(prompt, &edits),
+        full["result"]["optimized_prompt"].as_str().unwrap()
+    );
+
+    // Edits from applied optimizations carry their ids
+    let applied: Vec<&str> = full["result"]["optimizations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|opt| opt["id"].as_str().unwrap())
+        .collect();
+    let credited: Vec<&str> = edits
+        .iter()
+        .filter_map(|edit| edit.optimization_id.as_deref())
+        .collect();
+    assert!(!credited.is_empty());
+    assert!(credited.iter().all(|id| applied.contains(id)));
+}
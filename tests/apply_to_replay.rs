@@ -0,0 +1,101 @@
+//! Property test for `OptimizationResult::apply_to`
+//!
+//! Replaying a result's optimizations on its original prompt must give back
+//! exactly the optimized prompt, whichever optimizer produced it, and results
+//! a replay cannot reproduce must say so instead of diverging.
+
+use prompt_compress::{
+    seed_atlas, ConceptOptimizer, ConfidenceCalculator, Database, DatabaseOptimizer, OptimizationRequest,
+    OptimizationResult, Optimizer, Result, Tokenizer, TokenizerRegistry,
+};
+use std::sync::Arc;
+
+const PROMPTS: &[&str] = &[
+    "I would really appreciate it if you could basically summarize this report.",
+    "Please make sure to make sure to make sure to summarize this report.",
+    "In order to basically help me, could you please analyze the data? Thanks in advance!",
+    "I would really appreciate it if you could fix this:\n\n```python\nx  =  1   # aligned\nprint(\"a\" , x)\n```",
+    "Please really explain the hospital costs below.\n\n| item   | cost |\n|--------|------|\n| bed    | 10   |",
+    "Basically, run `make build`  ${TARGET} now. I want you to really check the output, thank you.",
+];
+
+fn database() -> Arc<Database> {
+    let db = Database::in_memory().unwrap();
+    seed_atlas(&db, &TokenizerRegistry::new().unwrap()).unwrap();
+    for (pattern_type, regex) in [
+        ("boilerplate", r"(?i)I would really appreciate it if you could\s*"),
+        ("boilerplate", r"(?i)\bplease\s+"),
+        ("filler", r"(?i)\b(really|basically)\s+"),
+        ("instruction", r"(?i)I want you to\s+"),
+    ] {
+        db.connection()
+            .unwrap()
+            .execute(
+                "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
+                 VALUES (?1, ?2, '', 0.95, 'Test')",
+                rusqlite::params![pattern_type, regex],
+            )
+            .unwrap();
+    }
+    Arc::new(db)
+}
+
+/// Hand `check` each optimizer's `optimize`, by name, over one shared database
+fn for_each_optimizer(mut check: impl FnMut(&str, &mut dyn FnMut(&OptimizationRequest) -> Result<OptimizationResult>)) {
+    let db = database();
+    let optimizer = Optimizer::default();
+    let mut database_optimizer =
+        DatabaseOptimizer::new(Arc::clone(&db), ConfidenceCalculator::default(), Tokenizer::new().unwrap()).unwrap();
+    let mut concept_optimizer = ConceptOptimizer::new(db).unwrap();
+
+    check("Optimizer", &mut |request| optimizer.optimize(request));
+    check("DatabaseOptimizer", &mut |request| database_optimizer.optimize(request));
+    check("ConceptOptimizer", &mut |request| concept_optimizer.optimize(request));
+}
+
+#[test]
+fn test_apply_to_reproduces_optimized_prompt() {
+    for_each_optimizer(|name, optimize| {
+        let mut changed = 0;
+        for prompt in PROMPTS {
+            let request = OptimizationRequest::builder(*prompt)
+                .confidence_threshold(0.5)
+                .aggressive_mode(true)
+                .language_directive(false)
+                .build();
+            let result = optimize(&request).unwrap();
+            changed += usize::from(!result.optimizations.is_empty());
+
+            assert!(result.replayable, "{}: {}", name, prompt);
+            assert_eq!(
+                result.apply_to(&result.original_prompt).as_deref(),
+                Some(result.optimized_prompt.as_str()),
+                "{}: {}",
+                name,
+                prompt
+            );
+        }
+        assert!(changed >= PROMPTS.len() / 2, "{} changed only {} prompts", name, changed);
+    });
+}
+
+#[test]
+fn test_apply_to_refuses_what_it_cannot_reproduce() {
+    for_each_optimizer(|name, optimize| {
+        let base = |prompt: &str| {
+            OptimizationRequest::builder(prompt)
+                .confidence_threshold(0.5)
+                .language_directive(false)
+        };
+
+        for request in [
+            base(PROMPTS[0]).language_directive(true).build(),
+            base(PROMPTS[0]).preserve_case(true).build(),
+            base(&format!("ID: 7\n{}", PROMPTS[0])).protected_prefix("ID: 7").build(),
+        ] {
+            let result = optimize(&request).unwrap();
+            assert!(!result.optimizations.is_empty(), "{}", name);
+            assert_eq!(result.apply_to(&result.original_prompt), None, "{}", name);
+        }
+    });
+}
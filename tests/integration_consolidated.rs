@@ -1,16 +1,16 @@
-/// Integration Test for Consolidated Database-Backed Pattern System
-///
-/// Tests the full pipeline:
-/// 1. Database setup
-/// 2. Pattern migration
-/// 3. Pattern loading
-/// 4. Optimization with database patterns
-/// 5. HITL feedback recording
-/// 6. Confidence updating
+//! Integration Test for Consolidated Database-Backed Pattern System
+//!
+//! Tests the full pipeline:
+//! 1. Database setup
+//! 2. Pattern migration
+//! 3. Pattern loading
+//! 4. Optimization with database patterns
+//! 5. HITL feedback recording
+//! 6. Confidence updating
 
 use prompt_compress::{
     Database, DatabaseOptimizer, HitlDecision, OptimizationRequest,
-    Language, DirectiveFormat, ConfidenceCalculator,
+    Language, DirectiveFormat, ConfidenceCalculator, SavingsFloor,
 };
 use std::sync::Arc;
 
@@ -22,6 +22,7 @@ fn test_consolidated_system_end_to_end() {
     // Step 2: Apply patterns migration schema
     let migration_sql = include_str!("../migrations/002_add_patterns_table.sql");
     db.connection()
+        .unwrap()
         .execute_batch(migration_sql)
         .expect("Failed to apply migration");
 
@@ -40,14 +41,13 @@ fn test_consolidated_system_end_to_end() {
     assert!(pattern_count > 0, "No patterns loaded");
     println!("✓ Loaded {} patterns", pattern_count);
 
-    // Step 5: Test optimization
-    let request = OptimizationRequest {
-        prompt: "I would really appreciate if you could please analyze this code. I want you to provide a detailed explanation.".to_string(),
-        output_language: Language::English,
-        confidence_threshold: 0.85,
-        aggressive_mode: false,
-        directive_format: DirectiveFormat::Bracketed,
-    };
+    // Step 5: Test optimization (on a prompt this short the directive
+    // would cost more than the patterns save, so it is left off)
+    let request = OptimizationRequest::builder("I would really appreciate if you could please analyze this code. I want you to provide a detailed explanation.")
+        .output_language(Language::English)
+        .directive_format(DirectiveFormat::Bracketed)
+        .savings_floor(SavingsFloor::OmitDirective)
+        .build();
 
     let result = optimizer.optimize(&request).expect("Optimization failed");
 
@@ -114,11 +114,13 @@ fn test_confidence_filtering() {
     // Apply migration
     let migration_sql = include_str!("../migrations/002_add_patterns_table.sql");
     db.connection()
+        .unwrap()
         .execute_batch(migration_sql)
         .expect("Failed to apply migration");
 
     // Insert patterns with varying confidence
     db.connection()
+        .unwrap()
         .execute(
             "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
              VALUES ('boilerplate', '(?i)test_high', '', 0.95, 'High confidence test')",
@@ -127,6 +129,7 @@ fn test_confidence_filtering() {
         .expect("Failed to insert pattern");
 
     db.connection()
+        .unwrap()
         .execute(
             "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
              VALUES ('boilerplate', '(?i)test_low', '', 0.60, 'Low confidence test')",
@@ -158,11 +161,13 @@ fn test_pattern_application_tracking() {
     // Apply migration
     let migration_sql = include_str!("../migrations/002_add_patterns_table.sql");
     db.connection()
+        .unwrap()
         .execute_batch(migration_sql)
         .expect("Failed to apply migration");
 
     // Insert test pattern
     db.connection()
+        .unwrap()
         .execute(
             "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
              VALUES ('boilerplate', '(?i)please', '', 0.90, 'Remove please')",
@@ -178,9 +183,10 @@ fn test_pattern_application_tracking() {
         .expect("Failed to create optimizer");
 
     // Get initial application count
-    let db_ref = optimizer.database();
+    let db_ref = Arc::clone(optimizer.database());
     let initial_count: i64 = db_ref
         .connection()
+        .unwrap()
         .query_row(
             "SELECT applied_count FROM patterns WHERE id = 1",
             [],
@@ -191,19 +197,17 @@ fn test_pattern_application_tracking() {
     println!("Initial applied_count: {}", initial_count);
 
     // Optimize text with "please"
-    let request = OptimizationRequest {
-        prompt: "Please help me with this task.".to_string(),
-        output_language: Language::English,
-        confidence_threshold: 0.85,
-        aggressive_mode: false,
-        directive_format: DirectiveFormat::Bracketed,
-    };
+    let request = OptimizationRequest::builder("Please help me with this task.")
+        .output_language(Language::English)
+        .directive_format(DirectiveFormat::Bracketed)
+        .build();
 
     let _result = optimizer.optimize(&request).expect("Optimization failed");
 
     // Check application count increased
     let final_count: i64 = db_ref
         .connection()
+        .unwrap()
         .query_row(
             "SELECT applied_count FROM patterns WHERE id = 1",
             [],
@@ -227,11 +231,13 @@ fn test_hitl_confidence_bayesian_update() {
     // Apply migration
     let migration_sql = include_str!("../migrations/002_add_patterns_table.sql");
     db.connection()
+        .unwrap()
         .execute_batch(migration_sql)
         .expect("Failed to apply migration");
 
     // Insert pattern with base confidence 0.80
     db.connection()
+        .unwrap()
         .execute(
             "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
              VALUES ('boilerplate', '(?i)test', '', 0.80, 'Test pattern')",
@@ -241,6 +247,7 @@ fn test_hitl_confidence_bayesian_update() {
 
     let initial_confidence: f64 = db
         .connection()
+        .unwrap()
         .query_row(
             "SELECT base_confidence FROM patterns WHERE id = 1",
             [],
@@ -270,6 +277,7 @@ fn test_hitl_confidence_bayesian_update() {
     // Check updated confidence (should blend with feedback before 10 decisions)
     let updated_confidence: f64 = db
         .connection()
+        .unwrap()
         .query_row(
             "SELECT base_confidence FROM patterns WHERE id = 1",
             [],
@@ -305,6 +313,7 @@ fn insert_test_patterns(db: &Database) {
 
     for (pattern_type, regex, replacement, confidence, reasoning) in patterns {
         db.connection()
+            .unwrap()
             .execute(
                 "INSERT INTO patterns (pattern_type, regex_pattern, replacement, base_confidence, reasoning)
                  VALUES (?1, ?2, ?3, ?4, ?5)",
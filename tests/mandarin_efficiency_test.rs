@@ -19,8 +19,8 @@ fn test_mandarin_token_efficiency() {
     println!("MANDARIN TOKEN EFFICIENCY TEST");
     println!("{}", "=".repeat(90));
     println!(
-        "{:<25} {:<12} {:<15} {:<12} {:<10} {}",
-        "English", "EN Tokens", "Mandarin", "ZH Tokens", "Savings", "Efficient?"
+        "{:<25} {:<12} {:<15} {:<12} {:<10} Efficient?",
+        "English", "EN Tokens", "Mandarin", "ZH Tokens", "Savings"
     );
     println!("{}", "-".repeat(90));
 
@@ -120,7 +120,7 @@ fn test_mandarin_in_context() {
         total_zh += zh_tokens;
 
         let savings = en_tokens as i32 - zh_tokens as i32;
-        let percent = (savings as f64 / en_tokens as f64 * 100.0);
+        let percent = savings as f64 / en_tokens as f64 * 100.0;
 
         println!("EN ({}t): {}", en_tokens, en);
         println!("ZH ({}t): {}", zh_tokens, zh);